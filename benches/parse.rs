@@ -0,0 +1,90 @@
+//! Benchmarks of the keyblock parse path, on a generated block of `KEY_COUNT` keys
+
+use banjo_keyring::id::GlobalId;
+use banjo_keyring::keyblock::{KeyBlock, KeyFile};
+use banjo_keyring::rootkey::RootKey;
+use banjo_keyring::secret::Secret256;
+use banjo_keyring::signature::Signature;
+use banjo_keyring::suite::CipherSuite;
+use banjo_keyring::utils::read_null_string;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use openssl::rsa::Rsa;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::io::Cursor;
+
+/// Number of keys of the generated block
+const KEY_COUNT: usize = 10_000;
+
+/// Serialized block of `KEY_COUNT` keys with realistic metadata
+fn generate_block(root_key: &RootKey) -> Vec<u8> {
+    let mut keys = HashMap::new();
+    for index in 0..KEY_COUNT {
+        let path = format!("/etc/services/service-{:05}/credentials/key.pem", index);
+        let content = vec![index as u8; 256];
+        keys.insert(path.clone(), KeyFile {
+            flags: 0,
+            secret: Secret256::generate().unwrap(),
+            uid: (u16::from(b'F') << 8) | (index % 256) as u16,
+            id: GlobalId::generate().unwrap(),
+            path,
+            name: format!("Service {} key", index),
+            description: "Private key of a generated service, used to benchmark parsing.".to_string(),
+            tags: vec!["env:prod".to_string(), format!("team:{}", index % 16)],
+            length: content.len() as u64,
+            content
+        });
+    }
+
+    let block = KeyBlock {
+        root_pubkey: root_key.clone(),
+        format_specifier: 0,
+        flags: 0,
+        cipher_suite: CipherSuite::Classic,
+        secret: Secret256::generate().unwrap(),
+        uid: (u16::from(b'B') << 8) | 0x42,
+        id: GlobalId::generate().unwrap(),
+        host: None,
+        name: "benchmark".to_string(),
+        description: "Generated keyblock".to_string(),
+        keys,
+        signature: Signature::none(),
+        reserved_uids: Vec::new()
+    };
+
+    block.serialize().unwrap()
+}
+
+fn parse(criterion: &mut Criterion) {
+    let rsa = Rsa::generate(2048).unwrap();
+    let root_key = RootKey::from_bytes(&rsa.public_key_to_pem().unwrap()).unwrap();
+    let serialized = generate_block(&root_key);
+    let mut group = criterion.benchmark_group("parse");
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes(serialized.len() as u64));
+
+    group.bench_function(format!("{} keys", KEY_COUNT), |bencher| bencher.iter_batched(
+        || root_key.clone(),
+        |root_key| KeyBlock::from_bytes_unverified(black_box(&serialized), root_key).unwrap(),
+        BatchSize::SmallInput
+    ));
+    group.finish();
+}
+
+fn null_strings(criterion: &mut Criterion) {
+    let mut strings = Vec::new();
+    for index in 0..KEY_COUNT {
+        strings.extend(format!("/etc/services/service-{:05}/credentials/key.pem", index).as_bytes());
+        strings.push(0);
+    }
+
+    criterion.bench_function("read_null_string", |bencher| bencher.iter(|| {
+        let mut reader = Cursor::new(black_box(&strings));
+        for _ in 0..KEY_COUNT {
+            black_box(read_null_string(&mut reader).unwrap());
+        }
+    }));
+}
+
+criterion_group!(benches, parse, null_strings);
+criterion_main!(benches);
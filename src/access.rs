@@ -0,0 +1,71 @@
+//! Last-access tracking, stored next to a keyblock as `<block>.access`
+//!
+//! Tracking is enabled with `--track-access` or the `track-access` option. Commands handing out the content
+//! of a key record the day they did, by key path. The log isn't part of the signed block, so reading a key
+//! doesn't need the signing key, and it is only as trustworthy as the directory holding it.
+
+use crate::permissions::write_private;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+/// Extension appended to the block path
+pub const ACCESS_EXTENSION: &str = ".access";
+
+/// Day every key of a keyblock was last handed out, in days since the UNIX epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLog {
+    /// Day tracking started, keys without an entry weren't used since
+    pub since: u32,
+    /// Day of the last access, by key path
+    pub keys: BTreeMap<String, u32>
+}
+
+impl AccessLog {
+    /// Location of the log of `block`
+    pub fn path(block: &Path) -> PathBuf {
+        let mut path = OsString::from(block.as_os_str());
+        path.push(ACCESS_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Load the log of `block`, `None` if tracking never recorded anything for it
+    pub fn load(block: &Path) -> io::Result<Option<AccessLog>> {
+        let content = match fs::read(AccessLog::path(block)) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error)
+        };
+
+        serde_json::from_slice(&content).map(Some).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Record that the keys at `paths` of `block` were handed out today
+    pub fn record(block: &Path, paths: &[&str]) -> io::Result<()> {
+        let today = today();
+        let mut log = AccessLog::load(block)?.unwrap_or(AccessLog { since: today, keys: BTreeMap::new() });
+        for path in paths {
+            log.keys.insert(path.to_string(), today);
+        }
+
+        write_private(&AccessLog::path(block), &serde_json::to_vec(&log)?)
+    }
+
+    /// Day the key at `path` was last handed out, if it was since tracking started
+    pub fn last_accessed(&self, path: &str) -> Option<u32> {
+        self.keys.get(path).copied()
+    }
+
+    /// Whether the key at `path` wasn't handed out since `day`
+    pub fn unused_since(&self, path: &str, day: u32) -> bool {
+        self.last_accessed(path).unwrap_or(self.since) < day
+    }
+}
+
+/// Current day, in days since the UNIX epoch
+pub fn today() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| (time.as_secs() / 86400) as u32).unwrap_or(0)
+}
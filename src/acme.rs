@@ -0,0 +1,397 @@
+//! Certificates issued by an ACME server such as Let's Encrypt, following RFC 8555
+//!
+//! Requests are JWS signed with ES256 by an ECDSA P-256 account key, which is stored in the keyblock like
+//! any other key. Domains are validated with HTTP-01, by writing the key authorization under a webroot served
+//! at `/.well-known/acme-challenge/`, or with DNS-01, by running a command publishing the TXT record.
+
+use native_tls::TlsConnector;
+use openssl::base64::encode_block;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, PKeyRef, Private};
+use openssl::sha::sha256;
+use openssl::x509::X509Req;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+use std::{fmt, io};
+
+/// Directory of the Let's Encrypt production server
+pub const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Timeout of each request to the server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Delay between two polls of a pending authorization or order
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Number of polls before giving up on an authorization or order
+const MAX_POLLS: usize = 60;
+/// Size of the coordinates of P-256 points and of the halves of ES256 signatures
+const COORDINATE_SIZE: usize = 32;
+
+/// Enumeration of the potential errors when talking to an ACME server
+#[derive(Debug)]
+pub enum AcmeErrors {
+    /// The TLS backend couldn't be initialized
+    TlsError(native_tls::Error),
+    /// A request failed before the server answered
+    RequestError(Box<ureq::Error>),
+    /// The server refused a request, with the type and detail of its problem document
+    Problem(String, String),
+    /// The server answered something RFC 8555 doesn't allow
+    InvalidResponse(String),
+    /// The account key isn't an ECDSA P-256 key
+    InvalidAccountKey,
+    /// The server doesn't offer the requested challenge type for a domain
+    NoChallenge(String, &'static str),
+    /// The server couldn't validate a domain
+    ValidationFailed(String, String),
+    /// An authorization or order is still pending after `MAX_POLLS` polls
+    Timeout(String),
+    /// The DNS-01 command exited with an error
+    CommandFailed(ExitStatus),
+    IOError(io::Error),
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for AcmeErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcmeErrors::TlsError(error) => write!(f, "failed to initialize TLS: {}", error),
+            AcmeErrors::RequestError(error) => write!(f, "ACME request failed: {}", error),
+            AcmeErrors::Problem(kind, detail) => write!(f, "the ACME server refused the request: {} ({})", detail, kind),
+            AcmeErrors::InvalidResponse(error) => write!(f, "invalid ACME response: {}", error),
+            AcmeErrors::InvalidAccountKey => write!(f, "the ACME account key has to be an ECDSA P-256 key"),
+            AcmeErrors::NoChallenge(domain, kind) => write!(f, "the server offers no {} challenge for {}", kind, domain),
+            AcmeErrors::ValidationFailed(domain, detail) => write!(f, "{} couldn't be validated: {}", domain, detail),
+            AcmeErrors::Timeout(url) => write!(f, "{} is still pending, giving up", url),
+            AcmeErrors::CommandFailed(status) => write!(f, "the DNS challenge command failed ({})", status),
+            AcmeErrors::IOError(error) => write!(f, "IO error: {}", error),
+            AcmeErrors::OpenSSLError(error) => write!(f, "OpenSSL error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for AcmeErrors {}
+
+impl From<io::Error> for AcmeErrors {
+    fn from(error: io::Error) -> Self {
+        AcmeErrors::IOError(error)
+    }
+}
+
+impl From<ErrorStack> for AcmeErrors {
+    fn from(error: ErrorStack) -> Self {
+        AcmeErrors::OpenSSLError(error)
+    }
+}
+
+/// How domains are validated
+#[derive(Debug, Clone)]
+pub enum Challenge {
+    /// HTTP-01, the key authorization being written under this directory served by the web server
+    Http(PathBuf),
+    /// DNS-01, this command being run with `sh -c` to publish and remove the TXT record
+    Dns(String)
+}
+
+impl Challenge {
+    /// Challenge type in RFC 8555
+    fn kind(&self) -> &'static str {
+        match self {
+            Challenge::Http(_) => "http-01",
+            Challenge::Dns(_) => "dns-01"
+        }
+    }
+}
+
+/// URLs of the ACME directory
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    identifier: Identifier,
+    status: String,
+    challenges: Vec<ChallengeObject>
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String
+}
+
+#[derive(Debug, Deserialize)]
+struct ChallengeObject {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: Option<String>,
+    error: Option<Value>
+}
+
+/// Session with an ACME server under an account
+pub struct AcmeClient {
+    agent: ureq::Agent,
+    directory: Directory,
+    key: PKey<Private>,
+    /// Public key of the account, as a JWK
+    jwk: Value,
+    /// URL of the account, once registered
+    account: Option<String>,
+    nonce: Option<String>
+}
+
+impl AcmeClient {
+    /// Fetch the directory of a server and register the account of `key`, or find it if it already exists
+    pub fn connect(directory: &str, key: PKey<Private>, email: Option<&str>) -> Result<AcmeClient, AcmeErrors> {
+        let jwk = jwk(&key)?;
+        let agent = ureq::AgentBuilder::new()
+            .tls_connector(Arc::new(TlsConnector::new().map_err(AcmeErrors::TlsError)?))
+            .timeout(REQUEST_TIMEOUT)
+            .build();
+        let directory = agent.get(directory).call().map_err(problem)?
+            .into_json().map_err(|error| AcmeErrors::InvalidResponse(error.to_string()))?;
+
+        let mut client = AcmeClient { agent, directory, key, jwk, account: None, nonce: None };
+        let contact: Vec<String> = email.map(|email| format!("mailto:{}", email)).into_iter().collect();
+        let payload = json!({ "termsOfServiceAgreed": true, "contact": contact });
+        let (location, _) = client.post(&client.directory.new_account.clone(), Some(&payload))?;
+        client.account = Some(location.ok_or_else(|| AcmeErrors::InvalidResponse("the account has no URL".to_string()))?);
+
+        Ok(client)
+    }
+
+    /// Order a certificate for `domains`, validate them and return the PEM encoded certificate chain
+    pub fn issue(&mut self, domains: &[String], request: &X509Req, challenge: &Challenge) -> Result<Vec<u8>, AcmeErrors> {
+        let identifiers: Vec<Value> = domains.iter().map(|domain| json!({ "type": "dns", "value": domain })).collect();
+        let (location, order) = self.post(&self.directory.new_order.clone(), Some(&json!({ "identifiers": identifiers })))?;
+        let order_url = location.ok_or_else(|| AcmeErrors::InvalidResponse("the order has no URL".to_string()))?;
+        let order: Order = parse(order)?;
+
+        for authorization in &order.authorizations {
+            self.authorize(authorization, challenge)?;
+        }
+
+        let csr = base64url(&request.to_der()?);
+        self.post(&order.finalize, Some(&json!({ "csr": csr })))?;
+        let order: Order = self.poll(&order_url, &["pending", "ready", "processing"])?;
+        if order.status != "valid" {
+            return Err(AcmeErrors::InvalidResponse(format!("the order is {} after finalization", order.status)))
+        }
+
+        let certificate = order.certificate.ok_or_else(|| AcmeErrors::InvalidResponse("the order has no certificate".to_string()))?;
+        let (_, chain) = self.post(&certificate, None)?;
+        Ok(chain)
+    }
+
+    /// Complete the challenge of an authorization, unless it is already valid
+    fn authorize(&mut self, url: &str, challenge: &Challenge) -> Result<(), AcmeErrors> {
+        let authorization: Authorization = parse(self.post(url, None)?.1)?;
+        let domain = authorization.identifier.value;
+        if authorization.status == "valid" { return Ok(()) }
+
+        let offered = authorization.challenges.into_iter().find(|offered| offered.kind == challenge.kind())
+            .ok_or_else(|| AcmeErrors::NoChallenge(domain.clone(), challenge.kind()))?;
+        let token = offered.token.ok_or_else(|| AcmeErrors::InvalidResponse("the challenge has no token".to_string()))?;
+        // Tokens end up in a file name
+        if !token.chars().all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_') {
+            return Err(AcmeErrors::InvalidResponse(format!("invalid challenge token {:?}", token)))
+        }
+        let key_authorization = format!("{}.{}", token, base64url(&sha256(self.jwk.to_string().as_bytes())));
+
+        challenge_step(challenge, &domain, &token, &key_authorization, true)?;
+        let result = self.post(&offered.url, Some(&json!({})))
+            .and_then(|_| self.poll::<Authorization>(url, &["pending"]));
+        challenge_step(challenge, &domain, &token, &key_authorization, false)?;
+
+        let authorization = result?;
+        if authorization.status == "valid" { return Ok(()) }
+
+        let detail = authorization.challenges.iter()
+            .find_map(|offered| offered.error.as_ref())
+            .and_then(|error| error.get("detail")).and_then(Value::as_str)
+            .unwrap_or(&authorization.status).to_string();
+        Err(AcmeErrors::ValidationFailed(domain, detail))
+    }
+
+    /// Fetch an object until its status isn't one of `pending`
+    fn poll<T: StatusObject>(&mut self, url: &str, pending: &[&str]) -> Result<T, AcmeErrors> {
+        for _ in 0..MAX_POLLS {
+            let object: T = parse(self.post(url, None)?.1)?;
+            if !pending.contains(&object.status()) { return Ok(object) }
+            sleep(POLL_INTERVAL);
+        }
+
+        Err(AcmeErrors::Timeout(url.to_string()))
+    }
+
+    /// Send a signed request, a POST-as-GET without `payload`, returning the `Location` header and the body
+    ///
+    /// Requests rejected because of their nonce are retried once with the fresh nonce of the rejection.
+    fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<(Option<String>, Vec<u8>), AcmeErrors> {
+        for attempt in 0..2 {
+            let body = self.sign(url, payload)?;
+            let result = self.agent.post(url).set("Content-Type", "application/jose+json").send_string(&body);
+
+            let response = match result {
+                Ok(response) => response,
+                Err(ureq::Error::Status(status, response)) => {
+                    self.nonce = response.header("Replay-Nonce").map(str::to_string);
+                    match problem(ureq::Error::Status(status, response)) {
+                        AcmeErrors::Problem(kind, _) if attempt == 0 && kind.ends_with(":badNonce") => continue,
+                        error => return Err(error)
+                    }
+                },
+                Err(error) => return Err(problem(error))
+            };
+
+            self.nonce = response.header("Replay-Nonce").map(str::to_string);
+            let location = response.header("Location").map(str::to_string);
+            let mut body = Vec::new();
+            response.into_reader().read_to_end(&mut body)?;
+            return Ok((location, body))
+        }
+
+        unreachable!("The second attempt always returns.")
+    }
+
+    /// JWS of a request in the flattened JSON serialization
+    fn sign(&mut self, url: &str, payload: Option<&Value>) -> Result<String, AcmeErrors> {
+        let nonce = match self.nonce.take() {
+            Some(nonce) => nonce,
+            None => {
+                let response = self.agent.head(&self.directory.new_nonce).call().map_err(problem)?;
+                response.header("Replay-Nonce")
+                    .ok_or_else(|| AcmeErrors::InvalidResponse("no Replay-Nonce header".to_string()))?
+                    .to_string()
+            }
+        };
+
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.account {
+            Some(account) => protected["kid"] = json!(account),
+            None => protected["jwk"] = self.jwk.clone()
+        }
+
+        let protected = base64url(protected.to_string().as_bytes());
+        let payload = payload.map(|payload| base64url(payload.to_string().as_bytes())).unwrap_or_default();
+        let signature = EcdsaSig::sign(&sha256(format!("{}.{}", protected, payload).as_bytes()), &*self.key.ec_key()?)?;
+        let signature = [
+            signature.r().to_vec_padded(COORDINATE_SIZE as i32)?, signature.s().to_vec_padded(COORDINATE_SIZE as i32)?
+        ].concat();
+
+        Ok(json!({ "protected": protected, "payload": payload, "signature": base64url(&signature) }).to_string())
+    }
+}
+
+/// ACME objects with a status
+trait StatusObject: for<'de> Deserialize<'de> {
+    fn status(&self) -> &str;
+}
+
+impl StatusObject for Order {
+    fn status(&self) -> &str { &self.status }
+}
+
+impl StatusObject for Authorization {
+    fn status(&self) -> &str { &self.status }
+}
+
+/// Generate an account key
+pub fn generate_account_key() -> Result<PKey<Private>, ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    PKey::from_ec_key(EcKey::generate(&group)?)
+}
+
+/// Public JWK of an ECDSA P-256 key, with its members sorted as the thumbprint of RFC 7638 needs
+fn jwk(key: &PKeyRef<Private>) -> Result<Value, AcmeErrors> {
+    if key.id() != Id::EC { return Err(AcmeErrors::InvalidAccountKey) }
+    let ec = key.ec_key()?;
+    if ec.group().curve_name() != Some(Nid::X9_62_PRIME256V1) { return Err(AcmeErrors::InvalidAccountKey) }
+
+    let mut x = BigNum::new()?;
+    let mut y = BigNum::new()?;
+    let mut context = BigNumContext::new()?;
+    ec.public_key().affine_coordinates(ec.group(), &mut x, &mut y, &mut context)?;
+
+    // serde_json objects keep their members sorted, which is the order of the thumbprint
+    Ok(json!({
+        "crv": "P-256",
+        "kty": "EC",
+        "x": base64url(&x.to_vec_padded(COORDINATE_SIZE as i32)?),
+        "y": base64url(&y.to_vec_padded(COORDINATE_SIZE as i32)?)
+    }))
+}
+
+/// Publish the challenge response when `present`, remove it otherwise
+fn challenge_step(challenge: &Challenge, domain: &str, token: &str, key_authorization: &str, present: bool) -> Result<(), AcmeErrors> {
+    match challenge {
+        Challenge::Http(webroot) => {
+            let directory = webroot.join(".well-known").join("acme-challenge");
+            let file = directory.join(token);
+            if present {
+                fs::create_dir_all(&directory)?;
+                // The web server has to read it, and it is public anyway
+                fs::write(&file, key_authorization)?;
+            } else if let Err(error) = fs::remove_file(&file) {
+                if error.kind() != io::ErrorKind::NotFound { return Err(error.into()) }
+            }
+        },
+        Challenge::Dns(command) => {
+            let status = Command::new("sh")
+                .arg("-c").arg(command)
+                .env("BANJO_ACME_ACTION", if present { "present" } else { "cleanup" })
+                .env("BANJO_ACME_DOMAIN", domain)
+                .env("BANJO_ACME_RECORD", format!("_acme-challenge.{}", domain.trim_start_matches("*.")))
+                .env("BANJO_ACME_VALUE", base64url(&sha256(key_authorization.as_bytes())))
+                .status()?;
+            if !status.success() { return Err(AcmeErrors::CommandFailed(status)) }
+        }
+    }
+
+    Ok(())
+}
+
+/// Error of a failed request, with the problem document of RFC 7807 when the server sent one
+fn problem(error: ureq::Error) -> AcmeErrors {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let document: Value = response.into_json().unwrap_or(Value::Null);
+            let kind = document.get("type").and_then(Value::as_str).unwrap_or("about:blank").to_string();
+            let detail = document.get("detail").and_then(Value::as_str).map_or_else(|| format!("HTTP {}", status), str::to_string);
+            AcmeErrors::Problem(kind, detail)
+        },
+        error => AcmeErrors::RequestError(Box::new(error))
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(body: Vec<u8>) -> Result<T, AcmeErrors> {
+    serde_json::from_slice(&body).map_err(|error| AcmeErrors::InvalidResponse(error.to_string()))
+}
+
+/// Unpadded base64url encoding of JWS
+fn base64url(data: &[u8]) -> String {
+    encode_block(data).trim_end_matches('=').replace('+', "-").replace('/', "_")
+}
@@ -0,0 +1,333 @@
+//! Declarative management of the keys of a block from a YAML manifest
+//!
+//! The manifest lists the desired keys, each with its content read from a source file or produced by a
+//! generator. Applying it adds missing keys, updates the content of source-backed keys and the declared
+//! metadata, and optionally removes keys it doesn't declare. Generated keys are only generated once, so
+//! applying the same manifest twice changes nothing.
+
+use crate::content::ContentType;
+use crate::crypto::CryptoErrors;
+use crate::diff::Change;
+use crate::history::{keep_version, version_of, HistoryErrors};
+use crate::keyblock::{validate_path, validate_text, InvalidField, KeyBlock, KeyFile};
+use crate::tags::{validate_tag, InvalidTag};
+use crate::tls::KeyAlgorithm;
+use crate::wireguard;
+use openssl::error::ErrorStack;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// Characters of generated passwords
+const PASSWORD_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Desired state of a block
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub keys: Vec<DesiredKey>
+}
+
+/// A key declared by a manifest, metadata left out isn't managed
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DesiredKey {
+    pub path: String,
+    /// File holding the content, relative to the manifest
+    pub source: Option<PathBuf>,
+    /// Generator of the content, used when the key doesn't exist yet
+    pub generate: Option<Generator>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[serde(rename = "type")]
+    pub content_type: Option<ContentType>
+}
+
+/// Generators of key content
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Generator {
+    /// Random bytes
+    Random {
+        #[serde(default = "default_random_length")]
+        length: usize
+    },
+    /// Random alphanumeric password
+    Password {
+        #[serde(default = "default_password_length")]
+        length: usize
+    },
+    /// PEM encoded TLS private key
+    TlsKey {
+        #[serde(default = "default_algorithm")]
+        algorithm: KeyAlgorithm
+    },
+    /// Base64 encoded WireGuard private key
+    Wireguard
+}
+
+fn default_random_length() -> usize { 32 }
+
+fn default_password_length() -> usize { 24 }
+
+fn default_algorithm() -> KeyAlgorithm { KeyAlgorithm::EcP256 }
+
+impl Generator {
+    /// Generate new content
+    pub fn generate(self) -> Result<Vec<u8>, ErrorStack> {
+        match self {
+            Generator::Random { length } => {
+                let mut content = vec![0; length];
+                rand_bytes(&mut content)?;
+                Ok(content)
+            },
+            Generator::Password { length } => {
+                let mut password = Vec::with_capacity(length);
+                let mut random = [0; 64];
+                while password.len() < length {
+                    rand_bytes(&mut random)?;
+                    // Rejecting the bytes past the last multiple of the alphabet size avoids any bias
+                    let limit = 256 - 256 % PASSWORD_ALPHABET.len();
+                    password.extend(random.iter()
+                        .filter(|byte| (**byte as usize) < limit)
+                        .map(|byte| PASSWORD_ALPHABET[*byte as usize % PASSWORD_ALPHABET.len()]));
+                }
+                password.truncate(length);
+                Ok(password)
+            },
+            Generator::TlsKey { algorithm } => algorithm.generate()?.private_key_to_pem_pkcs8(),
+            Generator::Wireguard => wireguard::generate_key()
+        }
+    }
+
+    /// Generator producing a replacement for `key`, `content` being its decrypted content
+    ///
+    /// Only the content types it knows how to generate have one.
+    pub fn for_key(key: &KeyFile, content: &[u8]) -> Option<Generator> {
+        match ContentType::from_flags(key.flags)? {
+            ContentType::Generic => Some(Generator::Random { length: content.len() }),
+            ContentType::Password => Some(Generator::Password { length: String::from_utf8_lossy(content).chars().count() }),
+            ContentType::SshKey => {
+                let private_key = PKey::private_key_from_pem(content).ok()?;
+                KeyAlgorithm::of(&private_key).map(|algorithm| Generator::TlsKey { algorithm })
+            },
+            ContentType::Wireguard => Some(Generator::Wireguard),
+            _ => None
+        }
+    }
+
+    /// Content type of generated content
+    pub fn content_type(self) -> ContentType {
+        match self {
+            Generator::Random { .. } => ContentType::Generic,
+            Generator::Password { .. } => ContentType::Password,
+            Generator::TlsKey { .. } => ContentType::SshKey,
+            Generator::Wireguard => ContentType::Wireguard
+        }
+    }
+}
+
+/// Enumeration of the potential errors when applying manifests
+#[derive(Debug)]
+pub enum ApplyErrors {
+    /// A file couldn't be read
+    IOError(PathBuf, io::Error),
+    /// The manifest isn't valid YAML or doesn't match the expected structure
+    InvalidManifest(serde_yaml::Error),
+    /// A key is declared more than once
+    DuplicatePath(String),
+    /// A new key has neither a source nor a generator
+    NoContent(String),
+    /// The content of a key doesn't match its type
+    InvalidContent(String, ContentType, String),
+    InvalidTag(InvalidTag),
+    InvalidField(InvalidField),
+    /// The content of a key couldn't be encrypted or decrypted
+    CryptoError(String, CryptoErrors),
+    /// The previous content of a key couldn't be kept
+    HistoryError(HistoryErrors),
+    /// No keyfile UID is left in the block
+    NoUidLeft,
+    /// An error occurred in OpenSSL
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for ApplyErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyErrors::IOError(path, error) => write!(f, "{}: {}", path.display(), error),
+            ApplyErrors::InvalidManifest(error) => write!(f, "invalid manifest: {}", error),
+            ApplyErrors::DuplicatePath(path) => write!(f, "{} is declared more than once", path),
+            ApplyErrors::NoContent(path) => write!(f, "{} doesn't exist and has neither a source nor a generator", path),
+            ApplyErrors::InvalidContent(path, content_type, error) => {
+                write!(f, "{} isn't a valid {} key: {}", path, content_type, error)
+            },
+            ApplyErrors::InvalidTag(error) => write!(f, "{}", error),
+            ApplyErrors::InvalidField(error) => write!(f, "{}", error),
+            ApplyErrors::CryptoError(path, error) => write!(f, "{}: {}", path, error),
+            ApplyErrors::HistoryError(error) => write!(f, "{}", error),
+            ApplyErrors::NoUidLeft => write!(f, "no keyfile UID is left in this block"),
+            ApplyErrors::OpenSSLError(error) => write!(f, "OpenSSL error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ApplyErrors {}
+
+impl From<ErrorStack> for ApplyErrors {
+    fn from(error: ErrorStack) -> Self {
+        ApplyErrors::OpenSSLError(error)
+    }
+}
+
+impl From<InvalidTag> for ApplyErrors {
+    fn from(error: InvalidTag) -> Self {
+        ApplyErrors::InvalidTag(error)
+    }
+}
+
+impl From<InvalidField> for ApplyErrors {
+    fn from(error: InvalidField) -> Self {
+        ApplyErrors::InvalidField(error)
+    }
+}
+
+impl From<HistoryErrors> for ApplyErrors {
+    fn from(error: HistoryErrors) -> Self {
+        ApplyErrors::HistoryError(error)
+    }
+}
+
+impl Manifest {
+    /// Read a manifest from a YAML file
+    pub fn load(path: &Path) -> Result<Manifest, ApplyErrors> {
+        let content = fs::read(path).map_err(|error| ApplyErrors::IOError(path.to_path_buf(), error))?;
+        serde_yaml::from_slice(&content).map_err(ApplyErrors::InvalidManifest)
+    }
+
+    /// Bring `block` to the state declared by this manifest, removing undeclared keys if `prune` is set
+    ///
+    /// Sources are relative to `base`, and up to `history` previous versions of overwritten content are kept.
+    /// The changes are returned sorted by path.
+    pub fn apply(&self, block: &mut KeyBlock, base: &Path, prune: bool, history: usize) -> Result<Vec<Change>, ApplyErrors> {
+        let mut declared = HashSet::new();
+        for desired in &self.keys {
+            if !declared.insert(desired.path.as_str()) { return Err(ApplyErrors::DuplicatePath(desired.path.clone())) }
+            validate_path(&desired.path)?;
+            if let Some(name) = &desired.name { validate_text("key name", name)? }
+            if let Some(description) = &desired.description { validate_text("key description", description)? }
+            for tag in desired.tags.iter().flatten() {
+                validate_tag(tag)?;
+            }
+        }
+
+        let mut changes = Vec::new();
+        for desired in &self.keys {
+            let source = match &desired.source {
+                Some(source) => {
+                    let path = base.join(source);
+                    Some(fs::read(&path).map_err(|error| ApplyErrors::IOError(path, error))?)
+                },
+                None => None
+            };
+
+            if let Some(change) = desired.apply(block, source, history)? {
+                changes.push(change);
+            }
+        }
+
+        if prune {
+            // Previous versions belong to their key
+            let undeclared: Vec<String> = block.keys.keys()
+                .filter(|path| !declared.contains(path.as_str()))
+                .filter(|path| !version_of(path).is_some_and(|base| declared.contains(base)))
+                .cloned()
+                .collect();
+            for path in undeclared {
+                block.keys.remove(&path);
+                changes.push(Change::Removed(path));
+            }
+        }
+
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        Ok(changes)
+    }
+}
+
+impl DesiredKey {
+    /// Add or update this key in `block`, `source` being the content of its source file
+    fn apply(&self, block: &mut KeyBlock, source: Option<Vec<u8>>, history: usize) -> Result<Option<Change>, ApplyErrors> {
+        let key = match block.keys.get(&self.path) {
+            Some(key) => key,
+            None => {
+                let content = match (source, self.generate) {
+                    (Some(content), _) => content,
+                    (None, Some(generator)) => generator.generate()?,
+                    (None, None) => return Err(ApplyErrors::NoContent(self.path.clone()))
+                };
+                let content_type = self.content_type
+                    .or_else(|| self.generate.map(Generator::content_type))
+                    .unwrap_or(ContentType::Generic);
+                self.validate(content_type, &content)?;
+
+                let name = self.name.as_deref().unwrap_or(&self.path);
+                let mut key = block.new_key(&self.path, name, self.description.as_deref().unwrap_or_default(), &content)
+                    .map_err(|error| ApplyErrors::CryptoError(self.path.clone(), error))?
+                    .ok_or(ApplyErrors::NoUidLeft)?;
+                key.flags = content_type.apply(key.flags);
+                key.tags = self.tags.clone().unwrap_or_default();
+
+                let change = Change::Added(self.path.clone(), key.length);
+                block.keys.insert(self.path.clone(), key);
+                return Ok(Some(change))
+            }
+        };
+
+        let current = key.open().map_err(|error| ApplyErrors::CryptoError(self.path.clone(), error))?;
+        let content = source.filter(|content| *content != current);
+
+        // Everything is checked before the previous version is kept, the content against its new type if any
+        let content_type = self.content_type.or_else(|| content.as_ref().and(ContentType::from_flags(key.flags)));
+        if let Some(content_type) = content_type {
+            self.validate(content_type, content.as_deref().unwrap_or(&current))?;
+        }
+        if content.is_some() {
+            keep_version(block, &self.path, history)?;
+        }
+
+        let key = block.keys.get_mut(&self.path).expect("The key was found above.");
+        let mut fields = Vec::new();
+        if let Some(content) = content {
+            key.seal(&content).map_err(|error| ApplyErrors::CryptoError(self.path.clone(), error))?;
+            fields.push("content");
+        }
+        if let Some(name) = self.name.as_ref().filter(|name| **name != key.name) {
+            key.name = name.clone();
+            fields.push("name");
+        }
+        if let Some(description) = self.description.as_ref().filter(|description| **description != key.description) {
+            key.description = description.clone();
+            fields.push("description");
+        }
+        if let Some(tags) = self.tags.as_ref().filter(|tags| **tags != key.tags) {
+            key.tags = tags.clone();
+            fields.push("tags");
+        }
+        if let Some(content_type) = self.content_type.filter(|content_type| ContentType::from_flags(key.flags) != Some(*content_type)) {
+            key.flags = content_type.apply(key.flags);
+            fields.push("type");
+        }
+
+        if fields.is_empty() { Ok(None) } else { Ok(Some(Change::Updated(self.path.clone(), fields))) }
+    }
+
+    /// Check that `content` is a valid key of `content_type`
+    fn validate(&self, content_type: ContentType, content: &[u8]) -> Result<(), ApplyErrors> {
+        content_type.handler().validate(content)
+            .map_err(|error| ApplyErrors::InvalidContent(self.path.clone(), content_type, error))
+    }
+}
@@ -0,0 +1,88 @@
+//! Local log of the subcommands run, summarized by `banjo usage-report`
+//!
+//! With the `audit-log` setting, every subcommand appends a JSON line to the log once it is done: when it ran,
+//! its name, whether it succeeded and the keys it handed out, by keyblock and path. Nothing leaves the host and
+//! no key content is ever logged.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A key handed out by a subcommand
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AuditKey {
+    pub block: String,
+    pub path: String
+}
+
+/// A subcommand run, one line of the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Time the subcommand finished, in seconds since the UNIX epoch
+    pub time: u64,
+    /// Name of the subcommand, with its own subcommand if any, such as `ssh authorize`
+    pub command: String,
+    pub success: bool,
+    pub keys: Vec<AuditKey>
+}
+
+impl AuditEntry {
+    /// Entry of a subcommand finishing now
+    pub fn now(command: &str, success: bool, keys: Vec<AuditKey>) -> AuditEntry {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        AuditEntry { time, command: command.to_string(), success, keys }
+    }
+
+    /// Append this entry to the log at `path`, creating it only readable by its owner
+    pub fn append(&self, path: &Path) -> io::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+
+        // A single write, so concurrent runs don't interleave their lines
+        OpenOptions::new().append(true).create(true).mode(0o600).open(path)?.write_all(&line)
+    }
+}
+
+/// Every entry of the log at `path`, skipping the lines an interrupted run left incomplete
+pub fn read_log(path: &Path) -> io::Result<Vec<AuditEntry>> {
+    let content = fs::read_to_string(path)?;
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Subcommand runs and key accesses over a time window
+#[derive(Debug, Default)]
+pub struct UsageReport {
+    /// Runs and failures, by subcommand
+    pub commands: HashMap<String, (u64, u64)>,
+    /// Number of times each key was handed out
+    pub keys: HashMap<AuditKey, u64>
+}
+
+impl UsageReport {
+    /// Summarize the entries logged at or after `since`, in seconds since the UNIX epoch
+    pub fn build(entries: &[AuditEntry], since: u64) -> UsageReport {
+        let mut report = UsageReport::default();
+        for entry in entries.iter().filter(|entry| entry.time >= since) {
+            let (runs, failures) = report.commands.entry(entry.command.clone()).or_default();
+            *runs += 1;
+            if !entry.success { *failures += 1 }
+
+            for key in &entry.keys {
+                *report.keys.entry(key.clone()).or_default() += 1;
+            }
+        }
+
+        report
+    }
+
+    /// Total runs and failures
+    pub fn totals(&self) -> (u64, u64) {
+        self.commands.values().fold((0, 0), |(runs, failures), (more_runs, more_failures)| {
+            (runs + more_runs, failures + more_failures)
+        })
+    }
+}
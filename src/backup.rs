@@ -0,0 +1,204 @@
+//! Self-describing backup bundles, encrypted to age recipients
+//!
+//! Here is the bundle format, before encryption:
+//! ```text
+//! bundle = magic_number, 16_number, section, section, section
+//! section = 64_number, { byte }
+//!
+//! magic_number = "banjo-backup"
+//! ```
+//!
+//! The three sections are, in order, the TOML manifest, the PEM encoded root public key and the keyblock.
+//! Anyone can encrypt a bundle to a recipient, so restored blocks are verified against a root key obtained
+//! out of band, never against the bundled one.
+
+use crate::keyblock::KeyBlock;
+use crate::utils::to_hex;
+use age::{DecryptError, EncryptError, Encryptor, Decryptor, Identity, Recipient};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, io};
+
+/// Magic number starting every backup bundle
+const MAGIC_NUMBER: &[u8; 12] = b"banjo-backup";
+/// Bundle version produced by this implementation
+const BUNDLE_VERSION: u16 = 1;
+
+/// Description of the backed up block, readable without parsing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupManifest {
+    /// Name of the backed up block
+    pub block_name: String,
+    /// UID of the backed up block
+    pub block_uid: u16,
+    /// Format specifier of the backed up block
+    pub format_specifier: u16,
+    /// Number of keyfiles in the block
+    pub key_count: u64,
+    /// Hex encoded SHA256 digest of the serialized block
+    pub block_sha256: String,
+    /// Creation time of the backup, in seconds since the UNIX epoch
+    pub created: u64
+}
+
+/// A decrypted backup bundle
+#[derive(Debug, Clone)]
+pub struct BackupBundle {
+    /// Description of the block
+    pub manifest: BackupManifest,
+    /// PEM encoded root public key of the block, along with its ML-DSA key if hybrid
+    ///
+    /// age doesn't authenticate who encrypted a bundle, so this key is informative and must never be used to
+    /// verify the block.
+    pub root_key: Vec<u8>,
+    /// Serialized keyblock
+    pub block: Vec<u8>
+}
+
+/// Enumeration of the potential errors when handling backups
+#[derive(Debug)]
+pub enum BackupErrors {
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The decrypted data isn't a backup bundle
+    InvalidMagicNumber,
+    /// The bundle was produced by a newer implementation
+    UnknownBundleVersion(u16),
+    /// The manifest isn't valid
+    InvalidManifest(String),
+    /// The block doesn't match the digest recorded in the manifest
+    DigestMismatch,
+    /// The bundle couldn't be encrypted
+    EncryptError(EncryptError),
+    /// The bundle couldn't be decrypted
+    DecryptError(DecryptError)
+}
+
+impl fmt::Display for BackupErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupErrors::IOError(error) => write!(f, "IO error: {}", error),
+            BackupErrors::InvalidMagicNumber => write!(f, "invalid magic number, this is not a backup bundle"),
+            BackupErrors::UnknownBundleVersion(version) => write!(f, "unknown backup bundle version {}", version),
+            BackupErrors::InvalidManifest(error) => write!(f, "invalid backup manifest: {}", error),
+            BackupErrors::DigestMismatch => write!(f, "the backed up block doesn't match its manifest digest"),
+            BackupErrors::EncryptError(error) => write!(f, "failed to encrypt the backup: {}", error),
+            BackupErrors::DecryptError(error) => write!(f, "failed to decrypt the backup: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for BackupErrors {}
+
+impl From<io::Error> for BackupErrors {
+    fn from(error: io::Error) -> Self {
+        BackupErrors::IOError(error)
+    }
+}
+
+impl BackupBundle {
+    /// Bundle a serialized block, already parsed into `block`, with its root key
+    pub fn new(serialized: Vec<u8>, block: &KeyBlock) -> Result<BackupBundle, BackupErrors> {
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let root_key = block.root_pubkey.to_pem()
+            .map_err(|error| BackupErrors::IOError(io::Error::other(error)))?;
+
+        Ok(BackupBundle {
+            manifest: BackupManifest {
+                block_name: block.name.clone(),
+                block_uid: block.uid,
+                format_specifier: block.format_specifier,
+                key_count: block.keys.len() as u64,
+                block_sha256: to_hex(&sha256(&serialized)),
+                created
+            },
+            root_key,
+            block: serialized
+        })
+    }
+
+    /// Serialize and encrypt this bundle to every recipient
+    pub fn encrypt(&self, recipients: &[Box<dyn Recipient>]) -> Result<Vec<u8>, BackupErrors> {
+        let encryptor = Encryptor::with_recipients(recipients.iter().map(|recipient| recipient.as_ref() as _))
+            .map_err(BackupErrors::EncryptError)?;
+
+        let mut output = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut output)?;
+        writer.write_all(&self.serialize()?)?;
+        writer.finish()?;
+
+        Ok(output)
+    }
+
+    /// Decrypt and parse a bundle, checking the block against the manifest digest
+    pub fn decrypt(encrypted: &[u8], identities: &[Box<dyn Identity>]) -> Result<BackupBundle, BackupErrors> {
+        let decryptor = Decryptor::new_buffered(encrypted).map_err(BackupErrors::DecryptError)?;
+        let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity.as_ref() as _))
+            .map_err(BackupErrors::DecryptError)?;
+
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+
+        let bundle = BackupBundle::parse(&content)?;
+        if bundle.manifest.block_sha256 != to_hex(&sha256(&bundle.block)) {
+            return Err(BackupErrors::DigestMismatch)
+        }
+
+        Ok(bundle)
+    }
+
+    /// Serialize this bundle, before encryption
+    fn serialize(&self) -> Result<Vec<u8>, BackupErrors> {
+        let manifest = toml::to_string(&self.manifest)
+            .map_err(|error| BackupErrors::InvalidManifest(error.to_string()))?;
+        let mut buffer: Vec<u8> = Vec::new();
+
+        buffer.extend(MAGIC_NUMBER);
+        buffer.write_u16::<LittleEndian>(BUNDLE_VERSION)?;
+
+        for section in [manifest.as_bytes(), &self.root_key, &self.block] {
+            buffer.write_u64::<LittleEndian>(section.len() as u64)?;
+            buffer.extend(section);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Parse a decrypted bundle
+    fn parse(mut content: &[u8]) -> Result<BackupBundle, BackupErrors> {
+        let mut magic_number = [0; MAGIC_NUMBER.len()];
+        content.read_exact(&mut magic_number)?;
+        if &magic_number != MAGIC_NUMBER { return Err(BackupErrors::InvalidMagicNumber) }
+
+        let version = content.read_u16::<LittleEndian>()?;
+        if version != BUNDLE_VERSION { return Err(BackupErrors::UnknownBundleVersion(version)) }
+
+        let manifest = read_section(&mut content)?;
+        let root_key = read_section(&mut content)?;
+        let block = read_section(&mut content)?;
+
+        let manifest = std::str::from_utf8(&manifest)
+            .map_err(|error| BackupErrors::InvalidManifest(error.to_string()))
+            .and_then(|manifest| toml::from_str(manifest)
+                .map_err(|error| BackupErrors::InvalidManifest(error.to_string())))?;
+
+        Ok(BackupBundle { manifest, root_key, block })
+    }
+}
+
+/// Read a 64 bits length-prefixed section of a bundle
+fn read_section(content: &mut &[u8]) -> Result<Vec<u8>, BackupErrors> {
+    let length = content.read_u64::<LittleEndian>()?;
+    let mut section = Vec::new();
+    content.take(length).read_to_end(&mut section)?;
+
+    if section.len() as u64 != length {
+        return Err(BackupErrors::IOError(io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    Ok(section)
+}
@@ -0,0 +1,187 @@
+//! Offline verification bundles, carrying everything needed to check a block on an air-gapped host
+//!
+//! Here is the bundle format:
+//! ```text
+//! bundle = magic_number, 16_number, section, section, section, section
+//! section = 64_number, { byte }
+//!
+//! magic_number = "banjo-bundle"
+//! ```
+//!
+//! The four sections are, in order, the keyblock, a detached root key signature of the whole keyblock
+//! (a keyblock signature section), the hex encoded fingerprint of the root public key and the signed
+//! JSON manifest of the keyblock. Unlike backups, bundles aren't encrypted.
+
+use crate::keyblock::KeyBlock;
+use crate::manifest::{Manifest, SignedManifest};
+use crate::report::{CheckKind, CheckStatus, VerifyReport};
+use crate::rootkey::{RootKey, SigningKey};
+use crate::signature::Signature;
+use crate::utils::to_hex;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use openssl::error::ErrorStack;
+use openssl::sha::sha256;
+use std::io::Read;
+use std::{fmt, io};
+
+/// Magic number starting every offline bundle
+const MAGIC_NUMBER: &[u8; 12] = b"banjo-bundle";
+/// Bundle version produced by this implementation
+const BUNDLE_VERSION: u16 = 1;
+
+/// A parsed offline bundle
+#[derive(Debug, Clone)]
+pub struct OfflineBundle {
+    /// Serialized keyblock
+    pub block: Vec<u8>,
+    /// Root key signature of the whole serialized keyblock
+    pub signature: Signature,
+    /// Fingerprint of the root public key the bundle was made for
+    pub root_key_fingerprint: String,
+    /// Signed inventory of the keyblock
+    pub manifest: SignedManifest
+}
+
+/// Enumeration of the potential errors when handling offline bundles
+#[derive(Debug)]
+pub enum BundleErrors {
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The file isn't an offline bundle
+    InvalidMagicNumber,
+    /// The bundle was produced by a newer implementation
+    UnknownBundleVersion(u16),
+    /// A section of the bundle isn't valid
+    InvalidSection(&'static str, String),
+    /// OpenSSL failed to sign or fingerprint
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for BundleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleErrors::IOError(error) => write!(f, "IO error: {}", error),
+            BundleErrors::InvalidMagicNumber => write!(f, "invalid magic number, this is not an offline bundle"),
+            BundleErrors::UnknownBundleVersion(version) => write!(f, "unknown offline bundle version {}", version),
+            BundleErrors::InvalidSection(section, error) => write!(f, "invalid {} section: {}", section, error),
+            BundleErrors::OpenSSLError(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for BundleErrors {}
+
+impl From<io::Error> for BundleErrors {
+    fn from(error: io::Error) -> Self {
+        BundleErrors::IOError(error)
+    }
+}
+
+impl From<ErrorStack> for BundleErrors {
+    fn from(error: ErrorStack) -> Self {
+        BundleErrors::OpenSSLError(error)
+    }
+}
+
+impl OfflineBundle {
+    /// Bundle a serialized block, already parsed into `block`, signing it and its manifest
+    pub fn new(serialized: Vec<u8>, block: &KeyBlock, key: &SigningKey) -> Result<OfflineBundle, BundleErrors> {
+        Ok(OfflineBundle {
+            signature: Signature::sign(&serialized, key)?,
+            root_key_fingerprint: block.root_pubkey.fingerprint()?,
+            manifest: Manifest::new(block, &serialized)?.sign(key)?,
+            block: serialized
+        })
+    }
+
+    /// Serialize this bundle
+    pub fn serialize(&self) -> Result<Vec<u8>, BundleErrors> {
+        let manifest = serde_json::to_vec(&self.manifest)
+            .map_err(|error| BundleErrors::InvalidSection("manifest", error.to_string()))?;
+        let mut buffer: Vec<u8> = Vec::new();
+
+        buffer.extend(MAGIC_NUMBER);
+        buffer.write_u16::<LittleEndian>(BUNDLE_VERSION)?;
+
+        let signature = self.signature.serialize()?;
+        for section in [&self.block, &signature, self.root_key_fingerprint.as_bytes(), &manifest] {
+            buffer.write_u64::<LittleEndian>(section.len() as u64)?;
+            buffer.extend(section);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Parse a bundle, without checking its content
+    pub fn parse(mut content: &[u8]) -> Result<OfflineBundle, BundleErrors> {
+        let mut magic_number = [0; MAGIC_NUMBER.len()];
+        content.read_exact(&mut magic_number)?;
+        if &magic_number != MAGIC_NUMBER { return Err(BundleErrors::InvalidMagicNumber) }
+
+        let version = content.read_u16::<LittleEndian>()?;
+        if version != BUNDLE_VERSION { return Err(BundleErrors::UnknownBundleVersion(version)) }
+
+        let block = read_section(&mut content)?;
+        let signature = read_section(&mut content)?;
+        let fingerprint = read_section(&mut content)?;
+        let manifest = read_section(&mut content)?;
+
+        let signature = Signature::read(&mut &signature[..])
+            .map_err(|error| BundleErrors::InvalidSection("signature", error.to_string()))?;
+        let root_key_fingerprint = String::from_utf8(fingerprint)
+            .map_err(|error| BundleErrors::InvalidSection("fingerprint", error.to_string()))?;
+        let manifest = serde_json::from_slice(&manifest)
+            .map_err(|error| BundleErrors::InvalidSection("manifest", error.to_string()))?;
+
+        Ok(OfflineBundle { block, signature, root_key_fingerprint, manifest })
+    }
+
+    /// Verify the bundled block and check every section is consistent with it and the root key
+    pub fn report(&self, name: &str, root_key: RootKey) -> VerifyReport {
+        let mut report = VerifyReport::build(name, &self.block, root_key.clone());
+
+        match root_key.fingerprint() {
+            Ok(fingerprint) if fingerprint == self.root_key_fingerprint => {
+                report.record("bundle-fingerprint", CheckKind::Bundle, CheckStatus::Pass, &fingerprint);
+            },
+            Ok(fingerprint) => report.record("bundle-fingerprint", CheckKind::Bundle, CheckStatus::Fail, &format!(
+                "the bundle was made for root key {}, not {}", self.root_key_fingerprint, fingerprint
+            )),
+            Err(error) => report.record("bundle-fingerprint", CheckKind::Bundle, CheckStatus::Fail, &error.to_string())
+        }
+
+        match self.signature.verify(&self.block, &root_key) {
+            Ok(()) => report.record("bundle-signature", CheckKind::Bundle, CheckStatus::Pass, &format!("{:?}", self.signature.algorithm)),
+            Err(error) => report.record("bundle-signature", CheckKind::Bundle, CheckStatus::Fail, &error.to_string())
+        }
+
+        match self.manifest.verify(&root_key) {
+            Ok(()) => report.record("manifest-signature", CheckKind::Bundle, CheckStatus::Pass, &self.manifest.manifest.format),
+            Err(error) => report.record("manifest-signature", CheckKind::Bundle, CheckStatus::Fail, &error.to_string())
+        }
+
+        let digest = to_hex(&sha256(&self.block));
+        if self.manifest.manifest.block_sha256 == digest {
+            report.record("manifest-digest", CheckKind::Bundle, CheckStatus::Pass, &digest);
+        } else {
+            report.record("manifest-digest", CheckKind::Bundle, CheckStatus::Fail, &format!(
+                "the manifest describes block {}, not {}", self.manifest.manifest.block_sha256, digest
+            ));
+        }
+
+        report
+    }
+}
+
+/// Read a 64 bits length-prefixed section of a bundle
+fn read_section(content: &mut &[u8]) -> Result<Vec<u8>, BundleErrors> {
+    let length = content.read_u64::<LittleEndian>()?;
+    let mut section = Vec::new();
+    content.take(length).read_to_end(&mut section)?;
+
+    if section.len() as u64 != length {
+        return Err(BundleErrors::IOError(io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    Ok(section)
+}
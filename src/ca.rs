@@ -0,0 +1,192 @@
+//! Internal certificate authorities backed by keyfiles
+//!
+//! A CA named `CA` is made of its private key at `CA.key` and its certificate at `CA.crt`, as created by
+//! `banjo gen-cert --is-ca`. Every certificate it issues is recorded in the JSON registry at `CA.issued`,
+//! which keeps track of the serial numbers in use and of renewals.
+
+use crate::crypto::CryptoErrors;
+use crate::keyblock::KeyBlock;
+use crate::tls::{issue_certificate, random_serial, Issuer};
+use crate::x509::describe_name;
+use openssl::bn::BigNum;
+use openssl::error::ErrorStack;
+use openssl::pkey::{HasPublic, PKey, PKeyRef, Private};
+use openssl::x509::{X509NameRef, X509};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A certificate issued by a CA
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedCertificate {
+    /// Hex encoded serial number
+    pub serial: String,
+    pub subject: String,
+    pub not_after: String,
+    /// Issuance time, in seconds since the UNIX epoch
+    pub issued: u64,
+    /// Serial of the certificate renewing this one, if any
+    pub renewed_by: Option<String>,
+    /// PEM encoded certificate
+    pub certificate: String
+}
+
+/// Certificates issued by a CA, the content of `CA.issued`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Registry {
+    pub certificates: Vec<IssuedCertificate>
+}
+
+/// Enumeration of the potential errors when using a CA
+#[derive(Debug)]
+pub enum CaErrors {
+    /// A keyfile of the CA is missing
+    MissingKey(String),
+    /// The issuance registry isn't valid JSON
+    InvalidRegistry(serde_json::Error),
+    /// No certificate with this serial was issued
+    UnknownSerial(String),
+    /// The keyfile at this path couldn't be decrypted
+    CryptoError(String, CryptoErrors),
+    /// An error occurred in OpenSSL
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for CaErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaErrors::MissingKey(path) => write!(f, "no key at path {}", path),
+            CaErrors::InvalidRegistry(error) => write!(f, "invalid issuance registry: {}", error),
+            CaErrors::UnknownSerial(serial) => write!(f, "no certificate with serial {} was issued", serial),
+            CaErrors::CryptoError(path, error) => write!(f, "{}: {}", path, error),
+            CaErrors::OpenSSLError(error) => write!(f, "OpenSSL error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for CaErrors {}
+
+impl From<ErrorStack> for CaErrors {
+    fn from(error: ErrorStack) -> Self {
+        CaErrors::OpenSSLError(error)
+    }
+}
+
+impl From<serde_json::Error> for CaErrors {
+    fn from(error: serde_json::Error) -> Self {
+        CaErrors::InvalidRegistry(error)
+    }
+}
+
+/// A CA loaded from a block
+pub struct CertificateAuthority {
+    pub name: String,
+    pub key: PKey<Private>,
+    pub certificate: X509,
+    pub registry: Registry
+}
+
+impl CertificateAuthority {
+    /// Load the CA `name` from `block`, with an empty registry if it didn't issue anything yet
+    pub fn load(block: &KeyBlock, name: &str) -> Result<CertificateAuthority, CaErrors> {
+        let content = |path: String| {
+            let key = block.keys.get(&path).ok_or_else(|| CaErrors::MissingKey(path.clone()))?;
+            key.open().map_err(|error| CaErrors::CryptoError(path, error))
+        };
+
+        let key = PKey::private_key_from_pem(&content(format!("{}.key", name))?)?;
+        let certificate = X509::from_pem(&content(format!("{}.crt", name))?)?;
+        let registry_path = CertificateAuthority::registry_path(name);
+        let registry = if block.keys.contains_key(&registry_path) {
+            serde_json::from_slice(&content(registry_path)?)?
+        } else {
+            Registry::default()
+        };
+
+        Ok(CertificateAuthority { name: name.to_string(), key, certificate, registry })
+    }
+
+    /// Path of the issuance registry of the CA `name`
+    pub fn registry_path(name: &str) -> String {
+        format!("{}.issued", name)
+    }
+
+    /// Issue and record a certificate for `public_key`, with a serial never used by this CA
+    pub fn issue<T: HasPublic>(
+        &mut self, public_key: &PKeyRef<T>, subject: &X509NameRef, names: &[String], days: u32
+    ) -> Result<X509, CaErrors> {
+        let mut serial = random_serial()?;
+        while self.find(&serial.to_hex_str()?.to_lowercase()).is_some() {
+            serial = random_serial()?;
+        }
+
+        let issuer = Issuer { key: &self.key, certificate: Some(&self.certificate) };
+        let certificate = issue_certificate(public_key, subject, names, days, false, &serial, &issuer)?;
+
+        self.registry.certificates.push(IssuedCertificate {
+            serial: serial.to_hex_str()?.to_lowercase(),
+            subject: describe_name(subject),
+            not_after: certificate.not_after().to_string(),
+            issued: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            renewed_by: None,
+            certificate: String::from_utf8_lossy(&certificate.to_pem()?).to_string()
+        });
+
+        Ok(certificate)
+    }
+
+    /// Issue a new certificate for the key, subject and names of the certificate `serial`, for `days` days
+    pub fn renew(&mut self, serial: &str, days: u32) -> Result<X509, CaErrors> {
+        let serial = normalize_serial(serial)?;
+        let previous = self.find(&serial).ok_or_else(|| CaErrors::UnknownSerial(serial.clone()))?;
+        let previous = X509::from_pem(previous.certificate.as_bytes())?;
+        let public_key = previous.public_key()?;
+
+        let certificate = self.issue(&public_key, previous.subject_name(), &alternative_names(&previous), days)?;
+        let renewed_by = certificate.serial_number().to_bn()?.to_hex_str()?.to_lowercase();
+        if let Some(entry) = self.registry.certificates.iter_mut().find(|entry| entry.serial == serial) {
+            entry.renewed_by = Some(renewed_by);
+        }
+
+        Ok(certificate)
+    }
+
+    /// Registry entry of the certificate `serial`, lowercase hex encoded
+    pub fn find(&self, serial: &str) -> Option<&IssuedCertificate> {
+        self.registry.certificates.iter().find(|entry| entry.serial == serial)
+    }
+
+    /// JSON serialization of the registry, to store at `CA.issued`
+    pub fn serialize_registry(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(&self.registry).expect("Serializing a registry can't fail.")
+    }
+}
+
+/// Lowercase hex serial without leading zeroes or separators, as stored in registries
+fn normalize_serial(serial: &str) -> Result<String, ErrorStack> {
+    let serial: String = serial.chars().filter(|character| *character != ':').collect();
+    Ok(BigNum::from_hex_str(&serial)?.to_hex_str()?.to_lowercase())
+}
+
+/// DNS names and IP addresses of the subject alternative names of a certificate
+fn alternative_names(certificate: &X509) -> Vec<String> {
+    let names = match certificate.subject_alt_names() {
+        Some(names) => names,
+        None => return Vec::new()
+    };
+
+    names.iter().filter_map(|name| {
+        if let Some(dns) = name.dnsname() { return Some(dns.to_string()) }
+
+        match name.ipaddress()? {
+            &[a, b, c, d] => Some(Ipv4Addr::new(a, b, c, d).to_string()),
+            address if address.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(address);
+                Some(Ipv6Addr::from(octets).to_string())
+            },
+            _ => None
+        }
+    }).collect()
+}
@@ -0,0 +1,141 @@
+//! Sidecar metadata cache, stored next to a keyblock as `<block>.idx`
+//!
+//! The cache holds the metadata of a verified block along with the SHA256 digest of the block and the
+//! fingerprint of the root key it was verified with. It is only used if both still match, which saves
+//! parsing and verifying large blocks for repeated queries. The cache isn't signed, so it is only as
+//! trustworthy as the permissions of the directory holding it.
+
+use crate::content::ContentType;
+use crate::id::GlobalId;
+use crate::keyblock::KeyBlock;
+use crate::permissions::write_private;
+use crate::rootkey::RootKey;
+use crate::ssh::SshPublicKey;
+use crate::x509::CertificateInfo;
+use openssl::error::ErrorStack;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Version of the cache layout, caches with another version are ignored
+const CACHE_VERSION: u16 = 4;
+/// Extension appended to the block path
+pub const CACHE_EXTENSION: &str = ".idx";
+
+/// Metadata of a keyfile, without its secret or content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    pub uid: u16,
+    /// Globally unique ID, optional so pubblocks signed before IDs existed can still be checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<GlobalId>,
+    pub flags: u64,
+    pub path: String,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub length: u64,
+    /// Hex encoded keyed digest of the stored content, see `ContentDigest`
+    pub content_hmac: String,
+    /// Day the content expires, in days since the UNIX epoch, for types which have an expiry date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u32>,
+    /// Subject, issuer, alternative names and end of validity of certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateInfo>,
+    /// SHA256 fingerprint of the public key of SSH keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_fingerprint: Option<String>,
+    /// Day the key was last handed out, filled from the access log by listings and never cached
+    #[serde(skip)]
+    pub last_access: Option<u32>
+}
+
+/// Cached metadata of a keyblock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataCache {
+    version: u16,
+    /// Hex encoded SHA256 digest of the serialized block
+    pub block_sha256: String,
+    /// Fingerprint of the root key the block was verified with
+    pub root_key_fingerprint: String,
+    pub name: String,
+    pub uid: u16,
+    pub id: GlobalId,
+    pub description: String,
+    pub format_specifier: u16,
+    /// Keyfiles, sorted by path
+    pub keys: Vec<KeyMetadata>
+}
+
+impl MetadataCache {
+    /// Metadata of the keyfiles of a block, sorted by path
+    pub fn keys_of(block: &KeyBlock) -> Result<Vec<KeyMetadata>, ErrorStack> {
+        let digest = block.content_digest()?;
+        let mut keys: Vec<KeyMetadata> = block.keys.values().map(|key| {
+            // Details are read from the content, keys which can't be decrypted are listed without them
+            let content = key.open().ok();
+            let content = content.as_deref();
+
+            Ok(KeyMetadata {
+                uid: key.uid,
+                id: Some(key.id),
+                flags: key.flags,
+                path: key.path.clone(),
+                name: key.name.clone(),
+                description: key.description.clone(),
+                tags: key.tags.clone(),
+                length: key.length,
+                content_hmac: digest.of(key)?,
+                expiry: ContentType::from_flags(key.flags).zip(content)
+                    .and_then(|(content_type, content)| content_type.handler().expiry(content)),
+                certificate: content.and_then(CertificateInfo::read),
+                ssh_fingerprint: content.and_then(|content| SshPublicKey::from_content(content).ok()).map(|public_key| public_key.fingerprint()),
+                last_access: None
+            })
+        }).collect::<Result<_, ErrorStack>>()?;
+        keys.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(keys)
+    }
+
+    /// Build the cache of a verified block
+    pub fn new(block: &KeyBlock, block_sha256: String) -> io::Result<MetadataCache> {
+        Ok(MetadataCache {
+            version: CACHE_VERSION,
+            block_sha256,
+            root_key_fingerprint: block.root_pubkey.fingerprint().map_err(io::Error::other)?,
+            name: block.name.clone(),
+            uid: block.uid,
+            id: block.id,
+            description: block.description.clone(),
+            format_specifier: block.format_specifier,
+            keys: MetadataCache::keys_of(block).map_err(io::Error::other)?
+        })
+    }
+
+    /// Location of the cache of `block`
+    pub fn path(block: &Path) -> PathBuf {
+        let mut path = OsString::from(block.as_os_str());
+        path.push(CACHE_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Load the cache of `block` if it is still valid for its current content and root key
+    pub fn load_valid(block: &Path, block_sha256: &str, root_key: &RootKey) -> Option<MetadataCache> {
+        let content = fs::read(MetadataCache::path(block)).ok()?;
+        let cache: MetadataCache = serde_json::from_slice(&content).ok()?;
+
+        let valid = cache.version == CACHE_VERSION
+            && cache.block_sha256 == block_sha256
+            && root_key.fingerprint().ok()? == cache.root_key_fingerprint;
+
+        if valid { Some(cache) } else { None }
+    }
+
+    /// Write this cache next to `block`
+    pub fn save(&self, block: &Path) -> io::Result<()> {
+        write_private(&MetadataCache::path(block), &serde_json::to_vec(self)?)
+    }
+}
@@ -0,0 +1,1522 @@
+use crate::content::ContentType;
+use crate::host::HostFingerprint;
+use crate::login::LoginField;
+use crate::policy::KeyPolicy;
+use crate::secret::WrapAlgorithm;
+use crate::suite::CipherSuite;
+use crate::table::{KeyColumn, OutputFormat, PorcelainVersion};
+use crate::tags::TagExpression;
+use crate::tls::KeyAlgorithm;
+use crate::utils::{parse_days, parse_size};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// Your all-in-one physical keyring manager
+#[derive(Debug, Parser)]
+#[command(name = "banjo", version, author)]
+pub struct Cli {
+    /// Increase the verbosity level.
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Root public key used to verify keyblocks, in PEM, DER or OpenSSH format.
+    #[arg(long, global = true, value_name = "KEY")]
+    pub root_key: Option<PathBuf>,
+
+    /// Refuse to run any subcommand that alters a keyblock.
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    /// Report what mutating subcommands would change, without writing anything.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Fail instead of warning when a keyblock or its directory is accessible by other users.
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// Refuse to load keyblocks needing more than this amount of memory, in bytes.
+    #[arg(long, global = true, value_name = "BYTES")]
+    pub max_memory: Option<u64>,
+
+    /// Record the day keys are handed out in a sidecar next to their keyblock, for `prune --unused-since`.
+    #[arg(long, global = true)]
+    pub track_access: bool,
+
+    /// Lock secrets in memory so they are never swapped to disk.
+    #[arg(long, global = true)]
+    pub lock_memory: bool,
+
+    /// Allow core dumps and debugger attachment, which could expose secrets. Only meant for debugging.
+    #[arg(long, global = true)]
+    pub allow_core_dumps: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Name of the subcommand run, with its own subcommand if any, such as `ssh authorize`
+    #[arg(skip)]
+    pub command_name: String
+}
+
+/// Top level subcommands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Verify the integrity of a keyblock
+    Verify(VerifyArgs),
+
+    /// Check that a keyblock is the canonical serialization of its content, to test other implementations
+    Conformance(ConformanceArgs),
+
+    /// List the keys of a keyblock
+    List(ListArgs),
+
+    /// Add or remove tags of a key
+    Tag(TagArgs),
+
+    /// Set or clear the usage policies of a key
+    Policy(PolicyArgs),
+
+    /// Set the content type of a key, after validating its content
+    SetType(SetTypeArgs),
+
+    /// Print the content of a key, or a field of a login entry
+    Show(ShowArgs),
+
+    /// Decrypt a key to a file, reading the keyblock one keyfile at a time instead of loading it whole
+    Extract(ExtractArgs),
+
+    /// Add a key to a keyblock from a file, standard input or the output of a command
+    Add(AddArgs),
+
+    /// Add a login entry to a keyblock, reading its password from the terminal or standard input
+    AddLogin(AddLoginArgs),
+
+    /// Generate a TLS private key into a keyblock and print its certificate signing request
+    GenCsr(GenCsrArgs),
+
+    /// Generate a TLS private key and certificate into a keyblock
+    GenCert(GenCertArgs),
+
+    /// Add, update and remove keys to match a manifest
+    Apply(ApplyArgs),
+
+    /// Remove the keys matching some criteria
+    Prune(PruneArgs),
+
+    /// Set the rotation schedule of a key, or record that it was rotated
+    SetRotation(SetRotationArgs),
+
+    /// List the keys due for rotation
+    RotationDue(RotationDueArgs),
+
+    /// List the keys whose content expires soon, such as certificates
+    CheckExpiry(CheckExpiryArgs),
+
+    /// Replace the content of a key with a newly generated one, keeping previous versions
+    RotateKey(RotateKeyArgs),
+
+    /// List the previous versions of a key
+    History(HistoryArgs),
+
+    /// Restore a previous version of a key
+    RollbackKey(RollbackKeyArgs),
+
+    /// Check the key references of service configurations against a directory of keyblocks
+    Xref(XrefArgs),
+
+    /// Explain an error code or message, with remediation steps
+    Explain(ExplainArgs),
+
+    /// Replace this binary with the latest release, after verifying its signature
+    SelfUpdate(SelfUpdateArgs),
+
+    /// Summarize the subcommands run and the keys handed out, from the local audit log
+    UsageReport(UsageReportArgs),
+
+    /// Issue and track certificates with a CA stored in a keyblock
+    #[command(subcommand)]
+    Ca(CaCommand),
+
+    /// Temporarily disable a key
+    Freeze(FreezeArgs),
+
+    /// Enable a frozen key again
+    Thaw(FreezeArgs),
+
+    /// Bind a keyblock to the only host allowed to deploy its keys
+    BindHost(BindHostArgs),
+
+    /// Print keyblock metadata as JSON facts for configuration management
+    Facts(FactsArgs),
+
+    /// Print a signed inventory of the keys of a keyblock, without any secret
+    Manifest(ManifestArgs),
+
+    /// Export the signed public metadata of a keyblock, without any secret or ciphertext
+    ExportPublic(ExportPublicArgs),
+
+    /// Derive a reproducible secret for a purpose from a key
+    Derive(DeriveArgs),
+
+    /// Browse a keyblock interactively
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+
+    /// Convert a keyblock to the current format and sign it
+    Migrate(MigrateArgs),
+
+    /// Assign fresh UIDs to a keyblock or some of its keys
+    ReissueUid(ReissueUidArgs),
+
+    /// Record the UIDs used across a directory of keyblocks and report collisions
+    UidRegistry(UidRegistryArgs),
+
+    /// Bundle a keyblock with its signatures and manifest for offline verification
+    Bundle(BundleArgs),
+
+    /// Check an offline bundle against the root key
+    VerifyBundle(VerifyBundleArgs),
+
+    /// Commit, verify, pull and push a git repository of keyblocks
+    Sync(SyncArgs),
+
+    /// Merge two versions of a keyblock edited from a common base
+    Merge3(Merge3Args),
+
+    /// Export an encrypted backup bundle of a keyblock
+    Backup(BackupArgs),
+
+    /// Restore a keyblock from an encrypted backup bundle
+    RestoreBackup(RestoreBackupArgs),
+
+    /// Print the block secret, sealed under a passphrase, and small keys as QR codes and word lists
+    ExportQr(ExportQrArgs),
+
+    /// Restore the block secret or a key from a QR code or word list
+    ImportQr(ImportQrArgs),
+
+    /// Print SSH keys stored in a keyblock, or install authorized_keys and known_hosts files from them
+    #[command(subcommand)]
+    Ssh(SshCommand),
+
+    /// Deploy and rotate WireGuard keys stored in a keyblock
+    #[command(subcommand)]
+    Wg(WgCommand),
+
+    /// Renew ACME certificates stored in a keyblock
+    #[cfg(feature = "acme")]
+    #[command(subcommand)]
+    Acme(AcmeCommand),
+
+    /// Print a key for a container secret provider, or create a container secret from it
+    DockerSecret(DockerSecretArgs),
+
+    /// Install the keys of a keyblock as files under a directory
+    Deploy(DeployArgs),
+
+    /// Encrypt a single key to age recipients
+    ExportAge(ExportAgeArgs),
+
+    /// Store the content of an age encrypted file as a key
+    ImportAge(ImportAgeArgs),
+
+    /// Debugging features [NOT SUITABLE FOR PRODUCTION]
+    #[cfg(feature = "enable_debug")]
+    #[command(subcommand)]
+    Debug(DebugCommand)
+}
+
+impl Command {
+    /// Whether this subcommand can alter or create keyblocks
+    pub fn is_mutating(&self) -> bool {
+        match self {
+            Command::Verify(_) | Command::List(_) | Command::Facts(_) | Command::Derive(_) => false,
+            Command::Manifest(_) | Command::ExportPublic(_) | Command::Bundle(_) | Command::VerifyBundle(_) => false,
+            Command::Backup(_) | Command::ExportQr(_) | Command::Ssh(_) | Command::DockerSecret(_) => false,
+            Command::Show(_) | Command::RotationDue(_) | Command::History(_) | Command::Xref(_) => false,
+            Command::ExportAge(_) | Command::Explain(_) | Command::Deploy(_) | Command::SelfUpdate(_) => false,
+            Command::UsageReport(_) | Command::Conformance(_) | Command::CheckExpiry(_) | Command::Extract(_) => false,
+            Command::Wg(command) => matches!(command, WgCommand::Rotate(_)),
+            Command::Tag(_) | Command::Migrate(_) | Command::RestoreBackup(_) | Command::ImportQr(_) => true,
+            Command::ReissueUid(_) | Command::Policy(_) | Command::Freeze(_) | Command::Thaw(_) | Command::BindHost(_) => true,
+            Command::SetType(_) | Command::Sync(_) | Command::Merge3(_) | Command::GenCsr(_) | Command::Add(_) | Command::AddLogin(_) => true,
+            Command::GenCert(_) | Command::Apply(_) | Command::Prune(_) | Command::SetRotation(_) => true,
+            Command::RotateKey(_) | Command::RollbackKey(_) | Command::ImportAge(_) | Command::UidRegistry(_) => true,
+            Command::Ca(command) => !matches!(command, CaCommand::List(_)),
+            #[cfg(feature = "tui")]
+            Command::Tui(args) => args.signing_key.is_some(),
+            #[cfg(feature = "acme")]
+            Command::Acme(_) => true,
+            #[cfg(feature = "enable_debug")]
+            Command::Debug(command) => !matches!(command, DebugCommand::Schema(_) | DebugCommand::Scavenge(_))
+        }
+    }
+}
+
+/// Arguments of `banjo verify`
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Keyblock to verify, or a directory of keyblocks.
+    pub block: PathBuf,
+
+    /// Number of keyblocks to verify concurrently when given a directory, defaults to the number of CPUs. They share the
+    /// --max-memory ceiling.
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Also decrypt every key, proving the block can be restored and not only that it is authentic.
+    #[arg(long)]
+    pub deep: bool,
+
+    /// Print a JSON report of every check performed, an array of reports when given a directory.
+    #[arg(long, conflicts_with = "format")]
+    pub json: bool,
+
+    /// Print a report of every check performed in this format, one row per check for CSV and TSV.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<OutputFormat>
+}
+
+/// Arguments of `banjo conformance`
+#[derive(Debug, Args)]
+pub struct ConformanceArgs {
+    /// Keyblock to check.
+    pub block: PathBuf,
+
+    /// Print a JSON report of every check performed.
+    #[arg(long, conflicts_with = "format")]
+    pub json: bool,
+
+    /// Print the report in this format.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<OutputFormat>
+}
+
+/// Arguments of `banjo list`
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Keyblock to list.
+    pub block: PathBuf,
+
+    /// Only list keys matching this tag expression, can be repeated.
+    #[arg(short, long, value_name = "EXPRESSION")]
+    pub tag: Vec<TagExpression>,
+
+    /// Also show the subject, issuer, alternative names and expiry of certificates, the fingerprint of SSH
+    /// keys and the last access of keys, like `--columns uid,path,type,size,tags,subject,issuer,sans,expiry,
+    /// fingerprint,last-access`.
+    #[arg(short, long, conflicts_with = "columns")]
+    pub long: bool,
+
+    /// Use and refresh the sidecar metadata cache (`<block>.idx`).
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Columns to print, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = KeyColumn::DEFAULT)]
+    pub columns: Vec<KeyColumn>,
+
+    /// Sort the keys along this column instead of by path.
+    #[arg(long, value_enum, value_name = "COLUMN")]
+    pub sort: Option<KeyColumn>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Print stable tab separated fields for scripts, sorted by path: UID, type, size, tags and path in v1, with the
+    /// key ID after the UID in v2.
+    #[arg(
+        long, value_enum, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1",
+        conflicts_with_all = ["format", "columns", "long", "sort", "ascii", "max_width"]
+    )]
+    pub porcelain: Option<PorcelainVersion>,
+
+    /// Terminate every porcelain field with a null byte instead of separating them with tabs.
+    #[arg(short = 'z', requires = "porcelain")]
+    pub null: bool,
+
+    /// Escape every character outside of printable ASCII.
+    #[arg(long)]
+    pub ascii: bool,
+
+    /// Truncate every cell to this width, in terminal columns.
+    #[arg(long, value_name = "COLUMNS", value_parser = clap::value_parser!(u16).range(4..))]
+    pub max_width: Option<u16>
+}
+
+/// Arguments of `banjo tag`
+#[derive(Debug, Args)]
+pub struct TagArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to tag.
+    pub path: String,
+
+    /// Tag to add, can be repeated.
+    #[arg(short, long)]
+    pub add: Vec<String>,
+
+    /// Tag to remove, can be repeated.
+    #[arg(short, long)]
+    pub remove: Vec<String>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo policy`
+#[derive(Debug, Args)]
+pub struct PolicyArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to update.
+    pub path: String,
+
+    /// Policy to set, can be repeated.
+    #[arg(short, long, value_enum)]
+    pub set: Vec<KeyPolicy>,
+
+    /// Policy to clear, can be repeated.
+    #[arg(short, long, value_enum)]
+    pub clear: Vec<KeyPolicy>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo set-type`
+#[derive(Debug, Args)]
+pub struct SetTypeArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to update.
+    pub path: String,
+
+    /// Content type of the key.
+    #[arg(value_enum)]
+    pub content_type: ContentType,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo show`
+#[derive(Debug, Args)]
+pub struct ShowArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to show.
+    pub path: String,
+
+    /// Only print this field of a login entry.
+    #[arg(long, value_enum)]
+    pub field: Option<LoginField>,
+
+    /// Copy the content to the clipboard instead of printing it.
+    #[arg(long)]
+    pub clipboard: bool
+}
+
+/// Arguments of `banjo extract`
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to extract.
+    pub path: String,
+
+    /// File to write the content to, which must not exist yet.
+    #[arg(short, long, value_name = "FILE")]
+    pub out: PathBuf
+}
+
+/// Arguments of `banjo add`
+#[derive(Debug, Args)]
+pub struct AddArgs {
+    /// Keyblock to add the key to.
+    pub block: PathBuf,
+
+    /// Path of the new key.
+    pub path: String,
+
+    /// File holding the content of the key, `-` to read it from standard input.
+    #[arg(required_unless_present = "from_cmd", conflicts_with = "from_cmd")]
+    pub source: Option<PathBuf>,
+
+    /// Shell command whose standard output is the content of the key.
+    #[arg(long, value_name = "COMMAND")]
+    pub from_cmd: Option<String>,
+
+    /// Name of the key, its path by default.
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Description of the key.
+    #[arg(long, default_value = "")]
+    pub description: String,
+
+    /// Content type of the key, the content is validated against it.
+    #[arg(long = "type", value_enum, default_value = "generic")]
+    pub content_type: ContentType,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo add-login`
+#[derive(Debug, Args)]
+pub struct AddLoginArgs {
+    /// Keyblock to add the entry to.
+    pub block: PathBuf,
+
+    /// Path of the new key.
+    pub path: String,
+
+    /// Account name of the entry.
+    #[arg(long)]
+    pub username: Option<String>,
+
+    /// Address of the service the entry logs in to.
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Free-form notes stored with the entry.
+    #[arg(long)]
+    pub notes: Option<String>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo gen-csr`
+#[derive(Debug, Args)]
+pub struct GenCsrArgs {
+    /// Keyblock to store the private key in.
+    pub block: PathBuf,
+
+    /// Path of the new key.
+    #[arg(long)]
+    pub path: String,
+
+    /// Common name of the certificate subject.
+    #[arg(long)]
+    pub cn: String,
+
+    /// Subject alternative name, DNS name or IP address, can be repeated.
+    #[arg(long, value_name = "NAME")]
+    pub san: Vec<String>,
+
+    /// Algorithm of the private key.
+    #[arg(long, value_enum, default_value = "ec-p256")]
+    pub algorithm: KeyAlgorithm,
+
+    /// Where to write the PEM encoded request, instead of printing it.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo gen-cert`
+#[derive(Debug, Args)]
+pub struct GenCertArgs {
+    /// Keyblock to store the key and certificate in.
+    pub block: PathBuf,
+
+    /// Path of the new keys, the private key is stored at PATH.key and the certificate at PATH.crt.
+    #[arg(long)]
+    pub path: String,
+
+    /// Common name of the certificate subject.
+    #[arg(long)]
+    pub cn: String,
+
+    /// Subject alternative name, DNS name or IP address, can be repeated. Defaults to the common name.
+    #[arg(long, value_name = "NAME")]
+    pub san: Vec<String>,
+
+    /// Validity period of the certificate, in days.
+    #[arg(long, default_value_t = 825)]
+    pub days: u32,
+
+    /// Algorithm of the private key.
+    #[arg(long, value_enum, default_value = "ec-p256")]
+    pub algorithm: KeyAlgorithm,
+
+    /// Sign the certificate with the CA stored in the block at CA.key and CA.crt, instead of self-signing it.
+    #[arg(long, value_name = "CA")]
+    pub ca: Option<String>,
+
+    /// Issue a self-signed CA certificate, able to sign other certificates.
+    #[arg(long, conflicts_with = "ca")]
+    pub is_ca: bool,
+
+    /// Where to write the PEM encoded certificate, in addition to the block.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo apply`
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    /// Keyblock to update.
+    pub block: PathBuf,
+
+    /// YAML manifest declaring the desired keys.
+    pub manifest: PathBuf,
+
+    /// Remove the keys the manifest doesn't declare.
+    #[arg(long)]
+    pub prune: bool,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo prune`
+#[derive(Debug, Args)]
+#[command(group(clap::ArgGroup::new("criteria").required(true).multiple(true).args(["expired", "larger_than", "unused_since"])))]
+pub struct PruneArgs {
+    /// Keyblock to prune.
+    pub block: PathBuf,
+
+    /// Remove keys past their expiry date, such as expired certificates.
+    #[arg(long)]
+    pub expired: bool,
+
+    /// Remove keys larger than this size, in bytes optionally followed by K, M or G.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub larger_than: Option<u64>,
+
+    /// Remove keys which weren't handed out for this many days, e.g. `180d`, according to access tracking.
+    #[arg(long, value_name = "DAYS", value_parser = parse_days)]
+    pub unused_since: Option<u32>,
+
+    /// Only consider the keys matching this tag expression, can be repeated.
+    #[arg(short, long, value_name = "EXPRESSION")]
+    pub tag: Vec<TagExpression>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo set-rotation`
+#[derive(Debug, Args)]
+#[command(group(clap::ArgGroup::new("change").required(true).multiple(true).args(["every", "clear", "rotated"])))]
+pub struct SetRotationArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to update.
+    pub path: String,
+
+    /// Maximum number of days between rotations. The key counts as rotated today if it had no schedule.
+    #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(u16).range(1..))]
+    pub every: Option<u16>,
+
+    /// Remove the rotation schedule.
+    #[arg(long, conflicts_with_all = ["every", "rotated"])]
+    pub clear: bool,
+
+    /// Record that the key was rotated today.
+    #[arg(long)]
+    pub rotated: bool,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo rotation-due`
+#[derive(Debug, Args)]
+pub struct RotationDueArgs {
+    /// Keyblock to check.
+    pub block: PathBuf,
+
+    /// Also list the keys due within this number of days.
+    #[arg(long, value_name = "DAYS", default_value_t = 0)]
+    pub within: u16,
+
+    /// Send a notification for every listed key.
+    #[arg(long)]
+    pub notify: bool
+}
+
+/// Arguments of `banjo check-expiry`
+#[derive(Debug, Args)]
+pub struct CheckExpiryArgs {
+    /// Keyblock to check.
+    pub block: PathBuf,
+
+    /// List the keys expiring within this number of days.
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pub within: u16,
+
+    /// Send a notification for every listed key.
+    #[arg(long)]
+    pub notify: bool
+}
+
+/// Arguments of `banjo rotate-key`
+#[derive(Debug, Args)]
+pub struct RotateKeyArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to rotate.
+    pub path: String,
+
+    /// Number of previous versions to keep, at PATH.previous, then PATH.previous.2 and so on. Defaults to the
+    /// key-history option, or 1.
+    #[arg(long, value_name = "COUNT")]
+    pub keep: Option<usize>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo history`
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key.
+    pub path: String
+}
+
+/// Arguments of `banjo rollback-key`
+#[derive(Debug, Args)]
+pub struct RollbackKeyArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to roll back.
+    pub path: String,
+
+    /// Previous version to restore, as listed by `banjo history`.
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub version: u16,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo explain`
+#[derive(Debug, Args)]
+pub struct ExplainArgs {
+    /// Error code such as E102, or an error message, which can be split over several arguments.
+    #[arg(required_unless_present = "list", num_args = 1..)]
+    pub error: Vec<String>,
+
+    /// List every error code.
+    #[arg(long)]
+    pub list: bool
+}
+
+/// Arguments of `banjo self-update`
+#[derive(Debug, Args)]
+pub struct SelfUpdateArgs {
+    /// Base URL of releases, overriding the update-url setting and the one pinned in the build.
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Only download and verify the latest release, reporting whether it differs from this binary.
+    #[arg(long)]
+    pub check: bool
+}
+
+/// Arguments of `banjo usage-report`
+#[derive(Debug, Args)]
+pub struct UsageReportArgs {
+    /// Only count the subcommands run over this many days, e.g. `30d`.
+    #[arg(long, value_name = "DAYS", value_parser = parse_days, default_value = "30")]
+    pub since: u32,
+
+    /// Number of most handed out keys to list.
+    #[arg(long, value_name = "COUNT", default_value_t = 10)]
+    pub top: usize
+}
+
+/// Arguments of `banjo xref`
+#[derive(Debug, Args)]
+pub struct XrefArgs {
+    /// Directory of the keyblocks referenced.
+    pub directory: PathBuf,
+
+    /// Configurations to scan for BLOCK-UID/KEY-UID references, e.g. '/etc/**/*.conf', can be repeated.
+    #[arg(long, value_name = "GLOB", required = true)]
+    pub config_glob: Vec<String>,
+
+    /// Also print the references that resolve to a key.
+    #[arg(long)]
+    pub all: bool
+}
+
+/// Subcommands of `banjo ca`
+#[derive(Debug, Subcommand)]
+pub enum CaCommand {
+    /// Issue a certificate for a certificate signing request
+    SignCert(CaSignArgs),
+
+    /// Issue a new certificate for the key and names of a previously issued one
+    Renew(CaRenewArgs),
+
+    /// List the certificates issued by a CA
+    List(CaListArgs)
+}
+
+/// Arguments of `banjo ca sign-cert`
+#[derive(Debug, Args)]
+pub struct CaSignArgs {
+    /// Keyblock containing the CA.
+    pub block: PathBuf,
+
+    /// CA to sign with, stored in the block at CA.key and CA.crt.
+    #[arg(long)]
+    pub ca: String,
+
+    /// PEM encoded certificate signing request.
+    #[arg(long)]
+    pub csr: PathBuf,
+
+    /// Subject alternative name, DNS name or IP address, can be repeated. Defaults to the requested common
+    /// name, the extensions of the request are never copied.
+    #[arg(long, value_name = "NAME")]
+    pub san: Vec<String>,
+
+    /// Validity period of the certificate, in days.
+    #[arg(long, default_value_t = 825)]
+    pub days: u32,
+
+    /// Where to write the PEM encoded certificate, instead of printing it.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo ca renew`
+#[derive(Debug, Args)]
+pub struct CaRenewArgs {
+    /// Keyblock containing the CA.
+    pub block: PathBuf,
+
+    /// CA which issued the certificate.
+    #[arg(long)]
+    pub ca: String,
+
+    /// Hex encoded serial number of the certificate to renew.
+    #[arg(long)]
+    pub serial: String,
+
+    /// Validity period of the new certificate, in days.
+    #[arg(long, default_value_t = 825)]
+    pub days: u32,
+
+    /// Where to write the PEM encoded certificate, instead of printing it.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo ca list`
+#[derive(Debug, Args)]
+pub struct CaListArgs {
+    /// Keyblock containing the CA.
+    pub block: PathBuf,
+
+    /// CA to list the certificates of.
+    #[arg(long)]
+    pub ca: String
+}
+
+/// Arguments of `banjo freeze` and `banjo thaw`
+#[derive(Debug, Args)]
+pub struct FreezeArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key to freeze or thaw.
+    pub path: String,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo bind-host`
+#[derive(Debug, Args)]
+pub struct BindHostArgs {
+    /// Keyblock to bind.
+    pub block: PathBuf,
+
+    /// Fingerprint of the host, as printed by `banjo facts` on it, defaults to this host.
+    #[arg(long, value_name = "HEX", conflicts_with = "unbind")]
+    pub fingerprint: Option<HostFingerprint>,
+
+    /// Remove the binding, allowing any host to deploy the keys.
+    #[arg(long)]
+    pub unbind: bool,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo facts`
+#[derive(Debug, Args)]
+pub struct FactsArgs {
+    /// Keyblock to describe.
+    pub block: PathBuf,
+
+    /// Shape of the JSON document.
+    #[arg(long, value_enum, default_value_t = FactsFormat::Ansible)]
+    pub format: FactsFormat,
+
+    /// Only describe keys matching this tag expression, can be repeated.
+    #[arg(short, long, value_name = "EXPRESSION")]
+    pub tag: Vec<TagExpression>
+}
+
+/// Arguments of `banjo manifest`
+#[derive(Debug, Args)]
+pub struct ManifestArgs {
+    /// Keyblock to inventory.
+    pub block: PathBuf,
+
+    /// Root private key used to sign the manifest.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf,
+
+    /// Where to write the manifest, instead of printing it.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>
+}
+
+/// Arguments of `banjo export-public`
+#[derive(Debug, Args)]
+pub struct ExportPublicArgs {
+    /// Keyblock to export the metadata of.
+    pub block: PathBuf,
+
+    /// Root private key used to sign the public metadata.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf,
+
+    /// Where to write the public metadata, instead of printing it.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>
+}
+
+/// Arguments of `banjo bundle`
+#[derive(Debug, Args)]
+pub struct BundleArgs {
+    /// Keyblock to bundle.
+    pub block: PathBuf,
+
+    /// Root private key used to sign the bundle.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf,
+
+    /// Where to write the bundle.
+    #[arg(short, long)]
+    pub out: PathBuf
+}
+
+/// Arguments of `banjo verify-bundle`
+#[derive(Debug, Args)]
+pub struct VerifyBundleArgs {
+    /// Offline bundle to check.
+    pub bundle: PathBuf,
+
+    /// Print a JSON report of every check performed.
+    #[arg(long)]
+    pub json: bool
+}
+
+/// Arguments of `banjo derive`
+#[derive(Debug, Args)]
+pub struct DeriveArgs {
+    /// Keyblock containing the master key.
+    pub block: PathBuf,
+
+    /// Path of the master key inside the block.
+    #[arg(long)]
+    pub path: String,
+
+    /// Label of the purpose of the derived secret, each purpose gets a different secret.
+    #[arg(long)]
+    pub purpose: String
+}
+
+/// JSON shapes supported by `banjo facts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FactsFormat {
+    /// Nested document, usable as Ansible local facts
+    Ansible,
+    /// Flat map of strings, as expected by Terraform external data sources
+    Terraform
+}
+
+/// Arguments of `banjo tui`, only available if the binary is built with tui.
+#[cfg(feature = "tui")]
+#[derive(Debug, Args)]
+pub struct TuiArgs {
+    /// Keyblock to browse.
+    pub block: PathBuf,
+
+    /// Root private key used to sign the block after edits, which are disabled without it.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: Option<PathBuf>
+}
+
+/// Arguments of `banjo migrate`
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    /// Keyblock to migrate.
+    pub block: PathBuf,
+
+    /// Root private key used to sign the migrated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf,
+
+    /// Where to write the migrated block, instead of replacing it.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Compress the names, descriptions, paths and tags of the block with zstd.
+    #[arg(long, conflicts_with = "no_compress")]
+    pub compress: bool,
+
+    /// Store the metadata of the block uncompressed again.
+    #[arg(long)]
+    pub no_compress: bool,
+
+    /// Wrap the stored key secrets under the block secret with this algorithm.
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub wrap: Option<WrapAlgorithm>,
+
+    /// Switch the block to this cipher suite, the signing key has to support its signature.
+    #[arg(long, value_enum, value_name = "SUITE")]
+    pub suite: Option<CipherSuite>,
+
+    /// Store the content shared by several keys only once.
+    #[arg(long, conflicts_with = "no_dedup")]
+    pub dedup: bool,
+
+    /// Store the content of every key separately again.
+    #[arg(long)]
+    pub no_dedup: bool
+}
+
+/// Arguments of `banjo reissue-uid`
+#[derive(Debug, Args)]
+pub struct ReissueUidArgs {
+    /// Keyblock to update.
+    pub block: PathBuf,
+
+    /// Only reissue the UID of the key at this path, can be repeated. Without it, the block UID and every
+    /// colliding key UID are reissued.
+    #[arg(short, long, value_name = "PATH")]
+    pub key: Vec<String>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo uid-registry`
+#[derive(Debug, Args)]
+pub struct UidRegistryArgs {
+    /// Directory of keyblocks, the registry is written to its .banjo-uids.json file.
+    #[arg(default_value = ".")]
+    pub directory: PathBuf
+}
+
+/// Arguments of `banjo sync`
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    /// Directory of keyblocks inside a git working tree.
+    #[arg(default_value = ".")]
+    pub directory: PathBuf,
+
+    /// Remote to pull from and push to.
+    #[arg(long, default_value = "origin")]
+    pub remote: String,
+
+    /// Remote branch to sync with, defaults to the current branch.
+    #[arg(long)]
+    pub branch: Option<String>,
+
+    /// Subject of the commit recording local changes.
+    #[arg(short, long)]
+    pub message: Option<String>,
+
+    /// Only commit and pull, without pushing.
+    #[arg(long)]
+    pub no_push: bool
+}
+
+/// Arguments of `banjo merge3`, also usable as a git merge driver (`banjo merge3 %O %A %B --signing-key KEY`)
+#[derive(Debug, Args)]
+pub struct Merge3Args {
+    /// Common ancestor of both versions.
+    pub base: PathBuf,
+
+    /// Our version, replaced by the merged block unless --output is given.
+    pub ours: PathBuf,
+
+    /// Their version.
+    pub theirs: PathBuf,
+
+    /// Root private key used to sign the merged block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf,
+
+    /// Where to write the merged block, instead of replacing our version.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Resolve the conflict on this key path or block field (`@name`, `@secret`...) with our version, can be repeated.
+    #[arg(long = "take-ours", value_name = "PATH")]
+    pub take_ours: Vec<String>,
+
+    /// Resolve the conflict on this key path or block field with their version, can be repeated.
+    #[arg(long = "take-theirs", value_name = "PATH")]
+    pub take_theirs: Vec<String>,
+
+    /// Where to write the JSON conflict report, instead of printing it.
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>
+}
+
+/// Arguments of `banjo backup`
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Keyblock to back up.
+    pub block: PathBuf,
+
+    /// age recipient (age1...) the backup is encrypted to, can be repeated.
+    #[arg(short, long, required = true)]
+    pub recipient: Vec<String>,
+
+    /// Where to write the backup bundle.
+    #[arg(short, long)]
+    pub out: PathBuf
+}
+
+/// Arguments of `banjo restore-backup`
+#[derive(Debug, Args)]
+pub struct RestoreBackupArgs {
+    /// Backup bundle to restore.
+    pub bundle: PathBuf,
+
+    /// age identity file used to decrypt the bundle, can be repeated.
+    #[arg(short, long, required = true)]
+    pub identity: Vec<PathBuf>,
+
+    /// Where to write the restored keyblock.
+    #[arg(short, long)]
+    pub out: PathBuf,
+
+    /// Overwrite the output file if it already exists.
+    #[arg(short, long)]
+    pub force: bool
+}
+
+/// Arguments of `banjo export-qr`
+#[derive(Debug, Args)]
+pub struct ExportQrArgs {
+    /// Keyblock to export from.
+    pub block: PathBuf,
+
+    /// Only export the block secret.
+    #[arg(long, conflicts_with = "key")]
+    pub secret_only: bool,
+
+    /// Only export the key at this path.
+    #[arg(long)]
+    pub key: Option<String>,
+
+    /// Only export keys matching this tag expression, can be repeated.
+    #[arg(short, long, value_name = "EXPRESSION")]
+    pub tag: Vec<TagExpression>,
+
+    /// Also write every QR code as a PNG image in this directory.
+    #[arg(long, value_name = "DIR")]
+    pub png: Option<PathBuf>
+}
+
+/// Arguments of `banjo import-qr`
+#[derive(Debug, Args)]
+#[command(group(clap::ArgGroup::new("source").required(true).args(["words", "png"])))]
+pub struct ImportQrArgs {
+    /// Keyblock to import into.
+    pub block: PathBuf,
+
+    /// Word list to import.
+    #[arg(long)]
+    pub words: Option<String>,
+
+    /// PNG image of the QR code to import.
+    #[arg(long, value_name = "FILE")]
+    pub png: Option<PathBuf>,
+
+    /// Path of the key to restore, instead of the block secret.
+    #[arg(long)]
+    pub key: Option<String>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo export-age`
+#[derive(Debug, Args)]
+pub struct ExportAgeArgs {
+    /// Keyblock to export from.
+    pub block: PathBuf,
+
+    /// Path of the key to export.
+    pub path: String,
+
+    /// age recipient (age1...) the key is encrypted to, can be repeated.
+    #[arg(short, long, required = true)]
+    pub recipient: Vec<String>,
+
+    /// Where to write the encrypted key, instead of printing it.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>
+}
+
+/// Arguments of `banjo import-age`
+#[derive(Debug, Args)]
+pub struct ImportAgeArgs {
+    /// Keyblock to import into.
+    pub block: PathBuf,
+
+    /// Path of the key to create or replace.
+    pub path: String,
+
+    /// age encrypted file holding the key content.
+    pub file: PathBuf,
+
+    /// age identity file used to decrypt the file, can be repeated.
+    #[arg(short, long, required = true)]
+    pub identity: Vec<PathBuf>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Arguments of `banjo docker-secret`
+#[derive(Debug, Args)]
+pub struct DockerSecretArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the key, the secret ID given by the container engine.
+    pub path: String,
+
+    /// Create a container secret with this name from the key instead of printing it.
+    #[arg(long, value_name = "NAME")]
+    pub create: Option<String>,
+
+    /// Container engine creating the secret.
+    #[arg(long, value_enum, default_value = "docker", requires = "create")]
+    pub engine: ContainerEngine
+}
+
+/// Arguments of `banjo deploy`
+#[derive(Debug, Args)]
+pub struct DeployArgs {
+    /// Keyblock containing the keys.
+    pub block: PathBuf,
+
+    /// Directory the keys are installed under, each at its path.
+    pub directory: PathBuf,
+
+    /// Only deploy keys matching this tag expression, can be repeated.
+    #[arg(short, long, value_name = "EXPRESSION")]
+    pub tag: Vec<TagExpression>,
+
+    /// Skip the keys an interrupted deployment already installed, if their content didn't change.
+    #[arg(long)]
+    pub resume: bool,
+
+    /// State file recording the installed keys, defaults to .banjo-deploy.json in the directory.
+    #[arg(long, value_name = "PATH")]
+    pub state: Option<PathBuf>
+}
+
+/// Container engines secrets can be created with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ContainerEngine {
+    Docker,
+    Podman
+}
+
+/// Subcommands of `banjo ssh`
+#[derive(Debug, Subcommand)]
+pub enum SshCommand {
+    /// Print the SHA256 fingerprint of an SSH key
+    Fingerprint(SshArgs),
+
+    /// Print the public key of an SSH key, as a line of an authorized_keys file
+    Pubkey(SshArgs),
+
+    /// Install the authorized_keys file of a user, from the keys tagged ssh-authorized:USER
+    Authorize(SshAuthorizeArgs),
+
+    /// Assemble a known_hosts file from the host keys tagged ssh-host:PATTERN
+    KnownHosts(SshKnownHostsArgs)
+}
+
+/// Arguments of `banjo ssh fingerprint` and `banjo ssh pubkey`
+#[derive(Debug, Args)]
+pub struct SshArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the SSH private or public key.
+    pub path: String
+}
+
+/// Arguments of `banjo ssh authorize`
+#[derive(Debug, Args)]
+pub struct SshAuthorizeArgs {
+    /// Keyblock containing the keys.
+    pub block: PathBuf,
+
+    /// Local user the keys are authorized for, who owns the installed file.
+    #[arg(short, long)]
+    pub user: String,
+
+    /// Where to install the file, instead of ~USER/.ssh/authorized_keys.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>
+}
+
+/// Arguments of `banjo ssh known-hosts`
+#[derive(Debug, Args)]
+pub struct SshKnownHostsArgs {
+    /// Keyblock containing the host keys.
+    pub block: PathBuf,
+
+    /// Where to install the file, instead of printing it.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>
+}
+
+/// Subcommands of `banjo wg`
+#[derive(Debug, Subcommand)]
+pub enum WgCommand {
+    /// Write a WireGuard private key and preshared keys to an interface configuration or a live interface
+    Deploy(WgDeployArgs),
+
+    /// Generate a new WireGuard private key, store it in the block and deploy it
+    Rotate(WgRotateArgs)
+}
+
+/// Arguments of `banjo wg deploy`
+#[derive(Debug, Args)]
+pub struct WgDeployArgs {
+    /// Keyblock containing the keys.
+    pub block: PathBuf,
+
+    /// Path of the WireGuard private key.
+    pub path: String,
+
+    /// Interface to configure with `wg set`.
+    #[arg(long, value_name = "NAME", required_unless_present = "config")]
+    pub interface: Option<String>,
+
+    /// wg-quick configuration file to update instead of the live interface.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Preshared key shared with a peer, as the base64 public key of the peer and the path of the key, can be
+    /// repeated.
+    #[arg(long, value_name = "PEER:PATH", value_parser = parse_peer_key)]
+    pub preshared_key: Vec<(String, String)>
+}
+
+/// Arguments of `banjo wg rotate`
+#[derive(Debug, Args)]
+pub struct WgRotateArgs {
+    /// Keyblock containing the key.
+    pub block: PathBuf,
+
+    /// Path of the WireGuard private key.
+    pub path: String,
+
+    /// Interface to configure with the new key using `wg set`.
+    #[arg(long, value_name = "NAME")]
+    pub interface: Option<String>,
+
+    /// wg-quick configuration file to update with the new key.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Number of previous versions to keep. Defaults to the key-history option, or 1.
+    #[arg(long, value_name = "COUNT")]
+    pub keep: Option<usize>,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Subcommands of `banjo acme`, only available if the binary is built with acme
+#[cfg(feature = "acme")]
+#[derive(Debug, Subcommand)]
+pub enum AcmeCommand {
+    /// Order a certificate when the stored one is about to expire, storing the new key and certificate
+    Renew(AcmeRenewArgs)
+}
+
+/// Arguments of `banjo acme renew`
+#[cfg(feature = "acme")]
+#[derive(Debug, Args)]
+pub struct AcmeRenewArgs {
+    /// Keyblock to store the key and certificate in.
+    pub block: PathBuf,
+
+    /// Domain to certify, can be repeated. The first one is the common name.
+    #[arg(long, required = true)]
+    pub domain: Vec<String>,
+
+    /// Path of the keys, the private key is stored at PATH.key and the certificate chain at PATH.crt.
+    #[arg(long)]
+    pub path: String,
+
+    /// Validate the domains with HTTP-01, writing the challenge responses under this directory.
+    #[arg(long, value_name = "DIR", required_unless_present = "dns_command", conflicts_with = "dns_command")]
+    pub webroot: Option<PathBuf>,
+
+    /// Validate the domains with DNS-01, running this command to publish and remove the TXT records. It gets
+    /// BANJO_ACME_ACTION (present or cleanup), BANJO_ACME_DOMAIN, BANJO_ACME_RECORD and BANJO_ACME_VALUE.
+    #[arg(long, value_name = "COMMAND")]
+    pub dns_command: Option<String>,
+
+    /// Directory URL of the ACME server.
+    #[arg(long, value_name = "URL", default_value = crate::acme::LETS_ENCRYPT)]
+    pub directory: String,
+
+    /// Contact address of the account, used when it is created.
+    #[arg(long)]
+    pub email: Option<String>,
+
+    /// Path of the ECDSA P-256 account key in the block, generated if it doesn't exist.
+    #[arg(long, value_name = "PATH", default_value = "acme/account.key")]
+    pub account_key: String,
+
+    /// Algorithm of the certificate private key.
+    #[arg(long, value_enum, default_value = "ec-p256")]
+    pub algorithm: KeyAlgorithm,
+
+    /// Renew when the stored certificate expires within this many days.
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pub within: u32,
+
+    /// Renew even if the stored certificate isn't about to expire.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Root private key used to sign the updated block.
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: PathBuf
+}
+
+/// Parse a `PEER:PATH` preshared key argument, base64 public keys never containing a colon
+fn parse_peer_key(text: &str) -> Result<(String, String), String> {
+    match text.split_once(':') {
+        Some((peer, path)) if !peer.is_empty() && !path.is_empty() => Ok((peer.to_string(), path.to_string())),
+        _ => Err(format!("invalid preshared key \"{}\", expected PEER:PATH", text))
+    }
+}
+
+/// Subcommands of `banjo debug`, only available if the binary is built with enable_debug.
+#[cfg(feature = "enable_debug")]
+#[derive(Debug, Subcommand)]
+pub enum DebugCommand {
+    /// Generate a fake .banjo directory
+    Fakeinit,
+
+    /// Print a formal description of the keyblock format
+    Schema(DebugSchemaArgs),
+
+    /// Recover keyfile metadata from a damaged keyblock
+    Scavenge(DebugScavengeArgs)
+}
+
+/// Formats of `banjo debug schema`
+#[cfg(feature = "enable_debug")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaFormat {
+    /// Kaitai Struct specification
+    Kaitai,
+    /// JSON description
+    Json,
+    /// 010 Editor binary template
+    #[value(name = "010-editor")]
+    Editor010
+}
+
+/// Arguments of `banjo debug scavenge`
+#[cfg(feature = "enable_debug")]
+#[derive(Debug, Args)]
+pub struct DebugScavengeArgs {
+    /// Damaged keyblock to scan.
+    pub block: PathBuf,
+
+    /// Print the report as JSON.
+    #[arg(long)]
+    pub json: bool,
+
+    /// Include the stored secrets and encrypted contents in the JSON report.
+    #[arg(long, requires = "json")]
+    pub include_data: bool
+}
+
+/// Arguments of `banjo debug schema`
+#[cfg(feature = "enable_debug")]
+#[derive(Debug, Args)]
+pub struct DebugSchemaArgs {
+    /// Format of the description.
+    #[arg(long, value_enum, default_value_t = SchemaFormat::Kaitai)]
+    pub format: SchemaFormat,
+
+    /// File to write the description to, instead of printing it.
+    #[arg(long, value_name = "FILE")]
+    pub out: Option<PathBuf>
+}
@@ -0,0 +1,99 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::acme::{generate_account_key, AcmeClient, Challenge};
+use banjo_keyring::cli::{AcmeCommand, AcmeRenewArgs};
+use banjo_keyring::content::ContentType;
+use banjo_keyring::history::keep_version;
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::keyblock::KEYFILE_FROZEN;
+use banjo_keyring::tls::certificate_request;
+use log::info;
+use openssl::asn1::Asn1Time;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use serde_json::json;
+
+pub fn run(context: &Context, command: &AcmeCommand) -> CommandResult {
+    match command {
+        AcmeCommand::Renew(args) => renew(context, args)
+    }
+}
+
+/// Order a new key and certificate when the stored certificate is about to expire, then run the post-renew hook
+fn renew(context: &Context, args: &AcmeRenewArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key_path = format!("{}.key", args.path);
+    let certificate_path = format!("{}.crt", args.path);
+    for path in [&key_path, &certificate_path, &args.account_key].iter() {
+        if block.keys.get(*path).is_some_and(|key| key.flags & KEYFILE_FROZEN != 0) {
+            return Err(format!("{} is frozen, thaw it first", path).into())
+        }
+    }
+    if let Some(key) = block.keys.get(&certificate_path) {
+        let certificate = X509::from_pem(&key.open()?)?;
+        if !args.force && certificate.not_after() > Asn1Time::days_from_now(args.within)? {
+            info!("{} expires {}, not renewing it yet.", certificate_path, certificate.not_after());
+            return Ok(())
+        }
+    }
+
+    let challenge = match (&args.webroot, &args.dns_command) {
+        (Some(webroot), _) if !webroot.is_dir() => return Err(format!("{} isn't a directory", webroot.display()).into()),
+        (Some(webroot), _) => Challenge::Http(webroot.clone()),
+        (None, Some(command)) => Challenge::Dns(command.clone()),
+        (None, None) => unreachable!("clap requires one of them.")
+    };
+
+    let account_key = match block.keys.get(&args.account_key) {
+        Some(key) => PKey::private_key_from_pem(&key.open()?)?,
+        None => {
+            let account_key = generate_account_key()?;
+            let mut key = block.new_key(&args.account_key, &args.account_key, "ACME account key", &account_key.private_key_to_pem_pkcs8()?)?
+                .ok_or("no keyfile UID is left in this block")?;
+            key.flags = ContentType::SshKey.apply(key.flags);
+            block.keys.insert(args.account_key.clone(), key);
+            account_key
+        }
+    };
+
+    let common_name = &args.domain[0];
+    let private_key = args.algorithm.generate()?;
+    let request = certificate_request(&private_key, common_name, &args.domain)?;
+    let mut client = AcmeClient::connect(&args.directory, account_key, args.email.as_deref())?;
+    let chain = client.issue(&args.domain, &request, &challenge)?;
+
+    let keys = vec![
+        (key_path.clone(), format!("TLS private key for {}", common_name), ContentType::SshKey, private_key.private_key_to_pem_pkcs8()?),
+        (certificate_path.clone(), format!("ACME certificate for {}", common_name), ContentType::X509, chain)
+    ];
+    let keep = context.config.key_history.unwrap_or(1);
+    for (path, description, content_type, content) in keys {
+        if block.keys.contains_key(&path) { keep_version(&mut block, &path, keep)? }
+        let mut key = match block.keys.remove(&path) {
+            Some(mut key) => {
+                key.seal(&content)?;
+                key
+            },
+            None => block.new_key(&path, common_name, &description, &content)?.ok_or("no keyfile UID is left in this block")?
+        };
+        key.flags = content_type.apply(key.flags);
+        block.keys.insert(path, key);
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+    info!("Renewed {} and {} for {}.", key_path, certificate_path, args.domain.join(", "));
+
+    let details = json!({
+        "block": args.block.display().to_string(),
+        "key": key_path,
+        "certificate": certificate_path,
+        "domains": args.domain.join(",")
+    });
+    run_hook(&context.config.hooks, Hook::PostRenew, details)?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::AddArgs;
+use log::info;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Add a key to a keyblock and re-sign it
+pub fn run(context: &Context, args: &AddArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    if block.keys.contains_key(&args.path) { return Err(format!("a key already exists at {}", args.path).into()) }
+
+    let content = match (&args.source, &args.from_cmd) {
+        (_, Some(command)) => command_output(command)?,
+        (Some(source), None) if source == Path::new("-") => {
+            let mut content = Vec::new();
+            io::stdin().read_to_end(&mut content)?;
+            content
+        },
+        (Some(source), None) => fs::read(source).map_err(|error| format!("{}: {}", source.display(), error))?,
+        (None, None) => unreachable!("clap requires one of them.")
+    };
+    args.content_type.handler().validate(&content)
+        .map_err(|error| format!("{} isn't a valid {} key: {}", args.path, args.content_type, error))?;
+
+    let name = args.name.as_deref().unwrap_or(&args.path);
+    let mut key = block.new_key(&args.path, name, &args.description, &content)?.ok_or("no keyfile UID is left in this block")?;
+    key.flags = args.content_type.apply(key.flags);
+    block.keys.insert(args.path.clone(), key);
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    info!("Added the {} key {}.", args.content_type, args.path);
+    Ok(())
+}
+
+/// Standard output of a shell command, which must succeed
+///
+/// The output is captured in memory, so the content never goes through a temporary file.
+fn command_output(command: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let output = Command::new("sh").arg("-c").arg(command).stdin(Stdio::null()).stderr(Stdio::inherit()).output()
+        .map_err(|error| format!("failed to run {:?}: {}", command, error))?;
+    if !output.status.success() { return Err(format!("{:?} failed ({})", command, output.status).into()) }
+
+    Ok(output.stdout)
+}
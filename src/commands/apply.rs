@@ -0,0 +1,31 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::apply::Manifest;
+use banjo_keyring::cli::ApplyArgs;
+use log::info;
+use std::path::Path;
+
+/// Bring a keyblock to the state declared by a manifest, printing every change
+pub fn run(context: &Context, args: &ApplyArgs) -> CommandResult {
+    let manifest = Manifest::load(&args.manifest)?;
+
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let base = args.manifest.parent().unwrap_or_else(|| Path::new("."));
+    let changes = manifest.apply(&mut block, base, args.prune, context.config.key_history.unwrap_or(0))?;
+    if changes.is_empty() {
+        info!("Keyblock \"{}\" already matches the manifest.", block.name);
+        return Ok(())
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    for change in &changes {
+        println!("{}", change);
+    }
+    info!("Applied {} changes to keyblock \"{}\".", changes.len(), block.name);
+    Ok(())
+}
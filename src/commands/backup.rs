@@ -0,0 +1,80 @@
+use crate::commands::{CommandResult, Context};
+use age::{IdentityFile, Identity, Recipient};
+use banjo_keyring::backup::BackupBundle;
+use banjo_keyring::cli::{BackupArgs, RestoreBackupArgs};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::i18n::{fluent_args, tr_with};
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::permissions::{open_private, write_private};
+use banjo_keyring::rootkey::RootKey;
+use log::{info, warn};
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Bundle a verified keyblock with its root key and encrypt it to the recipients
+pub fn backup(context: &Context, args: &BackupArgs) -> CommandResult {
+    let recipients = parse_recipients(&args.recipient)?;
+
+    let serialized = context.read_block(&args.block)?;
+    let block = KeyBlock::from_bytes(&serialized, context.root_key()?)?;
+
+    let bundle = BackupBundle::new(serialized, &block)?;
+    write_private(&args.out, &bundle.encrypt(&recipients)?)?;
+
+    info!("Backed up keyblock \"{}\" to {}.", block.name, args.out.display());
+    Ok(())
+}
+
+/// Decrypt a backup bundle, verify the keyblock it contains and write it back
+pub fn restore(context: &Context, args: &RestoreBackupArgs) -> CommandResult {
+    let identities = load_identities(&args.identity)?;
+    let bundle = BackupBundle::decrypt(&fs::read(&args.bundle)?, &identities)?;
+
+    // age doesn't authenticate the sender, so the bundled root key proves nothing
+    let root_key = context.root_key()
+        .map_err(|error| format!("{}, a root key obtained out of band is needed to verify backups", error))?;
+    let bundled = RootKey::from_bytes(&bundle.root_key).ok().and_then(|bundled| bundled.fingerprint().ok());
+    if bundled.is_some_and(|bundled| root_key.fingerprint().is_ok_and(|fingerprint| fingerprint != bundled)) {
+        warn!("The backup was made with another root key than the configured one.");
+    }
+    let block = KeyBlock::from_bytes(&bundle.block, root_key)?;
+    if context.dry_run() {
+        let args = fluent_args![
+            "name" => escape_controls(&block.name), "keys" => block.keys.len(), "size" => bundle.block.len(),
+            "path" => args.out.display().to_string()
+        ];
+        println!("{}", tr_with("dry-run-restore", Some(&args)));
+        return Ok(())
+    }
+
+    open_private(&args.out, args.force)
+        .map_err(|error| format!("failed to create {}: {}", args.out.display(), error))?
+        .write_all(&bundle.block)?;
+
+    info!("Restored keyblock \"{}\" ({} keys) to {}.", block.name, block.keys.len(), args.out.display());
+    Ok(())
+}
+
+/// Parse age X25519 recipients (age1...)
+pub fn parse_recipients(recipients: &[String]) -> Result<Vec<Box<dyn Recipient>>, Box<dyn Error>> {
+    let mut parsed: Vec<Box<dyn Recipient>> = Vec::new();
+    for recipient in recipients {
+        let recipient: age::x25519::Recipient = recipient.parse()
+            .map_err(|error| format!("invalid recipient {}: {}", recipient, error))?;
+        parsed.push(Box::new(recipient));
+    }
+
+    Ok(parsed)
+}
+
+/// Read the identities of age identity files
+pub fn load_identities(paths: &[PathBuf]) -> Result<Vec<Box<dyn Identity>>, Box<dyn Error>> {
+    let mut identities: Vec<Box<dyn Identity>> = Vec::new();
+    for path in paths {
+        identities.extend(IdentityFile::from_file(path.to_string_lossy().into_owned())?.into_identities()?);
+    }
+
+    Ok(identities)
+}
@@ -0,0 +1,51 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::bundle::OfflineBundle;
+use banjo_keyring::cli::{BundleArgs, VerifyBundleArgs};
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::permissions::write_private;
+use banjo_keyring::report::CheckStatus;
+use log::{error, info, warn};
+use std::fs;
+
+/// Bundle a verified keyblock with a detached signature, the root key fingerprint and its manifest
+pub fn create(context: &Context, args: &BundleArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+
+    let serialized = context.read_block(&args.block)?;
+    let block = KeyBlock::from_bytes(&serialized, root_key)?;
+
+    let bundle = OfflineBundle::new(serialized, &block, &signing_key)?;
+    write_private(&args.out, &bundle.serialize()?)?;
+
+    info!("Bundled keyblock \"{}\" to {}.", block.name, args.out.display());
+    Ok(())
+}
+
+/// Check the consistency of an offline bundle and the signatures it contains against the root key
+pub fn verify(context: &Context, args: &VerifyBundleArgs) -> CommandResult {
+    let bundle = OfflineBundle::parse(&fs::read(&args.bundle)?)?;
+    let report = bundle.report(&args.bundle.display().to_string(), context.root_key()?);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for check in &report.checks {
+            match check.status {
+                CheckStatus::Fail => error!("{}: {}", check.name, check.detail),
+                CheckStatus::Warn => warn!("{}: {}.", check.name, check.detail),
+                CheckStatus::Pass | CheckStatus::Skip => {}
+            }
+        }
+    }
+
+    if !report.valid {
+        return Err("the bundle failed verification".into())
+    }
+
+    if !args.json {
+        let manifest = &bundle.manifest.manifest;
+        info!("Bundle of keyblock \"{}\" is valid ({} keys).", manifest.block_name, manifest.keys.len());
+    }
+    Ok(())
+}
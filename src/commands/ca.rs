@@ -0,0 +1,132 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::ca::CertificateAuthority;
+use banjo_keyring::cli::{CaCommand, CaListArgs, CaRenewArgs, CaSignArgs};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::i18n::header;
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::permissions::write_private;
+use itertools::Itertools;
+use log::info;
+use openssl::nid::Nid;
+use openssl::x509::{X509Req, X509};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::{fs, iter};
+
+/// Run a `banjo ca` subcommand
+pub fn run(context: &Context, command: &CaCommand) -> CommandResult {
+    match command {
+        CaCommand::SignCert(args) => sign_cert(context, args),
+        CaCommand::Renew(args) => renew(context, args),
+        CaCommand::List(args) => list(context, args)
+    }
+}
+
+/// Issue a certificate for a request, after checking its signature
+fn sign_cert(context: &Context, args: &CaSignArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    let mut ca = CertificateAuthority::load(&block, &args.ca)?;
+
+    let request = X509Req::from_pem(&fs::read(&args.csr)?)?;
+    let public_key = request.public_key()?;
+    if !request.verify(&public_key)? { return Err("the certificate signing request has an invalid signature".into()) }
+
+    let names = if args.san.is_empty() {
+        request.subject_name().entries_by_nid(Nid::COMMONNAME)
+            .filter_map(|entry| entry.data().to_string().ok())
+            .take(1)
+            .collect()
+    } else {
+        args.san.clone()
+    };
+    let certificate = ca.issue(&public_key, request.subject_name(), &names, args.days)?;
+
+    store_registry(&mut block, &ca)?;
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    output(&certificate, args.out.as_deref())?;
+    info!("Issued certificate {} for {}.", serial_of(&certificate)?, names.join(", "));
+    Ok(())
+}
+
+/// Issue a new certificate replacing a previously issued one
+fn renew(context: &Context, args: &CaRenewArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    let mut ca = CertificateAuthority::load(&block, &args.ca)?;
+
+    let certificate = ca.renew(&args.serial, args.days)?;
+
+    store_registry(&mut block, &ca)?;
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    output(&certificate, args.out.as_deref())?;
+    info!("Renewed certificate {} as {}.", args.serial, serial_of(&certificate)?);
+    Ok(())
+}
+
+/// Print the certificates issued by a CA
+fn list(context: &Context, args: &CaListArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let ca = CertificateAuthority::load(&block, &args.ca)?;
+
+    let rows: Vec<[String; 4]> = ca.registry.certificates.iter()
+        .map(|entry| {
+            let renewed_by = entry.renewed_by.clone().unwrap_or_default();
+            [entry.serial.clone(), escape_controls(&entry.subject), entry.not_after.clone(), renewed_by]
+        })
+        .collect();
+
+    let header = [header("SERIAL"), header("SUBJECT"), header("NOT AFTER"), header("RENEWED BY")];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in iter::once(&header).chain(&rows) {
+        let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+/// Write the issuance registry of `ca` to the block, creating its keyfile if needed
+pub fn store_registry(block: &mut KeyBlock, ca: &CertificateAuthority) -> Result<(), Box<dyn Error>> {
+    let path = CertificateAuthority::registry_path(&ca.name);
+    let content = ca.serialize_registry();
+
+    match block.keys.get_mut(&path) {
+        Some(key) => key.seal(&content)?,
+        None => {
+            let description = format!("Certificates issued by {}", ca.name);
+            let key = block.new_key(&path, &path, &description, &content)?.ok_or("no keyfile UID is left in this block")?;
+            block.keys.insert(path, key);
+        }
+    }
+
+    Ok(())
+}
+
+/// Hex encoded serial number of a certificate
+fn serial_of(certificate: &X509) -> Result<String, Box<dyn Error>> {
+    Ok(certificate.serial_number().to_bn()?.to_hex_str()?.to_lowercase())
+}
+
+/// Write a PEM encoded certificate to `out`, or print it
+fn output(certificate: &X509, out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let pem = certificate.to_pem()?;
+    match out {
+        Some(out) => write_private(out, &pem)?,
+        None => io::stdout().write_all(&pem)?
+    }
+
+    Ok(())
+}
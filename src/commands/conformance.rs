@@ -0,0 +1,24 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::ConformanceArgs;
+use banjo_keyring::conformance::ConformanceReport;
+use banjo_keyring::display::RenderOptions;
+use banjo_keyring::report::CheckStatus;
+use banjo_keyring::table::OutputFormat;
+
+/// Check that a keyblock is the canonical serialization of its content and print the conformance report
+pub fn run(context: &Context, args: &ConformanceArgs) -> CommandResult {
+    let content = context.read_block(&args.block)?;
+    let report = ConformanceReport::build(&args.block.display().to_string(), &content, context.root_key()?);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.table().print_as(args.format.unwrap_or(OutputFormat::Table), RenderOptions::default());
+    }
+
+    let failures = report.checks.iter().filter(|check| check.status == CheckStatus::Fail).count();
+    if failures > 0 {
+        return Err(format!("the keyblock failed {} conformance checks", failures).into())
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::{DebugCommand, DebugScavengeArgs, DebugSchemaArgs, SchemaFormat};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::scavenge::scavenge;
+use banjo_keyring::schema::format_schema;
+use itertools::Itertools;
+use log::info;
+use std::{fs, iter};
+
+/// Run a `banjo debug` subcommand
+pub fn run(_context: &Context, command: &DebugCommand) -> CommandResult {
+    match command {
+        DebugCommand::Fakeinit => Ok(()),
+        DebugCommand::Schema(args) => schema(args),
+        DebugCommand::Scavenge(args) => scavenge_block(args)
+    }
+}
+
+/// Print or write the description of the keyblock format
+fn schema(args: &DebugSchemaArgs) -> CommandResult {
+    let schema = format_schema();
+    let description = match args.format {
+        SchemaFormat::Kaitai => schema.to_kaitai(),
+        SchemaFormat::Json => schema.to_json() + "\n",
+        SchemaFormat::Editor010 => schema.to_010_template()
+    };
+
+    match &args.out {
+        Some(out) => fs::write(out, description)?,
+        None => print!("{}", description)
+    }
+
+    Ok(())
+}
+
+/// Print the headers and keyfiles found anywhere in a possibly damaged keyblock
+fn scavenge_block(args: &DebugScavengeArgs) -> CommandResult {
+    // The block is read directly, it is most likely unparsable
+    let report = scavenge(&fs::read(&args.block)?, args.include_data);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(())
+    }
+
+    for header in &report.headers {
+        let name = header.name.as_deref().map_or("?".to_string(), escape_controls);
+        println!("Header at offset {}: format {}, name \"{}\"", header.offset, header.format, name);
+    }
+
+    let rows: Vec<[String; 5]> = report.keyfiles.iter()
+        .map(|key| {
+            let tags = key.tags.as_ref().map_or("-".to_string(), |tags| escape_controls(&tags.join(",")));
+            [format!("{}-{}", key.offset, key.end), key.uid.clone(), escape_controls(&key.path), key.length.to_string(), tags]
+        })
+        .collect();
+
+    if !rows.is_empty() {
+        let header = ["OFFSETS".to_string(), "UID".to_string(), "PATH".to_string(), "SIZE".to_string(), "TAGS".to_string()];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+            .collect();
+
+        for row in iter::once(&header).chain(&rows) {
+            let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+            println!("{}", line.trim_end());
+        }
+    }
+
+    info!("Found {} headers and {} keyfile candidates in {} bytes.", report.headers.len(), report.keyfiles.len(), report.size);
+    Ok(())
+}
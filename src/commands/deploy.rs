@@ -0,0 +1,66 @@
+use crate::commands::{deployable_content, CommandResult, Context};
+use banjo_keyring::cli::DeployArgs;
+use banjo_keyring::deploy::{target_path, DeployState, STATE_FILE};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::i18n::{fluent_args, tr_with};
+use banjo_keyring::install::install;
+use banjo_keyring::tags::matches_all;
+use itertools::Itertools;
+use log::info;
+use serde_json::json;
+use std::fs::DirBuilder;
+use std::os::unix::fs::DirBuilderExt;
+
+/// Install the keys of a keyblock under a directory, recording each installed key so the deployment can be resumed
+pub fn run(context: &Context, args: &DeployArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    context.check_host(&block)?;
+
+    let keys = block.keys.values()
+        .filter(|key| matches_all(&args.tag, &key.tags))
+        .sorted_by(|a, b| a.path.cmp(&b.path))
+        .collect_vec();
+    // Every path is checked before installing anything
+    let targets = keys.iter().map(|key| target_path(&args.directory, &key.path)).collect::<Result<Vec<_>, _>>()?;
+
+    let state_path = args.state.clone().unwrap_or_else(|| args.directory.join(STATE_FILE));
+    let mut state = if args.resume { DeployState::resume(&state_path, block.id)? } else { DeployState::new(block.id) };
+    let pending = keys.iter().zip(&targets).filter(|(key, target)| !state.is_deployed(key, target)).collect_vec();
+    let skipped = keys.len() - pending.len();
+    if pending.is_empty() {
+        info!("The {} keys of keyblock \"{}\" are already deployed.", skipped, escape_controls(&block.name));
+        return Ok(())
+    }
+
+    if context.dry_run() {
+        for (key, target) in &pending {
+            let args = fluent_args!["path" => escape_controls(&key.path), "target" => target.display().to_string()];
+            println!("{}", tr_with("dry-run-deploy", Some(&args)));
+        }
+        return Ok(())
+    }
+
+    let details = json!({
+        "block": args.block.display().to_string(),
+        "directory": args.directory.display().to_string(),
+        "keys": pending.iter().map(|(key, _)| key.path.as_str()).collect_vec()
+    });
+    run_hook(&context.config.hooks, Hook::PreDeploy, details.clone())?;
+    for (key, target) in &pending {
+        let content = deployable_content(key).map_err(|error| format!("{}: {}", key.path, error))?;
+        if let Some(parent) = target.parent() {
+            DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+        }
+        install(target, &content, 0o600, None)?;
+
+        // Saved after every key, so an interruption loses at most the key being installed
+        state.record(key);
+        state.save(&state_path)?;
+        context.record_access(&args.block, &[&key.path]);
+    }
+    run_hook(&context.config.hooks, Hook::PostDeploy, details)?;
+
+    info!("Deployed {} keys to {} ({} already deployed).", pending.len(), args.directory.display(), skipped);
+    Ok(())
+}
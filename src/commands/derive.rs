@@ -0,0 +1,32 @@
+use crate::commands::{exportable_content, CommandResult, Context};
+use banjo_keyring::cli::DeriveArgs;
+use banjo_keyring::keyblock::validate_path;
+use banjo_keyring::secret::Secret256;
+use banjo_keyring::utils::to_hex;
+use byteorder::{LittleEndian, WriteBytesExt};
+
+/// HKDF info prefix of purpose secrets, followed by the length prefixed key path and the purpose
+const DERIVE_INFO: &[u8] = b"banjo derive";
+
+/// Print the secret derived from the content of a master key for a purpose, as hexadecimal
+pub fn run(context: &Context, args: &DeriveArgs) -> CommandResult {
+    validate_path(&args.path)?;
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let key = block.keys.get(&args.path)
+        .ok_or_else(|| format!("no key at {} in this block", args.path))?;
+    // The derived secret is handed out, so the master key has to be exportable
+    let content = match exportable_content(key)? {
+        Some(content) => content,
+        None => return Ok(())
+    };
+
+    // The length prefix keeps every path and purpose pair apart
+    let mut info = DERIVE_INFO.to_vec();
+    info.write_u32::<LittleEndian>(args.path.len() as u32)?;
+    info.extend(args.path.as_bytes());
+    info.extend(args.purpose.as_bytes());
+
+    println!("{}", to_hex(Secret256::derive_from(&content, &info)?.as_bytes()));
+    context.record_access(&args.block, &[&args.path]);
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use crate::commands::{exportable_content, CommandResult, Context};
+use banjo_keyring::cli::{ContainerEngine, DockerSecretArgs};
+use log::info;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Print the decrypted content of a key as a secret provider does, or pipe it to `<engine> secret create`
+pub fn run(context: &Context, args: &DockerSecretArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    if args.create.is_some() { context.check_host(&block)? }
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let content = match exportable_content(key)? {
+        Some(content) => content,
+        None => return Ok(())
+    };
+
+    let name = match &args.create {
+        Some(name) => name,
+        None => {
+            io::stdout().write_all(&content)?;
+            context.record_access(&args.block, &[&args.path]);
+            return Ok(())
+        }
+    };
+    if name.is_empty() || name.starts_with('-') { return Err(format!("invalid secret name {:?}", name).into()) }
+
+    let engine = match args.engine {
+        ContainerEngine::Docker => "docker",
+        ContainerEngine::Podman => "podman"
+    };
+    // The content is read from standard input, so it never appears in process arguments
+    let mut child = Command::new(engine).args(["secret", "create", "--", name, "-"]).stdin(Stdio::piped()).spawn()
+        .map_err(|error| format!("failed to run {}: {}", engine, error))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&content)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() { return Err(format!("{} secret create failed ({})", engine, status).into()) }
+
+    context.record_access(&args.block, &[&args.path]);
+    info!("Created the {} secret {} from {}.", engine, name, args.path);
+    Ok(())
+}
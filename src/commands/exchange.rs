@@ -0,0 +1,73 @@
+use crate::commands::backup::{load_identities, parse_recipients};
+use crate::commands::{exportable_content, CommandResult, Context};
+use age::{Decryptor, Encryptor};
+use banjo_keyring::cli::{ExportAgeArgs, ImportAgeArgs};
+use banjo_keyring::history::keep_version;
+use banjo_keyring::keyblock::KEYFILE_FROZEN;
+use banjo_keyring::permissions::write_private;
+use log::info;
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// Encrypt a single key to age recipients, without exposing the block secret
+pub fn export(context: &Context, args: &ExportAgeArgs) -> CommandResult {
+    let recipients = parse_recipients(&args.recipient)?;
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let content = match exportable_content(key)? {
+        Some(content) => content,
+        None => return Ok(())
+    };
+
+    let encryptor = Encryptor::with_recipients(recipients.iter().map(|recipient| recipient.as_ref() as _))?;
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(&content)?;
+    writer.finish()?;
+
+    match &args.out {
+        Some(out) => {
+            write_private(out, &encrypted)?;
+            info!("Exported {} to {}.", args.path, out.display());
+        },
+        None => io::stdout().write_all(&encrypted)?
+    }
+
+    context.record_access(&args.block, &[&args.path]);
+    Ok(())
+}
+
+/// Decrypt an age encrypted file and store its content at a path, keeping the previous content if any
+pub fn import(context: &Context, args: &ImportAgeArgs) -> CommandResult {
+    let identities = load_identities(&args.identity)?;
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let encrypted = fs::read(&args.file)?;
+    let decryptor = Decryptor::new_buffered(encrypted.as_slice())?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity.as_ref() as _))?;
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+
+    match block.keys.get(&args.path) {
+        Some(key) => {
+            if key.flags & KEYFILE_FROZEN != 0 { return Err(format!("{} is frozen, thaw it first", args.path).into()) }
+            keep_version(&mut block, &args.path, context.config.key_history.unwrap_or(0))?;
+
+            let key = block.keys.get_mut(&args.path).expect("The key was just found.");
+            key.seal(&content)?;
+        },
+        None => {
+            let key = block.new_key(&args.path, &args.path, "", &content)?.ok_or("no keyfile UID is left in this block")?;
+            block.keys.insert(args.path.clone(), key);
+        }
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    info!("Imported {} into keyblock \"{}\".", args.path, block.name);
+    Ok(())
+}
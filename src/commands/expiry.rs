@@ -0,0 +1,58 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::CheckExpiryArgs;
+use banjo_keyring::content::ContentType;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::i18n::header;
+use banjo_keyring::notify::{notify, Event};
+use banjo_keyring::rotation::today;
+use banjo_keyring::utils::format_day;
+use itertools::Itertools;
+use log::{info, warn};
+use std::iter;
+
+/// Print the keys of a block whose content expires soon, optionally notifying about each of them
+pub fn run(context: &Context, args: &CheckExpiryArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let today = i64::from(today());
+
+    let expiring: Vec<(&str, ContentType, u32)> = block.keys.values()
+        .filter_map(|key| Some((key.path.as_str(), ContentType::from_flags(key.flags)?, ContentType::expiry_of(key)?)))
+        .filter(|(_, _, expiry)| i64::from(*expiry) - today <= i64::from(args.within))
+        .sorted_by_key(|(path, _, expiry)| (*expiry, *path))
+        .collect();
+    if expiring.is_empty() {
+        info!("No key of keyblock \"{}\" expires within {} days.", block.name, args.within);
+        return Ok(())
+    }
+
+    let rows: Vec<[String; 4]> = expiring.iter()
+        .map(|(path, content_type, expiry)| {
+            let state = match i64::from(*expiry) - today {
+                days if days < 0 => format!("expired {} days ago", -days),
+                0 => "expires today".to_string(),
+                days => format!("expires in {} days", days)
+            };
+            [escape_controls(path), content_type.to_string(), format_day(*expiry), state]
+        })
+        .collect();
+    let header = [header("PATH"), header("TYPE"), header("EXPIRY"), header("STATE")];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in iter::once(&header).chain(&rows) {
+        let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    if args.notify {
+        for (path, _, expiry) in &expiring {
+            let event = Event::KeyExpiring { block: &args.block, key: path, expiry: *expiry, days_left: i64::from(*expiry) - today };
+            if let Err(error) = notify(&context.config.notify, &event) {
+                warn!("Failed to send the notification: {}.", error);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use crate::commands::CommandResult;
+use banjo_keyring::cli::ExplainArgs;
+use banjo_keyring::explain::{lookup, ERRORS};
+
+/// Print the explanation of an error code or of the errors found in a message
+pub fn run(args: &ExplainArgs) -> CommandResult {
+    if args.list {
+        for info in ERRORS {
+            println!("{}  {}", info.code, info.title);
+        }
+        return Ok(())
+    }
+
+    let query = args.error.join(" ");
+    let found = lookup(&query);
+    if found.is_empty() { return Err(format!("no known error matches \"{}\", see `banjo explain --list`", query).into()) }
+
+    for (index, info) in found.iter().enumerate() {
+        if index > 0 { println!() }
+        println!("{}", info);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::commands::{check_export, confirm, CommandResult, Context};
+use banjo_keyring::cli::ExtractArgs;
+use banjo_keyring::crypto::decrypt_stream;
+use banjo_keyring::i18n::{fluent_args, tr_with};
+use banjo_keyring::keyblock::{validate_path, KeyBlock};
+use banjo_keyring::permissions::open_private;
+use banjo_keyring::store::open_store;
+use log::info;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor};
+
+/// Decrypt a key to a new file while reading its keyblock as a stream, one keyfile at a time
+///
+/// The file is removed again unless the signature following the keyfiles checks out.
+pub fn run(context: &Context, args: &ExtractArgs) -> CommandResult {
+    validate_path(&args.path)?;
+    let root_key = context.root_key()?;
+    let store = open_store(&args.block)?;
+    let reader: Box<dyn BufRead> = match store.local_path() {
+        Some(path) => {
+            context.check_block(path)?;
+            Box::new(BufReader::new(File::open(path)?))
+        },
+        // Remote blocks can't be streamed, they are downloaded within the memory ceiling first
+        None => Box::new(Cursor::new(store.load(context.max_memory())?.content))
+    };
+
+    let mut stream = KeyBlock::stream_signed_keys(reader)?;
+    let key = loop {
+        match stream.next() {
+            Some(key) => {
+                let key = key?;
+                if key.path == args.path { break key }
+            },
+            None => return Err(format!("no key at path {}", args.path).into())
+        }
+    };
+    let question = || tr_with("confirm-export", Some(&fluent_args!["path" => key.path.as_str()]));
+    if check_export(&key)? && !confirm(&question())? { return Ok(()) }
+
+    let mut file = open_private(&args.out, false)?;
+    let result = (|| -> CommandResult {
+        decrypt_stream(&key.secret, &mut key.content.as_slice(), &mut file)?;
+        file.sync_all()?;
+        // Keyfiles are only authentic once the signature following them is checked
+        stream.verify(&root_key)?;
+        Ok(())
+    })();
+    if result.is_err() { let _ = fs::remove_file(&args.out); }
+    result?;
+
+    context.record_access(&args.block, &[&args.path]);
+    info!("Extracted {} to {}.", args.path, args.out.display());
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::{FactsArgs, FactsFormat};
+use banjo_keyring::content::ContentType;
+use banjo_keyring::host::HostFingerprint;
+use banjo_keyring::tags::matches_all;
+use banjo_keyring::utils::{format_day, format_uid};
+use itertools::Itertools;
+use serde_json::{json, Map, Value};
+
+/// Print the metadata of a keyblock as JSON, only decrypting keys to read their expiry date
+pub fn run(context: &Context, args: &FactsArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let digest = block.content_digest()?;
+    let keys = block.keys.values()
+        .filter(|key| matches_all(&args.tag, &key.tags))
+        .sorted_by(|a, b| a.path.cmp(&b.path));
+    // Printed so the block can be bound to this host from elsewhere
+    let local_host = HostFingerprint::local().ok().map(|host| host.to_string());
+    let bound_host = block.host.map(|host| host.to_string());
+
+    let facts = match args.format {
+        FactsFormat::Ansible => {
+            let mut key_facts = Map::new();
+            for key in keys {
+                key_facts.insert(key.path.clone(), json!({
+                    "uid": format_uid(key.uid),
+                    "id": key.id.to_string(),
+                    "name": key.name,
+                    "size": key.length,
+                    "content_hmac": digest.of(key)?,
+                    "expiry": ContentType::expiry_of(key).map(format_day),
+                    "tags": key.tags
+                }));
+            }
+
+            json!({
+                "banjo": {
+                    "block": {
+                        "uid": format_uid(block.uid),
+                        "id": block.id.to_string(),
+                        "name": block.name,
+                        "format": block.format_specifier,
+                        "host": bound_host
+                    },
+                    "keys": key_facts,
+                    "host_fingerprint": local_host
+                }
+            })
+        },
+        FactsFormat::Terraform => {
+            // Terraform external data sources only accept a flat map of strings
+            let mut facts = Map::new();
+            facts.insert("block_uid".to_string(), Value::from(format_uid(block.uid)));
+            facts.insert("block_id".to_string(), Value::from(block.id.to_string()));
+            facts.insert("block_name".to_string(), Value::from(block.name.clone()));
+            facts.insert("block_host".to_string(), Value::from(bound_host.unwrap_or_default()));
+            facts.insert("host_fingerprint".to_string(), Value::from(local_host.unwrap_or_default()));
+
+            let mut paths = Vec::new();
+            for key in keys {
+                facts.insert(format!("{}.uid", key.path), Value::from(format_uid(key.uid)));
+                facts.insert(format!("{}.id", key.path), Value::from(key.id.to_string()));
+                facts.insert(format!("{}.size", key.path), Value::from(key.length.to_string()));
+                facts.insert(format!("{}.content_hmac", key.path), Value::from(digest.of(key)?));
+                facts.insert(format!("{}.tags", key.path), Value::from(key.tags.join(",")));
+                // Empty for keys without an expiry date, as values have to be strings
+                facts.insert(format!("{}.expiry", key.path), Value::from(ContentType::expiry_of(key).map(format_day).unwrap_or_default()));
+                paths.push(key.path.clone());
+            }
+            facts.insert("paths".to_string(), Value::from(paths.join(",")));
+
+            Value::Object(facts)
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&facts)?);
+    Ok(())
+}
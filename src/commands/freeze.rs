@@ -0,0 +1,20 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::FreezeArgs;
+use banjo_keyring::keyblock::KEYFILE_FROZEN;
+use log::info;
+
+/// Freeze or thaw a key, then re-sign the block
+pub fn run(context: &Context, args: &FreezeArgs, frozen: bool) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get_mut(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    if frozen { key.flags |= KEYFILE_FROZEN } else { key.flags &= !KEYFILE_FROZEN }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    info!("{} is {}.", args.path, if frozen { "frozen" } else { "thawed" });
+    Ok(())
+}
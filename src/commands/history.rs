@@ -0,0 +1,52 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::{HistoryArgs, RollbackKeyArgs};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::history::{rollback, versions};
+use banjo_keyring::i18n::header;
+use itertools::Itertools;
+use log::info;
+use openssl::error::ErrorStack;
+use std::iter;
+
+/// Print the current content of a key and its previous versions, latest first
+pub fn list(context: &Context, args: &HistoryArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let digest = block.content_digest()?;
+
+    let rows: Vec<[String; 4]> = iter::once(("current".to_string(), key))
+        .chain(versions(&block, &args.path).into_iter().map(|(version, key)| (version.to_string(), key)))
+        .map(|(version, key)| Ok([version, escape_controls(&key.path), format!("{} bytes", key.length), digest.of(key)?]))
+        .collect::<Result<_, ErrorStack>>()?;
+
+    let header = [header("VERSION"), header("PATH"), header("SIZE"), header("HMAC")];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in iter::once(&header).chain(&rows) {
+        let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+/// Restore a previous version of a key, keeping the replaced content as its latest previous version
+pub fn rollback_key(context: &Context, args: &RollbackKeyArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let version = args.version as usize;
+    // Rolling back never drops a version
+    let keep = versions(&block, &args.path).len() + 1;
+    rollback(&mut block, &args.path, version, keep)?;
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    info!("Restored version {} of {}.", version, args.path);
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::BindHostArgs;
+use banjo_keyring::host::HostFingerprint;
+use log::info;
+
+/// Bind a keyblock to a host, or remove its binding, then re-sign the block
+pub fn bind(context: &Context, args: &BindHostArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let host = match (&args.fingerprint, args.unbind) {
+        (_, true) => None,
+        (Some(fingerprint), false) => Some(*fingerprint),
+        (None, false) => Some(HostFingerprint::local().map_err(|error| format!("failed to fingerprint this host: {}", error))?)
+    };
+    block.bind_host(host);
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    match host {
+        Some(host) => info!("{} is bound to host {}.", args.block.display(), host),
+        None => info!("{} isn't bound to a host anymore.", args.block.display())
+    }
+    Ok(())
+}
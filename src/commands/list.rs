@@ -0,0 +1,57 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cache::{KeyMetadata, MetadataCache};
+use banjo_keyring::cli::ListArgs;
+use banjo_keyring::display::RenderOptions;
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::table::{KeyColumn, Table};
+use banjo_keyring::tags::matches_all;
+use banjo_keyring::utils::to_hex;
+use log::{debug, warn};
+use openssl::sha::sha256;
+use std::io::{self, Write};
+
+/// Print the keys of a keyblock matching the tag expressions
+pub fn run(context: &Context, args: &ListArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let content = context.read_block(&args.block)?;
+
+    let mut keys: Vec<KeyMetadata> = if args.cache || context.config.metadata_cache {
+        let digest = to_hex(&sha256(&content));
+
+        match MetadataCache::load_valid(&args.block, &digest, &root_key) {
+            Some(cache) => {
+                debug!("Using the metadata cache of {}.", args.block.display());
+                cache.keys
+            },
+            None => {
+                let cache = MetadataCache::new(&KeyBlock::from_bytes(&content, root_key)?, digest)?;
+                if let Err(error) = cache.save(&args.block) {
+                    warn!("Failed to write the metadata cache: {}", error);
+                }
+                cache.keys
+            }
+        }
+    } else {
+        MetadataCache::keys_of(&KeyBlock::from_bytes(&content, root_key)?)?
+    };
+
+    let columns = if args.long { KeyColumn::LONG.to_vec() } else { args.columns.clone() };
+    if columns.iter().chain(&args.sort).any(|column| *column == KeyColumn::LastAccess) {
+        if let Some(access_log) = context.access_log(&args.block)? {
+            for key in &mut keys {
+                key.last_access = access_log.last_accessed(&key.path);
+            }
+        }
+    }
+
+    // Paths, names and tags come from the block, tables may contain escape sequences or be arbitrarily wide
+    let options = RenderOptions { ascii: args.ascii, max_width: args.max_width.map(usize::from) };
+    let matching = keys.iter().filter(|key| matches_all(&args.tag, &key.tags));
+    if let Some(version) = args.porcelain {
+        io::stdout().write_all(Table::build(matching, KeyColumn::porcelain(version), None).to_porcelain(args.null).as_bytes())?;
+        return Ok(())
+    }
+    Table::build(matching, &columns, args.sort).print_as(args.format, options);
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::commands::{read_secret, CommandResult, Context};
+use banjo_keyring::cli::AddLoginArgs;
+use banjo_keyring::content::ContentType;
+use banjo_keyring::login::LoginEntry;
+use log::info;
+
+/// Add a login entry to a keyblock and re-sign it
+pub fn add(context: &Context, args: &AddLoginArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    if block.keys.contains_key(&args.path) { return Err(format!("a key already exists at {}", args.path).into()) }
+
+    let password = read_secret(&format!("Password of {}: ", args.path))?;
+    if password.as_str().is_empty() { return Err("the password is empty".into()) }
+    let entry = LoginEntry {
+        username: args.username.clone(),
+        password: password.as_str().to_string(),
+        url: args.url.clone(),
+        notes: args.notes.clone()
+    };
+
+    let name = args.username.as_deref().unwrap_or(&args.path);
+    let mut key = block.new_key(&args.path, name, args.url.as_deref().unwrap_or_default(), &entry.to_bytes())?
+        .ok_or("no keyfile UID is left in this block")?;
+    key.flags = ContentType::Login.apply(key.flags);
+    block.keys.insert(args.path.clone(), key);
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    info!("Added the login entry {}.", args.path);
+    Ok(())
+}
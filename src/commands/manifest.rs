@@ -0,0 +1,28 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::ManifestArgs;
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::manifest::Manifest;
+use banjo_keyring::permissions::write_private;
+use log::info;
+
+/// Print or write the signed inventory of a verified keyblock
+pub fn run(context: &Context, args: &ManifestArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+
+    let serialized = context.read_block(&args.block)?;
+    let block = KeyBlock::from_bytes(&serialized, root_key)?;
+
+    let manifest = Manifest::new(&block, &serialized)?.sign(&signing_key)?;
+    let json = serde_json::to_string_pretty(&manifest)?;
+
+    match &args.output {
+        Some(path) => {
+            write_private(path, (json + "\n").as_bytes())?;
+            info!("Wrote the manifest of \"{}\" to {}.", block.name, path.display());
+        },
+        None => println!("{}", json)
+    }
+
+    Ok(())
+}
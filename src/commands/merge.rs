@@ -0,0 +1,53 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::Merge3Args;
+use banjo_keyring::merge::{merge3, Side};
+use banjo_keyring::permissions::write_private;
+use log::{error, info};
+use std::collections::HashMap;
+
+/// Merge two versions of a keyblock from their common base, writing a conflict report on true conflicts
+pub fn run(context: &Context, args: &Merge3Args) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+
+    let base = context.load_block(&args.base, root_key.clone())?;
+    let ours = context.load_block(&args.ours, root_key.clone())?;
+    let theirs = context.load_block(&args.theirs, root_key)?;
+
+    let mut resolutions = HashMap::new();
+    for path in &args.take_ours {
+        resolutions.insert(path.clone(), Side::Ours);
+    }
+    for path in &args.take_theirs {
+        if resolutions.insert(path.clone(), Side::Theirs).is_some() {
+            return Err(format!("{} can't be resolved with both versions", path).into())
+        }
+    }
+
+    let mut result = merge3(&base, ours, &theirs, &resolutions);
+
+    if !result.conflicts.is_empty() {
+        let report = serde_json::to_string_pretty(&result.conflicts)?;
+        match &args.report {
+            Some(path) => write_private(path, (report + "\n").as_bytes())?,
+            None => println!("{}", report)
+        }
+
+        for conflict in &result.conflicts {
+            error!("Conflict on {}.", conflict.path);
+        }
+        return Err(format!(
+            "{} conflicts, resolve them with --take-ours or --take-theirs", result.conflicts.len()
+        ).into())
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.ours);
+    context.sign_block(output, &mut result.block, &signing_key)?;
+    context.write_block(output, &result.block.serialize()?)?;
+
+    info!(
+        "Merged keyblock \"{}\", {} changes taken from {}, written to {}.",
+        result.block.name, result.from_theirs.len(), args.theirs.display(), output.display()
+    );
+    Ok(())
+}
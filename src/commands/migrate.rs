@@ -0,0 +1,43 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::MigrateArgs;
+use banjo_keyring::keyblock::{KeyBlock, BLOCK_COMPRESSED_METADATA, BLOCK_DEDUPLICATED};
+use log::info;
+
+/// Re-serialize a keyblock in the current format and sign it
+pub fn run(context: &Context, args: &MigrateArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+
+    let mut block = KeyBlock::from_bytes_unverified(&context.read_block(&args.block)?, root_key)?;
+    let previous_format = block.format_specifier;
+
+    if args.compress {
+        block.flags |= BLOCK_COMPRESSED_METADATA;
+    } else if args.no_compress {
+        block.flags &= !BLOCK_COMPRESSED_METADATA;
+    }
+
+    if let Some(algorithm) = args.wrap {
+        // Wrapping doesn't change the secrets themselves, so the content stays valid
+        for key in block.keys.values_mut() {
+            key.set_wrap_algorithm(algorithm);
+        }
+    }
+
+    if let Some(suite) = args.suite {
+        block.cipher_suite = suite;
+    }
+
+    if args.dedup {
+        block.flags |= BLOCK_DEDUPLICATED;
+    } else if args.no_dedup {
+        block.flags &= !BLOCK_DEDUPLICATED;
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.block);
+    context.sign_block(output, &mut block, &signing_key)?;
+    context.write_block(output, &block.serialize()?)?;
+
+    info!("Migrated keyblock \"{}\" from format {}, written to {}.", block.name, previous_format, output.display());
+    Ok(())
+}
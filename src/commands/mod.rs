@@ -0,0 +1,488 @@
+#[cfg(feature = "acme")]
+mod acme;
+mod add;
+mod apply;
+mod backup;
+mod bundle;
+mod ca;
+mod conformance;
+mod deploy;
+mod derive;
+mod docker;
+mod exchange;
+mod expiry;
+mod explain;
+mod extract;
+mod facts;
+#[cfg(feature = "enable_debug")]
+mod debug;
+mod freeze;
+mod history;
+mod host;
+mod list;
+mod login;
+mod manifest;
+mod merge;
+mod migrate;
+mod paper;
+mod policy;
+mod prune;
+mod public;
+mod reissue;
+mod rotation;
+mod set_type;
+mod show;
+mod ssh;
+mod sync;
+mod tag;
+mod tls;
+#[cfg(feature = "tui")]
+mod tui;
+mod uids;
+mod update;
+mod usage;
+mod verify;
+mod wg;
+mod xref;
+
+use banjo_keyring::access::AccessLog;
+use banjo_keyring::audit::{AuditEntry, AuditKey};
+use banjo_keyring::cli::{Cli, Command};
+use banjo_keyring::config::Config;
+use banjo_keyring::diff::{diff, Change};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::hardening::harden;
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::host::HostFingerprint;
+use banjo_keyring::i18n::{fluent_args, init, is_yes, tr, tr_with, Language};
+use banjo_keyring::keyblock::{KeyBlock, KeyFile, KEYFILE_FROZEN};
+use banjo_keyring::memlock;
+use banjo_keyring::permissions::check_block_permissions;
+use banjo_keyring::policy::KeyPolicy;
+use banjo_keyring::rootkey::{discover_root_key, load_root_key, load_signing_key, RootKey, SigningKey};
+use banjo_keyring::secret::Passphrase;
+use banjo_keyring::store::open_store;
+use banjo_keyring::uids::UidRegistry;
+use banjo_keyring::utils::format_uid;
+use itertools::Itertools;
+use log::{error, warn};
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Result type returned by every subcommand
+pub type CommandResult = Result<(), Box<dyn Error>>;
+
+/// Shared state handed to the subcommands
+pub struct Context<'a> {
+    pub cli: &'a Cli,
+    pub config: Config,
+    /// Version of every keyblock read so far, to only overwrite blocks which didn't change since
+    versions: Mutex<HashMap<PathBuf, String>>,
+    /// Previous content of every keyblock written so far, `None` if it didn't exist, to undo failed commands
+    journal: Mutex<Vec<(PathBuf, Option<Vec<u8>>)>>,
+    /// Keys handed out so far, for the audit log
+    accessed: Mutex<Vec<AuditKey>>
+}
+
+impl Context<'_> {
+    /// Find and load the root public key
+    pub fn root_key(&self) -> Result<RootKey, Box<dyn Error>> {
+        let path = discover_root_key(self.cli.root_key.as_deref(), &self.config)?;
+        Ok(load_root_key(&path)?)
+    }
+
+    /// Memory ceiling for loading keyblocks, from the command line or the configuration
+    pub fn max_memory(&self) -> Option<u64> {
+        self.cli.max_memory.or(self.config.max_memory)
+    }
+
+    /// Check the permissions of a keyblock, failing in strict mode and warning otherwise
+    pub fn check_block(&self, path: &Path) -> Result<(), String> {
+        let problems = check_block_permissions(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        if problems.is_empty() { return Ok(()) }
+
+        if self.cli.strict || self.config.strict {
+            return Err(problems.join(", "))
+        }
+        for problem in problems {
+            warn!("{}.", problem);
+        }
+
+        Ok(())
+    }
+
+    /// Read a serialized keyblock from its store within the memory ceiling
+    pub fn read_block(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.read_block_within(path, self.max_memory())
+    }
+
+    /// Read a serialized keyblock from its store within `max_memory` bytes, when blocks are read concurrently
+    pub fn read_block_within(&self, path: &Path, max_memory: Option<u64>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let store = open_store(path)?;
+        if let Some(local_path) = store.local_path() {
+            self.check_block(local_path)?;
+        }
+
+        let stored = store.load(max_memory)?;
+        self.versions.lock().unwrap().insert(path.to_path_buf(), stored.version);
+
+        Ok(stored.content)
+    }
+
+    /// Load and verify the keyblock at `path` within the memory ceiling
+    ///
+    /// The UIDs of the other blocks of its directory are reserved if it keeps a registry.
+    pub fn load_block(&self, path: &Path, root_key: RootKey) -> Result<KeyBlock, Box<dyn Error>> {
+        let mut block = KeyBlock::from_bytes(&self.read_block(path)?, root_key)?;
+        if let Some((directory, file)) = self.uid_registry(path) {
+            block.reserved_uids = UidRegistry::load(&directory)?.reserved(&file);
+        }
+
+        Ok(block)
+    }
+
+    /// Directory and file name of the keyblock at `path`, if it is stored locally and its directory keeps a UID registry
+    fn uid_registry(&self, path: &Path) -> Option<(PathBuf, String)> {
+        UidRegistry::locate(open_store(path).ok()?.local_path()?)
+    }
+
+    /// Refuse to deploy the keys of a keyblock bound to another host
+    pub fn check_host(&self, block: &KeyBlock) -> Result<(), Box<dyn Error>> {
+        let bound = match block.host {
+            Some(bound) => bound,
+            None => return Ok(())
+        };
+        let local = HostFingerprint::local().map_err(|error| format!("failed to fingerprint this host: {}", error))?;
+        if local != bound {
+            let name = escape_controls(&block.name);
+            return Err(format!("keyblock \"{}\" is bound to host {}, not this one ({})", name, bound, local).into())
+        }
+
+        Ok(())
+    }
+
+    /// Whether changes must only be reported
+    pub fn dry_run(&self) -> bool {
+        self.cli.dry_run
+    }
+
+    /// Record that the keys at `paths` of the keyblock at `path` were handed out, for the audit log and, if
+    /// access tracking is enabled, in the access log of the block
+    ///
+    /// Only local keyblocks are tracked, and failing to record doesn't fail the command.
+    pub fn record_access(&self, path: &Path, paths: &[&str]) {
+        if self.dry_run() { return }
+        let keys = paths.iter().map(|key| AuditKey { block: path.display().to_string(), path: key.to_string() });
+        self.accessed.lock().unwrap().extend(keys);
+        if !(self.cli.track_access || self.config.track_access) { return }
+
+        let local_path = match open_store(path).ok().and_then(|store| store.local_path().map(Path::to_path_buf)) {
+            Some(local_path) => local_path,
+            None => return
+        };
+        if let Err(error) = AccessLog::record(&local_path, paths) {
+            warn!("Failed to record the access to {}: {}", paths.join(", "), error);
+        }
+    }
+
+    /// Access log of a keyblock, `None` if nothing was recorded or the block isn't stored locally
+    pub fn access_log(&self, path: &Path) -> Result<Option<AccessLog>, Box<dyn Error>> {
+        match open_store(path)?.local_path() {
+            Some(local_path) => Ok(AccessLog::load(local_path)?),
+            None => Ok(None)
+        }
+    }
+
+    /// Write a serialized keyblock to its store, refusing to overwrite it if it changed since it was read
+    ///
+    /// In dry runs, the changes to the stored block are printed instead.
+    pub fn write_block(&self, path: &Path, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.dry_run() { return self.report_write(path, content) }
+
+        let store = open_store(path)?;
+        let previous = if store.exists()? { Some(store.load(None)?.content) } else { None };
+
+        let expected = self.versions.lock().unwrap().get(path).cloned();
+        let version = store.save(content, expected.as_deref())?;
+        self.versions.lock().unwrap().insert(path.to_path_buf(), version);
+        self.journal.lock().unwrap().push((path.to_path_buf(), previous));
+
+        Ok(())
+    }
+
+    /// Keep the keyblocks written so far even if the command fails afterwards
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn commit(&self) {
+        self.journal.lock().unwrap().clear();
+    }
+
+    /// Restore every keyblock written so far to its previous content, latest first
+    ///
+    /// Blocks changed by somebody else since are left alone.
+    pub fn rollback(&self) {
+        let journal: Vec<_> = self.journal.lock().unwrap().drain(..).rev().collect();
+
+        for (path, previous) in journal {
+            let result = open_store(&path).map_err(Box::<dyn Error>::from).and_then(|store| {
+                let expected = self.versions.lock().unwrap().get(&path).cloned();
+                match (previous, store.local_path()) {
+                    (Some(previous), _) => { store.save(&previous, expected.as_deref())?; },
+                    (None, Some(local_path)) => fs::remove_file(local_path)?,
+                    (None, None) => return Err("remote keyblocks can't be deleted, remove it manually".into())
+                }
+                Ok(())
+            });
+
+            match result {
+                Ok(()) => warn!("Rolled back {}.", path.display()),
+                Err(error) => error!("Failed to roll back {}: {}", path.display(), error)
+            }
+        }
+    }
+
+    /// Print the changes writing `content` to the keyblock at `path` would make
+    fn report_write(&self, path: &Path, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        for line in self.describe_write(path, content)? {
+            println!("{}", line);
+        }
+
+        Ok(())
+    }
+
+    /// Lines describing the changes writing `content` to the keyblock at `path` would make
+    pub fn describe_write(&self, path: &Path, content: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+        let root_key = self.root_key()?;
+        let after = KeyBlock::from_bytes_unverified(content, root_key.clone())?;
+
+        let store = open_store(path)?;
+        let mut lines = Vec::new();
+        let changes = if store.exists()? {
+            let stored = store.load(self.max_memory())?;
+            let args = fluent_args!["size" => content.len(), "path" => path.display().to_string(), "current" => stored.content.len()];
+            lines.push(tr_with("dry-run-write", Some(&args)));
+            diff(&KeyBlock::from_bytes_unverified(&stored.content, root_key)?, &after)
+        } else {
+            let args = fluent_args!["path" => path.display().to_string(), "size" => content.len()];
+            lines.push(tr_with("dry-run-create", Some(&args)));
+            after.keys.values().sorted_by(|a, b| a.path.cmp(&b.path))
+                .map(|key| Change::Added(key.path.clone(), key.length))
+                .collect()
+        };
+
+        lines.extend(changes.iter().map(|change| format!("  {}", change)));
+        if changes.is_empty() { lines.push(format!("  {}", tr("dry-run-unchanged"))) }
+
+        Ok(lines)
+    }
+
+    /// Sign the keyblock stored at `path` after running the pre-sign hook, which is skipped in dry runs
+    pub fn sign_block(&self, path: &Path, block: &mut KeyBlock, key: &SigningKey) -> Result<(), Box<dyn Error>> {
+        if !self.dry_run() {
+            let details = json!({
+                "block": path.display().to_string(),
+                "name": block.name,
+                "uid": format_uid(block.uid),
+                "keys": block.keys.len()
+            });
+            run_hook(&self.config.hooks, Hook::PreSign, details)?;
+        }
+
+        block.sign(key)?;
+
+        // The registry is updated ahead of the write, at worst reserving UIDs that end up unused
+        if let Some((directory, file)) = self.uid_registry(path).filter(|_| !self.dry_run()) {
+            let mut registry = UidRegistry::load(&directory)?;
+            registry.record(&file, block);
+            registry.save(&directory)?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the root private key at `path`, checking it matches the root public key
+    pub fn signing_key(&self, path: &Path, root_key: &RootKey) -> Result<SigningKey, Box<dyn Error>> {
+        let signing_key = load_signing_key(path)?;
+
+        if !signing_key.matches(root_key) {
+            return Err("the signing key doesn't match the root key".into())
+        }
+
+        Ok(signing_key)
+    }
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+///
+/// Questions often name keys, their control characters are escaped.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{} ", tr_with("confirm-prompt", Some(&fluent_args!["question" => escape_controls(question)])));
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(is_yes(&answer))
+}
+
+/// Read a secret line from the terminal without echoing it, or from standard input when it isn't a terminal
+pub fn read_secret(prompt: &str) -> io::Result<Passphrase> {
+    let terminal = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    let mut previous = unsafe { std::mem::zeroed::<libc::termios>() };
+    if terminal {
+        eprint!("{}", prompt);
+        io::stderr().flush()?;
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut previous) } != 0 { return Err(io::Error::last_os_error()) }
+        let mut hidden = previous;
+        hidden.c_lflag &= !libc::ECHO;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden) } != 0 { return Err(io::Error::last_os_error()) }
+    }
+
+    let result = Passphrase::read_line(&mut io::stdin().lock());
+    if terminal {
+        // The echo is restored even if reading failed
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &previous) };
+        eprintln!();
+    }
+    result
+}
+
+/// Check that a key can leave the block, it mustn't be frozen and its policies must allow exports
+///
+/// Returns whether the export must be confirmed first.
+pub fn check_export(key: &KeyFile) -> Result<bool, Box<dyn Error>> {
+    if key.flags & KEYFILE_FROZEN != 0 { return Err(format!("{} is frozen, thaw it first", key.path).into()) }
+
+    let policies = KeyPolicy::from_flags(key.flags);
+    if !KeyPolicy::allows_export(key.flags) {
+        return Err(format!("{} can't be exported ({})", key.path, policies.iter().join(", ")).into())
+    }
+
+    Ok(policies.contains(&KeyPolicy::RequireConfirmation))
+}
+
+/// Decrypted content of a key about to leave the block, after checking that it isn't frozen and its policies
+///
+/// `None` when the export of a require-confirmation key isn't confirmed.
+pub fn exportable_content(key: &KeyFile) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    if check_export(key)? && !confirm(&tr_with("confirm-export", Some(&fluent_args!["path" => key.path.as_str()])))? {
+        return Ok(None)
+    }
+
+    Ok(Some(key.open()?))
+}
+
+/// Decrypted content of a key about to be deployed, after checking that it isn't frozen and its policies
+pub fn deployable_content(key: &KeyFile) -> Result<Vec<u8>, Box<dyn Error>> {
+    if key.flags & KEYFILE_FROZEN != 0 { return Err(format!("{} is frozen, thaw it first", key.path).into()) }
+
+    let policies = KeyPolicy::from_flags(key.flags);
+    if !KeyPolicy::allows_deploy(key.flags) {
+        return Err(format!("{} can't be deployed ({})", key.path, policies.iter().join(", ")).into())
+    }
+    let question = || tr_with("confirm-deploy", Some(&fluent_args!["path" => key.path.as_str()]));
+    if policies.contains(&KeyPolicy::RequireConfirmation) && !confirm(&question())? {
+        return Err(format!("the deployment of {} wasn't confirmed", key.path).into())
+    }
+
+    Ok(key.open()?)
+}
+
+/// Run the subcommand selected on the command line
+pub fn run(cli: &Cli) -> CommandResult {
+    let context = Context {
+        cli,
+        config: Config::load()?,
+        versions: Mutex::new(HashMap::new()),
+        journal: Mutex::new(Vec::new()),
+        accessed: Mutex::new(Vec::new())
+    };
+    init(Language::detect(context.config.language.as_deref()));
+    if cli.lock_memory || context.config.lock_memory { memlock::enable() }
+    if !cli.allow_core_dumps && !context.config.allow_core_dumps {
+        if let Err(error) = harden() { warn!("Failed to disable core dumps: {}.", error) }
+    }
+
+    if let Some(command) = &cli.command {
+        if command.is_mutating() && (cli.read_only || context.config.read_only) {
+            return Err("this subcommand alters keyblocks and banjo is running in read-only mode".into())
+        }
+    }
+
+    let result = match &cli.command {
+        Some(Command::Verify(args)) => verify::run(&context, args),
+        Some(Command::Conformance(args)) => conformance::run(&context, args),
+        Some(Command::List(args)) => list::run(&context, args),
+        Some(Command::Tag(args)) => tag::run(&context, args),
+        Some(Command::Policy(args)) => policy::run(&context, args),
+        Some(Command::SetType(args)) => set_type::run(&context, args),
+        Some(Command::Show(args)) => show::run(&context, args),
+        Some(Command::Extract(args)) => extract::run(&context, args),
+        Some(Command::Add(args)) => add::run(&context, args),
+        Some(Command::AddLogin(args)) => login::add(&context, args),
+        Some(Command::GenCsr(args)) => tls::gen_csr(&context, args),
+        Some(Command::GenCert(args)) => tls::gen_cert(&context, args),
+        Some(Command::Apply(args)) => apply::run(&context, args),
+        Some(Command::Prune(args)) => prune::run(&context, args),
+        Some(Command::SetRotation(args)) => rotation::set(&context, args),
+        Some(Command::RotationDue(args)) => rotation::due(&context, args),
+        Some(Command::CheckExpiry(args)) => expiry::run(&context, args),
+        Some(Command::RotateKey(args)) => rotation::rotate_key(&context, args),
+        Some(Command::History(args)) => history::list(&context, args),
+        Some(Command::RollbackKey(args)) => history::rollback_key(&context, args),
+        Some(Command::Xref(args)) => xref::run(&context, args),
+        Some(Command::Explain(args)) => explain::run(args),
+        Some(Command::SelfUpdate(args)) => update::run(&context, args),
+        Some(Command::UsageReport(args)) => usage::run(&context, args),
+        Some(Command::Ca(command)) => ca::run(&context, command),
+        Some(Command::Freeze(args)) => freeze::run(&context, args, true),
+        Some(Command::Thaw(args)) => freeze::run(&context, args, false),
+        Some(Command::BindHost(args)) => host::bind(&context, args),
+        Some(Command::Facts(args)) => facts::run(&context, args),
+        Some(Command::Manifest(args)) => manifest::run(&context, args),
+        Some(Command::ExportPublic(args)) => public::run(&context, args),
+        Some(Command::Derive(args)) => derive::run(&context, args),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui(args)) => tui::run(&context, args),
+        Some(Command::Migrate(args)) => migrate::run(&context, args),
+        Some(Command::ReissueUid(args)) => reissue::run(&context, args),
+        Some(Command::UidRegistry(args)) => uids::run(&context, args),
+        Some(Command::Bundle(args)) => bundle::create(&context, args),
+        Some(Command::VerifyBundle(args)) => bundle::verify(&context, args),
+        Some(Command::Sync(args)) => sync::run(&context, args),
+        Some(Command::Merge3(args)) => merge::run(&context, args),
+        Some(Command::Backup(args)) => backup::backup(&context, args),
+        Some(Command::RestoreBackup(args)) => backup::restore(&context, args),
+        Some(Command::ExportQr(args)) => paper::export(&context, args),
+        Some(Command::ImportQr(args)) => paper::import(&context, args),
+        Some(Command::Ssh(command)) => ssh::run(&context, command),
+        Some(Command::Wg(command)) => wg::run(&context, command),
+        #[cfg(feature = "acme")]
+        Some(Command::Acme(command)) => acme::run(&context, command),
+        Some(Command::DockerSecret(args)) => docker::run(&context, args),
+        Some(Command::Deploy(args)) => deploy::run(&context, args),
+        Some(Command::ExportAge(args)) => exchange::export(&context, args),
+        Some(Command::ImportAge(args)) => exchange::import(&context, args),
+        #[cfg(feature = "enable_debug")]
+        Some(Command::Debug(command)) => debug::run(&context, command),
+        None => Ok(())
+    };
+
+    // Don't leave some blocks updated when a later step failed
+    if result.is_err() {
+        context.rollback();
+    }
+
+    if let (Some(log), Some(_)) = (&context.config.audit_log, &cli.command) {
+        let keys = context.accessed.lock().unwrap().drain(..).collect();
+        if let Err(error) = AuditEntry::now(&cli.command_name, result.is_ok(), keys).append(log) {
+            warn!("Failed to append to the audit log {}: {}.", log.display(), error);
+        }
+    }
+
+    result
+}
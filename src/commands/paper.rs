@@ -0,0 +1,141 @@
+use crate::commands::{confirm, read_secret, CommandResult, Context};
+use banjo_keyring::cli::{ExportQrArgs, ImportQrArgs};
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::history::keep_version;
+use banjo_keyring::i18n::{fluent_args, tr_with};
+use banjo_keyring::keyblock::{KEYFILE_DERIVED_SECRET, KEYFILE_FROZEN};
+use banjo_keyring::paper::{read_png, render_png, render_terminal, words_to_bytes, PaperPayload, SealedSecret};
+use banjo_keyring::permissions::write_private;
+use banjo_keyring::policy::KeyPolicy;
+use banjo_keyring::secret::Passphrase;
+use banjo_keyring::tags::matches_all;
+use itertools::Itertools;
+use log::{info, warn};
+use std::error::Error;
+use std::fs;
+
+/// Print the block secret, sealed under a passphrase, and small keys as QR codes and word lists
+pub fn export(context: &Context, args: &ExportQrArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let mut payloads = Vec::new();
+
+    if args.key.is_none() {
+        let secret = SealedSecret::seal(&block.secret, &new_passphrase()?)?;
+        payloads.push(("Block secret".to_string(), "secret".to_string(), PaperPayload::Secret(secret)));
+    }
+
+    let mut exported = Vec::new();
+    if !args.secret_only {
+        for key in block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)) {
+            if args.key.as_ref().is_some_and(|path| *path != key.path) { continue }
+            if !matches_all(&args.tag, &key.tags) { continue }
+
+            if key.flags & KEYFILE_FROZEN != 0 {
+                if args.key.is_some() { return Err(format!("{} is frozen, thaw it first", key.path).into()) }
+                warn!("Skipping {}, it is frozen.", key.path);
+                continue
+            }
+
+            let policies = KeyPolicy::from_flags(key.flags);
+            if !KeyPolicy::allows_export(key.flags) {
+                let policies = policies.iter().join(", ");
+                if args.key.is_some() { return Err(format!("{} can't be exported ({})", key.path, policies).into()) }
+                warn!("Skipping {}, it can't be exported ({}).", key.path, policies);
+                continue
+            }
+            let question = || tr_with("confirm-export", Some(&fluent_args!["path" => key.path.as_str()]));
+            if policies.contains(&KeyPolicy::RequireConfirmation) && !confirm(&question())? { continue }
+
+            payloads.push((format!("Key {}", escape_controls(&key.path)), format!("key-{:04x}", key.uid), PaperPayload::Key(key.open()?)));
+            exported.push(key.path.as_str());
+        }
+
+        if let Some(path) = &args.key {
+            if payloads.is_empty() { return Err(format!("no key at path {}", path).into()) }
+        }
+    }
+
+    for (title, file_name, payload) in payloads {
+        let code = match payload.to_qr_code() {
+            Ok(code) => code,
+            Err(error) => {
+                warn!("Skipping {}: {}.", title, error);
+                continue
+            }
+        };
+
+        println!("{}:", title);
+        println!("{}", render_terminal(&code));
+        match payload.to_words() {
+            Some(words) => println!("{}\n", words.join(" ")),
+            None => println!("{}\n", tr_with("paper-no-words", Some(&fluent_args!["size" => payload.to_bytes().len()])))
+        }
+
+        if let Some(directory) = &args.png {
+            let path = directory.join(format!("{}.png", file_name));
+            write_private(&path, &render_png(&code)?)?;
+            info!("Wrote {} to {}.", title, path.display());
+        }
+    }
+
+    context.record_access(&args.block, &exported);
+    Ok(())
+}
+
+/// Replace the block secret or a key content with material read from a QR code or word list
+pub fn import(context: &Context, args: &ImportQrArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let payload = match (&args.words, &args.png) {
+        (Some(words), _) => {
+            let bytes = words_to_bytes(words)?;
+            if args.key.is_some() {
+                PaperPayload::Key(bytes)
+            } else {
+                PaperPayload::Secret(SealedSecret::from_bytes(&bytes)?)
+            }
+        },
+        (None, Some(png)) => PaperPayload::from_qr_text(&read_png(&fs::read(png)?)?)?,
+        (None, None) => unreachable!("clap requires a source")
+    };
+
+    match (payload, &args.key) {
+        (PaperPayload::Secret(sealed), None) => {
+            let passphrase = read_secret("Passphrase of the block secret: ")?;
+            let secret = sealed.open(&passphrase)?.ok_or("wrong passphrase, or the paper backup was altered")?;
+
+            // Derived secrets depend on the block secret, keep the current ones by storing them
+            for key in block.keys.values_mut() {
+                key.flags &= !KEYFILE_DERIVED_SECRET;
+            }
+            block.secret = secret;
+            info!("Restored the secret of keyblock \"{}\".", block.name);
+        },
+        (PaperPayload::Key(content), Some(path)) => {
+            keep_version(&mut block, path, context.config.key_history.unwrap_or(0))?;
+            let key = block.keys.get_mut(path).ok_or_else(|| format!("no key at path {}", path))?;
+            key.seal(&content)?;
+            info!("Restored key {} of keyblock \"{}\".", path, block.name);
+        },
+        (PaperPayload::Secret(_), Some(_)) => return Err("the QR code contains a block secret, not a key".into()),
+        (PaperPayload::Key(_), None) => return Err("the QR code contains a key, use --key to select its path".into())
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    Ok(())
+}
+
+/// Ask for the passphrase sealing the block secret, twice so a typo can't make the backup useless
+fn new_passphrase() -> Result<Passphrase, Box<dyn Error>> {
+    let passphrase = read_secret("Passphrase sealing the block secret: ")?;
+    if passphrase.as_str().is_empty() { return Err("the passphrase can't be empty".into()) }
+    if read_secret("Repeat the passphrase: ")?.as_str() != passphrase.as_str() {
+        return Err("the passphrases don't match".into())
+    }
+
+    Ok(passphrase)
+}
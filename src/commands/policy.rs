@@ -0,0 +1,27 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::PolicyArgs;
+use banjo_keyring::policy::KeyPolicy;
+use itertools::Itertools;
+use log::info;
+
+/// Set and clear usage policies of a key, then re-sign the block
+pub fn run(context: &Context, args: &PolicyArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get_mut(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    for policy in &args.clear {
+        key.flags &= !policy.flag();
+    }
+    for policy in &args.set {
+        key.flags |= policy.flag();
+    }
+    let policies = KeyPolicy::from_flags(key.flags).iter().join(", ");
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    info!("Policies of {}: {}.", args.path, if policies.is_empty() { "none" } else { &policies });
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::access::{today, AccessLog};
+use banjo_keyring::cli::PruneArgs;
+use banjo_keyring::content::ContentType;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::i18n::tr;
+use banjo_keyring::keyblock::KeyFile;
+use banjo_keyring::tags::matches_all;
+use itertools::Itertools;
+use log::info;
+
+/// Remove the keys matching any of the criteria, then re-sign the block
+pub fn run(context: &Context, args: &PruneArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    let access_log = match args.unused_since {
+        Some(_) => Some(context.access_log(&args.block)?.ok_or("no access log for this block, enable --track-access first")?),
+        None => None
+    };
+
+    let pruned: Vec<(String, &'static str)> = block.keys.values()
+        .filter(|key| matches_all(&args.tag, &key.tags))
+        .filter_map(|key| reason(key, args, access_log.as_ref()).map(|reason| (key.path.clone(), reason)))
+        .sorted()
+        .collect();
+    if pruned.is_empty() {
+        info!("No key of keyblock \"{}\" matches the criteria.", block.name);
+        return Ok(())
+    }
+
+    for (path, _) in &pruned {
+        block.keys.remove(path);
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    for (path, reason) in &pruned {
+        println!("- {} ({})", escape_controls(path), tr(reason));
+    }
+    info!("Pruned {} keys from keyblock \"{}\".", pruned.len(), block.name);
+    Ok(())
+}
+
+/// Criterion matched by `key`, if any
+fn reason(key: &KeyFile, args: &PruneArgs, access_log: Option<&AccessLog>) -> Option<&'static str> {
+    // Keys which can't be decrypted aren't known to be expired
+    let expired = || match (ContentType::from_flags(key.flags), key.open()) {
+        (Some(content_type), Ok(content)) => content_type.handler().expired(&content),
+        _ => false
+    };
+    if args.expired && expired() {
+        return Some("prune-expired")
+    }
+    if args.larger_than.is_some_and(|limit| key.length > limit) {
+        return Some("prune-too-large")
+    }
+    if let (Some(days), Some(access_log)) = (args.unused_since, access_log) {
+        if access_log.unused_since(&key.path, today().saturating_sub(days)) { return Some("prune-unused") }
+    }
+
+    None
+}
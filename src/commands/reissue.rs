@@ -0,0 +1,46 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::ReissueUidArgs;
+use banjo_keyring::keyblock::KEYFILE_DERIVED_SECRET;
+use banjo_keyring::utils::format_uid;
+use itertools::Itertools;
+use log::info;
+
+/// Assign fresh UIDs to the block or some keys, then re-sign the block
+///
+/// When the directory of the block keeps a UID registry, the UIDs of the other blocks are avoided too.
+pub fn run(context: &Context, args: &ReissueUidArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let paths: Vec<String> = if args.key.is_empty() {
+        let previous = block.uid;
+        block.uid = block.fresh_block_uid()?.ok_or("every block UID is already in use")?;
+        info!("Block UID {} reissued as {}.", format_uid(previous), format_uid(block.uid));
+
+        // Every key sharing its UID with another one
+        let counts = block.keys.values().counts_by(|key| key.uid);
+        block.keys.values().filter(|key| counts[&key.uid] > 1).map(|key| key.path.clone()).sorted().collect()
+    } else {
+        for path in &args.key {
+            if !block.keys.contains_key(path) { return Err(format!("no key at path {}", path).into()) }
+        }
+        args.key.clone()
+    };
+
+    for path in paths {
+        let uid = block.fresh_key_uid()?.ok_or("every key UID is already in use")?;
+        let key = block.keys.get_mut(&path).expect("The path was checked above.");
+
+        // Derived secrets depend on the UID, keep the current one by storing it
+        key.flags &= !KEYFILE_DERIVED_SECRET;
+
+        info!("UID {} of {} reissued as {}.", format_uid(key.uid), path, format_uid(uid));
+        key.uid = uid;
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::apply::Generator;
+use banjo_keyring::cli::{RotateKeyArgs, RotationDueArgs, SetRotationArgs};
+use banjo_keyring::content::ContentType;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::history::{keep_version, version_path};
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::i18n::header;
+use banjo_keyring::notify::{notify, Event};
+use banjo_keyring::rotation::{describe, today, Rotation};
+use itertools::Itertools;
+use log::{info, warn};
+use serde_json::json;
+use std::iter;
+
+/// Update the rotation schedule of a key, then re-sign the block
+pub fn set(context: &Context, args: &SetRotationArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get_mut(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let today = today();
+    let current = Rotation::from_flags(key.flags);
+
+    let state = if args.clear {
+        key.flags = Rotation::clear(key.flags);
+        "no rotation schedule".to_string()
+    } else {
+        let interval = args.every.or(current.map(|rotation| rotation.interval))
+            .ok_or_else(|| format!("{} has no rotation schedule, set one with --every", args.path))?;
+        let last_rotated = match current {
+            Some(rotation) if !args.rotated => rotation.last_rotated,
+            _ => today
+        };
+
+        let rotation = Rotation { interval, last_rotated };
+        key.flags = rotation.apply(key.flags);
+        format!("rotated every {} days, {}", interval, describe(rotation, today))
+    };
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    info!("Rotation of {}: {}.", args.path, state);
+    Ok(())
+}
+
+/// Print the keys of a block due for rotation, optionally notifying about each of them
+pub fn due(context: &Context, args: &RotationDueArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let today = today();
+
+    let due: Vec<(&str, Rotation)> = block.keys.values()
+        .filter_map(|key| Rotation::from_flags(key.flags).map(|rotation| (key.path.as_str(), rotation)))
+        .filter(|(_, rotation)| rotation.days_left(today) <= args.within as i64)
+        .sorted_by_key(|(path, rotation)| (rotation.due(), *path))
+        .collect();
+    if due.is_empty() {
+        info!("No key of keyblock \"{}\" is due for rotation.", block.name);
+        return Ok(())
+    }
+
+    let rows: Vec<[String; 3]> = due.iter()
+        .map(|(path, rotation)| [escape_controls(path), format!("{} days", rotation.interval), describe(*rotation, today)])
+        .collect();
+    let header = [header("PATH"), header("INTERVAL"), header("STATE")];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in iter::once(&header).chain(&rows) {
+        let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    if args.notify {
+        for (path, rotation) in &due {
+            let event = Event::RotationDue { block: &args.block, key: path, days_left: rotation.days_left(today) };
+            if let Err(error) = notify(&context.config.notify, &event) {
+                warn!("Failed to send the notification: {}.", error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate new content for a key, shifting its previous versions, then run the post-rotate hook
+pub fn rotate_key(context: &Context, args: &RotateKeyArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let generator = Generator::for_key(key, &key.open()?).ok_or_else(|| {
+        let content_type = ContentType::from_flags(key.flags).map_or("unknown".to_string(), |type_| type_.to_string());
+        format!("{} keys can't be generated, {} can't be rotated", content_type, args.path)
+    })?;
+    let keep = args.keep.or(context.config.key_history).unwrap_or(1);
+    keep_version(&mut block, &args.path, keep)?;
+
+    let key = block.keys.get_mut(&args.path).expect("The key was found above.");
+    key.seal(&generator.generate()?)?;
+    if let Some(rotation) = Rotation::from_flags(key.flags) {
+        key.flags = Rotation { last_rotated: today(), ..rotation }.apply(key.flags);
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    let details = json!({
+        "block": args.block.display().to_string(),
+        "key": args.path,
+        "previous": if keep > 0 { version_path(&args.path, 1) } else { String::new() }
+    });
+    run_hook(&context.config.hooks, Hook::PostRotate, details)?;
+
+    info!("Rotated {}, keeping up to {} previous versions.", args.path, keep);
+    Ok(())
+}
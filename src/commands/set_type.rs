@@ -0,0 +1,24 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::SetTypeArgs;
+use log::info;
+
+/// Validate the content of a key against a content type and record the type, then re-sign the block
+pub fn run(context: &Context, args: &SetTypeArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get_mut(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let content = key.open()?;
+    let handler = args.content_type.handler();
+    handler.validate(&content)
+        .map_err(|error| format!("{} isn't a valid {} key: {}", args.path, args.content_type, error))?;
+    key.flags = args.content_type.apply(key.flags);
+    let description = handler.describe(&content);
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    info!("{} is now a {} key ({}).", args.path, args.content_type, description);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::commands::{exportable_content, CommandResult, Context};
+use banjo_keyring::cli::ShowArgs;
+use banjo_keyring::content::ContentType;
+use banjo_keyring::login::LoginEntry;
+use log::info;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Print the decrypted content of a key or a field of a login entry, or copy it to the clipboard
+pub fn run(context: &Context, args: &ShowArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    let content = match exportable_content(key)? {
+        Some(content) => content,
+        None => return Ok(())
+    };
+
+    let content = match args.field {
+        Some(field) => {
+            if ContentType::from_flags(key.flags) != Some(ContentType::Login) {
+                return Err(format!("{} isn't a login entry", args.path).into())
+            }
+            let entry = LoginEntry::from_bytes(&content).map_err(|error| format!("{}: {}", args.path, error))?;
+            let value = entry.field(field).ok_or_else(|| format!("{} has no {}", args.path, field))?;
+            value.as_bytes().to_vec()
+        },
+        None => content
+    };
+
+    if !args.clipboard {
+        let mut stdout = io::stdout();
+        stdout.write_all(&content)?;
+        // Fields don't end with a newline, the prompt would follow the value
+        if args.field.is_some() { writeln!(stdout)? }
+        context.record_access(&args.block, &[&args.path]);
+        return Ok(())
+    }
+
+    copy_to_clipboard(&content)?;
+    context.record_access(&args.block, &[&args.path]);
+    info!("Copied {} to the clipboard.", args.path);
+    Ok(())
+}
+
+/// Pipe `content` to the clipboard tool of the session, so it never appears in process arguments
+fn copy_to_clipboard(content: &[u8]) -> CommandResult {
+    let (program, arguments): (&str, &[&str]) = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &[])
+    } else if env::var_os("DISPLAY").is_some() {
+        ("xclip", &["-selection", "clipboard"])
+    } else if cfg!(target_os = "macos") {
+        ("pbcopy", &[])
+    } else {
+        return Err("no clipboard is available in this session".into())
+    };
+
+    let mut child = Command::new(program).args(arguments).stdin(Stdio::piped()).spawn()
+        .map_err(|error| format!("failed to run {}: {}", program, error))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() { return Err(format!("{} failed ({})", program, status).into()) }
+    Ok(())
+}
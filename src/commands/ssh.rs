@@ -0,0 +1,103 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::{SshArgs, SshAuthorizeArgs, SshCommand, SshKnownHostsArgs};
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::install::{ensure_directory, install, Account};
+use banjo_keyring::keyblock::KeyFile;
+use banjo_keyring::ssh::{SshPublicKey, AUTHORIZED_TAG_PREFIX, HOST_TAG_PREFIX};
+use itertools::Itertools;
+use log::info;
+use serde_json::json;
+use std::error::Error;
+
+pub fn run(context: &Context, command: &SshCommand) -> CommandResult {
+    match command {
+        SshCommand::Fingerprint(args) | SshCommand::Pubkey(args) => print(context, command, args),
+        SshCommand::Authorize(args) => authorize(context, args),
+        SshCommand::KnownHosts(args) => known_hosts(context, args)
+    }
+}
+
+/// Print the public key or fingerprint of an SSH key, computed without writing the private key anywhere
+fn print(context: &Context, command: &SshCommand, args: &SshArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    let key = block.keys.get(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+
+    let public_key = public_key_of(key)?;
+    match command {
+        SshCommand::Pubkey(_) => println!("{}", public_key.to_line(&key.name).map_err(|error| format!("{}: {}", key.path, error))?),
+        _ => println!("{} {} ({})", public_key.fingerprint(), args.path, public_key.algorithm)
+    }
+
+    Ok(())
+}
+
+/// Install the authorized_keys file of a user, owned by the user and only writable by them
+fn authorize(context: &Context, args: &SshAuthorizeArgs) -> CommandResult {
+    let account = Account::lookup(&args.user)?;
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    context.check_host(&block)?;
+
+    let tag = format!("{}{}", AUTHORIZED_TAG_PREFIX, args.user);
+    let mut lines = Vec::new();
+    for key in block.keys.values().filter(|key| key.tags.contains(&tag)).sorted_by(|a, b| a.path.cmp(&b.path)) {
+        lines.push(public_key_of(key)?.to_line(&key.name).map_err(|error| format!("{}: {}", key.path, error))?);
+    }
+    // An empty file would lock the user out, most likely because of a typo
+    if lines.is_empty() { return Err(format!("no key of keyblock \"{}\" is tagged {}", block.name, tag).into()) }
+
+    let path = match &args.output {
+        Some(output) => output.clone(),
+        None => {
+            let directory = account.home.join(".ssh");
+            ensure_directory(&directory, 0o700, Some(&account))?;
+            directory.join("authorized_keys")
+        }
+    };
+    let details = json!({
+        "block": args.block.display().to_string(),
+        "user": account.name,
+        "path": path.display().to_string()
+    });
+    run_hook(&context.config.hooks, Hook::PreDeploy, details.clone())?;
+    install(&path, (lines.join("\n") + "\n").as_bytes(), 0o600, Some(&account))?;
+    run_hook(&context.config.hooks, Hook::PostDeploy, details)?;
+
+    info!("Installed {} keys for {} to {}.", lines.len(), account.name, path.display());
+    Ok(())
+}
+
+/// Print or install a known_hosts file, each host key being trusted for the patterns it is tagged with
+fn known_hosts(context: &Context, args: &SshKnownHostsArgs) -> CommandResult {
+    let block = context.load_block(&args.block, context.root_key()?)?;
+
+    let mut lines = Vec::new();
+    for key in block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)) {
+        let hosts: Vec<&str> = key.tags.iter()
+            .filter_map(|tag| tag.strip_prefix(HOST_TAG_PREFIX))
+            .filter(|host| !host.is_empty())
+            .collect();
+        if hosts.is_empty() { continue }
+
+        lines.push(format!("{} {}", hosts.join(","), public_key_of(key)?.to_line("")?));
+    }
+    let content = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+
+    match &args.output {
+        Some(output) => {
+            context.check_host(&block)?;
+            let details = json!({ "block": args.block.display().to_string(), "path": output.display().to_string() });
+            run_hook(&context.config.hooks, Hook::PreDeploy, details.clone())?;
+            install(output, content.as_bytes(), 0o644, None)?;
+            run_hook(&context.config.hooks, Hook::PostDeploy, details)?;
+            info!("Installed {} host keys to {}.", lines.len(), output.display());
+        },
+        None => print!("{}", content)
+    }
+
+    Ok(())
+}
+
+/// Public key of a key holding an SSH public or private key
+fn public_key_of(key: &KeyFile) -> Result<SshPublicKey, Box<dyn Error>> {
+    SshPublicKey::from_content(&key.open()?).map_err(|error| format!("{}: {}", key.path, error).into())
+}
@@ -0,0 +1,127 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::SyncArgs;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::format::{has_block_header, BLOCK_EXTENSION};
+use banjo_keyring::git::Repository;
+use banjo_keyring::i18n::{fluent_args, tr_with};
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::utils::{format_uid, to_hex};
+use log::{error, info, warn};
+use openssl::sha::sha256;
+use std::fs;
+use std::path::Path;
+
+/// Commit local keyblock changes, pull and verify remote changes, then push
+pub fn run(context: &Context, args: &SyncArgs) -> CommandResult {
+    // They are given to git, which would take them as options
+    if args.remote.starts_with('-') { return Err(format!("invalid remote name {:?}", args.remote).into()) }
+    if let Some(branch) = args.branch.as_ref().filter(|branch| branch.starts_with('-')) {
+        return Err(format!("invalid branch name {:?}", branch).into())
+    }
+
+    let root_key = context.root_key()?;
+    let repository = Repository::open(&args.directory)?;
+
+    // Commit local changes, refusing to record invalid blocks. Removed files are recognized by their last committed content
+    let mut changed = Vec::new();
+    for path in repository.changed_files()? {
+        let content = match fs::read(repository.path.join(&path)) {
+            Ok(content) => Some(content),
+            Err(_) => repository.show("HEAD", &path)?
+        };
+        if is_block(&path, content.as_deref()) { changed.push(path) }
+    }
+    if !changed.is_empty() {
+        let mut body = Vec::new();
+
+        for path in &changed {
+            let full_path = repository.path.join(path);
+            if !full_path.exists() {
+                body.push(format!("Removed: {}", path));
+                continue
+            }
+
+            let block = context.load_block(&full_path, root_key.clone())
+                .map_err(|error| format!("{}: {}, not committing local changes", path, error))?;
+            body.push(format!(
+                "Updated: {} \"{}\" (uid {}, {} keys, sha256 {})", path, escape_controls(&block.name), format_uid(block.uid),
+                block.keys.len(), to_hex(&sha256(&fs::read(&full_path)?))
+            ));
+        }
+
+        let subject = args.message.clone().unwrap_or_else(|| format!("banjo: update {} keyblocks", changed.len()));
+        let body = body.join("\n");
+        if context.dry_run() {
+            println!("{}\n{}", tr_with("dry-run-commit", Some(&fluent_args!["subject" => subject.as_str()])), body);
+        } else {
+            let mut add = vec!["add", "--all", "--"];
+            add.extend(changed.iter().map(String::as_str));
+            repository.run(&add)?;
+
+            let mut commit = vec!["commit", "--quiet", "-m", &subject, "-m", &body, "--"];
+            commit.extend(changed.iter().map(String::as_str));
+            repository.run(&commit)?;
+            info!("Committed {} changed keyblocks.", changed.len());
+        }
+    }
+
+    if context.dry_run() {
+        let message = if args.no_push { "dry-run-pull" } else { "dry-run-pull-push" };
+        println!("{}", tr_with(message, Some(&fluent_args!["remote" => args.remote.as_str()])));
+        return Ok(())
+    }
+
+    let branch = match &args.branch {
+        Some(branch) => branch.clone(),
+        None => repository.run(&["rev-parse", "--abbrev-ref", "HEAD"])?.trim().to_string()
+    };
+    let upstream = format!("{}/{}", args.remote, branch);
+
+    repository.run(&["fetch", "--quiet", "--", &args.remote])?;
+
+    // Verify every incoming block before merging anything
+    match repository.resolve(&upstream) {
+        Ok(upstream_commit) => {
+            let base = repository.run(&["merge-base", "HEAD", &upstream_commit])?.trim().to_string();
+            let mut incoming = Vec::new();
+            for path in repository.diff_files(&base, &upstream_commit)? {
+                // Removed blocks have nothing to verify
+                if let Some(content) = repository.show(&upstream_commit, &path)? {
+                    if is_block(&path, Some(&content)) { incoming.push((path, content)) }
+                }
+            }
+
+            let mut failures = 0;
+            for (path, content) in &incoming {
+
+                if let Err(verify_error) = KeyBlock::from_bytes(content, root_key.clone()) {
+                    error!("{} on {}: {}", path, upstream, verify_error);
+                    failures += 1;
+                }
+            }
+            if failures > 0 {
+                return Err(format!("refusing to merge {}, {} keyblocks failed verification", upstream, failures).into())
+            }
+
+            if let Err(merge_error) = repository.run(&["merge", "--quiet", "--no-edit", &upstream_commit]) {
+                let _ = repository.run(&["merge", "--abort"]);
+                return Err(format!("failed to merge {}, resolve diverging keyblocks with `banjo merge3`: {}", upstream, merge_error).into())
+            }
+            info!("Merged {} verified keyblock changes from {}.", incoming.len(), upstream);
+        },
+        Err(_) => warn!("{} doesn't exist yet, nothing to merge.", upstream)
+    }
+
+    if !args.no_push {
+        repository.run(&["push", "--quiet", "--", &args.remote, &format!("HEAD:{}", branch)])?;
+        info!("Pushed to {}.", upstream);
+    }
+
+    Ok(())
+}
+
+/// Whether a file of the repository is a keyblock, by its extension or, when known, the header of its content
+fn is_block(path: &str, content: Option<&[u8]>) -> bool {
+    let has_extension = Path::new(path).extension().is_some_and(|extension| extension == BLOCK_EXTENSION);
+    has_extension || content.is_some_and(has_block_header)
+}
@@ -0,0 +1,28 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::TagArgs;
+use banjo_keyring::tags::validate_tag;
+use log::info;
+
+/// Add and remove tags of a key, then re-sign the block
+pub fn run(context: &Context, args: &TagArgs) -> CommandResult {
+    for tag in &args.add {
+        validate_tag(tag)?;
+    }
+
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key = block.keys.get_mut(&args.path).ok_or_else(|| format!("no key at path {}", args.path))?;
+    key.tags.retain(|tag| !args.remove.contains(tag));
+    for tag in &args.add {
+        if !key.tags.contains(tag) { key.tags.push(tag.clone()) }
+    }
+    let tags = key.tags.join(", ");
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+
+    info!("Tags of {}: {}.", args.path, if tags.is_empty() { "none" } else { &tags });
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::commands::ca::store_registry;
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::ca::CertificateAuthority;
+use banjo_keyring::cli::{GenCertArgs, GenCsrArgs};
+use banjo_keyring::content::ContentType;
+use banjo_keyring::permissions::write_private;
+use banjo_keyring::tls::{certificate_request, issue_certificate, random_serial, subject_name, Issuer};
+use log::info;
+use std::io::{self, Write};
+
+/// Generate a private key into the block and output its certificate signing request
+pub fn gen_csr(context: &Context, args: &GenCsrArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    if block.keys.contains_key(&args.path) { return Err(format!("there already is a key at path {}", args.path).into()) }
+
+    let private_key = args.algorithm.generate()?;
+    let request = certificate_request(&private_key, &args.cn, &args.san)?;
+
+    let description = format!("TLS private key for {}", args.cn);
+    let mut key = block.new_key(&args.path, &args.cn, &description, &private_key.private_key_to_pem_pkcs8()?)?
+        .ok_or("no keyfile UID is left in this block")?;
+    key.flags = ContentType::SshKey.apply(key.flags);
+    block.keys.insert(args.path.clone(), key);
+
+    // The key is only stored once the block is saved, the request is useless before that
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    let pem = request.to_pem()?;
+    match &args.out {
+        Some(out) => write_private(out, &pem)?,
+        None => io::stdout().write_all(&pem)?
+    }
+
+    info!("Generated the key {} for {}.", args.path, args.cn);
+    Ok(())
+}
+
+/// Generate a private key and its certificate into the block, self-signed or signed by a CA of the block
+pub fn gen_cert(context: &Context, args: &GenCertArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+
+    let key_path = format!("{}.key", args.path);
+    let certificate_path = format!("{}.crt", args.path);
+    for path in [&key_path, &certificate_path].iter() {
+        if block.keys.contains_key(*path) { return Err(format!("there already is a key at path {}", path).into()) }
+    }
+
+    let private_key = args.algorithm.generate()?;
+    let names = if args.san.is_empty() { vec![args.cn.clone()] } else { args.san.clone() };
+    let subject = subject_name(&args.cn)?;
+    let certificate = match &args.ca {
+        Some(ca) => {
+            let mut ca = CertificateAuthority::load(&block, ca)?;
+            let certificate = ca.issue(&private_key, &subject, &names, args.days)?;
+            store_registry(&mut block, &ca)?;
+            certificate
+        },
+        None => {
+            let issuer = Issuer { key: &private_key, certificate: None };
+            let serial = random_serial()?;
+            issue_certificate(&private_key, &subject, &names, args.days, args.is_ca, &serial, &issuer)?
+        }
+    };
+    let pem = certificate.to_pem()?;
+
+    let keys = vec![
+        (key_path, format!("TLS private key for {}", args.cn), private_key.private_key_to_pem_pkcs8()?, ContentType::SshKey),
+        (certificate_path, format!("TLS certificate for {}", args.cn), pem.clone(), ContentType::X509)
+    ];
+    for (path, description, content, content_type) in keys {
+        let mut key = block.new_key(&path, &args.cn, &description, &content)?
+            .ok_or("no keyfile UID is left in this block")?;
+        key.flags = content_type.apply(key.flags);
+        block.keys.insert(path, key);
+    }
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+
+    if let Some(out) = &args.out {
+        write_private(out, &pem)?;
+    }
+
+    info!("Generated {}.key and {}.crt for {}, valid for {} days.", args.path, args.path, args.cn, args.days);
+    Ok(())
+}
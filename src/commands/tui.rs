@@ -0,0 +1,374 @@
+use crate::commands::{check_export, CommandResult, Context};
+use banjo_keyring::cli::TuiArgs;
+use banjo_keyring::content::ContentType;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::keyblock::{KeyBlock, KeyFile};
+use banjo_keyring::permissions::write_private;
+use banjo_keyring::rootkey::SigningKey;
+use banjo_keyring::utils::format_uid;
+use itertools::Itertools;
+use openssl::base64::encode_block;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// What keyboard input currently edits
+enum Mode {
+    Browse,
+    Search,
+    EditDescription(String),
+    /// Waiting for the user to confirm the export of a require-confirmation key
+    ConfirmExport(Export),
+    /// Typing the file the selected key is extracted to
+    ExtractPath(String)
+}
+
+/// Ways the content of a key leaves the block
+#[derive(Clone, Copy)]
+enum Export {
+    /// Written to a private file
+    Extract,
+    /// Copied to the clipboard of the terminal with an OSC 52 escape sequence
+    Clipboard
+}
+
+/// State of the browser
+struct Browser<'a> {
+    context: &'a Context<'a>,
+    block: KeyBlock,
+    block_path: &'a Path,
+    signing_key: Option<SigningKey>,
+    search: String,
+    selection: ListState,
+    mode: Mode,
+    status: String,
+    /// Changes a dry run write would make, shown until the next key press
+    preview: Vec<String>,
+    /// The block was edited since it was last written
+    modified: bool,
+    /// Quitting was requested once with unsaved changes
+    quit_requested: bool
+}
+
+/// Browse a keyblock in an interactive terminal interface
+pub fn run<'a>(context: &'a Context<'a>, args: &'a TuiArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let signing_key = match &args.signing_key {
+        Some(path) => Some(context.signing_key(path, &root_key)?),
+        None => None
+    };
+    let block = context.load_block(&args.block, root_key)?;
+
+    let mut browser = Browser {
+        context,
+        block,
+        block_path: &args.block,
+        signing_key,
+        search: String::new(),
+        selection: ListState::default().with_selected(Some(0)),
+        mode: Mode::Browse,
+        status: "q: quit  /: search  e: edit description  x: extract  c: copy  w: write".to_string(),
+        preview: Vec::new(),
+        modified: false,
+        quit_requested: false
+    };
+
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+impl Browser<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> CommandResult {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue
+            };
+
+            match &mut self.mode {
+                Mode::Browse => {
+                    if key.code != KeyCode::Char('q') { self.quit_requested = false }
+                    self.preview.clear();
+
+                    match key.code {
+                        KeyCode::Char('q') if !self.modified || self.quit_requested => return Ok(()),
+                        KeyCode::Char('q') => {
+                            self.quit_requested = true;
+                            self.status = "Unsaved changes, press q again to discard them or w to write them.".to_string();
+                        },
+                        KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                        KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                        KeyCode::Char('/') => self.mode = Mode::Search,
+                        KeyCode::Char('e') => self.start_edit(),
+                        KeyCode::Char('x') => self.start_export(Export::Extract),
+                        KeyCode::Char('c') => self.start_export(Export::Clipboard),
+                        KeyCode::Char('w') => self.write(),
+                        _ => {}
+                    }
+                },
+                Mode::Search => match key.code {
+                    KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => { self.search.pop(); self.selection.select(Some(0)); },
+                    KeyCode::Char(character) => { self.search.push(character); self.selection.select(Some(0)); },
+                    _ => {}
+                },
+                Mode::EditDescription(description) => match key.code {
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => { description.pop(); },
+                    KeyCode::Char(character) => description.push(character),
+                    KeyCode::Enter => {
+                        let description = description.clone();
+                        self.apply_edit(description);
+                    },
+                    _ => {}
+                },
+                Mode::ConfirmExport(export) => match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        let export = *export;
+                        self.export(export);
+                    },
+                    _ => {
+                        self.mode = Mode::Browse;
+                        self.status = "Export cancelled.".to_string();
+                    }
+                },
+                Mode::ExtractPath(path) => match key.code {
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => { path.pop(); },
+                    KeyCode::Char(character) => path.push(character),
+                    KeyCode::Enter => {
+                        let path = path.clone();
+                        self.extract(&path);
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Paths of the keys matching the search, sorted
+    fn visible_paths(&self) -> Vec<String> {
+        self.block.keys.values()
+            .filter(|key| {
+                key.path.contains(&self.search) || key.name.contains(&self.search)
+                    || key.tags.iter().any(|tag| tag.contains(&self.search))
+            })
+            .map(|key| key.path.clone())
+            .sorted()
+            .collect()
+    }
+
+    fn selected_key(&self) -> Option<&KeyFile> {
+        let paths = self.visible_paths();
+        self.selection.selected().and_then(|index| paths.get(index)).and_then(|path| self.block.keys.get(path))
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let count = self.visible_paths().len();
+        if count == 0 { return }
+
+        let current = self.selection.selected().unwrap_or(0) as isize;
+        self.selection.select(Some((current + offset).clamp(0, count as isize - 1) as usize));
+    }
+
+    fn start_edit(&mut self) {
+        if self.signing_key.is_none() {
+            self.status = "Read-only: pass --signing-key to edit the block.".to_string();
+            return
+        }
+
+        if let Some(description) = self.selected_key().map(|key| key.description.clone()) {
+            self.mode = Mode::EditDescription(description);
+        }
+    }
+
+    fn apply_edit(&mut self, description: String) {
+        let path = match self.selected_key() {
+            Some(key) => key.path.clone(),
+            None => return
+        };
+
+        if let Some(key) = self.block.keys.get_mut(&path) {
+            key.description = description;
+            self.modified = true;
+            self.status = format!("Edited the description of {}, press w to write the block.", path);
+        }
+        self.mode = Mode::Browse;
+    }
+
+    /// Check that the selected key can be exported, asking for confirmation when its policies require it
+    fn start_export(&mut self, export: Export) {
+        let needs_confirmation = match self.selected_key().map(check_export) {
+            Some(Ok(needs_confirmation)) => needs_confirmation,
+            Some(Err(error)) => {
+                self.status = error.to_string();
+                return
+            },
+            None => return
+        };
+
+        if needs_confirmation {
+            self.mode = Mode::ConfirmExport(export);
+            self.status = "This key requires confirmation, press y to export it.".to_string();
+        } else {
+            self.export(export);
+        }
+    }
+
+    fn export(&mut self, export: Export) {
+        match export {
+            Export::Extract => {
+                // Suggest the last component of the key path, in the current directory
+                let name = self.selected_key().and_then(|key| key.path.rsplit('/').next()).unwrap_or_default().to_string();
+                self.mode = Mode::ExtractPath(name);
+                self.status = "Enter: write the key to this file  Esc: cancel".to_string();
+            },
+            Export::Clipboard => {
+                self.mode = Mode::Browse;
+                self.status = match self.copy() {
+                    Ok(path) => format!("Copied {} to the clipboard.", escape_controls(&path)),
+                    Err(error) => format!("Failed to copy the key: {}", error)
+                };
+            }
+        }
+    }
+
+    /// Send the content of the selected key to the terminal clipboard
+    fn copy(&self) -> Result<String, Box<dyn Error>> {
+        let key = self.selected_key().ok_or("no key is selected")?;
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b]52;c;{}\x07", encode_block(&key.open()?).replace('\n', ""))?;
+        stdout.flush()?;
+
+        Ok(key.path.clone())
+    }
+
+    fn extract(&mut self, path: &str) {
+        self.mode = Mode::Browse;
+        self.status = self.extract_to(path).unwrap_or_else(|error| format!("Failed to extract the key: {}", error));
+    }
+
+    /// Write the content of the selected key to a new private file
+    fn extract_to(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let key = self.selected_key().ok_or("no key is selected")?;
+        if path.is_empty() { return Err("no file was given".into()) }
+        if Path::new(path).exists() { return Err(format!("{} already exists", escape_controls(path)).into()) }
+
+        let content = key.open()?;
+        if self.context.dry_run() {
+            return Ok(format!("Dry run, would write {} bytes to {}.", content.len(), escape_controls(path)))
+        }
+
+        write_private(Path::new(path), &content)?;
+        Ok(format!("Wrote {} to {}.", escape_controls(&key.path), escape_controls(path)))
+    }
+
+    fn write(&mut self) {
+        let signing_key = match &self.signing_key {
+            Some(key) => key,
+            None => {
+                self.status = "Read-only: pass --signing-key to edit the block.".to_string();
+                return
+            }
+        };
+
+        let content = self.context.sign_block(self.block_path, &mut self.block, signing_key)
+            .and_then(|_| self.block.serialize().map_err(Into::into));
+
+        // Dry runs would print the changes over the interface, they are shown in place of the metadata instead
+        if self.context.dry_run() {
+            self.status = match content.and_then(|content| self.context.describe_write(self.block_path, &content)) {
+                Ok(lines) => {
+                    self.preview = lines;
+                    "Dry run, nothing was written.".to_string()
+                },
+                Err(error) => format!("Failed to write the block: {}", error)
+            };
+            return
+        }
+
+        let result = content.and_then(|content| self.context.write_block(self.block_path, &content));
+        self.status = match result {
+            Ok(()) => {
+                // Every save is confirmed by the user, later failures mustn't undo it
+                self.context.commit();
+                self.modified = false;
+                format!("Signed and wrote {}.", self.block_path.display())
+            },
+            Err(error) => format!("Failed to write the block: {}", error)
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, search, status] = Layout::vertical([
+            Constraint::Min(1), Constraint::Length(1), Constraint::Length(1)
+        ]).areas(frame.area());
+        let [list_area, details_area] = Layout::horizontal([
+            Constraint::Percentage(40), Constraint::Percentage(60)
+        ]).areas(main);
+
+        let items: Vec<ListItem> = self.visible_paths().iter().map(|path| ListItem::new(escape_controls(path))).collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(" {} ", escape_controls(&self.block.name))))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.selection);
+
+        let (title, details) = match self.selected_key() {
+            _ if !self.preview.is_empty() => (" Dry run ", self.preview.iter().map(|line| Line::from(escape_controls(line))).collect()),
+            Some(key) => {
+                let description = match &self.mode {
+                    Mode::EditDescription(description) => format!("{}_", description),
+                    _ => key.description.clone()
+                };
+
+                (" Metadata ", vec![
+                    Line::from(format!("UID:         {}", format_uid(key.uid))),
+                    Line::from(format!("ID:          {}", key.id)),
+                    Line::from(format!("Path:        {}", escape_controls(&key.path))),
+                    Line::from(format!("Name:        {}", escape_controls(&key.name))),
+                    Line::from(format!("Description: {}", escape_controls(&description))),
+                    Line::from(format!("Type:        {}", match (ContentType::from_flags(key.flags), key.open()) {
+                        (Some(content_type), Ok(content)) => {
+                            format!("{} ({})", content_type, escape_controls(&content_type.handler().describe(&content)))
+                        },
+                        (Some(content_type), Err(_)) => format!("{} (can't be decrypted)", content_type),
+                        (None, _) => "unknown".to_string()
+                    })),
+                    Line::from(format!("Size:        {} bytes", key.length)),
+                    Line::from(format!("Tags:        {}", escape_controls(&key.tags.join(", ")))),
+                    Line::from(format!("Flags:       {:#x}", key.flags)),
+                    Line::from(format!("HMAC:        {}", match self.block.content_digest().and_then(|digest| digest.of(key)) {
+                        Ok(digest) => digest,
+                        Err(error) => format!("unavailable: {}", error)
+                    }))
+                ])
+            },
+            None => (" Metadata ", vec![Line::from("No matching key.")])
+        };
+        frame.render_widget(
+            Paragraph::new(details).wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(title)),
+            details_area
+        );
+
+        let search_line = match &self.mode {
+            Mode::Search => format!("/{}_", self.search),
+            Mode::ExtractPath(path) => format!("Extract to: {}_", escape_controls(path)),
+            _ if !self.search.is_empty() => format!("/{}", self.search),
+            _ => String::new()
+        };
+        frame.render_widget(Paragraph::new(search_line), search);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::commands::verify::list_blocks;
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::UidRegistryArgs;
+use banjo_keyring::i18n::header;
+use banjo_keyring::uids::UidRegistry;
+use banjo_keyring::utils::format_uid;
+use itertools::Itertools;
+use log::{info, warn};
+use std::iter;
+
+/// Rebuild the UID registry of a directory of keyblocks and report the UIDs used more than once
+pub fn run(context: &Context, args: &UidRegistryArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+
+    let mut blocks = Vec::new();
+    for path in list_blocks(&args.directory)? {
+        let file = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file) => file.to_string(),
+            None => continue
+        };
+        match context.load_block(&path, root_key.clone()) {
+            Ok(block) => blocks.push((file, block)),
+            Err(error) => warn!("Skipping {}: {}.", path.display(), error)
+        }
+    }
+
+    let registry = UidRegistry::scan(blocks.iter().map(|(file, block)| (file.as_str(), block)));
+    if !context.dry_run() {
+        registry.save(&args.directory)?;
+    }
+
+    let collisions = registry.collisions();
+    if !collisions.is_empty() {
+        let rows: Vec<[String; 2]> = collisions.iter()
+            .map(|(uid, allocations)| [format_uid(*uid), allocations.iter().join(", ")])
+            .collect();
+        let header = [header("UID"), header("USED BY")];
+        let width = rows.iter().chain(iter::once(&header)).map(|row| row[0].chars().count()).max().unwrap_or(0);
+
+        for row in iter::once(&header).chain(&rows) {
+            println!("{:width$}  {}", row[0], row[1], width = width);
+        }
+
+        return Err(format!("{} UIDs are used more than once, see reissue-uid", collisions.len()).into())
+    }
+
+    info!("Registered {} UIDs of {} keyblocks, none is used twice.", registry.allocations.len(), blocks.len());
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::SelfUpdateArgs;
+use banjo_keyring::update::{Release, UpdateErrors, RELEASE_URL};
+use log::info;
+use std::{env, fs};
+
+/// Replace the running binary with the latest release, once its signature is verified
+pub fn run(context: &Context, args: &SelfUpdateArgs) -> CommandResult {
+    let base_url = args.url.as_deref()
+        .or(context.config.update_url.as_deref())
+        .or(RELEASE_URL)
+        .ok_or(UpdateErrors::NoReleaseUrl)?;
+    let release = Release::fetch(base_url)?;
+
+    let executable = env::current_exe()?;
+    if fs::read(&executable)? == release.binary {
+        info!("banjo is already up to date.");
+        return Ok(())
+    }
+    if args.check || context.dry_run() {
+        info!("{} is a verified release differing from {}.", release.url, executable.display());
+        return Ok(())
+    }
+
+    release.install(&executable)?;
+    info!("Replaced {} with the verified release {}.", executable.display(), release.url);
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::audit::{read_log, UsageReport};
+use banjo_keyring::cli::UsageReportArgs;
+use banjo_keyring::display::escape_controls;
+use banjo_keyring::i18n::header;
+use itertools::Itertools;
+use log::info;
+use std::iter;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Print the subcommands run and the keys handed out most over a time window, from the audit log
+pub fn run(context: &Context, args: &UsageReportArgs) -> CommandResult {
+    let log = context.config.audit_log.as_ref().ok_or("no audit log is configured, set audit-log")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let report = UsageReport::build(&read_log(log)?, now.saturating_sub(u64::from(args.since) * 86400));
+
+    let (runs, failures) = report.totals();
+    info!("{} subcommands were run over the last {} days, {} failed.", runs, args.since, failures);
+    if runs == 0 { return Ok(()) }
+
+    let rows = report.commands.iter()
+        .sorted_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)))
+        .map(|(command, (runs, failures))| [command.clone(), runs.to_string(), failures.to_string()])
+        .collect_vec();
+    print_rows(&[header("COMMAND"), header("RUNS"), header("FAILURES")], &rows);
+
+    if !report.keys.is_empty() {
+        println!();
+        let rows = report.keys.iter()
+            .sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)))
+            .take(args.top)
+            .map(|(key, count)| [escape_controls(&key.block).to_string(), escape_controls(&key.path).to_string(), count.to_string()])
+            .collect_vec();
+        print_rows(&[header("BLOCK"), header("PATH"), header("ACCESSES")], &rows);
+    }
+
+    Ok(())
+}
+
+/// Print rows of three columns under their header
+fn print_rows(header: &[String; 3], rows: &[[String; 3]]) {
+    let width = |column: usize| iter::once(header).chain(rows).map(|row| row[column].chars().count()).max().unwrap_or(0);
+    let (first, second) = (width(0), width(1));
+
+    for row in iter::once(header).chain(rows) {
+        println!("{:first$}  {:second$}  {}", row[0], row[1], row[2]);
+    }
+}
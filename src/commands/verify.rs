@@ -0,0 +1,263 @@
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::VerifyArgs;
+use banjo_keyring::display::RenderOptions;
+use banjo_keyring::format::{has_block_header, BLOCK_EXTENSION, MAGIC_NUMBER};
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::keyblock::{KeyBlock, ParseErrors};
+use banjo_keyring::permissions::check_block_permissions;
+use banjo_keyring::report::{CheckKind, CheckStatus, VerifyReport};
+use banjo_keyring::rootkey::RootKey;
+use banjo_keyring::rotation::{describe, today, Rotation};
+use banjo_keyring::signature::SignatureErrors;
+use banjo_keyring::store::open_store;
+use banjo_keyring::table::OutputFormat;
+use banjo_keyring::notify::{notify, Event};
+use banjo_keyring::x509;
+use itertools::Itertools;
+use log::{error, info, warn};
+use serde_json::json;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::{fs, io, thread};
+
+/// Parse a keyblock, or every keyblock of a directory, and check their signature against the root key
+pub fn run(context: &Context, args: &VerifyArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+    let is_directory = args.block.is_dir();
+    let blocks = if is_directory { list_blocks(&args.block)? } else { vec![args.block.clone()] };
+
+    let format = if args.json { Some(OutputFormat::Json) } else { args.format.filter(|format| *format != OutputFormat::Table) };
+    let jobs = jobs(args).min(blocks.len()).max(1);
+    // Every worker can hold a block at once, so they share the memory ceiling
+    let max_memory = context.max_memory().map(|max_memory| max_memory / jobs as u64);
+
+    if let Some(format) = format {
+        let reports = parallel_map(&blocks, jobs, |path| report_block(context, path, &root_key, max_memory, args.deep));
+        let failures = reports.iter().filter(|report| !report.valid).count();
+
+        match format {
+            OutputFormat::Json if is_directory => println!("{}", serde_json::to_string_pretty(&reports)?),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports[0])?),
+            format => VerifyReport::checks_table(&reports).print_as(format, RenderOptions::default())
+        }
+
+        if failures > 0 {
+            return Err(format!("{} keyblocks failed verification", failures).into())
+        }
+        return Ok(())
+    }
+
+    if !is_directory {
+        let block = match verify_block(context, &args.block, &root_key, max_memory) {
+            Ok(block) => block,
+            Err(ParseErrors::SignatureError(SignatureErrors::Unsigned)) => {
+                return Err("the block is not signed, sign it with `banjo migrate`".into())
+            },
+            Err(error) => return Err(error.into())
+        };
+
+        warn_expired(&block);
+        check_rotation(context, &args.block, &block)?;
+        if args.deep { check_decryption(&block)? }
+        info!("Keyblock \"{}\" is valid ({} keys).", block.name, block.keys.len());
+        return Ok(())
+    }
+
+    let results = parallel_map(&blocks, jobs, |path| verify_block(context, path, &root_key, max_memory));
+
+    let mut failures = 0;
+    for (path, result) in blocks.iter().zip(results) {
+        let result = result.map_err(|error| error.to_string())
+            .and_then(|block| check_rotation(context, path, &block).map(|_| block))
+            .and_then(|block| if args.deep { check_decryption(&block).map(|_| block) } else { Ok(block) });
+        match result {
+            Ok(block) => {
+                warn_expired(&block);
+                info!("{}: \"{}\" is valid ({} keys).", path.display(), block.name, block.keys.len());
+            },
+            Err(verify_error) => {
+                error!("{}: {}", path.display(), verify_error);
+                failures += 1;
+            }
+        }
+    }
+
+    info!("{} of {} keyblocks are valid.", blocks.len() - failures, blocks.len());
+    if failures > 0 {
+        return Err(format!("{} keyblocks failed verification", failures).into())
+    }
+
+    Ok(())
+}
+
+/// Number of worker threads, defaulting to the number of CPUs
+fn jobs(args: &VerifyArgs) -> usize {
+    match args.jobs {
+        Some(jobs) => jobs as usize,
+        None => thread::available_parallelism().map(|jobs| jobs.get()).unwrap_or(1)
+    }
+}
+
+/// Apply `function` to every block on `jobs` threads, returning the results in the block order
+fn parallel_map<T: Send, F: Fn(&Path) -> T + Sync>(blocks: &[PathBuf], jobs: usize, function: F) -> Vec<T> {
+    // Workers pick the next block from a shared index
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new(blocks.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let path = match blocks.get(index) {
+                    Some(path) => path,
+                    None => break
+                };
+
+                let result = function(path);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().unwrap().into_iter().map(|result| result.expect("Every block is processed.")).collect()
+}
+
+/// Verify a single keyblock within `max_memory` bytes, sending a notification on failure
+fn verify_block(context: &Context, path: &Path, root_key: &RootKey, max_memory: Option<u64>) -> Result<KeyBlock, ParseErrors> {
+    let result = context.read_block_within(path, max_memory)
+        .map_err(|error| ParseErrors::IOError(io::Error::other(error.to_string())))
+        .and_then(|content| KeyBlock::from_bytes(&content, root_key.clone()));
+
+    if let Err(error) = &result {
+        notify_failure(context, path, error.to_string());
+    }
+
+    result
+}
+
+/// Warn about expired certificates, which don't make the block invalid but are worth knowing about
+fn warn_expired(block: &KeyBlock) {
+    for key in block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)) {
+        if let Some(certificate) = key.open().ok().and_then(|content| x509::parse(&content)).filter(|certificate| x509::has_expired(certificate)) {
+            warn!("Certificate {} expired on {}.", key.path, certificate.not_after());
+        }
+    }
+}
+
+/// Build the verification report of a single keyblock within `max_memory` bytes, sending a notification on failure
+///
+/// Keys that can't be decrypted only make the block invalid in `deep` mode.
+fn report_block(context: &Context, path: &Path, root_key: &RootKey, max_memory: Option<u64>, deep: bool) -> VerifyReport {
+    let name = path.display().to_string();
+    let store = match open_store(path) {
+        Ok(store) => store,
+        Err(error) => return VerifyReport::unreadable(&name, &error.to_string())
+    };
+
+    let mut report = match store.load(max_memory) {
+        Ok(stored) => VerifyReport::build(&name, &stored.content, root_key.clone()),
+        Err(error) => VerifyReport::unreadable(&name, &error.to_string())
+    };
+
+    if let Some(local_path) = store.local_path() {
+        match check_block_permissions(local_path) {
+            Ok(problems) if problems.is_empty() => report.record("permissions", CheckKind::Permissions, CheckStatus::Pass, "private"),
+            Ok(problems) => {
+                let status = if context.cli.strict || context.config.strict { CheckStatus::Fail } else { CheckStatus::Warn };
+                report.record("permissions", CheckKind::Permissions, status, &problems.join(", "));
+            },
+            Err(error) => report.record("permissions", CheckKind::Permissions, CheckStatus::Fail, &error.to_string())
+        }
+    }
+
+    if context.cli.strict || context.config.strict {
+        report.escalate(CheckKind::Rotation);
+    }
+    if deep {
+        report.escalate(CheckKind::Decryption);
+    }
+
+    if let Some(check) = report.checks.iter().find(|check| check.status == CheckStatus::Fail) {
+        notify_failure(context, path, format!("{}: {}", check.name, check.detail));
+    }
+
+    report
+}
+
+/// Decrypt every key of a block, their secrets having been unwrapped while parsing it
+fn check_decryption(block: &KeyBlock) -> Result<(), String> {
+    for key in block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)) {
+        key.open().map_err(|error| format!("{} can't be decrypted: {}", key.path, error))?;
+    }
+
+    Ok(())
+}
+
+/// Warn about keys overdue for rotation, failing in strict mode
+fn check_rotation(context: &Context, path: &Path, block: &KeyBlock) -> Result<(), String> {
+    let today = today();
+    let overdue: Vec<(&String, Rotation)> = block.keys.values()
+        .filter_map(|key| Rotation::from_flags(key.flags).map(|rotation| (&key.path, rotation)))
+        .filter(|(_, rotation)| rotation.days_left(today) < 0)
+        .sorted_by_key(|(key, _)| *key)
+        .collect();
+    if overdue.is_empty() { return Ok(()) }
+
+    for (key, rotation) in &overdue {
+        warn!("{}: rotation of {} is {}.", path.display(), key, describe(*rotation, today));
+    }
+
+    if context.cli.strict || context.config.strict {
+        let error = format!("{} keys are overdue for rotation", overdue.len());
+        notify_failure(context, path, error.clone());
+        return Err(error)
+    }
+
+    Ok(())
+}
+
+/// Send the verification failure notification and run the failure hook, if configured
+fn notify_failure(context: &Context, path: &Path, error: String) {
+    let details = json!({ "block": path.display().to_string(), "error": error });
+    if let Err(hook_error) = run_hook(&context.config.hooks, Hook::OnVerifyFailure, details) {
+        warn!("{}.", hook_error);
+    }
+
+    let event = Event::VerificationFailed { block: path, error };
+    if let Err(notify_error) = notify(&context.config.notify, &event) {
+        warn!("Failed to send the notification: {}.", notify_error);
+    }
+}
+
+/// Keyblocks of a directory, sorted by path, recognized by their extension or their header
+///
+/// Lock files, access logs, caches, backups, bundles and manifests are skipped, as well as hidden files such as
+/// the UID registry and the temporary files of interrupted writes.
+pub fn list_blocks(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut blocks = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let is_hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden || !path.is_file() { continue }
+
+        let has_extension = path.extension().is_some_and(|extension| extension == BLOCK_EXTENSION);
+        if has_extension || starts_with_block_header(&path)? {
+            blocks.push(path);
+        }
+    }
+
+    blocks.sort();
+    Ok(blocks)
+}
+
+/// Whether the file at `path` starts with the magic number and a known format specifier
+fn starts_with_block_header(path: &Path) -> io::Result<bool> {
+    let mut header = Vec::new();
+    File::open(path)?.take(MAGIC_NUMBER.len() as u64 + 2).read_to_end(&mut header)?;
+
+    Ok(has_block_header(&header))
+}
@@ -0,0 +1,116 @@
+use crate::commands::{deployable_content, CommandResult, Context};
+use banjo_keyring::cli::{WgCommand, WgDeployArgs, WgRotateArgs};
+use banjo_keyring::content::ContentType;
+use banjo_keyring::history::keep_version;
+use banjo_keyring::hooks::{run_hook, Hook};
+use banjo_keyring::keyblock::KeyBlock;
+use banjo_keyring::permissions::write_private;
+use banjo_keyring::wireguard::{decode_key, generate_key, public_key, set_interface, update_config, validate_interface};
+use log::info;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub fn run(context: &Context, command: &WgCommand) -> CommandResult {
+    match command {
+        WgCommand::Deploy(args) => deploy(context, args),
+        WgCommand::Rotate(args) => rotate(context, args)
+    }
+}
+
+/// Write a private key and preshared keys to a wg-quick configuration or a live interface
+fn deploy(context: &Context, args: &WgDeployArgs) -> CommandResult {
+    if let Some(interface) = &args.interface { validate_interface(interface)? }
+    let block = context.load_block(&args.block, context.root_key()?)?;
+    context.check_host(&block)?;
+
+    let private_key = deployable_key(&block, &args.path)?;
+    let mut preshared = HashMap::new();
+    for (peer, path) in &args.preshared_key {
+        if decode_key(peer.as_bytes()).is_none() { return Err(format!("invalid peer public key {}", peer).into()) }
+        preshared.insert(peer.clone(), deployable_key(&block, path)?);
+    }
+
+    deploy_keys(context, &args.block, &args.path, args.interface.as_deref(), args.config.as_deref(), &private_key, &preshared)
+}
+
+/// Replace a private key with a new one, re-sign the block, then deploy the new key and print its public key
+fn rotate(context: &Context, args: &WgRotateArgs) -> CommandResult {
+    if let Some(interface) = &args.interface { validate_interface(interface)? }
+    let root_key = context.root_key()?;
+    let signing_key = context.signing_key(&args.signing_key, &root_key)?;
+    let mut block = context.load_block(&args.block, root_key)?;
+    // Refused before rotating, so a key isn't replaced without being deployed
+    if args.interface.is_some() || args.config.is_some() { context.check_host(&block)? }
+
+    // Only replace keys which already are WireGuard keys
+    deployable_key(&block, &args.path)?;
+    let private_key = generate_key()?;
+    keep_version(&mut block, &args.path, args.keep.or(context.config.key_history).unwrap_or(1))?;
+    let key = block.keys.get_mut(&args.path).expect("The key was checked above.");
+    key.seal(&private_key)?;
+    key.flags = ContentType::Wireguard.apply(key.flags);
+
+    context.sign_block(&args.block, &mut block, &signing_key)?;
+    context.write_block(&args.block, &block.serialize()?)?;
+    if context.dry_run() { return Ok(()) }
+    info!("Rotated {}.", args.path);
+
+    if args.interface.is_some() || args.config.is_some() {
+        deploy_keys(
+            context, &args.block, &args.path, args.interface.as_deref(), args.config.as_deref(), &private_key, &HashMap::new()
+        )?;
+    }
+
+    println!("{}", public_key(&private_key).ok_or_else(|| format!("{} isn't a WireGuard private key", args.path))?);
+    Ok(())
+}
+
+/// Decrypted content of a key, after checking that it is a WireGuard key
+fn deployable_key(block: &KeyBlock, path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let key = block.keys.get(path).ok_or_else(|| format!("no key at path {}", path))?;
+    let content = deployable_content(key)?;
+
+    if decode_key(&content).is_none() { return Err(format!("{} isn't a base64 encoded WireGuard key", path).into()) }
+    Ok(content)
+}
+
+/// Write the keys with `write_keys`, between the pre-deploy and post-deploy hooks
+fn deploy_keys(
+    context: &Context, block: &Path, path: &str, interface: Option<&str>, config: Option<&Path>, private_key: &[u8],
+    preshared: &HashMap<String, Vec<u8>>
+) -> CommandResult {
+    let details = json!({
+        "block": block.display().to_string(),
+        "key": path,
+        "interface": interface,
+        "config": config.map(|config| config.display().to_string())
+    });
+    run_hook(&context.config.hooks, Hook::PreDeploy, details.clone())?;
+    write_keys(path, interface, config, private_key, preshared)?;
+    context.record_access(block, &[path]);
+    run_hook(&context.config.hooks, Hook::PostDeploy, details)?;
+
+    Ok(())
+}
+
+/// Update the configuration file if any, then the live interface if any
+fn write_keys(
+    path: &str, interface: Option<&str>, config: Option<&Path>, private_key: &[u8], preshared: &HashMap<String, Vec<u8>>
+) -> CommandResult {
+    if let Some(config) = config {
+        let text = |key: &[u8]| String::from_utf8_lossy(key).trim().to_string();
+        let preshared = preshared.iter().map(|(peer, key)| (peer.clone(), text(key))).collect();
+        let updated = update_config(&fs::read_to_string(config)?, &text(private_key), &preshared)?;
+        write_private(config, updated.as_bytes())?;
+        info!("Wrote {} to {}.", path, config.display());
+    }
+
+    if let Some(interface) = interface {
+        set_interface(interface, private_key, preshared)?;
+        info!("Set {} on interface {}.", path, interface);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::commands::verify::list_blocks;
+use crate::commands::{CommandResult, Context};
+use banjo_keyring::cli::XrefArgs;
+use banjo_keyring::i18n::header;
+use banjo_keyring::xref::{expand_glob, find_references, resolve, Resolution};
+use itertools::Itertools;
+use log::{info, warn};
+use std::{fs, iter};
+
+/// Report the references of configurations that don't designate exactly one key of the keyblocks
+pub fn run(context: &Context, args: &XrefArgs) -> CommandResult {
+    let root_key = context.root_key()?;
+
+    let mut blocks = Vec::new();
+    for path in list_blocks(&args.directory)? {
+        match context.load_block(&path, root_key.clone()) {
+            Ok(block) => blocks.push((path, block)),
+            Err(error) => warn!("Skipping {}: {}.", path.display(), error)
+        }
+    }
+
+    let mut references = Vec::new();
+    for pattern in &args.config_glob {
+        for file in expand_glob(pattern)? {
+            match fs::read(&file) {
+                Ok(content) => references.extend(find_references(&file, &String::from_utf8_lossy(&content))),
+                Err(error) => warn!("Skipping {}: {}.", file.display(), error)
+            }
+        }
+    }
+    references.dedup();
+
+    let resolutions: Vec<_> = references.iter().map(|reference| (reference, resolve(reference, &blocks))).collect();
+    let problems = resolutions.iter().filter(|(_, resolution)| !matches!(resolution, Resolution::Resolved(..))).count();
+
+    let rows: Vec<[String; 3]> = resolutions.iter()
+        .filter(|(_, resolution)| args.all || !matches!(resolution, Resolution::Resolved(..)))
+        .map(|(reference, resolution)| {
+            [format!("{}:{}", reference.file.display(), reference.line), reference.to_string(), resolution.to_string()]
+        })
+        .collect();
+
+    if !rows.is_empty() {
+        let header = [header("LOCATION"), header("REFERENCE"), header("TARGET")];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| rows.iter().chain(iter::once(&header)).map(|row| row[column].chars().count()).max().unwrap_or(0))
+            .collect();
+
+        for row in iter::once(&header).chain(&rows) {
+            let line = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).join("  ");
+            println!("{}", line.trim_end());
+        }
+    }
+
+    if problems > 0 {
+        return Err(format!("{} of {} references are dangling or ambiguous", problems, references.len()).into())
+    }
+
+    info!("All {} references resolve to a key of {} keyblocks.", references.len(), blocks.len());
+    Ok(())
+}
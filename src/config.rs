@@ -0,0 +1,88 @@
+use crate::hooks::HooksConfig;
+use crate::notify::NotifyConfig;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::{env, fmt, fs, io};
+
+/// Name of the configuration file inside the banjo configuration directory
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// User configuration, read from `$XDG_CONFIG_HOME/banjo/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Path to the root public key
+    pub root_key: Option<PathBuf>,
+    /// Refuse to run any subcommand that alters a keyblock, like `--read-only`
+    pub read_only: bool,
+    /// Fail instead of warning on unsafe keyblock permissions, like `--strict`
+    pub strict: bool,
+    /// Keep a sidecar metadata cache next to listed keyblocks, like `list --cache`
+    pub metadata_cache: bool,
+    /// Record the day keys are handed out next to their keyblock, like `--track-access`
+    pub track_access: bool,
+    /// Refuse to load keyblocks needing more than this amount of memory, in bytes, like `--max-memory`
+    pub max_memory: Option<u64>,
+    /// Previous versions kept when a command overwrites the content of a key, see `banjo history`
+    pub key_history: Option<usize>,
+    /// Allow core dumps and debugger attachment, like `--allow-core-dumps`
+    pub allow_core_dumps: bool,
+    /// Base URL of releases for `banjo self-update`, instead of the one pinned in the build
+    pub update_url: Option<String>,
+    /// Lock secrets in memory so they are never swapped to disk, like `--lock-memory`
+    pub lock_memory: bool,
+    /// Log of the subcommands run and the keys they handed out, for `banjo usage-report`
+    pub audit_log: Option<PathBuf>,
+    /// Language of the messages, such as `en` or `fr`, instead of the one of the locale
+    pub language: Option<String>,
+    /// Notification channels
+    pub notify: NotifyConfig,
+    /// Commands run around lifecycle events
+    pub hooks: HooksConfig
+}
+
+/// Enumeration of the potential errors when loading the configuration
+#[derive(Debug)]
+pub enum ConfigErrors {
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The configuration file isn't valid TOML or contains unknown options
+    InvalidConfig(toml::de::Error)
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigErrors::IOError(error) => write!(f, "failed to read the configuration: {}", error),
+            ConfigErrors::InvalidConfig(error) => write!(f, "invalid configuration: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl Config {
+    /// Location of the configuration file, if a configuration directory can be found
+    pub fn path() -> Option<PathBuf> {
+        let directory = match env::var_os("XDG_CONFIG_HOME") {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config")
+        };
+
+        Some(directory.join("banjo").join(CONFIG_FILE_NAME))
+    }
+
+    /// Load the configuration file, falling back to the default configuration if it doesn't exist
+    pub fn load() -> Result<Config, ConfigErrors> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default())
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(ConfigErrors::InvalidConfig),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(ConfigErrors::IOError(error))
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Conformance reports, checking that a keyblock is the canonical serialization of its content
+//!
+//! Other implementations of the format can run `banjo conformance` against the blocks they write to prove
+//! compatibility. A block conforms when it is parsed and serialized back to the exact same bytes, which
+//! needs the current format, keyfiles sorted by path and, in deduplicated blocks, each content stored by the
+//! first keyfile holding it. The signature is kept as is, so signing with randomized schemes doesn't matter.
+
+use crate::format::{
+    BLOCK_COMPRESSED_METADATA, BLOCK_HOST_BOUND, FORMAT_SPECIFIER, HOST_FINGERPRINT_SIZE, MAGIC_NUMBER, SECRET_SIZE
+};
+use crate::id::ID_SIZE;
+use crate::keyblock::KeyBlock;
+use crate::report::{Check, CheckKind, CheckStatus};
+use crate::rootkey::RootKey;
+use crate::signature::SignatureErrors;
+use crate::table::Table;
+use crate::utils::to_hex;
+use byteorder::{ByteOrder, LittleEndian};
+use openssl::sha::sha256;
+use serde::Serialize;
+use std::io::Cursor;
+
+/// Every check performed while comparing a block to its canonical serialization
+#[derive(Debug, Clone, Serialize)]
+pub struct ConformanceReport {
+    /// Location of the block
+    pub block: String,
+    /// Hex encoded SHA256 digest of the whole block
+    pub sha256: String,
+    /// Whether no check failed
+    pub conformant: bool,
+    pub checks: Vec<Check>
+}
+
+impl ConformanceReport {
+    /// Table of the checks, one row per check
+    pub fn table(&self) -> Table {
+        let header = ["CHECK", "STATUS", "DETAIL"];
+        let rows = self.checks.iter()
+            .map(|check| vec![check.name.clone(), check.status.name().to_string(), check.detail.clone()])
+            .collect();
+
+        Table { header: header.iter().map(|header| header.to_string()).collect(), rows }
+    }
+
+    /// Parse a serialized block and compare it to its canonical serialization, recording every check
+    pub fn build(block: &str, content: &[u8], root_key: RootKey) -> ConformanceReport {
+        let mut report = ConformanceReport {
+            block: block.to_string(),
+            sha256: to_hex(&sha256(content)),
+            conformant: true,
+            checks: Vec::new()
+        };
+
+        let (parsed, signed_length) = match KeyBlock::parse(content, root_key) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                report.push("parse", CheckStatus::Fail, &format!("{} ({})", error, error.root_code()));
+                for name in ["format-specifier", "signature", "keyfile-order", "round-trip"] {
+                    report.push(name, CheckStatus::Skip, "the block couldn't be parsed");
+                }
+                return report
+            }
+        };
+        report.push("parse", CheckStatus::Pass, &format!("{} keys", parsed.keys.len()));
+
+        if parsed.format_specifier == FORMAT_SPECIFIER {
+            report.push("format-specifier", CheckStatus::Pass, &FORMAT_SPECIFIER.to_string());
+        } else {
+            let detail = format!("format {} blocks are serialized to format {}", parsed.format_specifier, FORMAT_SPECIFIER);
+            report.push("format-specifier", CheckStatus::Fail, &detail);
+        }
+
+        // Unsigned blocks still round-trip, they just can't be trusted
+        match parsed.signature.verify(&content[..signed_length], &parsed.root_pubkey) {
+            Ok(()) => report.push("signature", CheckStatus::Pass, &format!("{:?}", parsed.signature.algorithm)),
+            Err(SignatureErrors::Unsigned) => report.push("signature", CheckStatus::Warn, "the block is not signed"),
+            Err(error) => report.push("signature", CheckStatus::Fail, &error.to_string())
+        }
+
+        report.check_order(content);
+        report.check_round_trip(content, &parsed);
+
+        report
+    }
+
+    /// Check that keyfiles are stored sorted by path, as they are serialized
+    fn check_order(&mut self, content: &[u8]) {
+        let paths: Result<Vec<String>, _> = match KeyBlock::stream_keys(Cursor::new(content)) {
+            Ok(stream) => stream.map(|key| key.map(|key| key.path)).collect(),
+            Err(error) => Err(error)
+        };
+        let paths = match paths {
+            Ok(paths) => paths,
+            Err(error) => return self.push("keyfile-order", CheckStatus::Fail, &error.to_string())
+        };
+
+        match paths.windows(2).find(|pair| pair[0] >= pair[1]) {
+            Some(pair) if pair[0] == pair[1] => {
+                self.push("keyfile-order", CheckStatus::Fail, &format!("{} is stored twice", pair[0]))
+            },
+            Some(pair) => self.push("keyfile-order", CheckStatus::Fail, &format!("{} is stored after {}", pair[1], pair[0])),
+            None => self.push("keyfile-order", CheckStatus::Pass, "sorted by path")
+        }
+    }
+
+    /// Compare the block byte for byte with its canonical serialization
+    fn check_round_trip(&mut self, content: &[u8], parsed: &KeyBlock) {
+        let canonical = match parsed.serialize() {
+            Ok(canonical) => canonical,
+            Err(error) => return self.push("round-trip", CheckStatus::Fail, &error.to_string())
+        };
+
+        let difference = content.iter().zip(&canonical).position(|(found, expected)| found != expected);
+        let detail = match difference {
+            None if content.len() == canonical.len() => {
+                return self.push("round-trip", CheckStatus::Pass, &format!("{} identical bytes", content.len()))
+            },
+            None if content.len() > canonical.len() => {
+                format!("{} trailing bytes after the signature", content.len() - canonical.len())
+            },
+            None => format!("the block ends at byte {} of {}", content.len(), canonical.len()),
+            Some(offset) => format!(
+                "first difference at byte {} in the {}, found 0x{:02x} instead of 0x{:02x}",
+                offset, section(parsed, &canonical, offset), content[offset], canonical[offset]
+            )
+        };
+
+        self.push("round-trip", CheckStatus::Fail, &detail);
+    }
+
+    fn push(&mut self, name: &str, status: CheckStatus, detail: &str) {
+        if status == CheckStatus::Fail { self.conformant = false }
+        self.checks.push(Check { name: name.to_string(), kind: CheckKind::Structure, status, detail: detail.to_string() });
+    }
+}
+
+/// Section of the canonical serialization of `parsed` holding the byte at `offset`
+fn section(parsed: &KeyBlock, canonical: &[u8], offset: usize) -> &'static str {
+    // Magic number, format, flags, cipher suite, secret, UID, ID and host fingerprint
+    let host = if parsed.flags & BLOCK_HOST_BOUND != 0 { HOST_FINGERPRINT_SIZE } else { 0 };
+    let fixed = MAGIC_NUMBER.len() + 2 + 8 + 2 + SECRET_SIZE + 2 + ID_SIZE + host;
+    let signature = canonical.len() - (2 + 4 + parsed.signature.data.len());
+
+    if offset < fixed { return "header" }
+    if offset >= signature { return "signature" }
+
+    if parsed.flags & BLOCK_COMPRESSED_METADATA != 0 {
+        // Compressed length and frame, followed by the key contents
+        let length = canonical.get(fixed..fixed + 4).map_or(0, LittleEndian::read_u32);
+        if offset < fixed + 4 + length as usize { "compressed metadata" } else { "key contents" }
+    } else if offset < fixed + parsed.name.len() + 1 + parsed.description.len() + 1 + 8 {
+        // Name, description and keyfile count
+        "header"
+    } else {
+        "keyfiles"
+    }
+}
@@ -0,0 +1,186 @@
+//! Chunked AES-256-GCM encryption of key content
+//!
+//! Content is split in `CHUNK_SIZE` chunks, each encrypted and authenticated on its own so it can be
+//! processed as a stream. The nonce of every chunk is made of a random prefix, the chunk counter and a
+//! flag marking the last chunk, so reordered, dropped or truncated chunks are detected.
+//!
+//! ```text
+//! stream = nonce_prefix, chunk_size, { chunk }, last_chunk
+//! chunk = chunk_size * byte, tag
+//! last_chunk = { byte }, tag
+//!
+//! nonce_prefix = 7 * byte
+//! chunk_size = 32_number
+//! tag = 16 * byte
+//! nonce = nonce_prefix, 32_number (big endian counter), last_flag
+//! ```
+
+use crate::secret::Secret256;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use openssl::error::ErrorStack;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::io::{Read, Write};
+use std::{fmt, io};
+
+/// Size of the plaintext chunks, in bytes
+pub const CHUNK_SIZE: u32 = 64 * 1024;
+/// Size of the random part of the nonces, in bytes
+const NONCE_PREFIX_SIZE: usize = 7;
+/// Size of the GCM authentication tags, in bytes
+const TAG_SIZE: usize = 16;
+/// Largest chunk size accepted when decrypting, to bound memory usage
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Enumeration of the potential errors when encrypting or decrypting content
+#[derive(Debug)]
+pub enum CryptoErrors {
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The stream header is invalid
+    InvalidHeader,
+    /// The stream ended before its last chunk
+    Truncated,
+    /// The chunk with this index failed authentication: it was altered, reordered or encrypted with another key
+    AuthenticationFailed(u32),
+    /// The stream has more chunks than the counter can hold
+    TooManyChunks,
+    /// OpenSSL failed to encrypt
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for CryptoErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoErrors::IOError(error) => write!(f, "IO error: {}", error),
+            CryptoErrors::InvalidHeader => write!(f, "invalid encrypted content header"),
+            CryptoErrors::Truncated => write!(f, "the encrypted content is truncated"),
+            CryptoErrors::AuthenticationFailed(chunk) => write!(f, "chunk {} failed authentication", chunk),
+            CryptoErrors::TooManyChunks => write!(f, "the content is too large to be encrypted"),
+            CryptoErrors::OpenSSLError(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for CryptoErrors {}
+
+impl From<io::Error> for CryptoErrors {
+    fn from(error: io::Error) -> Self {
+        CryptoErrors::IOError(error)
+    }
+}
+
+impl From<ErrorStack> for CryptoErrors {
+    fn from(error: ErrorStack) -> Self {
+        CryptoErrors::OpenSSLError(error)
+    }
+}
+
+/// Encrypt everything from `reader` to `writer`, holding at most two chunks in memory
+pub fn encrypt_stream<R: Read, W: Write>(key: &Secret256, reader: &mut R, writer: &mut W) -> Result<(), CryptoErrors> {
+    let mut prefix = [0; NONCE_PREFIX_SIZE];
+    rand_bytes(&mut prefix)?;
+
+    writer.write_all(&prefix)?;
+    writer.write_u32::<LittleEndian>(CHUNK_SIZE)?;
+
+    // A full chunk is only known to be the last one once the next read comes back empty
+    let mut current = read_chunk(reader, CHUNK_SIZE as usize)?;
+    let mut counter: u32 = 0;
+
+    loop {
+        let next = if current.len() == CHUNK_SIZE as usize {
+            read_chunk(reader, CHUNK_SIZE as usize)?
+        } else {
+            Vec::new()
+        };
+        let last = next.is_empty();
+
+        let mut tag = [0; TAG_SIZE];
+        let nonce = make_nonce(&prefix, counter, last);
+        let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key.as_bytes(), Some(&nonce), &[], &current, &mut tag)?;
+        writer.write_all(&ciphertext)?;
+        writer.write_all(&tag)?;
+
+        if last { return Ok(()) }
+
+        current = next;
+        counter = counter.checked_add(1).ok_or(CryptoErrors::TooManyChunks)?;
+    }
+}
+
+/// Decrypt and authenticate everything from `reader` to `writer`, chunk by chunk
+///
+/// Chunks are written as soon as they are authenticated, so a failure can happen after part of the
+/// content was written. Callers should discard the output on error.
+pub fn decrypt_stream<R: Read, W: Write>(key: &Secret256, reader: &mut R, writer: &mut W) -> Result<(), CryptoErrors> {
+    let mut prefix = [0; NONCE_PREFIX_SIZE];
+    reader.read_exact(&mut prefix).map_err(|_| CryptoErrors::InvalidHeader)?;
+    let chunk_size = reader.read_u32::<LittleEndian>().map_err(|_| CryptoErrors::InvalidHeader)?;
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE { return Err(CryptoErrors::InvalidHeader) }
+
+    let sealed_size = chunk_size as usize + TAG_SIZE;
+    let mut current = read_chunk(reader, sealed_size)?;
+    let mut counter: u32 = 0;
+
+    loop {
+        if current.len() < TAG_SIZE { return Err(CryptoErrors::Truncated) }
+
+        let next = if current.len() == sealed_size {
+            read_chunk(reader, sealed_size)?
+        } else {
+            Vec::new()
+        };
+        let last = next.is_empty();
+
+        let (ciphertext, tag) = current.split_at(current.len() - TAG_SIZE);
+        let nonce = make_nonce(&prefix, counter, last);
+        let plaintext = match decrypt_aead(Cipher::aes_256_gcm(), key.as_bytes(), Some(&nonce), &[], ciphertext, tag) {
+            Ok(plaintext) => plaintext,
+            // A stream cut on a chunk boundary ends with a chunk that doesn't have the last flag
+            Err(_) if last && decrypt_aead(
+                Cipher::aes_256_gcm(), key.as_bytes(), Some(&make_nonce(&prefix, counter, false)), &[], ciphertext, tag
+            ).is_ok() => return Err(CryptoErrors::Truncated),
+            Err(_) => return Err(CryptoErrors::AuthenticationFailed(counter))
+        };
+        writer.write_all(&plaintext)?;
+
+        if last { return Ok(()) }
+
+        current = next;
+        counter = counter.checked_add(1).ok_or(CryptoErrors::TooManyChunks)?;
+    }
+}
+
+/// Encrypt `content` in memory
+pub fn encrypt(key: &Secret256, content: &[u8]) -> Result<Vec<u8>, CryptoErrors> {
+    let mut buffer = Vec::new();
+    encrypt_stream(key, &mut &content[..], &mut buffer)?;
+
+    Ok(buffer)
+}
+
+/// Decrypt and authenticate `content` in memory
+pub fn decrypt(key: &Secret256, content: &[u8]) -> Result<Vec<u8>, CryptoErrors> {
+    let mut buffer = Vec::new();
+    decrypt_stream(key, &mut &content[..], &mut buffer)?;
+
+    Ok(buffer)
+}
+
+/// Nonce of a chunk: random prefix, big endian counter and last chunk flag
+fn make_nonce(prefix: &[u8; NONCE_PREFIX_SIZE], counter: u32, last: bool) -> Vec<u8> {
+    let mut nonce = prefix.to_vec();
+    nonce.write_u32::<BigEndian>(counter).expect("Writing to memory can't fail.");
+    nonce.push(last as u8);
+
+    nonce
+}
+
+/// Read up to `size` bytes, only returning less at the end of the stream
+fn read_chunk<R: Read>(reader: &mut R, size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut chunk)?;
+
+    Ok(chunk)
+}
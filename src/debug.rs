@@ -1,68 +1,80 @@
-use crate::keyblock::{KeyBlock, SECRET_SIZE, SIGNATURE_SIZE, KeyFile};
+use crate::id::GlobalId;
+use crate::keyblock::{KeyBlock, KeyFile};
+use crate::rootkey::{RootKey, SigningKey};
+use crate::secret::Secret256;
+use crate::signature::Signature;
+use crate::suite::CipherSuite;
+use openssl::pkey::Private;
 use openssl::rsa::Rsa;
-use openssl::pkey::Public;
-use rand::{thread_rng, Rng, RngCore};
 use std::collections::HashMap;
-use itertools::Itertools;
 
 
 #[cfg(feature = "enable_debug")]
-pub fn make_fake_rsa() -> Rsa<Public> {
-    Rsa::public_key_from_pem(
-        &*Rsa::generate(4096).unwrap().public_key_to_pem().unwrap()
-    ).unwrap()
+pub fn make_fake_rsa() -> Rsa<Private> {
+    Rsa::generate(4096).unwrap()
 }
 
 
 #[cfg(feature = "enable_debug")]
 impl KeyBlock {
-    pub fn make_fake() -> KeyBlock {
-        let rsa = make_fake_rsa();
+    /// Make a fake keyblock, signed by `root_key`
+    pub fn make_fake(root_key: &Rsa<Private>) -> KeyBlock {
+        let rsa = RootKey::from_bytes(&root_key.public_key_to_pem().unwrap()).unwrap();
         let mut keys: HashMap<String, KeyFile> = HashMap::new();
 
-        let mut secret: Vec<u8> = vec![0; SECRET_SIZE / 8];
-        thread_rng().fill_bytes(secret.as_mut_slice());
+        let secret = Secret256::generate().unwrap();
 
-        let key1 = KeyFile {
-            flags: 6,
+        let mut key1 = KeyFile {
+            flags: 0,
             secret,
             uid: (('K' as u16) << 8) + 52,
+            id: GlobalId::generate().unwrap(),
             path: "~/key1".to_string(),
             name: "key1".to_string(),
             description: "Fake key 1.".to_string(),
-            length: 6 * 8,
-            content: vec![1, 2, 3, 4, 5, 6]
+            tags: vec!["env:prod".to_string(), "team:infra".to_string()],
+            length: 0,
+            content: Vec::new()
         };
+        key1.seal(&[1, 2, 3, 4, 5, 6]).unwrap();
         keys.insert("~/key1".parse().unwrap(), key1);
 
-        let mut secret: Vec<u8> = vec![0; SECRET_SIZE / 8];
-        thread_rng().fill_bytes(secret.as_mut_slice());
+        let secret = Secret256::generate().unwrap();
 
-        let key2 = KeyFile {
-            flags: 4,
+        let mut key2 = KeyFile {
+            flags: 0,
             secret,
             uid: (('K' as u16) << 8) + 45,
+            id: GlobalId::generate().unwrap(),
             path: "~/key2".to_string(),
             name: "key2".to_string(),
             description: "Fake key 2.".to_string(),
-            length: 8 * 8,
-            content: vec![8, 7, 6, 5, 4, 3, 2, 1]
+            tags: vec!["env:dev".to_string()],
+            length: 0,
+            content: Vec::new()
         };
+        key2.seal(&[8, 7, 6, 5, 4, 3, 2, 1]).unwrap();
         keys.insert("~/key2".parse().unwrap(), key2);
 
-        let mut secret: Vec<u8> = vec![0; SECRET_SIZE / 8];
-        thread_rng().fill_bytes(secret.as_mut_slice());
+        let secret = Secret256::generate().unwrap();
 
-        KeyBlock {
+        let mut block = KeyBlock {
             root_pubkey: rsa,
             format_specifier: 0,
             flags: 0,
+            cipher_suite: CipherSuite::Classic,
             secret,
             uid: (('B' as u16) << 8) + 89,
+            id: GlobalId::generate().unwrap(),
+            host: None,
             name: "fake".to_string(),
             description: "This is a totally fake keyblock.".to_string(),
             keys,
-            signature: vec![0; SIGNATURE_SIZE / 8]
-        }
+            signature: Signature::none(),
+            reserved_uids: Vec::new()
+        };
+
+        block.sign(&SigningKey::from(root_key.clone())).unwrap();
+        block
     }
 }
\ No newline at end of file
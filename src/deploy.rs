@@ -0,0 +1,109 @@
+//! Deployment of the keys of a keyblock as files under a directory, resumable through a state file
+//!
+//! Every key is installed at its path under the target directory. After each key, the state file records the
+//! SHA256 digest of the encrypted content that was installed, so an interrupted deployment resumed with
+//! `--resume` skips the keys whose file is in place and whose content didn't change since. The digest is the
+//! one of the ciphertext, the state file doesn't reveal anything about the key contents.
+
+use crate::id::GlobalId;
+use crate::keyblock::KeyFile;
+use crate::permissions::write_private;
+use crate::utils::to_hex;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// Name of the state file, in the target directory unless given elsewhere
+pub const STATE_FILE: &str = ".banjo-deploy.json";
+
+/// Enumeration of the potential errors when deploying keys
+#[derive(Debug)]
+pub enum DeployErrors {
+    /// A key path would be installed outside of the target directory
+    InvalidPath(String),
+    /// The state file was written by the deployment of another block
+    StateMismatch(GlobalId),
+    IOError(io::Error)
+}
+
+impl fmt::Display for DeployErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeployErrors::InvalidPath(path) => write!(f, "{:?} can't be deployed under a directory", path),
+            DeployErrors::StateMismatch(id) => write!(f, "the deployment state belongs to another keyblock ({})", id),
+            DeployErrors::IOError(error) => write!(f, "IO error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for DeployErrors {}
+
+impl From<io::Error> for DeployErrors {
+    fn from(error: io::Error) -> Self {
+        DeployErrors::IOError(error)
+    }
+}
+
+/// Keys of a block already installed, with the digest of their encrypted content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployState {
+    /// ID of the deployed block
+    pub block: GlobalId,
+    /// Digest of the installed content, by key path
+    pub keys: BTreeMap<String, String>
+}
+
+impl DeployState {
+    /// Empty state of a new deployment of the block with `block` ID
+    pub fn new(block: GlobalId) -> DeployState {
+        DeployState { block, keys: BTreeMap::new() }
+    }
+
+    /// Resume the deployment recorded at `path` of the block with `block` ID, starting over if there is none
+    pub fn resume(path: &Path, block: GlobalId) -> Result<DeployState, DeployErrors> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(DeployState::new(block)),
+            Err(error) => return Err(error.into())
+        };
+
+        let state: DeployState = serde_json::from_slice(&content)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if state.block != block { return Err(DeployErrors::StateMismatch(state.block)) }
+
+        Ok(state)
+    }
+
+    /// Write this state to `path`, only readable by its owner
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_private(path, &serde_json::to_vec(self)?)
+    }
+
+    /// Whether `key` is installed at `target` with its current content
+    pub fn is_deployed(&self, key: &KeyFile, target: &Path) -> bool {
+        self.keys.get(&key.path) == Some(&content_digest(key)) && target.is_file()
+    }
+
+    /// Record that `key` was installed with its current content
+    pub fn record(&mut self, key: &KeyFile) {
+        self.keys.insert(key.path.clone(), content_digest(key));
+    }
+}
+
+/// Digest of the encrypted content of a key
+fn content_digest(key: &KeyFile) -> String {
+    to_hex(&sha256(&key.content))
+}
+
+/// Location of the key at `path` under `directory`, refusing paths which would leave it
+pub fn target_path(directory: &Path, path: &str) -> Result<PathBuf, DeployErrors> {
+    let relative = Path::new(path);
+    let plain = relative.components().all(|component| matches!(component, Component::Normal(_)));
+    if !plain || relative.file_name().is_none() || path.ends_with('/') || path == STATE_FILE {
+        return Err(DeployErrors::InvalidPath(path.to_string()))
+    }
+
+    Ok(directory.join(relative))
+}
@@ -0,0 +1,89 @@
+//! Differences between two versions of a keyblock, as reported by `banjo apply` and dry runs
+//!
+//! Changes to the block fields themselves are reported on the `@block` pseudo-path.
+
+use crate::display::escape_controls;
+use crate::keyblock::{KeyBlock, KeyFile};
+use itertools::Itertools;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Pseudo-path of changes to the block fields
+pub const BLOCK_PATH: &str = "@block";
+
+/// A change made to a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The key was added, with content of this size
+    Added(String, u64),
+    /// These fields of the key were updated
+    Updated(String, Vec<&'static str>),
+    Removed(String)
+}
+
+impl Change {
+    /// Path of the key this change is about
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added(path, _) | Change::Updated(path, _) | Change::Removed(path) => path
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(path, length) => write!(f, "+ {} ({} bytes)", escape_controls(path), length),
+            Change::Updated(path, fields) => write!(f, "~ {} ({})", escape_controls(path), fields.join(", ")),
+            Change::Removed(path) => write!(f, "- {}", escape_controls(path))
+        }
+    }
+}
+
+/// Changes turning `before` into `after`, sorted by path
+pub fn diff(before: &KeyBlock, after: &KeyBlock) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let mut fields = Vec::new();
+    if before.name != after.name { fields.push("name") }
+    if before.description != after.description { fields.push("description") }
+    if before.uid != after.uid { fields.push("uid") }
+    if before.id != after.id { fields.push("id") }
+    if before.flags != after.flags { fields.push("flags") }
+    if before.cipher_suite != after.cipher_suite { fields.push("cipher suite") }
+    if before.format_specifier != after.format_specifier { fields.push("format") }
+    if before.secret != after.secret { fields.push("secret") }
+    if !fields.is_empty() {
+        changes.push(Change::Updated(BLOCK_PATH.to_string(), fields));
+    }
+
+    let paths: HashSet<&String> = before.keys.keys().chain(after.keys.keys()).collect();
+    for path in paths.into_iter().sorted() {
+        match (before.keys.get(path), after.keys.get(path)) {
+            (None, Some(key)) => changes.push(Change::Added(path.clone(), key.length)),
+            (Some(_), None) => changes.push(Change::Removed(path.clone())),
+            (Some(old), Some(new)) => {
+                let fields = key_fields(old, new);
+                if !fields.is_empty() { changes.push(Change::Updated(path.clone(), fields)) }
+            },
+            (None, None) => {}
+        }
+    }
+
+    changes
+}
+
+/// Fields differing between two versions of a key
+fn key_fields(before: &KeyFile, after: &KeyFile) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if before.content != after.content { fields.push("content") }
+    if before.name != after.name { fields.push("name") }
+    if before.description != after.description { fields.push("description") }
+    if before.tags != after.tags { fields.push("tags") }
+    if before.uid != after.uid { fields.push("uid") }
+    if before.id != after.id { fields.push("id") }
+    if before.flags != after.flags { fields.push("flags") }
+    if before.secret != after.secret { fields.push("secret") }
+
+    fields
+}
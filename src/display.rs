@@ -0,0 +1,96 @@
+//! Rendering of untrusted strings on terminals
+//!
+//! Names, descriptions, paths and tags come from keyblocks which may have been crafted. Printed as is, they
+//! could move the cursor, rewrite previous lines or reorder text with ANSI escape sequences and bidirectional
+//! overrides. Rendering escapes those characters, can escape everything outside of ASCII, and measures text by
+//! its width on the terminal rather than by its number of characters.
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Marker of truncated text
+const ELLIPSIS: &str = "…";
+/// Marker of truncated text in ASCII mode
+const ASCII_ELLIPSIS: &str = "...";
+
+/// How to render untrusted text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Escape every character outside of printable ASCII
+    pub ascii: bool,
+    /// Truncate the text to this width, in terminal columns
+    pub max_width: Option<usize>
+}
+
+/// Whether a character can change how the terminal displays the text around it
+fn is_unsafe(character: char) -> bool {
+    character.is_control()
+        // Bidirectional embeddings, overrides and isolates
+        || ('\u{202a}'..='\u{202e}').contains(&character)
+        || ('\u{2066}'..='\u{2069}').contains(&character)
+}
+
+/// Escaped form of a character, like `\x1b` or `\u{202e}`
+fn escape(character: char) -> String {
+    if (character as u32) < 0x80 { format!("\\x{:02x}", character as u32) } else { format!("\\u{{{:x}}}", character as u32) }
+}
+
+/// `text` with its control characters and bidirectional overrides escaped, and its backslashes doubled so escapes
+/// can't be forged
+pub fn escape_controls(text: &str) -> String {
+    text.chars().map(|character| match character {
+        '\\' => "\\\\".to_string(),
+        character if is_unsafe(character) => escape(character),
+        character => character.to_string()
+    }).collect()
+}
+
+/// `text` with every character outside of printable ASCII escaped
+pub fn to_ascii(text: &str) -> String {
+    text.chars().map(|character| match character {
+        '\\' => "\\\\".to_string(),
+        ' '..='~' => character.to_string(),
+        character => escape(character)
+    }).collect()
+}
+
+/// Width of `text` on a terminal, in columns
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// `text` truncated to `max_width` columns, ending with `ellipsis` when truncated
+fn truncate(text: &str, max_width: usize, ellipsis: &str) -> String {
+    if text.width() <= max_width { return text.to_string() }
+
+    let budget = max_width.saturating_sub(ellipsis.width());
+    let mut truncated = String::new();
+    let mut used = 0;
+    for character in text.chars() {
+        let character_width = character.width().unwrap_or(0);
+        if used + character_width > budget { break }
+        used += character_width;
+        truncated.push(character);
+    }
+
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+/// Safe form of untrusted `text`, for printing on a terminal
+pub fn render(text: &str, options: RenderOptions) -> String {
+    let (rendered, ellipsis) = if options.ascii {
+        (to_ascii(text), ASCII_ELLIPSIS)
+    } else {
+        (escape_controls(text), ELLIPSIS)
+    };
+
+    match options.max_width {
+        Some(max_width) => truncate(&rendered, max_width, ellipsis),
+        None => rendered
+    }
+}
+
+/// `text` padded with spaces to `target` columns
+pub fn pad(text: &str, target: usize) -> String {
+    format!("{}{}", text, " ".repeat(target.saturating_sub(text.width())))
+}
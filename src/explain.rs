@@ -0,0 +1,254 @@
+//! Explanations and remediation steps of the errors reported when loading keyblocks
+//!
+//! Every variant of `ParseErrors` and `SignatureErrors` has a code, returned by their `code` method, and an
+//! entry in `ERRORS`. The entries also hold a fragment of the message of their error, so an error message
+//! copied from a terminal can be explained as is.
+
+use std::fmt;
+
+/// Explanation of an error
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorInfo {
+    /// Stable code, e.g. `E102`
+    pub code: &'static str,
+    pub title: &'static str,
+    /// Fragment of the error message, `None` for errors with a message from another library
+    pub message: Option<&'static str>,
+    pub explanation: &'static str,
+    pub remediation: &'static str
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.code, self.title)?;
+        writeln!(f)?;
+        writeln!(f, "{}", self.explanation)?;
+        writeln!(f)?;
+        write!(f, "Remediation: {}", self.remediation)
+    }
+}
+
+/// Every known error, sorted by code
+pub const ERRORS: &[ErrorInfo] = &[
+    ErrorInfo {
+        code: "E100",
+        title: "a keyfile couldn't be parsed",
+        message: Some("failed to parse keyfile"),
+        explanation: "The keyfile with the given index, counted from 0 in path order, is damaged. Its own error \
+                      follows the index and has its own code.",
+        remediation: "Explain the error following the index. `banjo debug scavenge` can recover the metadata of \
+                      the keyfiles that are still readable."
+    },
+    ErrorInfo {
+        code: "E101",
+        title: "the block couldn't be read",
+        message: Some("IO error"),
+        explanation: "The operating system failed to read the block file.",
+        remediation: "Check that the file exists and is readable by the current user."
+    },
+    ErrorInfo {
+        code: "E102",
+        title: "the block is truncated",
+        message: Some("unexpected end of file"),
+        explanation: "The block ended in the middle of a field, usually because a copy or a download was \
+                      interrupted or the disk is full.",
+        remediation: "Restore the block from a backup or from version control. `banjo debug scavenge` shows \
+                      which keyfiles are still complete."
+    },
+    ErrorInfo {
+        code: "E103",
+        title: "the file isn't a keyblock",
+        message: Some("invalid magic number"),
+        explanation: "Keyblocks start with the bytes \"banjo\", which this file doesn't.",
+        remediation: "Check the path. Backup bundles are restored with `restore-backup` and offline bundles are \
+                      checked with `verify-bundle`."
+    },
+    ErrorInfo {
+        code: "E104",
+        title: "unknown format version",
+        message: Some("unknown format specifier"),
+        explanation: "The block was written by a newer version of banjo, in a format this version can't read.",
+        remediation: "Upgrade banjo. `banjo debug schema` lists the format versions this build knows."
+    },
+    ErrorInfo {
+        code: "E105",
+        title: "the key length doesn't match its content",
+        message: Some("declared key length"),
+        explanation: "The length stored before a key content isn't the number of bytes found, because the block \
+                      is truncated or the key was edited without updating its length.",
+        remediation: "Restore the block from a backup. Tools writing blocks must update the length with the \
+                      content."
+    },
+    ErrorInfo {
+        code: "E106",
+        title: "the legacy key length isn't a whole number of bytes",
+        message: Some("is not a whole number of bytes"),
+        explanation: "Format 1 blocks store key lengths in bits, and this one isn't a multiple of 8.",
+        remediation: "Restore the block from a backup, the format 1 block is damaged."
+    },
+    ErrorInfo {
+        code: "E107",
+        title: "the signature is invalid",
+        message: Some("invalid signature"),
+        explanation: "The block was parsed but its signature can't be trusted. The reason follows and has its \
+                      own code.",
+        remediation: "Explain the reason following the message."
+    },
+    ErrorInfo {
+        code: "E108",
+        title: "OpenSSL failed",
+        message: None,
+        explanation: "OpenSSL reported an error while deriving or unwrapping a key secret.",
+        remediation: "Run with --verbose for the OpenSSL error queue, and check that the OpenSSL build supports \
+                      the algorithms of the block."
+    },
+    ErrorInfo {
+        code: "E109",
+        title: "unknown secret wrap algorithm",
+        message: Some("unknown secret wrap algorithm"),
+        explanation: "A key secret is wrapped with an algorithm this version doesn't know, because the block is \
+                      newer or damaged.",
+        remediation: "Upgrade banjo, or restore the block from a backup if it is damaged."
+    },
+    ErrorInfo {
+        code: "E110",
+        title: "a wrapped key secret is corrupted",
+        message: Some("the wrapped key secret failed its integrity check"),
+        explanation: "AES key wrap detected that a key secret was altered or wrapped under another block secret.",
+        remediation: "Restore the block from a backup. Keys copied from another block must be rewrapped, which \
+                      apply and import commands do."
+    },
+    ErrorInfo {
+        code: "E111",
+        title: "unknown cipher suite",
+        message: Some("unknown cipher suite"),
+        explanation: "The block uses a cipher suite this version doesn't know, because it is newer or damaged.",
+        remediation: "Upgrade banjo, or restore the block from a backup if it is damaged."
+    },
+    ErrorInfo {
+        code: "E112",
+        title: "algorithm outside of the cipher suite",
+        message: Some("the block uses algorithms outside of its"),
+        explanation: "The signature or a key wrap uses an algorithm the declared cipher suite forbids, which \
+                      would let an attacker downgrade the protection of the block.",
+        remediation: "Re-sign the block with a key matching its suite, and rewrap the keys with \
+                      `banjo migrate --wrap`."
+    },
+    ErrorInfo {
+        code: "E113",
+        title: "memory limit exceeded",
+        message: Some("over the limit of"),
+        explanation: "Parsing the block would use more memory than allowed by --max-memory or the max_memory \
+                      setting.",
+        remediation: "Raise the limit if the block is expected to be this large, otherwise check where it comes \
+                      from."
+    },
+    ErrorInfo {
+        code: "E114",
+        title: "shared content is missing",
+        message: Some("which doesn't store any"),
+        explanation: "In a deduplicated block, a keyfile refers to the content of another keyfile that doesn't \
+                      exist or doesn't store its content.",
+        remediation: "Restore the block from a backup. Writing it with `banjo migrate --no-dedup` afterwards \
+                      avoids shared content."
+    },
+    ErrorInfo {
+        code: "E115",
+        title: "shared content is ambiguous",
+        message: Some("which several keyfiles claim"),
+        explanation: "In a deduplicated block, a keyfile refers to the content of another keyfile by a UID \
+                      that more than one keyfile of the block has.",
+        remediation: "Restore the block from a backup. Writing it with `banjo migrate --no-dedup` afterwards \
+                      avoids shared content."
+    },
+    ErrorInfo {
+        code: "E116",
+        title: "string field isn't UTF-8",
+        message: Some("is not valid UTF-8"),
+        explanation: "A block or keyfile name, description, path or tag isn't valid UTF-8, the encoding every \
+                      string of a block is written in. Blocks written by other implementations may have stored \
+                      them in another encoding.",
+        remediation: "Restore the block from a backup, or rewrite it with an implementation storing UTF-8 strings."
+    },
+    ErrorInfo {
+        code: "E200",
+        title: "the block isn't signed",
+        message: Some("the block is not signed"),
+        explanation: "The block has no signature, so its content can't be trusted.",
+        remediation: "Sign it with `banjo migrate --signing-key`, after checking its content."
+    },
+    ErrorInfo {
+        code: "E201",
+        title: "unknown signature algorithm",
+        message: Some("unknown signature algorithm"),
+        explanation: "The block is signed with an algorithm this version doesn't know, because it is newer or \
+                      damaged.",
+        remediation: "Upgrade banjo, or restore the block from a backup if it is damaged."
+    },
+    ErrorInfo {
+        code: "E202",
+        title: "the signature doesn't match",
+        message: Some("the signature doesn't match the root key"),
+        explanation: "The block was modified after being signed, or signed by another root key.",
+        remediation: "Check that --root-key or BANJO_ROOT_KEY designates the right key, and compare the block \
+                      with its last known good version. Never re-sign a block you didn't modify yourself."
+    },
+    ErrorInfo {
+        code: "E203",
+        title: "unsupported signature algorithm",
+        message: Some("signatures are not supported by this build"),
+        explanation: "The block has a post-quantum signature and this build was compiled without the pq \
+                      feature.",
+        remediation: "Use a build with the pq feature, which needs OpenSSL 3.5 or later."
+    },
+    ErrorInfo {
+        code: "E204",
+        title: "the root key has no ML-DSA key",
+        message: Some("the root key has no ML-DSA key"),
+        explanation: "The block has a hybrid signature but only the RSA part of the root key was given.",
+        remediation: "Use the hybrid root key, holding both the RSA and ML-DSA-65 public keys."
+    },
+    ErrorInfo {
+        code: "E205",
+        title: "signature downgrade",
+        message: Some("the block only has an RSA signature"),
+        explanation: "The root key is hybrid but the block is only signed with RSA, which is refused to prevent \
+                      downgrades.",
+        remediation: "Re-sign the block with the hybrid signing key."
+    },
+    ErrorInfo {
+        code: "E206",
+        title: "OpenSSL failed to check the signature",
+        message: None,
+        explanation: "OpenSSL reported an error while checking the signature.",
+        remediation: "Run with --verbose for the OpenSSL error queue, and check the root key format."
+    },
+    ErrorInfo {
+        code: "E207",
+        title: "signature can't be checked while streaming",
+        message: Some("signatures can only be checked over a whole block"),
+        explanation: "ML-DSA signs the content itself rather than its digest, so hybrid signatures can't be checked \
+                      while a block is read as a stream.",
+        remediation: "Use a subcommand that loads the whole block, such as show, within --max-memory."
+    }
+];
+
+/// Entry of the error `code`
+pub fn info(code: &str) -> &'static ErrorInfo {
+    ERRORS.iter().find(|info| info.code == code).expect("Every error code has an entry.")
+}
+
+/// Entries designated by a code or matching an error message, in the order they appear in the message
+pub fn lookup(query: &str) -> Vec<&'static ErrorInfo> {
+    let query = query.trim();
+    if let Some(info) = ERRORS.iter().find(|info| info.code.eq_ignore_ascii_case(query)) {
+        return vec![info]
+    }
+
+    let mut found: Vec<(usize, &ErrorInfo)> = ERRORS.iter()
+        .filter_map(|info| Some((query.find(info.message?)?, info)))
+        .collect();
+    found.sort_by_key(|(position, _)| *position);
+
+    found.into_iter().map(|(_, info)| info).collect()
+}
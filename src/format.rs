@@ -0,0 +1,132 @@
+//! Constants, identifiers and compressed metadata of the keyblock format, shared by the full parser and the metadata parser
+//!
+//! Nothing here needs cryptography, so this module is always built, even without the `crypto` feature.
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use std::io::{self, Read};
+
+/// Magic number starting every keyblock
+pub const MAGIC_NUMBER: &[u8; 5] = b"banjo";
+/// Extension of keyblock files
+pub const BLOCK_EXTENSION: &str = "banjo";
+/// Version specifier used by this implementation
+pub const FORMAT_SPECIFIER: u16 = 4;
+/// Version specifier of blocks without IDs
+pub const PRE_ID_FORMAT_SPECIFIER: u16 = 3;
+/// Version specifier of signed blocks without a cipher suite field
+pub const PRE_SUITE_FORMAT_SPECIFIER: u16 = 2;
+/// Version specifier of blocks using the fixed size signature field
+pub const LEGACY_FORMAT_SPECIFIER: u16 = 1;
+
+/// Size of the signature field of format 1 keyblocks, in bits
+pub const LEGACY_SIGNATURE_SIZE: usize = 50;
+
+/// Prefix letter of block UIDs
+pub const BLOCK_UID_PREFIX: u8 = b'B';
+/// Prefix letter of keyfile UIDs
+pub const KEYFILE_UID_PREFIX: u8 = b'F';
+
+/// Block flag: the name, description and keyfile metadata are zstd compressed, ahead of the key contents
+pub const BLOCK_COMPRESSED_METADATA: u64 = 1 << 0;
+/// Block flag: keys with the same plaintext store it once, see `KEYFILE_SHARED_CONTENT`
+pub const BLOCK_DEDUPLICATED: u64 = 1 << 1;
+/// Block flag: the block is bound to a host, whose fingerprint follows the block ID
+pub const BLOCK_HOST_BOUND: u64 = 1 << 2;
+
+/// Keyfile flag: the key secret isn't stored but derived from the block secret
+pub const KEYFILE_DERIVED_SECRET: u64 = 1 << 0;
+/// Keyfile flag: the key is temporarily disabled and can't be used until thawed
+pub const KEYFILE_FROZEN: u64 = 1 << 1;
+/// Keyfile flag: the content and secret are those of another keyfile, only set in serialized blocks
+pub const KEYFILE_SHARED_CONTENT: u64 = 1 << 2;
+/// Keyfile flags: wrap algorithm of the key secret
+pub const KEYFILE_WRAP_MASK: u64 = 0xff << KEYFILE_WRAP_SHIFT;
+/// Position of the wrap algorithm in the keyfile flags
+pub const KEYFILE_WRAP_SHIFT: u32 = 8;
+
+/// Largest metadata section accepted once decompressed, so a small block can't claim a huge one
+#[cfg(feature = "zstd")]
+const MAX_METADATA_SIZE: u64 = 1 << 26;
+
+/// Size of AES256 secrets, in bytes
+pub const SECRET_SIZE: usize = 32;
+/// Size of host fingerprints, in bytes
+pub const HOST_FINGERPRINT_SIZE: usize = 32;
+
+/// Algorithms used to wrap a secret under another secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum WrapAlgorithm {
+    /// The secret is stored as is
+    None,
+    /// AES-256 key wrap, RFC 3394
+    AesKw,
+    /// AES-256 key wrap with padding, RFC 5649
+    AesKwp
+}
+
+impl WrapAlgorithm {
+    /// Identifier of this algorithm in the serialized format
+    pub fn identifier(self) -> u8 {
+        match self {
+            WrapAlgorithm::None => 0,
+            WrapAlgorithm::AesKw => 1,
+            WrapAlgorithm::AesKwp => 2
+        }
+    }
+
+    /// Algorithm matching a serialized identifier, if known
+    pub fn from_identifier(identifier: u8) -> Option<WrapAlgorithm> {
+        match identifier {
+            0 => Some(WrapAlgorithm::None),
+            1 => Some(WrapAlgorithm::AesKw),
+            2 => Some(WrapAlgorithm::AesKwp),
+            _ => None
+        }
+    }
+
+    /// Size of a secret wrapped with this algorithm, in bytes
+    pub fn wrapped_size(self) -> usize {
+        match self {
+            WrapAlgorithm::None => SECRET_SIZE,
+            // Both add a 64 bits integrity check value, 32 bytes don't need any padding
+            WrapAlgorithm::AesKw | WrapAlgorithm::AesKwp => SECRET_SIZE + 8
+        }
+    }
+}
+
+/// Read and decompress the metadata section of a block, refusing sections over `MAX_METADATA_SIZE`
+#[cfg(feature = "zstd")]
+pub(crate) fn read_compressed_metadata<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length);
+    let mut compressed = Vec::new();
+    reader.take(u64::from(length)).read_to_end(&mut compressed)?;
+    if compressed.len() as u64 != u64::from(length) { return Err(io::ErrorKind::UnexpectedEof.into()) }
+
+    let mut metadata = Vec::new();
+    zstd::stream::read::Decoder::new(compressed.as_slice())?.take(MAX_METADATA_SIZE + 1).read_to_end(&mut metadata)?;
+    if metadata.len() as u64 > MAX_METADATA_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the metadata section is too large once decompressed"))
+    }
+
+    Ok(metadata)
+}
+
+/// Read the metadata section of a block, which needs the `zstd` feature
+#[cfg(not(feature = "zstd"))]
+pub(crate) fn read_compressed_metadata<R: Read>(_: &mut R) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the metadata of this block is zstd compressed, which needs the zstd feature"))
+}
+
+/// Whether `content` starts with the magic number followed by a known format specifier
+pub fn has_block_header(content: &[u8]) -> bool {
+    let known = [FORMAT_SPECIFIER, PRE_ID_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER, LEGACY_FORMAT_SPECIFIER];
+
+    match content.strip_prefix(&MAGIC_NUMBER[..]) {
+        Some([low, high, ..]) => known.contains(&u16::from_le_bytes([*low, *high])),
+        _ => false
+    }
+}
@@ -0,0 +1,106 @@
+//! Minimal access to git repositories of keyblocks, through the `git` executable
+//!
+//! Going through the executable reuses the user's git configuration, credentials and SSH setup. Neither gix nor
+//! libgit2 handle credential helpers, SSH agents, `insteadOf` rewrites and hooks the way git does, so syncing
+//! through them would break on setups where `git pull` works. They would also add a large dependency for a handful
+//! of commands. Arguments coming from the user are always placed after `--` or checked not to start with a dash,
+//! so they can't be read as options.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fmt, io};
+
+/// A git working tree
+#[derive(Debug, Clone)]
+pub struct Repository {
+    pub path: PathBuf
+}
+
+/// Enumeration of the potential errors when running git
+#[derive(Debug)]
+pub enum GitErrors {
+    /// git couldn't be started
+    IOError(io::Error),
+    /// git exited with an error, with its arguments and standard error
+    CommandFailed(String, String)
+}
+
+impl fmt::Display for GitErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitErrors::IOError(error) => write!(f, "failed to run git: {}", error),
+            GitErrors::CommandFailed(command, error) => write!(f, "git {} failed: {}", command, error.trim())
+        }
+    }
+}
+
+impl std::error::Error for GitErrors {}
+
+impl From<io::Error> for GitErrors {
+    fn from(error: io::Error) -> Self {
+        GitErrors::IOError(error)
+    }
+}
+
+impl Repository {
+    /// Open the working tree containing `path`
+    pub fn open(path: &Path) -> Result<Repository, GitErrors> {
+        let repository = Repository { path: path.to_path_buf() };
+        let root = repository.run(&["rev-parse", "--show-toplevel"])?;
+
+        Ok(Repository { path: PathBuf::from(root.trim_end()) })
+    }
+
+    /// Run git in this working tree, returning its standard output
+    pub fn run(&self, arguments: &[&str]) -> Result<String, GitErrors> {
+        Ok(String::from_utf8_lossy(&self.run_bytes(arguments)?).into_owned())
+    }
+
+    /// Run git in this working tree, returning its raw standard output
+    pub fn run_bytes(&self, arguments: &[&str]) -> Result<Vec<u8>, GitErrors> {
+        let output = Command::new("git").arg("-C").arg(&self.path).args(arguments).output()?;
+
+        if !output.status.success() {
+            return Err(GitErrors::CommandFailed(
+                arguments.join(" "), String::from_utf8_lossy(&output.stderr).into_owned()
+            ))
+        }
+
+        Ok(output.stdout)
+    }
+
+    /// Paths of the uncommitted changes, relative to the working tree, including untracked files
+    pub fn changed_files(&self) -> Result<Vec<String>, GitErrors> {
+        let status = self.run(&["status", "--porcelain", "--untracked-files=all", "-z"])?;
+
+        // Entries are "XY path", renames are followed by their original path
+        let mut files = Vec::new();
+        let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+        while let Some(entry) = entries.next() {
+            if entry.len() < 4 { continue }
+            if entry.starts_with('R') || entry.starts_with('C') { entries.next(); }
+            files.push(entry[3..].to_string());
+        }
+
+        Ok(files)
+    }
+
+    /// Paths changed between two revisions
+    pub fn diff_files(&self, from: &str, to: &str) -> Result<Vec<String>, GitErrors> {
+        let diff = self.run(&["diff", "--name-only", "--no-renames", "-z", from, to])?;
+        Ok(diff.split('\0').filter(|path| !path.is_empty()).map(ToString::to_string).collect())
+    }
+
+    /// Content of `path` at `revision`, `None` if it doesn't exist there
+    pub fn show(&self, revision: &str, path: &str) -> Result<Option<Vec<u8>>, GitErrors> {
+        let object = format!("{}:{}", revision, path);
+        if self.run(&["cat-file", "-e", &object]).is_err() { return Ok(None) }
+
+        self.run_bytes(&["show", &object]).map(Some)
+    }
+
+    /// Resolve a revision to a commit hash
+    pub fn resolve(&self, revision: &str) -> Result<String, GitErrors> {
+        Ok(self.run(&["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", revision)])?.trim().to_string())
+    }
+}
@@ -0,0 +1,52 @@
+//! Process hardening applied at startup, before any secret is loaded
+//!
+//! Crashes must never write key material into core files, and other processes of the same user must not be
+//! able to read it by attaching a debugger. Both can be allowed again for debugging with `--allow-core-dumps`
+//! or the `allow-core-dumps` setting.
+
+use std::io;
+
+/// Disable core dumps and, where the platform allows it, debugger attachment
+pub fn harden() -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // Safety: setrlimit only reads `limit`
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 { return Err(io::Error::last_os_error()) }
+
+    deny_tracing()
+}
+
+/// Make the process non-dumpable, which also forbids ptrace by unprivileged processes
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn deny_tracing() -> io::Result<()> {
+    // Safety: PR_SET_DUMPABLE only changes a flag of the process
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 { return Err(io::Error::last_os_error()) }
+    Ok(())
+}
+
+/// Refuse debugger attachment
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn deny_tracing() -> io::Result<()> {
+    // Safety: PT_DENY_ATTACH only changes a flag of the process
+    if unsafe { libc::ptrace(libc::PT_DENY_ATTACH, 0, std::ptr::null_mut(), 0) } != 0 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+/// Disable tracing of the process
+#[cfg(target_os = "freebsd")]
+fn deny_tracing() -> io::Result<()> {
+    let mut control = libc::PROC_TRACE_CTL_DISABLE;
+    // Safety: `control` outlives the call, which only changes a flag of the process
+    let result = unsafe {
+        libc::procctl(libc::P_PID, 0, libc::PROC_TRACE_CTL, &mut control as *mut libc::c_int as *mut libc::c_void)
+    };
+    if result != 0 { return Err(io::Error::last_os_error()) }
+    Ok(())
+}
+
+/// Other platforms only get the core file size limit
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+fn deny_tracing() -> io::Result<()> {
+    Ok(())
+}
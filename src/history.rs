@@ -0,0 +1,120 @@
+//! Previous versions of keys, kept next to them in the block
+//!
+//! Version 1, the latest previous content, is stored at `PATH.previous`, and older versions at
+//! `PATH.previous.2`, `PATH.previous.3` and so on. The current key keeps its UID, previous versions get
+//! fresh ones.
+
+use crate::crypto::CryptoErrors;
+use crate::id::GlobalId;
+use crate::keyblock::{KeyBlock, KeyFile, KEYFILE_DERIVED_SECRET};
+use openssl::error::ErrorStack;
+use std::fmt;
+
+/// Suffix of the paths of previous versions
+const VERSION_SUFFIX: &str = ".previous";
+
+/// Enumeration of the potential errors when handling key versions
+#[derive(Debug)]
+pub enum HistoryErrors {
+    /// No key is stored at this path
+    NoKey(String),
+    /// The key doesn't have this previous version
+    NoVersion(String, usize),
+    /// No keyfile UID is left in the block
+    NoUidLeft,
+    /// The content of a version couldn't be decrypted or encrypted again
+    CryptoError(CryptoErrors),
+    /// An error occurred in OpenSSL
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for HistoryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryErrors::NoKey(path) => write!(f, "no key at path {}", path),
+            HistoryErrors::NoVersion(path, version) => write!(f, "{} has no previous version {}", path, version),
+            HistoryErrors::NoUidLeft => write!(f, "no keyfile UID is left in this block"),
+            HistoryErrors::CryptoError(error) => write!(f, "{}", error),
+            HistoryErrors::OpenSSLError(error) => write!(f, "OpenSSL error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for HistoryErrors {}
+
+impl From<ErrorStack> for HistoryErrors {
+    fn from(error: ErrorStack) -> Self {
+        HistoryErrors::OpenSSLError(error)
+    }
+}
+
+impl From<CryptoErrors> for HistoryErrors {
+    fn from(error: CryptoErrors) -> Self {
+        HistoryErrors::CryptoError(error)
+    }
+}
+
+/// Path of the previous version `version` of the key at `path`, starting at 1
+pub fn version_path(path: &str, version: usize) -> String {
+    match version {
+        1 => format!("{}{}", path, VERSION_SUFFIX),
+        version => format!("{}{}.{}", path, VERSION_SUFFIX, version)
+    }
+}
+
+/// Path of the key `path` is a previous version of, if it is one
+pub fn version_of(path: &str) -> Option<&str> {
+    let (base, suffix) = path.rsplit_once(VERSION_SUFFIX)?;
+    let is_version = suffix.is_empty() || suffix.strip_prefix('.').is_some_and(|number| number.parse::<usize>().is_ok());
+
+    if is_version && !base.is_empty() { Some(base) } else { None }
+}
+
+/// Previous versions of the key at `path`, latest first
+pub fn versions<'a>(block: &'a KeyBlock, path: &str) -> Vec<(usize, &'a KeyFile)> {
+    (1..).map(|version| block.keys.get(&version_path(path, version)).map(|key| (version, key)))
+        .take_while(Option::is_some)
+        .flatten()
+        .collect()
+}
+
+/// Keep a copy of the key at `path` as its version 1, dropping the versions older than `keep`
+pub fn keep_version(block: &mut KeyBlock, path: &str, keep: usize) -> Result<(), HistoryErrors> {
+    if keep == 0 { return Ok(()) }
+    let mut current = block.keys.get(path).cloned().ok_or_else(|| HistoryErrors::NoKey(path.to_string()))?;
+
+    // Every version moves one step back, the ones past `keep` being dropped
+    let previous: Vec<KeyFile> = versions(block, path).into_iter().map(|(_, key)| key.clone()).collect();
+    for key in &previous {
+        block.keys.remove(&key.path);
+    }
+    for (version, mut key) in (2..=keep).zip(previous) {
+        key.path = version_path(path, version);
+        block.keys.insert(key.path.clone(), key);
+    }
+
+    current.uid = block.fresh_key_uid()?.ok_or(HistoryErrors::NoUidLeft)?;
+    current.id = GlobalId::generate()?;
+    // A secret derived from the old UID has to be stored, the content is encrypted under it
+    current.flags &= !KEYFILE_DERIVED_SECRET;
+    current.path = version_path(path, 1);
+    block.keys.insert(current.path.clone(), current);
+
+    Ok(())
+}
+
+/// Restore the content of version `version` of the key at `path`, the replaced content becoming version 1
+pub fn rollback(block: &mut KeyBlock, path: &str, version: usize, keep: usize) -> Result<(), HistoryErrors> {
+    let restored = block.keys.get(&version_path(path, version))
+        .ok_or_else(|| HistoryErrors::NoVersion(path.to_string(), version))?
+        .open()?;
+
+    // Never drop the version being restored
+    keep_version(block, path, keep.max(version + 1))?;
+
+    // Versions may have another secret than the current key
+    let key = block.keys.get_mut(path).expect("The key was kept above.");
+    key.seal(&restored)?;
+
+    Ok(())
+}
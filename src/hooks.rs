@@ -0,0 +1,130 @@
+//! User commands run around lifecycle events, configured in the `[hooks]` table
+//!
+//! Hooks are run with `sh -c`. The details of the event are passed as a JSON object on the standard input,
+//! and as `BANJO_*` environment variables for the simple ones. A failing `pre-*` hook aborts the operation.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::process::{Command, ExitStatus, Stdio};
+use std::{fmt, io};
+
+/// Hook commands, the `[hooks]` table of the configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run before a keyblock is signed, a failure aborts the change
+    pub pre_sign: Option<String>,
+    /// Run when a keyblock fails verification
+    pub on_verify_failure: Option<String>,
+    /// Run before keys are written to their deployment target, a failure aborts the deployment
+    pub pre_deploy: Option<String>,
+    /// Run once keys have been written to their deployment target
+    pub post_deploy: Option<String>,
+    /// Run once an ACME certificate has been renewed and its block written
+    pub post_renew: Option<String>,
+    /// Run once a key has been rotated and its block written, to redeploy it
+    pub post_rotate: Option<String>
+}
+
+/// Lifecycle events hooks can run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreSign,
+    OnVerifyFailure,
+    PreDeploy,
+    PostDeploy,
+    PostRenew,
+    PostRotate
+}
+
+impl Hook {
+    /// Name of this hook in the configuration and in `BANJO_HOOK`
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreSign => "pre-sign",
+            Hook::OnVerifyFailure => "on-verify-failure",
+            Hook::PreDeploy => "pre-deploy",
+            Hook::PostDeploy => "post-deploy",
+            Hook::PostRenew => "post-renew",
+            Hook::PostRotate => "post-rotate"
+        }
+    }
+
+    /// Command configured for this hook, if any
+    fn command(self, config: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::PreSign => config.pre_sign.as_deref(),
+            Hook::OnVerifyFailure => config.on_verify_failure.as_deref(),
+            Hook::PreDeploy => config.pre_deploy.as_deref(),
+            Hook::PostDeploy => config.post_deploy.as_deref(),
+            Hook::PostRenew => config.post_renew.as_deref(),
+            Hook::PostRotate => config.post_rotate.as_deref()
+        }
+    }
+}
+
+/// Enumeration of the potential errors when running hooks
+#[derive(Debug)]
+pub enum HookErrors {
+    /// The hook couldn't be started
+    IOError(io::Error),
+    /// The hook exited with an error
+    Failed(&'static str, ExitStatus)
+}
+
+impl fmt::Display for HookErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookErrors::IOError(error) => write!(f, "failed to run the hook: {}", error),
+            HookErrors::Failed(hook, status) => write!(f, "the {} hook failed ({})", hook, status)
+        }
+    }
+}
+
+impl std::error::Error for HookErrors {}
+
+impl From<io::Error> for HookErrors {
+    fn from(error: io::Error) -> Self {
+        HookErrors::IOError(error)
+    }
+}
+
+/// Run the command configured for `hook`, if any, with the event `details`, a JSON object
+pub fn run_hook(config: &HooksConfig, hook: Hook, details: Value) -> Result<(), HookErrors> {
+    let command = match hook.command(config) {
+        Some(command) => command,
+        None => return Ok(())
+    };
+    let mut payload = match details {
+        Value::Object(details) => details,
+        _ => Map::new()
+    };
+
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command).env("BANJO_HOOK", hook.name()).stdin(Stdio::piped());
+    for (name, value) in &payload {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            _ => continue
+        };
+        process.env(format!("BANJO_{}", name.to_uppercase()), value);
+    }
+
+    payload.insert("hook".to_string(), Value::from(hook.name()));
+
+    let mut child = process.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks don't have to read their input
+        match stdin.write_all(&serde_json::to_vec(&payload).expect("Serializing JSON values can't fail.")) {
+            Err(error) if error.kind() != io::ErrorKind::BrokenPipe => return Err(error.into()),
+            _ => {}
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() { return Err(HookErrors::Failed(hook.name(), status)) }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Host fingerprints, binding a keyblock to the machine allowed to deploy its keys
+//!
+//! The fingerprint of a host is the SHA256 digest of its machine ID, so blocks don't reveal the ID itself.
+//! Deploying commands refuse blocks bound to another host. The binding is part of the signed block, but the
+//! machine ID is only as trustworthy as the host reporting it, so it guards against mistakes, not attackers.
+
+use crate::utils::to_hex;
+pub use crate::format::HOST_FINGERPRINT_SIZE;
+use openssl::sha::Sha256;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// Files holding the machine ID, by preference
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Prefix of the hashed machine ID, so the fingerprint can't be mistaken for another digest of it
+const FINGERPRINT_INFO: &[u8] = b"banjo host fingerprint";
+
+/// SHA256 digest of the machine ID of a host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostFingerprint(pub [u8; HOST_FINGERPRINT_SIZE]);
+
+impl HostFingerprint {
+    /// Fingerprint of a machine ID
+    pub fn of_machine_id(machine_id: &str) -> HostFingerprint {
+        let mut hasher = Sha256::new();
+        hasher.update(FINGERPRINT_INFO);
+        hasher.update(machine_id.trim().as_bytes());
+        HostFingerprint(hasher.finish())
+    }
+
+    /// Fingerprint of this host
+    pub fn local() -> io::Result<HostFingerprint> {
+        for path in MACHINE_ID_PATHS {
+            match fs::read_to_string(path) {
+                Ok(machine_id) if !machine_id.trim().is_empty() => return Ok(HostFingerprint::of_machine_id(&machine_id)),
+                Ok(_) => continue,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error)
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::NotFound, "this host has no machine ID"))
+    }
+}
+
+impl fmt::Display for HostFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0))
+    }
+}
+
+/// Error returned when parsing an invalid fingerprint
+#[derive(Debug)]
+pub struct InvalidFingerprint(pub String);
+
+impl fmt::Display for InvalidFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid host fingerprint \"{}\", expected {} hexadecimal digits", self.0, HOST_FINGERPRINT_SIZE * 2)
+    }
+}
+
+impl std::error::Error for InvalidFingerprint {}
+
+impl FromStr for HostFingerprint {
+    type Err = InvalidFingerprint;
+
+    fn from_str(text: &str) -> Result<HostFingerprint, InvalidFingerprint> {
+        if text.len() != HOST_FINGERPRINT_SIZE * 2 || !text.chars().all(|character| character.is_ascii_hexdigit()) {
+            return Err(InvalidFingerprint(text.to_string()))
+        }
+
+        let mut bytes = [0; HOST_FINGERPRINT_SIZE];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).map_err(|_| InvalidFingerprint(text.to_string()))?;
+        }
+
+        Ok(HostFingerprint(bytes))
+    }
+}
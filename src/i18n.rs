@@ -0,0 +1,112 @@
+//! Translations of the messages printed for the user
+//!
+//! Prompts, dry run reports and table headers are looked up in the Fluent catalogs of `src/i18n`, in the
+//! language of the `language` setting, or else of the `LC_ALL`, `LC_MESSAGES` and `LANG` environment
+//! variables. Messages missing from a catalog fall back to English. Log messages and errors stay in English,
+//! so they can be searched for and explained with `banjo explain`.
+
+use fluent::concurrent::FluentBundle;
+use fluent::{FluentArgs, FluentResource};
+use std::env;
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+pub use fluent::fluent_args;
+
+/// Languages with a catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    French
+}
+
+impl Language {
+    /// Language of a locale such as `fr`, `fr-CA` or `fr_FR.UTF-8`, if it has a catalog
+    pub fn from_locale(locale: &str) -> Option<Language> {
+        let language = locale.split(['_', '-', '.', '@'].as_ref()).next()?;
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Language::English),
+            "fr" => Some(Language::French),
+            _ => None
+        }
+    }
+
+    /// Language of the `configured` setting or of the environment, English by default
+    pub fn detect(configured: Option<&str>) -> Language {
+        if let Some(language) = configured.and_then(Language::from_locale) { return language }
+
+        // Like gettext, the first variable set wins even if its language has no catalog
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Language::from_locale(&locale))
+            .unwrap_or(Language::English)
+    }
+
+    fn identifier(self) -> LanguageIdentifier {
+        match self {
+            Language::English => "en",
+            Language::French => "fr"
+        }.parse().expect("Language identifiers are valid.")
+    }
+
+    fn catalog(self) -> &'static str {
+        match self {
+            Language::English => include_str!("i18n/en.ftl"),
+            Language::French => include_str!("i18n/fr.ftl")
+        }
+    }
+
+    fn bundle(self) -> FluentBundle<FluentResource> {
+        let resource = FluentResource::try_new(self.catalog().to_string()).expect("Catalogs are valid Fluent.");
+        let mut bundle = FluentBundle::new_concurrent(vec![self.identifier()]);
+        // Unicode isolation marks show up as garbage in most terminals
+        bundle.set_use_isolating(false);
+        bundle.add_resource(resource).expect("Catalogs don't define a message twice.");
+        bundle
+    }
+}
+
+/// Bundles messages are looked up in, the selected language first
+static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// Select the language of the messages, which can only be done once and before any message is translated
+pub fn init(language: Language) {
+    let mut bundles = vec![language.bundle()];
+    if language != Language::English { bundles.push(Language::English.bundle()) }
+
+    let _ = BUNDLES.set(bundles);
+}
+
+/// Message `id` in the selected language
+pub fn tr(id: &str) -> String {
+    tr_with(id, None)
+}
+
+/// Message `id` in the selected language, with its variables set to `args`
+///
+/// Unknown messages are returned as their ID, so a missing message shows up instead of failing a command.
+pub fn tr_with(id: &str, args: Option<&FluentArgs>) -> String {
+    let bundles = BUNDLES.get_or_init(|| vec![Language::English.bundle()]);
+    bundles.iter()
+        .find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = Vec::new();
+            Some(bundle.format_pattern(pattern, args, &mut errors).into_owned())
+        })
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// Translated table header, `header` itself if it has no translation
+pub fn header(header: &str) -> String {
+    let id = format!("header-{}", header.to_lowercase().replace(' ', "-"));
+    match tr(&id) {
+        translated if translated == id => header.to_string(),
+        translated => translated
+    }
+}
+
+/// Whether `answer` to a yes or no question means yes
+pub fn is_yes(answer: &str) -> bool {
+    tr("answer-yes").split(',').any(|yes| yes.trim() == answer.trim().to_lowercase())
+}
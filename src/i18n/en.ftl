@@ -0,0 +1,63 @@
+# Messages printed by the command line, see src/i18n.rs
+
+## Prompts
+
+confirm-prompt = { $question } [y/N]
+# Comma separated answers meaning yes, compared in lowercase
+answer-yes = y, yes
+confirm-export = Export { $path }?
+confirm-deploy = Deploy { $path }?
+
+## Dry runs
+
+dry-run-write = Would write { $size } bytes to { $path } (currently { $current } bytes):
+dry-run-create = Would create { $path } ({ $size } bytes):
+dry-run-unchanged = no change besides the signature
+dry-run-restore = Would restore keyblock "{ $name }" ({ $keys ->
+        [one] { $keys } key
+       *[other] { $keys } keys
+    }, { $size } bytes) to { $path }.
+dry-run-deploy = Would install { $path } to { $target }.
+dry-run-commit = Would commit "{ $subject }":
+dry-run-pull = Would pull from { $remote }.
+dry-run-pull-push = Would pull from { $remote } and push the result.
+
+## Reports
+
+paper-no-words = (no word list, { $size } bytes can't be encoded)
+prune-expired = expired
+prune-too-large = too large
+prune-unused = unused
+
+## Table headers
+
+header-uid = UID
+header-id = ID
+header-path = PATH
+header-name = NAME
+header-description = DESCRIPTION
+header-type = TYPE
+header-size = SIZE
+header-tags = TAGS
+header-expiry = EXPIRY
+header-rotation = ROTATION
+header-hmac = HMAC
+header-serial = SERIAL
+header-subject = SUBJECT
+header-not-after = NOT AFTER
+header-renewed-by = RENEWED BY
+header-version = VERSION
+header-interval = INTERVAL
+header-state = STATE
+header-used-by = USED BY
+header-location = LOCATION
+header-reference = REFERENCE
+header-target = TARGET
+header-command = COMMAND
+header-runs = RUNS
+header-failures = FAILURES
+header-block = BLOCK
+header-accesses = ACCESSES
+header-check = CHECK
+header-status = STATUS
+header-detail = DETAIL
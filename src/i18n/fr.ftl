@@ -0,0 +1,63 @@
+# Messages affichés par la ligne de commande, voir src/i18n.rs
+
+## Questions
+
+confirm-prompt = { $question } [o/N]
+# Réponses signifiant oui, séparées par des virgules et comparées en minuscules
+answer-yes = o, oui, y, yes
+confirm-export = Exporter { $path } ?
+confirm-deploy = Déployer { $path } ?
+
+## Simulations
+
+dry-run-write = Écrirait { $size } octets dans { $path } (actuellement { $current } octets) :
+dry-run-create = Créerait { $path } ({ $size } octets) :
+dry-run-unchanged = aucun changement hormis la signature
+dry-run-restore = Restaurerait le trousseau « { $name } » ({ $keys ->
+        [one] { $keys } clé
+       *[other] { $keys } clés
+    }, { $size } octets) dans { $path }.
+dry-run-deploy = Installerait { $path } dans { $target }.
+dry-run-commit = Créerait le commit « { $subject } » :
+dry-run-pull = Récupérerait les changements de { $remote }.
+dry-run-pull-push = Récupérerait les changements de { $remote } et y pousserait le résultat.
+
+## Rapports
+
+paper-no-words = (pas de liste de mots, { $size } octets ne peuvent pas être encodés)
+prune-expired = expirée
+prune-too-large = trop grande
+prune-unused = inutilisée
+
+## En-têtes de tableaux
+
+header-uid = UID
+header-id = ID
+header-path = CHEMIN
+header-name = NOM
+header-description = DESCRIPTION
+header-type = TYPE
+header-size = TAILLE
+header-tags = ÉTIQUETTES
+header-expiry = EXPIRATION
+header-rotation = ROTATION
+header-hmac = HMAC
+header-serial = NUMÉRO DE SÉRIE
+header-subject = SUJET
+header-not-after = EXPIRE LE
+header-renewed-by = RENOUVELÉ PAR
+header-version = VERSION
+header-interval = INTERVALLE
+header-state = ÉTAT
+header-used-by = UTILISÉ PAR
+header-location = EMPLACEMENT
+header-reference = RÉFÉRENCE
+header-target = CIBLE
+header-command = COMMANDE
+header-runs = EXÉCUTIONS
+header-failures = ÉCHECS
+header-block = TROUSSEAU
+header-accesses = ACCÈS
+header-check = VÉRIFICATION
+header-status = STATUT
+header-detail = DÉTAIL
@@ -0,0 +1,109 @@
+//! Globally unique identifiers of blocks and keyfiles
+//!
+//! UIDs only have 8 bits of entropy and collide across blocks, so every block and keyfile of a format 4
+//! block also has a 128-bit ID. New IDs are random (UUID version 4). Blocks of older formats get IDs derived
+//! from their block secret (UUID version 8), which are stable until the block is written in format 4 and
+//! they are stored.
+
+#[cfg(feature = "crypto")]
+use openssl::error::ErrorStack;
+#[cfg(feature = "crypto")]
+use openssl::rand::rand_bytes;
+#[cfg(feature = "crypto")]
+use openssl::sha::Sha256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Size of IDs, in bytes
+pub const ID_SIZE: usize = 16;
+
+/// A 128-bit identifier, formatted like a UUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlobalId(pub [u8; ID_SIZE]);
+
+#[cfg(feature = "crypto")]
+impl GlobalId {
+    /// New random ID
+    pub fn generate() -> Result<GlobalId, ErrorStack> {
+        let mut bytes = [0; ID_SIZE];
+        rand_bytes(&mut bytes)?;
+
+        Ok(GlobalId::with_version(bytes, 4))
+    }
+
+    /// ID derived from the SHA256 digest of `parts`, always the same for the same parts
+    pub fn derive(parts: &[&[u8]]) -> GlobalId {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            // Length prefixes keep the parts apart
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+
+        let mut bytes = [0; ID_SIZE];
+        bytes.copy_from_slice(&hasher.finish()[..ID_SIZE]);
+        GlobalId::with_version(bytes, 8)
+    }
+
+    /// Set the UUID version and variant bits of `bytes`
+    fn with_version(mut bytes: [u8; ID_SIZE], version: u8) -> GlobalId {
+        bytes[6] = (bytes[6] & 0x0f) | version << 4;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        GlobalId(bytes)
+    }
+}
+
+impl fmt::Display for GlobalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if [4, 6, 8, 10].contains(&index) { write!(f, "-")? }
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned when parsing an invalid ID
+#[derive(Debug)]
+pub struct InvalidId(pub String);
+
+impl fmt::Display for InvalidId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid ID \"{}\", expected 32 hexadecimal digits", self.0)
+    }
+}
+
+impl std::error::Error for InvalidId {}
+
+impl FromStr for GlobalId {
+    type Err = InvalidId;
+
+    /// Parse an ID, with or without its dashes
+    fn from_str(text: &str) -> Result<GlobalId, InvalidId> {
+        let digits: String = text.chars().filter(|character| *character != '-').collect();
+        if digits.len() != ID_SIZE * 2 || !digits.chars().all(|character| character.is_ascii_hexdigit()) {
+            return Err(InvalidId(text.to_string()))
+        }
+
+        let mut bytes = [0; ID_SIZE];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|_| InvalidId(text.to_string()))?;
+        }
+
+        Ok(GlobalId(bytes))
+    }
+}
+
+impl Serialize for GlobalId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for GlobalId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<GlobalId, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
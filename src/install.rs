@@ -0,0 +1,118 @@
+//! Atomic installation of the files assembled from keyblocks, such as `authorized_keys`
+//!
+//! Files are written to a temporary file next to their destination, given their mode and owner, then renamed
+//! over the destination, so readers only ever see the previous or the new file.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{chown, DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// File listing the local accounts
+const PASSWD_PATH: &str = "/etc/passwd";
+
+/// Enumeration of the potential errors when installing files
+#[derive(Debug)]
+pub enum InstallErrors {
+    /// No local account has this name
+    UnknownUser(String),
+    /// The destination has no file name
+    InvalidPath(PathBuf),
+    IOError(io::Error)
+}
+
+impl fmt::Display for InstallErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallErrors::UnknownUser(name) => write!(f, "no local user named {:?}", name),
+            InstallErrors::InvalidPath(path) => write!(f, "{} isn't a file path", path.display()),
+            InstallErrors::IOError(error) => write!(f, "IO error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for InstallErrors {}
+
+impl From<io::Error> for InstallErrors {
+    fn from(error: io::Error) -> Self {
+        InstallErrors::IOError(error)
+    }
+}
+
+/// Local account owning installed files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf
+}
+
+impl Account {
+    /// Look up a local account by name in /etc/passwd
+    pub fn lookup(name: &str) -> Result<Account, InstallErrors> {
+        let passwd = fs::read_to_string(PASSWD_PATH)?;
+
+        passwd.lines()
+            .map(|line| line.split(':').collect::<Vec<_>>())
+            .find(|fields| fields.len() >= 7 && fields[0] == name)
+            .and_then(|fields| Some(Account {
+                name: name.to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+                home: PathBuf::from(fields[5])
+            }))
+            .ok_or_else(|| InstallErrors::UnknownUser(name.to_string()))
+    }
+}
+
+/// Create `path` with `mode` and `owner` if it doesn't exist, without changing an existing directory
+pub fn ensure_directory(path: &Path, mode: u32, owner: Option<&Account>) -> Result<(), InstallErrors> {
+    if path.is_dir() { return Ok(()) }
+
+    fs::DirBuilder::new().mode(mode).create(path)?;
+    if let Some(owner) = owner {
+        chown(path, Some(owner.uid), Some(owner.gid))?;
+    }
+
+    Ok(())
+}
+
+/// Atomically replace `path` with `content`, giving it `mode` and `owner`
+pub fn install(path: &Path, content: &[u8], mode: u32, owner: Option<&Account>) -> Result<(), InstallErrors> {
+    let file_name = path.file_name().ok_or_else(|| InstallErrors::InvalidPath(path.to_path_buf()))?;
+    let mut temporary_name = OsString::from(".");
+    temporary_name.push(file_name);
+    temporary_name.push(".banjo-tmp");
+    let temporary = path.with_file_name(temporary_name);
+
+    // A leftover from an interrupted installation is replaced
+    match fs::remove_file(&temporary) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+        _ => {}
+    }
+
+    let result = write_temporary(&temporary, content, mode, owner)
+        .and_then(|()| fs::rename(&temporary, path).map_err(InstallErrors::from));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+/// Write the temporary file of `install`, readable by its owner only until its mode is set
+fn write_temporary(temporary: &Path, content: &[u8], mode: u32, owner: Option<&Account>) -> Result<(), InstallErrors> {
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(temporary)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+
+    if let Some(owner) = owner {
+        chown(temporary, Some(owner.uid), Some(owner.gid))?;
+    }
+    fs::set_permissions(temporary, fs::Permissions::from_mode(mode))?;
+
+    Ok(())
+}
@@ -1,77 +1,153 @@
-use std::collections::HashMap;
-use openssl::rsa::Rsa;
-use openssl::pkey::Public;
+//! This file will be used to parse and provide a structure to represent a keyblock
+//!
+//! Here is the keyblock format:
+//! ```text
+//! keyblock = magic_number, flags, cipher_suite, aes256, metadata, 64_number, { keyfile }, signature, [ crc ]
+//! compressed_keyblock = magic_number, flags, cipher_suite, aes256, uid, [ id ], 32_number, zstd_frame, { content }, signature
+//!
+//! keyfile = flags, [ aes256 ], null_string, metadata, tags, 64_number, ( { byte } | uid )
+//! metadata = uid, [ id ], null_string, null_string
+//! tags = 16_number, { null_string }
+//!
+//! aes256 = 256 * bit
+//! magic_number = "banjo", 16 * bit
+//! cipher_suite = 16_number
+//! signature = 16_number, 32_number, { byte }
+//! crc = 32 * bit
+//! uid = "F" | "B", 8 * bit
+//! id = 128 * bit
+//!
+//! null_string = ? UTF-8 characters except "\0" ?, "\0"
+//! 16_number = 16 * bit
+//! 32_number = 32 * bit
+//! 64_number = 64 * bit
+//! flags = 64 * bit
+//! content = { byte }
+//! byte = 8 * bit
+//! bit = (0b0 | 0b1)
+//! ```
+//!
+//! Structure content:
+//!     - keyblock:
+//!         - magic number "banjo"
+//!         - 16 bits format specifier
+//!         - 64 bits feature/setting flags, bit 0 enabling metadata compression and bit 1 content deduplication
+//!         - 16 bits cipher suite identifier, see the `suite` module (not in format 1 and 2 blocks)
+//!         - aes256 block secret, encrypted by the block password (if any) and by the root key
+//!         - 16 bits UID starting with "B"
+//!         - 128 bits ID, see the `id` module (not in format 1 to 3 blocks)
+//!         - Name and description null terminated strings
+//!         - 64 bits number of keyfiles
+//!         - List of keyfiles
+//!         - Signature of the above content:
+//!             - 16 bits signature algorithm (0: unsigned, 1: RSA4096/SHA256, 2: RSA4096/SHA256 and ML-DSA-65)
+//!             - 32 bits signature length, in bytes
+//!             - Signature bytes
+//!         - CRC checksum (if any)
+//!     - keyfile:
+//!         - 64 bits feature/setting flags, bits 16 to 19 holding usage policies (see the `policy` module),
+//!           bits 24 to 31 the content type (see the `content` module) and bits 32 to 63 the rotation
+//!           schedule (see the `rotation` module)
+//!         - aes256 key secret, encrypted by the key password (if any) and by the block secret
+//!           Omitted when the `KEYFILE_DERIVED_SECRET` flag is set, the secret is then derived from the
+//!           block secret and the key UID with HKDF-SHA256
+//!           Omitted as well when the `KEYFILE_SHARED_CONTENT` flag is set
+//!           Wrapped under the block secret when bits 8 to 15 of the flags select a wrap algorithm
+//!           (0: none, 1: AES-KW, 2: AES-KWP), the wrapped secret is then 40 bytes long
+//!         - 16 bits UID starting with "F"
+//!         - 128 bits ID (not in format 1 to 3 blocks)
+//!         - Null terminated key path
+//!         - Name and description null terminated strings
+//!         - 16 bits number of tags, followed by the null terminated tags (not in format 1 blocks)
+//!         - 64 bits key length, in bytes (in bits for format 1 blocks)
+//!         - 8 bits aligned key content, encrypted under the key secret with chunked AES-256-GCM (see the
+//!           `crypto` module), the key length being the length of the encrypted content
+//!           Replaced by the 16 bits UID of the keyfile storing the same content when the
+//!           `KEYFILE_SHARED_CONTENT` flag is set, the key then uses the secret of that keyfile
+//!
+//! Blocks with the `BLOCK_COMPRESSED_METADATA` flag store everything from the block name up to the key contents
+//! as a single zstd frame, prefixed by its 32 bits compressed length: the block name and description, the number
+//! of keyfiles and every keyfile up to its key length. The key contents follow in the same order. Key contents
+//! are encrypted, so compressing them wouldn't gain anything.
+//!
+//! Blocks with the `BLOCK_DEDUPLICATED` flag store the content of keys with the same plaintext once, under the
+//! secret of the first of them by path. Plaintexts are compared by their HMAC-SHA256 under a secret derived from
+//! the block secret, so the block doesn't reveal which keys share content to anyone without it. The keyfile
+//! storing the content has to be the only one with its UID, otherwise the block is invalid.
+//!
+//! Blocks with the `BLOCK_HOST_BOUND` flag store the fingerprint of the only host allowed to deploy their keys
+//! right after the block ID, see the `host` module.
+//!
+//! Format 1 blocks used a fixed 50 bits signature field instead of the signature section.
+//! They can still be loaded without verification, and are serialized to the current format.
+//! Format 2 blocks don't have a cipher suite field, the suite is implied by their signature.
+//! Format 1 to 3 blocks don't have IDs, they are derived from the block secret when loading them.
+
+use std::collections::{HashMap, HashSet};
+use crate::crypto::{self, CryptoErrors};
+use std::convert::TryFrom;
+use crate::host::{HostFingerprint, HOST_FINGERPRINT_SIZE};
+use crate::id::{GlobalId, ID_SIZE};
+use crate::rootkey::{RootKey, SigningKey};
+use crate::secret::{Secret256, WrapAlgorithm};
+use crate::signature::{Signature, SignatureAlgorithm, SignatureErrors};
+use crate::suite::CipherSuite;
 use byteorder::{WriteBytesExt, LittleEndian, ReadBytesExt};
-use std::io;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rand::rand_bytes;
+use openssl::sha::Sha256;
+use openssl::sign::Signer;
+use std::{fmt, io, iter};
 use std::fs::File;
-use std::io::{BufReader, Read, Error};
-use crate::utils::{compare_buffers, buffer_to_string, read_null_string};
+use std::io::{BufRead, Cursor, Read, Error};
+use std::str::Utf8Error;
+use crate::utils::{compare_buffers, buffer_to_string, read_null_string_with, to_hex, write_null_string};
+use itertools::Itertools;
 use log::debug;
 use crate::keyblock::ParseErrors::KeyfileParseError;
 
-/// This file will be used to parse and provide a structure to represent a keyblock
-///
-/// Here is the keyblock format:
-/// ```
-/// keyblock = magic_number, flags, aes256, metadata, 64_number, { keyfile }, signature, [ crc ]
-///
-/// keyfile = flags, aes256, null_string, metadata, 64_number, { byte }
-/// metadata = uid, null_string, null_string
-///
-/// aes256 = 256 * bit
-/// magic_number = "banjo", 16 * bit
-/// signature = 50 * bit
-/// crc = 32 * bit
-/// uid = "F" | "B", 8 * bit
-///
-/// null_string = ? ASCII characters ?, "\0"
-/// 64_number = 64 * bit
-/// flags = 64 * bit
-/// byte = 8 * bit
-/// bit = (0b0 | 0b1)
-/// ```
-///
-/// Structure content:
-///     - keyblock:
-///         - magic number "banjo"
-///         - 16 bits format specifier
-///         - 64 bits feature/setting flags
-///         - aes256 block secret, encrypted by the block password (if any) and by the root key
-///         - 16 bits UID starting with "B"
-///         - Name and description null terminated strings
-///         - 64 bits number of keyfiles
-///         - List of keyfiles
-///         - RSA4096/SHA256 signature of the above content
-///         - CRC checksum (if any)
-///     - keyfile:
-///         - 64 bits feature/setting flags
-///         - aes256 key secret, encrypted by the key password (if any) and by the block secret
-///         - 16 bits UID starting with "F"
-///         - Null terminated key path
-///         - Name and description null terminated strings
-///         - 64 bits key length
-///         - 8 bits aligned key content
-
-/// Magic number starting every keyblock
-const MAGIC_NUMBER: &[u8; 5] = b"banjo";
-/// Version specifier used by this implementation
-const FORMAT_SPECIFIER: u16 = 1;
-
-pub(crate) const SECRET_SIZE: usize = 256;
-pub(crate) const SIGNATURE_SIZE: usize = 50;
+pub use crate::format::{
+    BLOCK_COMPRESSED_METADATA, BLOCK_DEDUPLICATED, BLOCK_HOST_BOUND, BLOCK_UID_PREFIX, FORMAT_SPECIFIER, KEYFILE_DERIVED_SECRET,
+    KEYFILE_FROZEN, KEYFILE_SHARED_CONTENT, KEYFILE_UID_PREFIX, KEYFILE_WRAP_MASK, KEYFILE_WRAP_SHIFT, LEGACY_FORMAT_SPECIFIER,
+    MAGIC_NUMBER, PRE_ID_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER
+};
+use crate::format::read_compressed_metadata;
+
+/// zstd level of compressed metadata, which is small enough for the slower levels
+#[cfg(feature = "zstd")]
+const METADATA_COMPRESSION_LEVEL: i32 = 19;
+
+/// HKDF info prefix of derived key secrets, followed by the key UID
+const DERIVED_SECRET_INFO: &[u8] = b"banjo key secret";
+/// HKDF info of the key of content digests, of plaintexts in deduplicated blocks and of stored contents in outputs
+const CONTENT_DIGEST_INFO: &[u8] = b"banjo content digest";
+/// Domain of the IDs derived for blocks of formats without IDs
+const DERIVED_ID_INFO: &[u8] = b"banjo id";
+/// Smallest serialized keyfile: flags, UID, three empty strings, tag count and length
+const MIN_KEYFILE_SIZE: usize = 8 + 2 + 3 + 2 + 8;
+/// Most memory preallocated for a key content from its declared length, larger contents grow as read
+const MAX_CONTENT_PREALLOCATION: u64 = 1 << 20;
 
 #[derive(Debug)]
 pub struct KeyBlock {
     /// Reference to the root public key
-    pub root_pubkey: Rsa<Public>,
+    pub root_pubkey: RootKey,
     /// Format specifier
     pub format_specifier: u16,
     /// Set of option/setting flags for this block
     pub flags: u64,
+    /// Algorithms protecting this block
+    pub cipher_suite: CipherSuite,
     /// AES256 secret
-    pub secret: Vec<u8>,
+    pub secret: Secret256,
     /// Unique ID of this block
     pub uid: u16,
+    /// Globally unique ID of this block
+    pub id: GlobalId,
+    /// Fingerprint of the host this block is bound to, set along the `BLOCK_HOST_BOUND` flag
+    pub host: Option<HostFingerprint>,
     /// Name of this block
     pub name: String,
     /// Description of this block
@@ -79,29 +155,74 @@ pub struct KeyBlock {
     /// Mapping of file locations to the keys inside this block
     pub keys: HashMap<String, KeyFile>,
     /// Block signature
-    pub(crate) signature: Vec<u8>
+    pub signature: Signature,
+    /// UIDs allocated by other blocks, which fresh UIDs avoid, see the `uids` module (not serialized)
+    pub reserved_uids: Vec<u16>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct KeyFile {
     /// Set of option/setting flags for this key
     pub flags: u64,
     /// AES256 secret
-    pub secret: Vec<u8>,
+    pub secret: Secret256,
     /// Unique ID of this block
     pub uid: u16,
+    /// Globally unique ID of this key
+    pub id: GlobalId,
     /// Path to the key
     pub path: String,
     /// Name of this key
     pub name: String,
     /// Description of this key
     pub description: String,
-    /// Length of the key
+    /// Free-form tags attached to this key
+    pub tags: Vec<String>,
+    /// Length of the stored key content, in bytes
     pub length: u64,
-    /// Encrypted key content
+    /// Key content encrypted under the key secret, see `seal` and `open`
     pub content: Vec<u8>
 }
 
+/// Keyfiles of a block, parsed one at a time from a reader
+///
+/// Keyfiles of deduplicated blocks may share the content of an earlier keyfile, so the content of every
+/// keyfile of such blocks is kept until the end of the stream. Other blocks only hold one keyfile at a time.
+pub struct KeyStream<R: BufRead> {
+    reader: R,
+    /// Decompressed metadata of blocks with the `BLOCK_COMPRESSED_METADATA` flag, the contents stay in `reader`
+    metadata: Option<Cursor<Vec<u8>>>,
+    /// Format specifier
+    pub format_specifier: u16,
+    /// Set of option/setting flags for this block
+    pub flags: u64,
+    /// Algorithms protecting this block, `None` for format 1 and 2 blocks which imply it from their signature
+    pub cipher_suite: Option<CipherSuite>,
+    /// AES256 secret
+    pub secret: Secret256,
+    /// Unique ID of this block
+    pub uid: u16,
+    /// Globally unique ID of this block
+    pub id: GlobalId,
+    /// Fingerprint of the host this block is bound to, if any
+    pub host: Option<HostFingerprint>,
+    /// Name of this block
+    pub name: String,
+    /// Description of this block
+    pub description: String,
+    /// Number of keyfiles declared by the block, not trusted until they are all read
+    pub keyfile_number: u64,
+    /// Number of keyfiles read so far
+    read: u64,
+    /// Whether reading a keyfile failed, after which nothing more can be read
+    failed: bool,
+    /// Secret and content of the keyfiles read so far in a deduplicated block by UID, `None` once a UID repeats
+    stored: HashMap<u16, Option<(Secret256, Vec<u8>)>>,
+    /// UIDs whose content was shared, which no later keyfile may reuse
+    referenced: HashSet<u16>,
+    scratch: Vec<u8>
+}
+
 /// Enumeration of the potential errors when parsing keyblocks
 #[derive(Debug)]
 pub enum ParseErrors {
@@ -114,12 +235,109 @@ pub enum ParseErrors {
     /// Magic number doesn't match `MAGIC_NUMBER`
     InvalidMagicNumber,
     /// We don't know how to parse this specifier
-    UnknownFormatSpecifier
+    UnknownFormatSpecifier,
+    /// The declared key length (first) doesn't match its content length (second)
+    KeyLengthMismatch(u64, u64),
+    /// A format 1 key length isn't a whole number of bytes
+    UnalignedKeyLength(u64),
+    /// The block signature is missing or invalid
+    SignatureError(SignatureErrors),
+    /// OpenSSL failed to derive a key secret
+    OpenSSLError(ErrorStack),
+    /// The key secret is wrapped with an unknown algorithm
+    UnknownWrapAlgorithm(u8),
+    /// The wrapped key secret failed its integrity check
+    InvalidWrappedSecret,
+    /// The cipher suite identifier isn't known by this implementation
+    UnknownCipherSuite(u16),
+    /// The block uses an algorithm its cipher suite doesn't allow
+    CipherSuiteMismatch(CipherSuite),
+    /// Parsing the block would need more memory (first) than allowed (second), in bytes
+    MemoryLimitExceeded(u64, u64),
+    /// The keyfile at this path shares the content of a keyfile UID (second) that doesn't store any
+    DanglingSharedContent(String, u16),
+    /// The keyfile at this path shares the content of a keyfile UID (second) held by several keyfiles
+    AmbiguousSharedContent(String, u16),
+    /// A string field isn't valid UTF-8
+    InvalidUtf8(Utf8Error)
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyfileParseError(index, error) => write!(f, "failed to parse keyfile {}: {}", index, error),
+            ParseErrors::IOError(error) => write!(f, "IO error: {}", error),
+            ParseErrors::UnexpectedEof => write!(f, "unexpected end of file"),
+            ParseErrors::InvalidMagicNumber => write!(f, "invalid magic number, this is not a keyblock"),
+            ParseErrors::UnknownFormatSpecifier => write!(f, "unknown format specifier"),
+            ParseErrors::KeyLengthMismatch(declared, actual) => write!(
+                f, "declared key length of {} bytes but found {} bytes of content", declared, actual
+            ),
+            ParseErrors::UnalignedKeyLength(bits) => write!(f, "key length of {} bits is not a whole number of bytes", bits),
+            ParseErrors::SignatureError(error) => write!(f, "invalid signature: {}", error),
+            ParseErrors::OpenSSLError(error) => write!(f, "{}", error),
+            ParseErrors::UnknownWrapAlgorithm(identifier) => write!(f, "unknown secret wrap algorithm {}", identifier),
+            ParseErrors::InvalidWrappedSecret => write!(f, "the wrapped key secret failed its integrity check"),
+            ParseErrors::UnknownCipherSuite(identifier) => write!(f, "unknown cipher suite {}", identifier),
+            ParseErrors::CipherSuiteMismatch(suite) => write!(f, "the block uses algorithms outside of its {} cipher suite", suite),
+            ParseErrors::MemoryLimitExceeded(needed, limit) => write!(
+                f, "parsing the block needs about {} bytes of memory, over the limit of {} bytes", needed, limit
+            ),
+            ParseErrors::DanglingSharedContent(path, uid) => write!(
+                f, "keyfile {} shares the content of keyfile {:#06x}, which doesn't store any", path, uid
+            ),
+            ParseErrors::AmbiguousSharedContent(path, uid) => write!(
+                f, "keyfile {} shares the content of keyfile {:#06x}, which several keyfiles claim", path, uid
+            ),
+            ParseErrors::InvalidUtf8(error) => write!(f, "a string field is not valid UTF-8: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+impl ParseErrors {
+    /// Code of this error, explained by `banjo explain`
+    pub fn code(&self) -> &'static str {
+        match self {
+            KeyfileParseError(..) => "E100",
+            ParseErrors::IOError(_) => "E101",
+            ParseErrors::UnexpectedEof => "E102",
+            ParseErrors::InvalidMagicNumber => "E103",
+            ParseErrors::UnknownFormatSpecifier => "E104",
+            ParseErrors::KeyLengthMismatch(..) => "E105",
+            ParseErrors::UnalignedKeyLength(_) => "E106",
+            ParseErrors::SignatureError(_) => "E107",
+            ParseErrors::OpenSSLError(_) => "E108",
+            ParseErrors::UnknownWrapAlgorithm(_) => "E109",
+            ParseErrors::InvalidWrappedSecret => "E110",
+            ParseErrors::UnknownCipherSuite(_) => "E111",
+            ParseErrors::CipherSuiteMismatch(_) => "E112",
+            ParseErrors::MemoryLimitExceeded(..) => "E113",
+            ParseErrors::DanglingSharedContent(..) => "E114",
+            ParseErrors::AmbiguousSharedContent(..) => "E115",
+            ParseErrors::InvalidUtf8(_) => "E116"
+        }
+    }
+
+    /// Code of the innermost error, the one to explain first
+    pub fn root_code(&self) -> &'static str {
+        match self {
+            KeyfileParseError(_, error) => error.root_code(),
+            ParseErrors::SignatureError(error) => error.code(),
+            error => error.code()
+        }
+    }
 }
 
 /// Convert IO errors to parse errors
 impl From<io::Error> for ParseErrors {
     fn from(error: Error) -> Self {
+        // Strings that aren't UTF-8 are reported by the string reader as wrapped `Utf8Error`s
+        if let Some(utf8_error) = error.get_ref().and_then(|inner| inner.downcast_ref::<Utf8Error>()) {
+            return ParseErrors::InvalidUtf8(*utf8_error)
+        }
+
         match error.kind() {
             io::ErrorKind::UnexpectedEof => ParseErrors::UnexpectedEof,
             _ => ParseErrors::IOError(error)
@@ -127,76 +345,210 @@ impl From<io::Error> for ParseErrors {
     }
 }
 
+impl From<ErrorStack> for ParseErrors {
+    fn from(error: ErrorStack) -> Self {
+        ParseErrors::OpenSSLError(error)
+    }
+}
+
 impl KeyBlock {
-    /// Load a keyblock from disk and return it
-    pub fn load(file: File, root_pubkey: Rsa<Public>) -> Result<KeyBlock, ParseErrors> {
-        let mut reader = BufReader::new(file);
+    /// Load a keyblock from disk, verify its signature and return it
+    pub fn load(file: File, root_pubkey: RootKey) -> Result<KeyBlock, ParseErrors> {
+        KeyBlock::load_limited(file, root_pubkey, None)
+    }
 
-        // Check the validity of the magic number
-        let mut magic_number_buffer = vec![0; MAGIC_NUMBER.len()];
-        if reader.read(&mut magic_number_buffer)? < MAGIC_NUMBER.len() {
-            return Err(ParseErrors::InvalidMagicNumber)
-        }
-        debug!("Magic number: {}", buffer_to_string(&magic_number_buffer));
+    /// Load a keyblock from disk like `load`, refusing to use more than `max_memory` bytes
+    pub fn load_limited(file: File, root_pubkey: RootKey, max_memory: Option<u64>) -> Result<KeyBlock, ParseErrors> {
+        KeyBlock::from_bytes(&read_limited(file, max_memory)?, root_pubkey)
+    }
 
-        if !compare_buffers(&magic_number_buffer, &MAGIC_NUMBER.to_vec()) {
-            return Err(ParseErrors::InvalidMagicNumber)
-        }
+    /// Parse a serialized keyblock, verify its signature and return it
+    pub fn from_bytes(content: &[u8], root_pubkey: RootKey) -> Result<KeyBlock, ParseErrors> {
+        let (block, signed_length) = KeyBlock::parse(content, root_pubkey)?;
 
-        // Format specifier
-        let format_specifier = reader.read_u16::<LittleEndian>()?;
-        // Right now if the format specifier isn't `FORMAT_SPECIFIER` we return
-        if format_specifier != FORMAT_SPECIFIER { return Err(ParseErrors::UnknownFormatSpecifier) }
+        block.signature.verify(&content[..signed_length], &block.root_pubkey)
+            .map_err(ParseErrors::SignatureError)?;
 
-        // Flags
-        let flags = reader.read_u64::<LittleEndian>()?;
+        Ok(block)
+    }
 
-        // AES256 secret
-        let mut secret: Vec<u8> = vec![0; SECRET_SIZE / 8];
-        reader.read_exact(&mut secret)?;
+    /// Load a keyblock from disk without checking its signature
+    ///
+    /// This should only be used to inspect or migrate blocks, never to trust their content.
+    pub fn load_unverified(file: File, root_pubkey: RootKey) -> Result<KeyBlock, ParseErrors> {
+        KeyBlock::from_bytes_unverified(&read_limited(file, None)?, root_pubkey)
+    }
 
-        // UID
-        let uid = reader.read_u16::<LittleEndian>()?;
+    /// Parse a serialized keyblock like `load_unverified`
+    pub fn from_bytes_unverified(content: &[u8], root_pubkey: RootKey) -> Result<KeyBlock, ParseErrors> {
+        Ok(KeyBlock::parse(content, root_pubkey)?.0)
+    }
 
-        // Name and description
-        let name = read_null_string(&mut reader);
-        let description = read_null_string(&mut reader);
+    /// Parse the keyfiles of a block one at a time as they are read from `reader`
+    ///
+    /// The header is read right away, keyfiles are only parsed when the returned stream is advanced, so
+    /// blocks larger than memory can be processed. Their signature can only be read once every keyfile was,
+    /// so the keyfiles are unverified, like with `load_unverified`.
+    pub fn stream_keys<R: BufRead>(reader: R) -> Result<KeyStream<R>, ParseErrors> {
+        KeyStream::new(reader)
+    }
 
-        // Keyfiles
-        let keyfile_number = reader.read_u64::<LittleEndian>()?;
-        let mut keys :HashMap<String, KeyFile> = HashMap::new();
+    /// Like `stream_keys`, also hashing the signed content so `KeyStream::verify` can check the signature once
+    /// every keyfile was read
+    pub fn stream_signed_keys<R: BufRead>(reader: R) -> Result<KeyStream<SignedReader<R>>, ParseErrors> {
+        KeyStream::new(SignedReader { inner: reader, digest: Some(Sha256::new()) })
+    }
 
-        for i in 0..keyfile_number {
-            debug!("Parsing key {}", i);
-            let keyfile = KeyFile::load(&mut reader);
+    /// Parse a keyblock, returning it along with the length of its signed content
+    pub(crate) fn parse(content: &[u8], root_pubkey: RootKey) -> Result<(KeyBlock, usize), ParseErrors> {
+        let mut stream = KeyStream::new(Cursor::new(content))?;
 
-            match keyfile {
-                Ok(key) => keys.insert(key.path.clone(), key),
+        // Keyfiles, never preallocating for more than the remaining metadata can hold
+        let remaining = match &stream.metadata {
+            Some(metadata) => metadata.get_ref().len().saturating_sub(metadata.position() as usize),
+            None => content.len().saturating_sub(stream.reader.position() as usize)
+        };
+        let capacity = usize::try_from(stream.keyfile_number).unwrap_or(usize::MAX).min(remaining / MIN_KEYFILE_SIZE);
+        let mut keys :HashMap<String, KeyFile> = HashMap::with_capacity(capacity);
+        let mut shared = Vec::new();
+
+        for i in 0..stream.keyfile_number {
+            debug!("Parsing key {}", i);
+            match stream.read_entry() {
+                Ok((key, Some(source))) => shared.push((key, source)),
+                Ok((key, None)) => { keys.insert(key.path.clone(), key); },
                 Err(error) => return Err(KeyfileParseError(i, Box::new(error)))
             };
         }
 
+        // Shared content comes from the single keyfile holding the source UID, which has to store its own
+        let mut resolved = Vec::new();
+        for (key, source) in &shared {
+            let claimed = keys.values().chain(shared.iter().map(|(key, _)| key)).filter(|other| other.uid == *source).count();
+            if claimed > 1 { return Err(ParseErrors::AmbiguousSharedContent(key.path.clone(), *source)) }
+            let stored = keys.values().find(|stored| stored.uid == *source)
+                .ok_or_else(|| ParseErrors::DanglingSharedContent(key.path.clone(), *source))?;
+            if stored.length != key.length { return Err(ParseErrors::KeyLengthMismatch(key.length, stored.length)) }
+
+            resolved.push(KeyFile {
+                flags: key.flags & !(KEYFILE_SHARED_CONTENT | KEYFILE_DERIVED_SECRET),
+                secret: stored.secret.clone(),
+                content: stored.content.clone(),
+                ..key.clone()
+            });
+        }
+        keys.extend(resolved.into_iter().map(|key| (key.path.clone(), key)));
+
         // Signature
-        let mut signature: Vec<u8> = vec![0; SIGNATURE_SIZE / 8];
-        reader.read_exact(&mut signature)?;
+        let KeyStream {
+            mut reader, format_specifier, flags, cipher_suite, secret, uid, id, host, name, description, ..
+        } = stream;
+        let signed_length = reader.position() as usize;
+        let signature = if format_specifier == LEGACY_FORMAT_SPECIFIER {
+            Signature::read_legacy(&mut reader)?
+        } else {
+            Signature::read(&mut reader)?
+        };
 
-        // TODO: Check signature
+        let cipher_suite = cipher_suite.unwrap_or_else(|| CipherSuite::for_signature(signature.algorithm));
+        let signature_allowed = signature.algorithm == SignatureAlgorithm::None
+            || signature.algorithm == cipher_suite.signature_algorithm();
+        let wrap_allowed = keys.values().all(|key| key.wrap_algorithm().is_some_and(|wrap| cipher_suite.allows_wrap(wrap)));
+        if !signature_allowed || !wrap_allowed {
+            return Err(ParseErrors::CipherSuiteMismatch(cipher_suite))
+        }
 
-        Ok(KeyBlock {
+        let block = KeyBlock {
             root_pubkey,
             format_specifier,
             flags,
+            cipher_suite,
             secret,
             uid,
+            id,
+            host,
             name,
             description,
             keys,
-            signature
-        })
+            signature,
+            reserved_uids: Vec::new()
+        };
+
+        Ok((block, signed_length))
+    }
+
+    /// Random keyfile UID neither used by any key of this block nor reserved, if any is left
+    pub fn fresh_key_uid(&self) -> Result<Option<u16>, ErrorStack> {
+        let used: Vec<u16> = self.keys.values().map(|key| key.uid).chain(self.reserved_uids.iter().copied()).collect();
+        random_uid(KEYFILE_UID_PREFIX, &used)
+    }
+
+    /// New keyfile holding `content` encrypted at `path`, with a fresh UID and secret, `None` if no UID is left
+    ///
+    /// The keyfile isn't added to the block.
+    pub fn new_key(&self, path: &str, name: &str, description: &str, content: &[u8]) -> Result<Option<KeyFile>, CryptoErrors> {
+        let uid = match self.fresh_key_uid()? {
+            Some(uid) => uid,
+            None => return Ok(None)
+        };
+
+        let mut key = KeyFile {
+            flags: 0,
+            secret: Secret256::generate()?,
+            uid,
+            id: GlobalId::generate()?,
+            path: path.to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+            tags: Vec::new(),
+            length: 0,
+            content: Vec::new()
+        };
+        key.seal(content)?;
+
+        Ok(Some(key))
+    }
+
+    /// Bind this block to the host with the given fingerprint, or remove its binding
+    pub fn bind_host(&mut self, host: Option<HostFingerprint>) {
+        self.host = host;
+        if host.is_some() { self.flags |= BLOCK_HOST_BOUND } else { self.flags &= !BLOCK_HOST_BOUND }
+    }
+
+    /// Keyed digests of the content of the keys of this block, see `ContentDigest`
+    pub fn content_digest(&self) -> Result<ContentDigest, ErrorStack> {
+        let secret = self.secret.derive(CONTENT_DIGEST_INFO)?;
+        Ok(ContentDigest(PKey::hmac(secret.as_bytes())?))
+    }
+
+    /// Random block UID different from the current one and not reserved, if any is left
+    pub fn fresh_block_uid(&self) -> Result<Option<u16>, ErrorStack> {
+        let used: Vec<u16> = self.reserved_uids.iter().copied().chain(iter::once(self.uid)).collect();
+        random_uid(BLOCK_UID_PREFIX, &used)
+    }
+
+    /// Sign this block with the root private key, replacing any previous signature
+    ///
+    /// The signature uses the algorithm of the block cipher suite, which `key` has to support.
+    pub fn sign(&mut self, key: &SigningKey) -> Result<(), SignatureErrors> {
+        let content = self.serialize_unsigned().expect("Serializing to memory can't fail.");
+        self.signature = Signature::sign_with(&content, key, self.cipher_suite.signature_algorithm())?;
+
+        Ok(())
     }
 
-    /// Serialize this keyfile to a vector of bytes
+    /// Serialize this keyblock to a vector of bytes
     pub fn serialize(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buffer = self.serialize_unsigned()?;
+
+        // Signature
+        buffer.extend(self.signature.serialize()?);
+
+        Ok(buffer)
+    }
+
+    /// Serialize the signed part of this keyblock, everything but the signature
+    fn serialize_unsigned(&self) -> Result<Vec<u8>, io::Error> {
         let mut buffer: Vec<u8> = Vec::new();
 
         // Magic number
@@ -208,97 +560,649 @@ impl KeyBlock {
         // Flags
         buffer.write_u64::<LittleEndian>(self.flags)?;
 
+        // Cipher suite
+        buffer.write_u16::<LittleEndian>(self.cipher_suite.identifier())?;
+
         // AES256 secret
-        buffer.extend(&self.secret);
+        buffer.extend(self.secret.as_bytes());
 
-        // UID
+        // UID and ID
         buffer.write_u16::<LittleEndian>(self.uid)?;
+        buffer.extend(self.id.0);
+
+        // Host binding
+        if self.flags & BLOCK_HOST_BOUND != 0 {
+            buffer.extend(self.host.ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "host bound block without a host"))?.0);
+        }
+
+        // Metadata, compressed ahead of the key contents if the block asks for it
+        let compressed = self.flags & BLOCK_COMPRESSED_METADATA != 0;
+        let mut metadata: Vec<u8> = Vec::new();
+        let mut contents: Vec<u8> = Vec::new();
 
         // Name and description
-        buffer.extend(self.name.as_bytes());
-        buffer.write_u8(0)?;
-        buffer.extend(self.description.as_bytes());
-        buffer.write_u8(0)?;
+        write_null_string(&mut metadata, "block name", &self.name)?;
+        write_null_string(&mut metadata, "block description", &self.description)?;
 
         // Number of keyfiles
-        buffer.write_u64::<LittleEndian>(self.keys.len() as u64)?;
+        metadata.write_u64::<LittleEndian>(self.keys.len() as u64)?;
 
-        // Keyfiles
-        for keyfiles in self.keys.values() {
-            buffer.extend(keyfiles.serialize()?);
+        // Keyfiles, sorted by path so the serialization is deterministic
+        let sources = self.content_sources()?;
+        for path in self.keys.keys().sorted() {
+            let source = sources.get(path.as_str()).map(|source| &self.keys[*source]);
+            let (key_metadata, content) = self.keys[path].serialize_parts(&self.secret, source)?;
+            metadata.extend(key_metadata);
+            if compressed { contents.extend(content) } else { metadata.extend(content) }
         }
 
-        // Signature
-        buffer.extend(&self.signature);
+        if compressed {
+            buffer.extend(compress_metadata(&metadata)?);
+            buffer.extend(contents);
+        } else {
+            buffer.extend(metadata);
+        }
 
         Ok(buffer)
     }
+
+    /// Path of the key storing the content of every key sharing it, by path, when the block is deduplicated
+    ///
+    /// The first key by path with a given plaintext stores it, as long as no other key has its UID. Keys whose
+    /// content can't be decrypted store it as is.
+    fn content_sources(&self) -> Result<HashMap<&str, &str>, io::Error> {
+        let mut sources = HashMap::new();
+        if self.flags & BLOCK_DEDUPLICATED == 0 { return Ok(sources) }
+
+        let digest_key = PKey::hmac(self.secret.derive(CONTENT_DIGEST_INFO)?.as_bytes())?;
+        let mut stored: HashMap<Vec<u8>, &str> = HashMap::new();
+        for path in self.keys.keys().sorted() {
+            let key = &self.keys[path];
+            let plaintext = match key.open() {
+                Ok(plaintext) => plaintext,
+                Err(_) => continue
+            };
+            let mut signer = Signer::new(MessageDigest::sha256(), &digest_key)?;
+            let digest = signer.sign_oneshot_to_vec(&plaintext)?;
+
+            match stored.get(&digest) {
+                Some(source) => { sources.insert(path.as_str(), *source); },
+                None if self.keys.values().filter(|other| other.uid == key.uid).count() == 1 => {
+                    stored.insert(digest, path);
+                },
+                None => {}
+            }
+        }
+
+        Ok(sources)
+    }
+}
+
+/// HMAC-SHA256 of stored key content, under a key derived from the block secret
+///
+/// Published in place of plain digests, which would let anyone test guesses of the content. Only holders of
+/// the block can compute or compare them.
+pub struct ContentDigest(PKey<Private>);
+
+impl ContentDigest {
+    /// Hex encoded digest of the stored content of `key`
+    pub fn of(&self, key: &KeyFile) -> Result<String, ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.0)?;
+        signer.update(&key.content)?;
+        Ok(to_hex(&signer.sign_to_vec()?))
+    }
 }
 
 impl KeyFile {
-    pub fn load(reader: &mut BufReader<File>) -> Result<KeyFile, ParseErrors> {
+    /// Parse a keyfile storing its own content
+    pub fn load<R: BufRead>(reader: &mut R, format_specifier: u16, block_secret: &Secret256) -> Result<KeyFile, ParseErrors> {
+        match KeyFile::load_metadata(reader, format_specifier, block_secret, &mut Vec::new())? {
+            (key, None) => KeyFile::load_content(reader, key),
+            (key, Some(source)) => Err(ParseErrors::DanglingSharedContent(key.path, source))
+        }
+    }
+
+    /// Parse the metadata of a keyfile, everything up to its content which is left empty
+    ///
+    /// Keyfiles sharing the content of another one are returned along with its UID, their secret is then
+    /// replaced by the one of that keyfile. Strings are read through `scratch`, which can be reused across keyfiles.
+    fn load_metadata<R: BufRead>(
+        reader: &mut R, format_specifier: u16, block_secret: &Secret256, scratch: &mut Vec<u8>
+    ) -> Result<(KeyFile, Option<u16>), ParseErrors> {
         // Flags
         let flags = reader.read_u64::<LittleEndian>()?;
 
-        // AES256 secret
-        let mut secret: Vec<u8> = vec![0; SECRET_SIZE / 8];
-        reader.read_exact(&mut secret)?;
+        // AES256 secret, unless it is derived from the UID or shared
+        let wrap_identifier = ((flags & KEYFILE_WRAP_MASK) >> KEYFILE_WRAP_SHIFT) as u8;
+        let wrap_algorithm = WrapAlgorithm::from_identifier(wrap_identifier)
+            .ok_or(ParseErrors::UnknownWrapAlgorithm(wrap_identifier))?;
+
+        let secret = if flags & (KEYFILE_DERIVED_SECRET | KEYFILE_SHARED_CONTENT) == 0 {
+            let mut wrapped = vec![0; wrap_algorithm.wrapped_size()];
+            reader.read_exact(&mut wrapped)?;
+            Some(Secret256::unwrap(&wrapped, block_secret, wrap_algorithm).ok_or(ParseErrors::InvalidWrappedSecret)?)
+        } else {
+            None
+        };
 
         // UID
         let uid = reader.read_u16::<LittleEndian>()?;
+        let secret = match secret {
+            Some(secret) => secret,
+            None => KeyFile::derive_secret(block_secret, uid)?
+        };
 
         // Path, name and description
-        let path = read_null_string(reader);
-        let name = read_null_string(reader);
-        let description = read_null_string(reader);
+        // ID, derived from the path in formats without IDs since UIDs aren't always unique
+        let id = if format_specifier == FORMAT_SPECIFIER { Some(read_id(reader)?) } else { None };
 
-        // Key length
-        let length = reader.read_u64::<LittleEndian>()?;
+        let path = read_null_string_with(reader, scratch)?;
+        let id = id.unwrap_or_else(|| {
+            GlobalId::derive(&[DERIVED_ID_INFO, block_secret.as_bytes(), &uid.to_le_bytes(), path.as_bytes()])
+        });
+        let name = read_null_string_with(reader, scratch)?;
+        let description = read_null_string_with(reader, scratch)?;
+
+        // Tags, format 1 didn't have any
+        let mut tags = Vec::new();
+        if format_specifier != LEGACY_FORMAT_SPECIFIER {
+            let tag_number = reader.read_u16::<LittleEndian>()?;
+            // The declared count isn't trusted for large allocations either
+            tags.reserve(usize::from(tag_number).min(16));
+            for _ in 0..tag_number {
+                tags.push(read_null_string_with(reader, scratch)?);
+            }
+        }
+
+        // Key length, format 1 stored it in bits
+        let mut length = reader.read_u64::<LittleEndian>()?;
+        if format_specifier == LEGACY_FORMAT_SPECIFIER {
+            if length % 8 != 0 { return Err(ParseErrors::UnalignedKeyLength(length)) }
+            length /= 8;
+        }
 
-        // Key content
-        let mut content = vec![0; (length / 8) as usize];
-        reader.read_exact(&mut content)?;
+        // UID of the keyfile storing the content, if it is shared
+        let source = if flags & KEYFILE_SHARED_CONTENT != 0 { Some(reader.read_u16::<LittleEndian>()?) } else { None };
 
-        Ok(KeyFile {
+        Ok((KeyFile {
             flags,
             secret,
             uid,
+            id,
             path,
             name,
             description,
+            tags,
             length,
-            content
-        })
+            content: Vec::new()
+        }, source))
     }
 
-    pub fn serialize(&self) -> Result<Vec<u8>, io::Error> {
+    /// Read the content of a keyfile whose metadata was parsed
+    fn load_content<R: Read>(reader: &mut R, mut key: KeyFile) -> Result<KeyFile, ParseErrors> {
+        // Key content, without trusting the declared length for large allocations
+        key.content.reserve(key.length.min(MAX_CONTENT_PREALLOCATION) as usize);
+        reader.take(key.length).read_to_end(&mut key.content)?;
+        if key.content.len() as u64 != key.length {
+            return Err(ParseErrors::KeyLengthMismatch(key.length, key.content.len() as u64))
+        }
+
+        Ok(key)
+    }
+
+    /// Secret of the key `uid` when derived from the block secret
+    pub fn derive_secret(block_secret: &Secret256, uid: u16) -> Result<Secret256, ErrorStack> {
+        let mut info = DERIVED_SECRET_INFO.to_vec();
+        info.extend(&uid.to_le_bytes());
+
+        block_secret.derive(&info)
+    }
+
+    /// Switch this key to a secret derived from the block secret, encrypting its content again
+    pub fn use_derived_secret(&mut self, block_secret: &Secret256) -> Result<(), CryptoErrors> {
+        let plaintext = self.open()?;
+        self.secret = KeyFile::derive_secret(block_secret, self.uid)?;
+        self.flags |= KEYFILE_DERIVED_SECRET;
+
+        self.seal(&plaintext)
+    }
+
+    /// Algorithm wrapping the stored secret of this key, if known
+    pub fn wrap_algorithm(&self) -> Option<WrapAlgorithm> {
+        WrapAlgorithm::from_identifier(((self.flags & KEYFILE_WRAP_MASK) >> KEYFILE_WRAP_SHIFT) as u8)
+    }
+
+    /// Select the algorithm wrapping the stored secret of this key under the block secret
+    pub fn set_wrap_algorithm(&mut self, algorithm: WrapAlgorithm) {
+        self.flags = (self.flags & !KEYFILE_WRAP_MASK) | ((algorithm.identifier() as u64) << KEYFILE_WRAP_SHIFT);
+    }
+
+    /// Serialize this keyfile, wrapping its secret under the block secret if needed
+    pub fn serialize(&self, block_secret: &Secret256) -> Result<Vec<u8>, io::Error> {
+        let (mut buffer, content) = self.serialize_parts(block_secret, None)?;
+        buffer.extend(content);
+
+        Ok(buffer)
+    }
+
+    /// Serialize the metadata of this keyfile, then separately its content
+    ///
+    /// With a `source`, the keyfile shares its content and secret instead of storing them.
+    fn serialize_parts(&self, block_secret: &Secret256, source: Option<&KeyFile>) -> Result<(Vec<u8>, Vec<u8>), io::Error> {
+        if self.length != self.content.len() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "keyfile {}: {}", self.path, ParseErrors::KeyLengthMismatch(self.length, self.content.len() as u64)
+            )))
+        }
+
         let mut buffer: Vec<u8> = Vec::new();
 
         // Flags
-        buffer.write_u64::<LittleEndian>(self.flags)?;
+        let flags = match source {
+            Some(_) => (self.flags | KEYFILE_SHARED_CONTENT) & !KEYFILE_DERIVED_SECRET,
+            None => self.flags & !KEYFILE_SHARED_CONTENT
+        };
+        buffer.write_u64::<LittleEndian>(flags)?;
 
-        // AES256 secret
-        buffer.extend(&self.secret);
+        // AES256 secret, derived and shared secrets aren't stored
+        if flags & (KEYFILE_DERIVED_SECRET | KEYFILE_SHARED_CONTENT) == 0 {
+            let algorithm = self.wrap_algorithm().ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData, format!("keyfile {}: unknown secret wrap algorithm", self.path)
+            ))?;
+            buffer.extend(self.secret.wrap(block_secret, algorithm).map_err(io::Error::other)?);
+        }
 
-        // UID
+        // UID and ID
         buffer.write_u16::<LittleEndian>(self.uid)?;
+        buffer.extend(self.id.0);
 
         // Key path
-        buffer.extend(self.path.as_bytes());
-        buffer.write_u8(0)?;
+        validate_path(&self.path).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        write_null_string(&mut buffer, "key path", &self.path)?;
 
         // Name and description
-        buffer.extend(self.name.as_bytes());
-        buffer.write_u8(0)?;
-        buffer.extend(self.description.as_bytes());
-        buffer.write_u8(0)?;
+        write_null_string(&mut buffer, &format!("keyfile {}: name", self.path), &self.name)?;
+        write_null_string(&mut buffer, &format!("keyfile {}: description", self.path), &self.description)?;
 
-        // Key length
-        buffer.write_u64::<LittleEndian>(self.length)?;
+        // Tags
+        if self.tags.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("keyfile {}: too many tags", self.path)))
+        }
+        buffer.write_u16::<LittleEndian>(self.tags.len() as u16)?;
+        for tag in &self.tags {
+            write_null_string(&mut buffer, &format!("keyfile {}: tag", self.path), tag)?;
+        }
 
-        // Key content
-        buffer.extend(&self.content);
+        // Key length, and the keyfile storing the content if it is shared
+        match source {
+            Some(source) => {
+                buffer.write_u64::<LittleEndian>(source.length)?;
+                buffer.write_u16::<LittleEndian>(source.uid)?;
+                Ok((buffer, Vec::new()))
+            },
+            None => {
+                buffer.write_u64::<LittleEndian>(self.length)?;
+                Ok((buffer, self.content.clone()))
+            }
+        }
+    }
 
-        Ok(buffer)
+    /// Encrypt `plaintext` under the key secret and store it as the key content
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<(), CryptoErrors> {
+        self.content = crypto::encrypt(&self.secret, plaintext)?;
+        self.length = self.content.len() as u64;
+
+        Ok(())
+    }
+
+    /// Decrypt and authenticate the key content
+    pub fn open(&self) -> Result<Vec<u8>, CryptoErrors> {
+        crypto::decrypt(&self.secret, &self.content)
+    }
+}
+
+/// Compress the metadata section of a block, prefixed by its 32 bits compressed length
+#[cfg(feature = "zstd")]
+fn compress_metadata(metadata: &[u8]) -> io::Result<Vec<u8>> {
+    let compressed = zstd::bulk::compress(metadata, METADATA_COMPRESSION_LEVEL)?;
+    if compressed.len() > u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the metadata section is too large"))
+    }
+
+    let mut section = (compressed.len() as u32).to_le_bytes().to_vec();
+    section.extend(compressed);
+    Ok(section)
+}
+
+/// Compress the metadata section of a block, which needs the `zstd` feature
+#[cfg(not(feature = "zstd"))]
+fn compress_metadata(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "compressing the metadata needs the zstd feature"))
+}
+
+impl<R: BufRead> KeyStream<R> {
+    /// Read the header of a block, up to its keyfiles
+    fn new(mut reader: R) -> Result<KeyStream<R>, ParseErrors> {
+        // Check the validity of the magic number
+        let mut magic_number_buffer = vec![0; MAGIC_NUMBER.len()];
+        if reader.read_exact(&mut magic_number_buffer).is_err() {
+            return Err(ParseErrors::InvalidMagicNumber)
+        }
+        debug!("Magic number: {}", buffer_to_string(&magic_number_buffer));
+
+        if !compare_buffers(&magic_number_buffer, MAGIC_NUMBER) {
+            return Err(ParseErrors::InvalidMagicNumber)
+        }
+
+        // Format specifier
+        let format_specifier = reader.read_u16::<LittleEndian>()?;
+        // Right now we only know about the current and the two previous formats
+        let known = [FORMAT_SPECIFIER, PRE_ID_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER, LEGACY_FORMAT_SPECIFIER];
+        if !known.contains(&format_specifier) {
+            return Err(ParseErrors::UnknownFormatSpecifier)
+        }
+
+        // Flags
+        let flags = reader.read_u64::<LittleEndian>()?;
+
+        // Cipher suite, older blocks get the one implied by their signature once it is read
+        let cipher_suite = if format_specifier >= PRE_ID_FORMAT_SPECIFIER {
+            let identifier = reader.read_u16::<LittleEndian>()?;
+            Some(CipherSuite::from_identifier(identifier).ok_or(ParseErrors::UnknownCipherSuite(identifier))?)
+        } else {
+            None
+        };
+
+        // AES256 secret
+        let secret = Secret256::read(&mut reader)?;
+
+        // UID and ID
+        let uid = reader.read_u16::<LittleEndian>()?;
+        let id = if format_specifier == FORMAT_SPECIFIER {
+            read_id(&mut reader)?
+        } else {
+            GlobalId::derive(&[DERIVED_ID_INFO, secret.as_bytes(), &uid.to_le_bytes()])
+        };
+
+        // Host binding
+        let host = if flags & BLOCK_HOST_BOUND != 0 {
+            let mut fingerprint = [0; HOST_FINGERPRINT_SIZE];
+            reader.read_exact(&mut fingerprint)?;
+            Some(HostFingerprint(fingerprint))
+        } else {
+            None
+        };
+
+        // Metadata, either decompressed up front or read along the key contents
+        let mut metadata = if flags & BLOCK_COMPRESSED_METADATA != 0 {
+            Some(Cursor::new(read_compressed_metadata(&mut reader)?))
+        } else {
+            None
+        };
+        let mut header: &mut dyn BufRead = match &mut metadata {
+            Some(metadata) => metadata,
+            None => &mut reader
+        };
+
+        // Name and description
+        let mut scratch = Vec::new();
+        let name = read_null_string_with(&mut header, &mut scratch)?;
+        let description = read_null_string_with(&mut header, &mut scratch)?;
+
+        // Number of keyfiles
+        let keyfile_number = header.read_u64::<LittleEndian>()?;
+
+        Ok(KeyStream {
+            reader,
+            metadata,
+            format_specifier,
+            flags,
+            cipher_suite,
+            secret,
+            uid,
+            id,
+            host,
+            name,
+            description,
+            keyfile_number,
+            read: 0,
+            failed: false,
+            stored: HashMap::new(),
+            referenced: HashSet::new(),
+            scratch
+        })
+
+    }
+
+    /// Number of keyfiles left to read
+    pub fn remaining(&self) -> u64 {
+        if self.failed { 0 } else { self.keyfile_number - self.read }
+    }
+
+    /// Read the signature following the keyfiles, once they were all read
+    ///
+    /// Keyfiles left are skipped. The signature is only read, checking it needs the whole signed content.
+    pub fn finish(mut self) -> Result<Signature, ParseErrors> {
+        self.skip_keys()?;
+        self.read_signature()
+    }
+
+    /// Skip the keyfiles left, up to the signature
+    fn skip_keys(&mut self) -> Result<(), ParseErrors> {
+        for key in self.by_ref() {
+            key?;
+        }
+        if self.failed {
+            let error = io::Error::new(io::ErrorKind::InvalidData, "a keyfile failed to parse, the signature can't be located");
+            return Err(ParseErrors::IOError(error))
+        }
+
+        Ok(())
+    }
+
+    /// Read the signature section, checking it matches the cipher suite
+    fn read_signature(&mut self) -> Result<Signature, ParseErrors> {
+        let signature = if self.format_specifier == LEGACY_FORMAT_SPECIFIER {
+            Signature::read_legacy(&mut self.reader)?
+        } else {
+            Signature::read(&mut self.reader)?
+        };
+
+        let cipher_suite = self.cipher_suite.unwrap_or_else(|| CipherSuite::for_signature(signature.algorithm));
+        if signature.algorithm != SignatureAlgorithm::None && signature.algorithm != cipher_suite.signature_algorithm() {
+            return Err(ParseErrors::CipherSuiteMismatch(cipher_suite))
+        }
+
+        Ok(signature)
+    }
+
+    /// Parse the next keyfile, along with the UID of the keyfile storing its content if it is shared
+    fn read_entry(&mut self) -> Result<(KeyFile, Option<u16>), ParseErrors> {
+        let (key, source) = match &mut self.metadata {
+            Some(metadata) => KeyFile::load_metadata(metadata, self.format_specifier, &self.secret, &mut self.scratch)?,
+            None => KeyFile::load_metadata(&mut self.reader, self.format_specifier, &self.secret, &mut self.scratch)?
+        };
+
+        match source {
+            Some(_) => Ok((key, source)),
+            None => Ok((KeyFile::load_content(&mut self.reader, key)?, None))
+        }
     }
+
+    /// Parse the next keyfile, resolving the content it shares with an earlier one
+    fn read_key(&mut self) -> Result<KeyFile, ParseErrors> {
+        let (mut key, source) = self.read_entry()?;
+
+        if let Some(suite) = self.cipher_suite {
+            if !key.wrap_algorithm().is_some_and(|wrap| suite.allows_wrap(wrap)) {
+                return Err(ParseErrors::CipherSuiteMismatch(suite))
+            }
+        }
+
+        if let Some(source) = source {
+            let (secret, content) = match self.stored.get(&source) {
+                Some(Some(stored)) => stored,
+                Some(None) => return Err(ParseErrors::AmbiguousSharedContent(key.path, source)),
+                None => return Err(ParseErrors::DanglingSharedContent(key.path, source))
+            };
+            if content.len() as u64 != key.length {
+                return Err(ParseErrors::KeyLengthMismatch(key.length, content.len() as u64))
+            }
+
+            key.flags &= !(KEYFILE_SHARED_CONTENT | KEYFILE_DERIVED_SECRET);
+            key.secret = secret.clone();
+            key.content = content.clone();
+            self.referenced.insert(source);
+        }
+
+        // Shared content has to come from the single keyfile holding the source UID
+        if self.flags & BLOCK_DEDUPLICATED != 0 {
+            if self.referenced.contains(&key.uid) && self.stored.contains_key(&key.uid) {
+                return Err(ParseErrors::AmbiguousSharedContent(key.path, key.uid))
+            }
+
+            match self.stored.get_mut(&key.uid) {
+                Some(stored) => *stored = None,
+                None if source.is_none() => { self.stored.insert(key.uid, Some((key.secret.clone(), key.content.clone()))); },
+                None => { self.stored.insert(key.uid, None); }
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+impl<R: BufRead> Iterator for KeyStream<R> {
+    type Item = Result<KeyFile, ParseErrors>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining() == 0 { return None }
+
+        let index = self.read;
+        debug!("Parsing key {}", index);
+        self.read += 1;
+
+        let key = self.read_key().map_err(|error| KeyfileParseError(index, Box::new(error)));
+        if key.is_err() { self.failed = true; }
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The declared count may not fit in memory, nor be honest
+        (0, usize::try_from(self.remaining()).ok())
+    }
+}
+
+impl<R: BufRead> KeyStream<SignedReader<R>> {
+    /// Skip the keyfiles left, then check the signature of everything read against `root_key`
+    ///
+    /// Keyfiles read from the stream are only authentic once this succeeds.
+    pub fn verify(mut self, root_key: &RootKey) -> Result<(), ParseErrors> {
+        self.skip_keys()?;
+        let digest = self.reader.digest.take().expect("The digest is only taken once.").finish();
+        let signature = self.read_signature()?;
+
+        signature.verify_digest(&digest, root_key).map_err(ParseErrors::SignatureError)
+    }
+}
+
+/// Reader hashing the bytes consumed from it, so the signature of a streamed block can be checked at its end
+pub struct SignedReader<R: BufRead> {
+    inner: R,
+    /// SHA256 of the bytes consumed so far, taken once the signature is reached
+    digest: Option<Sha256>
+}
+
+impl<R: BufRead> Read for SignedReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> BufRead for SignedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        // Filling a non-empty buffer returns it as is, so these are the bytes handed out by the last fill
+        if let (Some(digest), Ok(buffered)) = (&mut self.digest, self.inner.fill_buf()) {
+            digest.update(&buffered[..amount.min(buffered.len())]);
+        }
+        self.inner.consume(amount);
+    }
+}
+
+/// Error returned for strings that can't be stored in a keyblock, with the name of the field
+#[derive(Debug)]
+pub struct InvalidField(pub &'static str, pub String);
+
+impl fmt::Display for InvalidField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "invalid {} \"{}\": key paths can't be empty and no string can contain null bytes", self.0, self.1.escape_debug()
+        )
+    }
+}
+
+impl std::error::Error for InvalidField {}
+
+/// Check that `path` can be stored as a key path
+pub fn validate_path(path: &str) -> Result<(), InvalidField> {
+    if path.is_empty() || path.contains('\0') { return Err(InvalidField("key path", path.to_string())) }
+    Ok(())
+}
+
+/// Check that a name or description can be stored, `field` naming it in the error
+pub fn validate_text(field: &'static str, text: &str) -> Result<(), InvalidField> {
+    if text.contains('\0') { return Err(InvalidField(field, text.to_string())) }
+    Ok(())
+}
+
+/// Read a 128-bit ID
+fn read_id<R: Read>(reader: &mut R) -> io::Result<GlobalId> {
+    let mut id = [0; ID_SIZE];
+    reader.read_exact(&mut id)?;
+    Ok(GlobalId(id))
+}
+
+/// Random UID with the given prefix letter, avoiding `used`
+fn random_uid(prefix: u8, used: &[u16]) -> Result<Option<u16>, ErrorStack> {
+    let free: Vec<u16> = (0..=u8::MAX)
+        .map(|number| ((prefix as u16) << 8) | number as u16)
+        .filter(|uid| !used.contains(uid))
+        .collect();
+    if free.is_empty() { return Ok(None) }
+
+    let mut random = [0; 2];
+    rand_bytes(&mut random)?;
+
+    Ok(Some(free[u16::from_le_bytes(random) as usize % free.len()]))
+}
+
+/// Read the whole content of a keyblock file, checking it can be parsed within `max_memory` bytes
+///
+/// Parsing holds both the serialized block and the parsed keyfiles, so it needs about twice the block size.
+pub fn read_limited(mut file: File, max_memory: Option<u64>) -> Result<Vec<u8>, ParseErrors> {
+    let mut content = Vec::new();
+
+    match max_memory {
+        Some(limit) => {
+            let needed = file.metadata()?.len().saturating_mul(2);
+            if needed > limit { return Err(ParseErrors::MemoryLimitExceeded(needed, limit)) }
+
+            // The file could grow while we read it, never buffer more than the limit allows
+            file.take(limit / 2 + 1).read_to_end(&mut content)?;
+            let needed = (content.len() as u64).saturating_mul(2);
+            if needed > limit { return Err(ParseErrors::MemoryLimitExceeded(needed, limit)) }
+        },
+        None => { file.read_to_end(&mut content)?; }
+    }
+
+    Ok(content)
 }
@@ -0,0 +1,120 @@
+//! Parsing and management of banjo keyblocks
+//!
+//! Without default features, the library only holds the metadata parser of the `metadata` module and what
+//! it needs, without OpenSSL nor clap. The features add:
+//! - `crypto`: decryption, signatures and the full `keyblock` parser, on OpenSSL
+//! - `network`: webhook and email notifications, and self-updates
+//! - `cli`: everything the `banjo` binary is made of, enabled by default
+//! - `zstd`: blocks whose metadata is compressed, enabled by default
+
+pub mod display;
+pub mod explain;
+pub mod format;
+pub mod id;
+pub mod metadata;
+pub mod rotation;
+pub mod tags;
+pub mod utils;
+
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "crypto")]
+pub mod hardening;
+#[cfg(feature = "crypto")]
+pub mod host;
+#[cfg(feature = "crypto")]
+pub mod keyblock;
+#[cfg(feature = "crypto")]
+pub mod memlock;
+#[cfg(feature = "crypto")]
+pub mod rootkey;
+#[cfg(feature = "crypto")]
+pub mod secret;
+#[cfg(feature = "crypto")]
+pub mod signature;
+#[cfg(feature = "crypto")]
+pub mod suite;
+
+#[cfg(feature = "network")]
+pub mod install;
+#[cfg(feature = "network")]
+pub mod notify;
+#[cfg(feature = "network")]
+pub mod update;
+
+#[cfg(feature = "cli")]
+pub mod access;
+#[cfg(feature = "cli")]
+pub mod apply;
+#[cfg(feature = "cli")]
+pub mod audit;
+#[cfg(feature = "cli")]
+pub mod backup;
+#[cfg(feature = "cli")]
+pub mod bundle;
+#[cfg(feature = "cli")]
+pub mod ca;
+#[cfg(feature = "cli")]
+pub mod cache;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod conformance;
+#[cfg(feature = "cli")]
+pub mod content;
+#[cfg(feature = "cli")]
+pub mod deploy;
+#[cfg(feature = "cli")]
+pub mod diff;
+#[cfg(feature = "cli")]
+pub mod git;
+#[cfg(feature = "cli")]
+pub mod history;
+#[cfg(feature = "cli")]
+pub mod hooks;
+#[cfg(feature = "cli")]
+pub mod i18n;
+#[cfg(feature = "cli")]
+pub mod logging;
+#[cfg(feature = "cli")]
+pub mod login;
+#[cfg(feature = "cli")]
+pub mod manifest;
+#[cfg(feature = "cli")]
+pub mod merge;
+#[cfg(feature = "cli")]
+pub mod paper;
+#[cfg(feature = "cli")]
+pub mod permissions;
+#[cfg(feature = "cli")]
+pub mod policy;
+#[cfg(feature = "cli")]
+pub mod public;
+#[cfg(feature = "cli")]
+pub mod report;
+#[cfg(feature = "cli")]
+pub mod scavenge;
+#[cfg(feature = "cli")]
+pub mod schema;
+#[cfg(feature = "cli")]
+pub mod ssh;
+#[cfg(feature = "cli")]
+pub mod store;
+#[cfg(feature = "cli")]
+pub mod table;
+#[cfg(feature = "cli")]
+pub mod tls;
+#[cfg(feature = "cli")]
+pub mod uids;
+#[cfg(feature = "cli")]
+pub mod wireguard;
+#[cfg(feature = "cli")]
+pub mod x509;
+#[cfg(feature = "cli")]
+pub mod xref;
+#[cfg(feature = "acme")]
+pub mod acme;
+#[cfg(feature = "enable_debug")]
+pub mod debug;
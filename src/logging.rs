@@ -1,9 +1,41 @@
+use crate::display::escape_controls;
 use simplelog::{TermLogger, LevelFilter, ConfigBuilder, TerminalMode, ColorChoice};
-use log::SetLoggerError;
+use log::{Log, Metadata, Record, SetLoggerError};
+
+/// Logger escaping the control characters of messages before handing them to another logger
+///
+/// Messages often contain names, paths and errors coming from keyblocks, which mustn't be able to send escape
+/// sequences to the terminal or forge log lines.
+struct SanitizingLogger<L: Log> {
+    inner: L
+}
+
+impl<L: Log> Log for SanitizingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return }
+
+        let message = escape_controls(&record.args().to_string());
+        self.inner.log(&Record::builder()
+            .metadata(record.metadata().clone())
+            .args(format_args!("{}", message))
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build());
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
 
 /// Initialize the logger for the CLI app
 pub fn init_cli_logging(level: LevelFilter) -> Result<(), SetLoggerError> {
-    TermLogger::init(
+    let logger = TermLogger::new(
         level,
         ConfigBuilder::new()
             .set_time_level(LevelFilter::Off)
@@ -12,5 +44,9 @@ pub fn init_cli_logging(level: LevelFilter) -> Result<(), SetLoggerError> {
             .build(),
         TerminalMode::Mixed,
         ColorChoice::Auto
-    )
-}
\ No newline at end of file
+    );
+
+    log::set_boxed_logger(Box::new(SanitizingLogger { inner: logger }))?;
+    log::set_max_level(level);
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Login entries, the content of `login` keys
+//!
+//! An entry is a sequence of fields, each a one byte tag, a 32 bits little endian length and the UTF-8
+//! value. The content of keys is sealed under the key secret, the fields are never stored in clear.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use clap::ValueEnum;
+use std::fmt;
+use std::io::{self, Cursor, Read};
+
+/// Enumeration of the potential errors when reading login entries
+#[derive(Debug)]
+pub enum LoginErrors {
+    /// A field is cut short
+    Truncated,
+    /// A field has a tag no login field uses
+    UnknownField(u8),
+    /// A field appears twice
+    DuplicateField(LoginField),
+    /// A field isn't valid UTF-8
+    InvalidEncoding(LoginField),
+    /// The entry has no password
+    MissingPassword
+}
+
+impl fmt::Display for LoginErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginErrors::Truncated => write!(f, "a field is truncated"),
+            LoginErrors::UnknownField(tag) => write!(f, "unknown field tag {}", tag),
+            LoginErrors::DuplicateField(field) => write!(f, "the {} field appears twice", field),
+            LoginErrors::InvalidEncoding(field) => write!(f, "the {} field isn't valid UTF-8", field),
+            LoginErrors::MissingPassword => write!(f, "the entry has no password")
+        }
+    }
+}
+
+impl std::error::Error for LoginErrors {}
+
+impl From<io::Error> for LoginErrors {
+    fn from(_: io::Error) -> Self {
+        LoginErrors::Truncated
+    }
+}
+
+/// Field of a login entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LoginField {
+    Username,
+    Password,
+    Url,
+    Notes
+}
+
+/// Credentials of an account, stored as the content of a `login` key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoginEntry {
+    pub username: Option<String>,
+    pub password: String,
+    pub url: Option<String>,
+    pub notes: Option<String>
+}
+
+impl LoginField {
+    /// Tag of this field in serialized entries
+    fn tag(self) -> u8 {
+        match self {
+            LoginField::Username => 1,
+            LoginField::Password => 2,
+            LoginField::Url => 3,
+            LoginField::Notes => 4
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<LoginField> {
+        [LoginField::Username, LoginField::Password, LoginField::Url, LoginField::Notes].iter().copied()
+            .find(|field| field.tag() == tag)
+    }
+}
+
+impl fmt::Display for LoginField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginField::Username => write!(f, "username"),
+            LoginField::Password => write!(f, "password"),
+            LoginField::Url => write!(f, "url"),
+            LoginField::Notes => write!(f, "notes")
+        }
+    }
+}
+
+impl LoginEntry {
+    /// Parse a serialized entry, every field but the password is optional
+    pub fn from_bytes(content: &[u8]) -> Result<LoginEntry, LoginErrors> {
+        let mut reader = Cursor::new(content);
+        let mut fields: [Option<String>; 4] = Default::default();
+
+        while (reader.position() as usize) < content.len() {
+            let tag = reader.read_u8()?;
+            let field = LoginField::from_tag(tag).ok_or(LoginErrors::UnknownField(tag))?;
+            let length = reader.read_u32::<LittleEndian>()? as usize;
+            // The length is checked against the content before allocating
+            if length > content.len() - reader.position() as usize { return Err(LoginErrors::Truncated) }
+
+            let mut value = vec![0; length];
+            reader.read_exact(&mut value)?;
+            let value = String::from_utf8(value).map_err(|_| LoginErrors::InvalidEncoding(field))?;
+
+            let slot = &mut fields[field.tag() as usize - 1];
+            if slot.is_some() { return Err(LoginErrors::DuplicateField(field)) }
+            *slot = Some(value);
+        }
+
+        let [username, password, url, notes] = fields;
+        Ok(LoginEntry { username, password: password.ok_or(LoginErrors::MissingPassword)?, url, notes })
+    }
+
+    /// Serialize this entry, skipping the fields which aren't set
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        for field in [LoginField::Username, LoginField::Password, LoginField::Url, LoginField::Notes] {
+            if let Some(value) = self.field(field) {
+                content.push(field.tag());
+                content.write_u32::<LittleEndian>(value.len() as u32).expect("Writing to a Vec can't fail.");
+                content.extend(value.as_bytes());
+            }
+        }
+
+        content
+    }
+
+    /// Value of a field, `None` if it isn't set
+    pub fn field(&self, field: LoginField) -> Option<&str> {
+        match field {
+            LoginField::Username => self.username.as_deref(),
+            LoginField::Password => Some(&self.password),
+            LoginField::Url => self.url.as_deref(),
+            LoginField::Notes => self.notes.as_deref()
+        }
+    }
+}
@@ -1,33 +1,43 @@
-mod logging;
-mod keyblock;
-mod utils;
-mod debug;
+mod commands;
 
-#[macro_use]
-extern crate clap;
-
-use clap::{App, SubCommand};
-use crate::logging::init_cli_logging;
-use log::{debug, warn, LevelFilter};
+use banjo_keyring::cli::Cli;
+use banjo_keyring::keyblock::ParseErrors;
+use banjo_keyring::logging::init_cli_logging;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use log::{debug, error, info, LevelFilter};
+#[cfg(feature = "enable_debug")]
+use log::warn;
+use std::process;
 
 fn main() {
-    let cli_yaml = load_yaml!("cli-definition.yaml");
-    let app = App::from_yaml(cli_yaml);
-
-
-    // Add debug command if required.
-    #[cfg(feature = "enable_debug")]
-    let debug_cli_yaml = load_yaml!("cli-debug-subcommand.yaml");
-    #[cfg(feature = "enable_debug")]
-    let app = app.subcommand(SubCommand::from_yaml(debug_cli_yaml));
-
-    let matches = app.get_matches();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    cli.command_name = command_name(&matches);
 
     init_cli_logging(
-        if matches.is_present("verbose") {LevelFilter::Debug} else {LevelFilter::Info}
+        if cli.verbose {LevelFilter::Debug} else {LevelFilter::Info}
     ).expect("Failed to initialize logging.");
 
     debug!("Logging successfully initialized.");
     #[cfg(feature = "enable_debug")]
     warn!("Debug mode is enabled! NOT SUITABLE FOR PRODUCTION.");
+
+    if let Err(error) = commands::run(&cli) {
+        error!("{}", error);
+
+        if let Some(code) = error.downcast_ref::<ParseErrors>().map(ParseErrors::root_code) { info!("Run `banjo explain {}` for remediation steps.", code) }
+        process::exit(1);
+    }
+}
+
+/// Name of the subcommand run, followed by the names of its own subcommands
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut matches = matches;
+    while let Some((name, submatches)) = matches.subcommand() {
+        names.push(name);
+        matches = submatches;
+    }
+
+    names.join(" ")
 }
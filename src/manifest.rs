@@ -0,0 +1,125 @@
+//! Signed inventory of the keys of a block, without any secret, for auditors and asset management
+
+use crate::id::GlobalId;
+use crate::keyblock::{KeyBlock, KEYFILE_FROZEN};
+use crate::policy::KeyPolicy;
+use crate::rootkey::{RootKey, SigningKey};
+use crate::signature::{Signature, SignatureAlgorithm, SignatureErrors};
+use crate::utils::{format_uid, to_hex};
+use crate::x509;
+use itertools::Itertools;
+use openssl::base64::{decode_block, encode_block};
+use openssl::error::ErrorStack;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Identifier of the manifest layout
+const MANIFEST_FORMAT: &str = "banjo-inventory/2";
+
+/// Inventory entry of a key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestKey {
+    pub path: String,
+    pub uid: String,
+    /// Globally unique ID, missing from manifests signed before IDs existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<GlobalId>,
+    pub name: String,
+    pub size: u64,
+    /// Hex encoded keyed digest of the stored content, see `ContentDigest`
+    pub hmac: String,
+    /// Expiry date of certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<String>,
+    pub tags: Vec<String>,
+    pub policies: Vec<String>,
+    pub frozen: bool
+}
+
+/// Inventory of a block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub block_name: String,
+    pub block_uid: String,
+    /// Globally unique ID of the block, missing from manifests signed before IDs existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_id: Option<GlobalId>,
+    pub format_specifier: u16,
+    pub cipher_suite: String,
+    /// Hex encoded SHA256 digest of the serialized block
+    pub block_sha256: String,
+    /// Generation time, in seconds since the UNIX epoch
+    pub generated: u64,
+    /// Keys, sorted by path
+    pub keys: Vec<ManifestKey>
+}
+
+/// A manifest along with the root key signature of its JSON serialization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: Manifest,
+    /// Signature algorithm identifier, as in keyblocks
+    pub signature_algorithm: u16,
+    /// Base64 encoded signature
+    pub signature: String
+}
+
+impl Manifest {
+    /// Inventory of `block`, serialized as `serialized`
+    pub fn new(block: &KeyBlock, serialized: &[u8]) -> Result<Manifest, ErrorStack> {
+        let digest = block.content_digest()?;
+        let keys = block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)).map(|key| Ok(ManifestKey {
+            path: key.path.clone(),
+            uid: format_uid(key.uid),
+            id: Some(key.id),
+            name: key.name.clone(),
+            size: key.length,
+            hmac: digest.of(key)?,
+            expiry: key.open().ok().and_then(|content| x509::parse(&content)).map(|certificate| certificate.not_after().to_string()),
+            tags: key.tags.clone(),
+            policies: KeyPolicy::from_flags(key.flags).iter().map(ToString::to_string).collect(),
+            frozen: key.flags & KEYFILE_FROZEN != 0
+        })).collect::<Result<_, ErrorStack>>()?;
+
+        Ok(Manifest {
+            format: MANIFEST_FORMAT.to_string(),
+            block_name: block.name.clone(),
+            block_uid: format_uid(block.uid),
+            block_id: Some(block.id),
+            format_specifier: block.format_specifier,
+            cipher_suite: block.cipher_suite.to_string(),
+            block_sha256: to_hex(&sha256(serialized)),
+            generated: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            keys
+        })
+    }
+
+    /// Sign this manifest with the root private key
+    pub fn sign(self, key: &SigningKey) -> Result<SignedManifest, ErrorStack> {
+        let signature = Signature::sign(&self.signed_content(), key)?;
+
+        Ok(SignedManifest {
+            manifest: self,
+            signature_algorithm: signature.algorithm.identifier(),
+            signature: encode_block(&signature.data)
+        })
+    }
+
+    /// Content covered by the signature, the compact JSON serialization
+    fn signed_content(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serializing a manifest can't fail.")
+    }
+}
+
+impl SignedManifest {
+    /// Check the signature of this manifest against the root key
+    pub fn verify(&self, root_key: &RootKey) -> Result<(), SignatureErrors> {
+        let algorithm = SignatureAlgorithm::from_identifier(self.signature_algorithm)
+            .ok_or(SignatureErrors::UnknownAlgorithm(self.signature_algorithm))?;
+        let data = decode_block(&self.signature).map_err(|_| SignatureErrors::Mismatch)?;
+
+        Signature { algorithm, data }.verify(&self.manifest.signed_content(), root_key)
+    }
+}
@@ -0,0 +1,97 @@
+//! Locking of the pages holding secrets in memory, so they are never written to swap
+//!
+//! Locking is optional, enabled with `--lock-memory` or the `lock-memory` setting before any secret is loaded.
+//! `munlock` isn't counted while several secrets can share a page, so pages are only unlocked once the last
+//! secret on them is dropped. Failures to lock, usually because `RLIMIT_MEMLOCK` is too low, are reported once
+//! and otherwise ignored: the secrets are still usable, only swappable.
+
+use log::warn;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::{io, mem};
+
+/// Locked memory below which not every secret of a large block may be locked, in bytes
+const MIN_MEMLOCK_LIMIT: u64 = 1 << 20;
+
+/// Whether secrets are locked in memory
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Whether a failure to lock was already reported
+static WARNED: AtomicBool = AtomicBool::new(false);
+/// Number of secrets on every locked page, by page address
+static LOCKED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Lock the secrets created from now on in memory, warning if the locked memory limit is low
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+
+    // Safety: rlimit is a plain struct, filled in by getrlimit
+    let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        warn!("Failed to read the locked memory limit: {}.", io::Error::last_os_error());
+        return
+    }
+    if limit.rlim_cur != libc::RLIM_INFINITY && (limit.rlim_cur as u64) < MIN_MEMLOCK_LIMIT {
+        warn!("The locked memory limit is only {} bytes, some secrets may stay swappable.", limit.rlim_cur);
+    }
+}
+
+/// Whether secrets are locked in memory
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Size of memory pages, in bytes
+fn page_size() -> usize {
+    // Safety: sysconf has no side effect
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Addresses of the pages holding `bytes`
+fn pages(bytes: &[u8]) -> impl Iterator<Item = usize> {
+    let size = page_size();
+    let start = bytes.as_ptr() as usize / size * size;
+    let end = bytes.as_ptr() as usize + bytes.len();
+    (start..end).step_by(size)
+}
+
+/// Lock the pages holding `bytes` in memory, if enabled
+pub fn lock(bytes: &[u8]) {
+    if !enabled() || bytes.is_empty() { return }
+
+    let mut locked = LOCKED_PAGES.lock().unwrap_or_else(|error| error.into_inner());
+    for page in pages(bytes) {
+        if let Some(count) = locked.get_mut(&page) {
+            *count += 1;
+            continue
+        }
+
+        // Safety: the page is mapped, since it holds part of `bytes`
+        if unsafe { libc::mlock(page as *const libc::c_void, page_size()) } == 0 {
+            locked.insert(page, 1);
+        } else if !WARNED.swap(true, Ordering::Relaxed) {
+            warn!("Failed to lock secrets in memory, they may be swapped to disk: {}.", io::Error::last_os_error());
+        }
+    }
+}
+
+/// Release the pages holding `bytes` locked by `lock`, unlocking them if no other secret is on them
+pub fn unlock(bytes: &[u8]) {
+    if !enabled() || bytes.is_empty() { return }
+
+    let mut locked = LOCKED_PAGES.lock().unwrap_or_else(|error| error.into_inner());
+    for page in pages(bytes) {
+        let count = match locked.get_mut(&page) {
+            Some(count) => count,
+            // Locking this page failed
+            None => continue
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            locked.remove(&page);
+            // Safety: the page is still mapped, since it holds part of `bytes`
+            unsafe { libc::munlock(page as *const libc::c_void, page_size()) };
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Three-way merge of concurrently edited keyblocks, at the keyfile level
+//!
+//! Every keyfile path and every block field is merged on its own: a side changing it from the base wins,
+//! both sides making the same change is no conflict, and both sides making different changes is a conflict
+//! which must be resolved by picking a side. Block fields use the `@name`, `@description`, `@uid`, `@flags`,
+//! `@cipher_suite` and `@secret` pseudo-paths.
+
+use crate::keyblock::{ContentDigest, KeyBlock, KeyFile};
+use crate::utils::{format_uid, to_hex};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// HKDF info of the block secret fingerprints shown in conflict reports
+const SECRET_FINGERPRINT_INFO: &[u8] = b"banjo secret fingerprint";
+
+/// Side picked to resolve a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs
+}
+
+/// A path changed differently by both sides
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    /// Keyfile path, or block field pseudo-path
+    pub path: String,
+    /// Description of each version, `None` if it doesn't exist on that side
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>
+}
+
+/// Outcome of a three-way merge
+#[derive(Debug)]
+pub struct MergeResult {
+    /// Merged block, unsigned, with conflicts left at our version
+    pub block: KeyBlock,
+    /// Paths taken from their side
+    pub from_theirs: Vec<String>,
+    /// Conflicts without a resolution
+    pub conflicts: Vec<Conflict>
+}
+
+/// Merge the changes made to `base` by `ours` and `theirs`, resolving conflicts with `resolutions`
+pub fn merge3(base: &KeyBlock, ours: KeyBlock, theirs: &KeyBlock, resolutions: &HashMap<String, Side>) -> MergeResult {
+    let mut merge = Merge { resolutions, from_theirs: Vec::new(), conflicts: Vec::new() };
+    // Every version is digested under our key, so equal content shows equal digests
+    let digest = ours.content_digest().ok();
+    let mut block = ours;
+
+    block.name = merge.field("@name", &base.name, &block.name, &theirs.name, |name| Some(name.clone()));
+    block.description = merge.field(
+        "@description", &base.description, &block.description, &theirs.description, |description| Some(description.clone())
+    );
+    block.uid = merge.field("@uid", &base.uid, &block.uid, &theirs.uid, |uid| Some(format_uid(*uid)));
+    block.id = merge.field("@id", &base.id, &block.id, &theirs.id, |id| Some(id.to_string()));
+    block.flags = merge.field("@flags", &base.flags, &block.flags, &theirs.flags, |flags| Some(format!("{:#x}", flags)));
+    block.cipher_suite = merge.field(
+        "@cipher_suite", &base.cipher_suite, &block.cipher_suite, &theirs.cipher_suite, |suite| Some(suite.to_string())
+    );
+    block.secret = merge.field("@secret", &base.secret, &block.secret, &theirs.secret, |secret| {
+        // A value derived with HKDF tells secrets apart without publishing a digest of them
+        secret.derive(SECRET_FINGERPRINT_INFO).ok().map(|fingerprint| format!("fingerprint {}", to_hex(&fingerprint.as_bytes()[..16])))
+    });
+
+    let paths: HashSet<String> = base.keys.keys().chain(block.keys.keys()).chain(theirs.keys.keys()).cloned().collect();
+    for path in paths.into_iter().sorted() {
+        let merged = merge.field(&path, &base.keys.get(&path), &block.keys.get(&path), &theirs.keys.get(&path), |key| {
+            key.map(|key| describe_key(key, digest.as_ref()))
+        }).cloned();
+
+        match merged {
+            Some(key) => block.keys.insert(path, key),
+            None => block.keys.remove(&path)
+        };
+    }
+
+    MergeResult { block, from_theirs: merge.from_theirs, conflicts: merge.conflicts }
+}
+
+/// State of a merge in progress
+struct Merge<'a> {
+    resolutions: &'a HashMap<String, Side>,
+    from_theirs: Vec<String>,
+    conflicts: Vec<Conflict>
+}
+
+impl Merge<'_> {
+    /// Merge a single value, `describe` giving the conflict report description of each version
+    fn field<T, F>(&mut self, path: &str, base: &T, ours: &T, theirs: &T, describe: F) -> T
+        where T: PartialEq + Clone, F: Fn(&T) -> Option<String>
+    {
+        if ours == theirs || theirs == base { return ours.clone() }
+        if ours == base {
+            self.from_theirs.push(path.to_string());
+            return theirs.clone()
+        }
+
+        match self.resolutions.get(path) {
+            Some(Side::Ours) => ours.clone(),
+            Some(Side::Theirs) => {
+                self.from_theirs.push(path.to_string());
+                theirs.clone()
+            },
+            None => {
+                self.conflicts.push(Conflict {
+                    path: path.to_string(),
+                    base: describe(base),
+                    ours: describe(ours),
+                    theirs: describe(theirs)
+                });
+                ours.clone()
+            }
+        }
+    }
+}
+
+/// Description of a keyfile in conflict reports, with the keyed digest of its content
+fn describe_key(key: &KeyFile, digest: Option<&ContentDigest>) -> String {
+    let hmac = digest.and_then(|digest| digest.of(key).ok()).unwrap_or_else(|| "unavailable".to_string());
+    format!(
+        "uid {}, id {}, \"{}\", {} bytes, hmac {}, tags [{}], flags {:#x}",
+        format_uid(key.uid), key.id, key.name, key.length, hmac, key.tags.join(", "), key.flags
+    )
+}
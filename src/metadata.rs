@@ -0,0 +1,249 @@
+//! Metadata of keyblocks, read without any cryptography
+//!
+//! This is the parser left when the library is built without its default features, for tools that only need
+//! to know what a block holds. Nothing is decrypted nor verified: secrets and key contents are skipped, and
+//! only the algorithm of the signature is read. The metadata of a tampered block is read just as well, so it
+//! must never be trusted. IDs of blocks older than format 4 are derived from the block secret, which can't be
+//! decrypted here, so they are `None`.
+
+use crate::format::{
+    read_compressed_metadata, WrapAlgorithm, BLOCK_COMPRESSED_METADATA, BLOCK_HOST_BOUND, FORMAT_SPECIFIER, HOST_FINGERPRINT_SIZE,
+    KEYFILE_DERIVED_SECRET, KEYFILE_SHARED_CONTENT, KEYFILE_WRAP_MASK, KEYFILE_WRAP_SHIFT, LEGACY_FORMAT_SPECIFIER,
+    LEGACY_SIGNATURE_SIZE, MAGIC_NUMBER, PRE_ID_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER, SECRET_SIZE
+};
+use crate::id::{GlobalId, ID_SIZE};
+use crate::utils::read_null_string_with;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, BufRead, Read};
+use std::{fmt, iter};
+use std::str::Utf8Error;
+
+/// Metadata of a keyblock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMetadata {
+    pub format_specifier: u16,
+    pub flags: u64,
+    /// Cipher suite identifier, `None` for format 1 and 2 blocks which imply it from their signature
+    pub cipher_suite: Option<u16>,
+    pub uid: u16,
+    /// Globally unique ID, `None` for blocks older than format 4
+    pub id: Option<GlobalId>,
+    /// Fingerprint of the host the block is bound to, if any
+    pub host: Option<[u8; HOST_FINGERPRINT_SIZE]>,
+    pub name: String,
+    pub description: String,
+    /// Keyfiles, in the order they are stored
+    pub keys: Vec<KeyFileMetadata>,
+    /// Signature algorithm identifier, 0 for unsigned and format 1 blocks
+    pub signature_algorithm: u16
+}
+
+/// Metadata of a keyfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyFileMetadata {
+    pub flags: u64,
+    pub uid: u16,
+    /// Globally unique ID, `None` for blocks older than format 4
+    pub id: Option<GlobalId>,
+    pub path: String,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Length of the key content, in bytes
+    pub length: u64,
+    /// UID of the keyfile storing the content of this one, in deduplicated blocks
+    pub shared_with: Option<u16>
+}
+
+/// Enumeration of the potential errors when reading metadata
+#[derive(Debug)]
+pub enum MetadataErrors {
+    /// An error occurred when reading the keyfile with this index
+    KeyfileError(u64, Box<MetadataErrors>),
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The block ended in the middle of a field
+    UnexpectedEof,
+    /// Magic number doesn't match `MAGIC_NUMBER`
+    InvalidMagicNumber,
+    /// Unknown format specifier
+    UnknownFormatSpecifier(u16),
+    /// The key secret is wrapped with an unknown algorithm
+    UnknownWrapAlgorithm(u8),
+    /// A format 1 key length isn't a whole number of bytes
+    UnalignedKeyLength(u64),
+    /// A string field isn't valid UTF-8
+    InvalidUtf8(Utf8Error)
+}
+
+impl fmt::Display for MetadataErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataErrors::KeyfileError(index, error) => write!(f, "failed to read keyfile {}: {}", index, error),
+            MetadataErrors::IOError(error) => write!(f, "IO error: {}", error),
+            MetadataErrors::UnexpectedEof => write!(f, "unexpected end of file"),
+            MetadataErrors::InvalidMagicNumber => write!(f, "invalid magic number"),
+            MetadataErrors::UnknownFormatSpecifier(format) => write!(f, "unknown format specifier {}", format),
+            MetadataErrors::UnknownWrapAlgorithm(identifier) => write!(f, "unknown secret wrap algorithm {}", identifier),
+            MetadataErrors::UnalignedKeyLength(length) => write!(f, "key length of {} bits is not a whole number of bytes", length),
+            MetadataErrors::InvalidUtf8(error) => write!(f, "a string field is not valid UTF-8: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for MetadataErrors {}
+
+impl From<io::Error> for MetadataErrors {
+    fn from(error: io::Error) -> Self {
+        // Strings that aren't UTF-8 are reported by the string reader as wrapped `Utf8Error`s
+        if let Some(utf8_error) = error.get_ref().and_then(|inner| inner.downcast_ref::<Utf8Error>()) {
+            return MetadataErrors::InvalidUtf8(*utf8_error)
+        }
+
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => MetadataErrors::UnexpectedEof,
+            _ => MetadataErrors::IOError(error)
+        }
+    }
+}
+
+impl BlockMetadata {
+    /// Read the metadata of a serialized keyblock
+    pub fn from_bytes(content: &[u8]) -> Result<BlockMetadata, MetadataErrors> {
+        BlockMetadata::read(&mut io::Cursor::new(content))
+    }
+
+    /// Read the metadata of a keyblock from `reader`, key contents being skipped instead of buffered
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<BlockMetadata, MetadataErrors> {
+        let mut magic_number = [0; MAGIC_NUMBER.len()];
+        reader.read_exact(&mut magic_number).map_err(|_| MetadataErrors::InvalidMagicNumber)?;
+        if &magic_number != MAGIC_NUMBER { return Err(MetadataErrors::InvalidMagicNumber) }
+
+        let format_specifier = reader.read_u16::<LittleEndian>()?;
+        let known = [FORMAT_SPECIFIER, PRE_ID_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER, LEGACY_FORMAT_SPECIFIER];
+        if !known.contains(&format_specifier) { return Err(MetadataErrors::UnknownFormatSpecifier(format_specifier)) }
+
+        let flags = reader.read_u64::<LittleEndian>()?;
+        let cipher_suite = if format_specifier >= PRE_ID_FORMAT_SPECIFIER {
+            Some(reader.read_u16::<LittleEndian>()?)
+        } else {
+            None
+        };
+
+        skip(reader, SECRET_SIZE as u64)?;
+        let uid = reader.read_u16::<LittleEndian>()?;
+        let id = read_id(reader, format_specifier)?;
+
+        let host = if flags & BLOCK_HOST_BOUND != 0 {
+            let mut fingerprint = [0; HOST_FINGERPRINT_SIZE];
+            reader.read_exact(&mut fingerprint)?;
+            Some(fingerprint)
+        } else {
+            None
+        };
+
+        // Compressed metadata is read up front, the key contents following it are then skipped one by one
+        let mut compressed = if flags & BLOCK_COMPRESSED_METADATA != 0 {
+            Some(io::Cursor::new(read_compressed_metadata(reader)?))
+        } else {
+            None
+        };
+
+        let mut scratch = Vec::new();
+        let mut header: &mut dyn BufRead = match &mut compressed {
+            Some(metadata) => metadata,
+            None => &mut *reader
+        };
+        let name = read_null_string_with(&mut header, &mut scratch)?;
+        let description = read_null_string_with(&mut header, &mut scratch)?;
+
+        // The declared count isn't trusted for allocations, keyfiles are only stored once read
+        let keyfile_number = header.read_u64::<LittleEndian>()?;
+        let keys = (0..keyfile_number)
+            .map(|index| {
+                let key = match &mut compressed {
+                    Some(metadata) => KeyFileMetadata::read(metadata, format_specifier, &mut scratch),
+                    None => KeyFileMetadata::read(reader, format_specifier, &mut scratch)
+                };
+                key.and_then(|key| {
+                    if key.shared_with.is_none() { skip(reader, key.length)? }
+                    Ok(key)
+                }).map_err(|error| MetadataErrors::KeyfileError(index, Box::new(error)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let signature_algorithm = if format_specifier == LEGACY_FORMAT_SPECIFIER {
+            skip(reader, (LEGACY_SIGNATURE_SIZE / 8) as u64)?;
+            0
+        } else {
+            reader.read_u16::<LittleEndian>()?
+        };
+
+        Ok(BlockMetadata {
+            format_specifier,
+            flags,
+            cipher_suite,
+            uid,
+            id,
+            host,
+            name,
+            description,
+            keys,
+            signature_algorithm
+        })
+    }
+}
+
+impl KeyFileMetadata {
+    /// Read the metadata of a keyfile, skipping its secret, up to its content
+    fn read<R: BufRead>(reader: &mut R, format_specifier: u16, scratch: &mut Vec<u8>) -> Result<KeyFileMetadata, MetadataErrors> {
+        let flags = reader.read_u64::<LittleEndian>()?;
+
+        if flags & (KEYFILE_DERIVED_SECRET | KEYFILE_SHARED_CONTENT) == 0 {
+            let identifier = ((flags & KEYFILE_WRAP_MASK) >> KEYFILE_WRAP_SHIFT) as u8;
+            let algorithm = WrapAlgorithm::from_identifier(identifier).ok_or(MetadataErrors::UnknownWrapAlgorithm(identifier))?;
+            skip(reader, algorithm.wrapped_size() as u64)?;
+        }
+
+        let uid = reader.read_u16::<LittleEndian>()?;
+        let id = read_id(reader, format_specifier)?;
+        let path = read_null_string_with(reader, scratch)?;
+        let name = read_null_string_with(reader, scratch)?;
+        let description = read_null_string_with(reader, scratch)?;
+
+        let tags = if format_specifier != LEGACY_FORMAT_SPECIFIER {
+            let tag_number = reader.read_u16::<LittleEndian>()?;
+            iter::repeat_with(|| read_null_string_with(reader, scratch)).take(tag_number as usize).collect::<io::Result<_>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut length = reader.read_u64::<LittleEndian>()?;
+        if format_specifier == LEGACY_FORMAT_SPECIFIER {
+            if length % 8 != 0 { return Err(MetadataErrors::UnalignedKeyLength(length)) }
+            length /= 8;
+        }
+
+        let shared_with = if flags & KEYFILE_SHARED_CONTENT != 0 { Some(reader.read_u16::<LittleEndian>()?) } else { None };
+
+        Ok(KeyFileMetadata { flags, uid, id, path, name, description, tags, length, shared_with })
+    }
+}
+
+/// Read a 128-bit ID if `format_specifier` stores them
+fn read_id<R: Read>(reader: &mut R, format_specifier: u16) -> io::Result<Option<GlobalId>> {
+    if format_specifier != FORMAT_SPECIFIER { return Ok(None) }
+
+    let mut id = [0; ID_SIZE];
+    reader.read_exact(&mut id)?;
+    Ok(Some(GlobalId(id)))
+}
+
+/// Skip `length` bytes, failing if the reader ends first
+fn skip<R: Read>(reader: &mut R, length: u64) -> io::Result<()> {
+    if io::copy(&mut reader.take(length), &mut io::sink())? != length {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+
+    Ok(())
+}
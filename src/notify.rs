@@ -0,0 +1,293 @@
+//! Notifications pushed to external channels when something needs attention
+//!
+//! Events are POSTed as JSON to a webhook, and mailed through an SMTP server. Mail always goes over TLS, either
+//! from the start with `smtps://` or after STARTTLS with `smtp://`, so the login and the alerts are never sent in
+//! clear text.
+
+use crate::display::escape_controls;
+use crate::utils::format_day;
+use native_tls::TlsConnector;
+use openssl::base64::encode_block;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fmt, iter};
+
+/// Timeout of webhook requests
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Timeout of every read and write of an SMTP session
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Environment variable holding the password of the SMTP login
+pub const SMTP_PASSWORD_VARIABLE: &str = "BANJO_SMTP_PASSWORD";
+
+/// Notification settings, the `[notify]` table of the configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// URL receiving a JSON POST request for every event
+    pub webhook: Option<String>,
+    /// Mail server sending an email for every event
+    pub smtp: Option<SmtpConfig>
+}
+
+/// Mail notification settings, the `[notify.smtp]` table of the configuration
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SmtpConfig {
+    /// `smtps://host[:port]` for TLS from the start, or `smtp://host[:port]` upgraded with STARTTLS
+    pub server: String,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Login of the server, its password being read from `BANJO_SMTP_PASSWORD`
+    pub username: Option<String>
+}
+
+/// Events worth notifying about
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A keyblock failed verification
+    VerificationFailed { block: &'a Path, error: String },
+    /// A key is due for rotation, `days_left` being negative once overdue
+    RotationDue { block: &'a Path, key: &'a str, days_left: i64 },
+    /// The content of a key expires on day `expiry`, `days_left` being negative once expired
+    KeyExpiring { block: &'a Path, key: &'a str, expiry: u32, days_left: i64 }
+}
+
+impl Event<'_> {
+    /// Identifier of this event kind in notifications
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::VerificationFailed { .. } => "verification-failed",
+            Event::RotationDue { .. } => "rotation-due",
+            Event::KeyExpiring { .. } => "key-expiring"
+        }
+    }
+
+    /// One line description of this event
+    pub fn summary(&self) -> String {
+        let summary = match self {
+            Event::VerificationFailed { block, error } => format!("{} failed verification: {}", block.display(), error),
+            Event::RotationDue { block, key, days_left } if *days_left < 0 => {
+                format!("{} in {} is overdue for rotation by {} days", key, block.display(), -days_left)
+            },
+            Event::RotationDue { block, key, days_left } => {
+                format!("{} in {} is due for rotation in {} days", key, block.display(), days_left)
+            },
+            Event::KeyExpiring { block, key, expiry, days_left } => {
+                let verb = if *days_left < 0 { "expired" } else { "expires" };
+                format!("{} in {} {} on {}", key, block.display(), verb, format_day(*expiry))
+            }
+        };
+
+        escape_controls(&summary)
+    }
+
+    /// JSON description of this event, as sent to webhooks
+    pub fn payload(&self) -> Value {
+        match self {
+            Event::VerificationFailed { block, error } => json!({
+                "event": self.name(),
+                "block": block.display().to_string(),
+                "error": error
+            }),
+            Event::RotationDue { block, key, days_left } => json!({
+                "event": self.name(),
+                "block": block.display().to_string(),
+                "key": key,
+                "days_left": days_left
+            }),
+            Event::KeyExpiring { block, key, expiry, days_left } => json!({
+                "event": self.name(),
+                "block": block.display().to_string(),
+                "key": key,
+                "expiry": format_day(*expiry),
+                "days_left": days_left
+            })
+        }
+    }
+}
+
+/// Enumeration of the potential errors when sending notifications
+#[derive(Debug)]
+pub enum NotifyErrors {
+    /// The TLS backend couldn't be initialized
+    TlsError(native_tls::Error),
+    /// The webhook request failed
+    WebhookError(Box<ureq::Error>),
+    /// The SMTP settings are invalid, or the server refused a command
+    SmtpError(String),
+    IOError(io::Error)
+}
+
+impl fmt::Display for NotifyErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyErrors::TlsError(error) => write!(f, "failed to initialize TLS: {}", error),
+            NotifyErrors::WebhookError(error) => write!(f, "webhook request failed: {}", error),
+            NotifyErrors::SmtpError(error) => write!(f, "failed to send the email: {}", error),
+            NotifyErrors::IOError(error) => write!(f, "IO error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for NotifyErrors {}
+
+impl From<io::Error> for NotifyErrors {
+    fn from(error: io::Error) -> Self {
+        NotifyErrors::IOError(error)
+    }
+}
+
+/// Send `event` to every configured channel
+pub fn notify(config: &NotifyConfig, event: &Event) -> Result<(), NotifyErrors> {
+    if let Some(url) = &config.webhook {
+        send_webhook(url, event)?;
+    }
+    if let Some(smtp) = &config.smtp {
+        send_mail(smtp, event)?;
+    }
+
+    Ok(())
+}
+
+/// POST `event` as JSON to a webhook
+fn send_webhook(url: &str, event: &Event) -> Result<(), NotifyErrors> {
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(TlsConnector::new().map_err(NotifyErrors::TlsError)?))
+        .timeout(WEBHOOK_TIMEOUT)
+        .build();
+
+    agent.post(url).send_json(event.payload()).map_err(|error| NotifyErrors::WebhookError(Box::new(error)))?;
+    Ok(())
+}
+
+/// Mail `event` to the recipients of `config`
+fn send_mail(config: &SmtpConfig, event: &Event) -> Result<(), NotifyErrors> {
+    let (implicit_tls, address) = match config.server.split_once("://") {
+        Some(("smtps", address)) => (true, address),
+        Some(("smtp", address)) => (false, address),
+        _ => return Err(NotifyErrors::SmtpError(format!("{} isn't an smtp:// or smtps:// URL", config.server)))
+    };
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| NotifyErrors::SmtpError(format!("invalid port {:?}", port)))?),
+        None => (address, if implicit_tls { 465 } else { 587 })
+    };
+
+    // Addresses end up in commands and headers, where line breaks and brackets would change their meaning
+    if config.to.is_empty() { return Err(NotifyErrors::SmtpError("no recipient is configured".to_string())) }
+    for address in iter::once(&config.from).chain(&config.to) {
+        if address.is_empty() || address.contains(|char: char| char.is_control() || "<>,".contains(char)) {
+            return Err(NotifyErrors::SmtpError(format!("invalid address {:?}", address)))
+        }
+    }
+
+    let stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+    let connector = TlsConnector::new().map_err(NotifyErrors::TlsError)?;
+    let handshake = |error| NotifyErrors::SmtpError(format!("TLS handshake with {} failed: {}", host, error));
+
+    let mut session = if implicit_tls {
+        let mut session = SmtpSession::new(connector.connect(host, stream).map_err(handshake)?);
+        session.reply(2)?;
+        session
+    } else {
+        let mut session = SmtpSession::new(stream);
+        session.reply(2)?;
+        session.command("EHLO localhost", 2)?;
+        session.command("STARTTLS", 2)?;
+        SmtpSession::new(connector.connect(host, session.stream.into_inner()).map_err(handshake)?)
+    };
+    session.command("EHLO localhost", 2)?;
+
+    if let Some(username) = &config.username {
+        let password = env::var(SMTP_PASSWORD_VARIABLE)
+            .map_err(|_| NotifyErrors::SmtpError(format!("{} isn't set", SMTP_PASSWORD_VARIABLE)))?;
+        let credentials = format!("\0{}\0{}", username, password);
+        session.command(&format!("AUTH PLAIN {}", encode_block(credentials.as_bytes())), 2)?;
+    }
+
+    session.command(&format!("MAIL FROM:<{}>", config.from), 2)?;
+    for recipient in &config.to {
+        session.command(&format!("RCPT TO:<{}>", recipient), 2)?;
+    }
+    session.command("DATA", 3)?;
+    session.command(&mail_message(config, event), 2)?;
+    session.command("QUIT", 2)?;
+
+    Ok(())
+}
+
+/// Headers and body of the email describing `event`, ending with the end of data marker
+fn mail_message(config: &SmtpConfig, event: &Event) -> String {
+    let summary = event.summary();
+    let payload = serde_json::to_string_pretty(&event.payload()).unwrap_or_default();
+
+    let mut lines = vec![
+        format!("From: <{}>", config.from),
+        format!("To: {}", config.to.iter().map(|address| format!("<{}>", address)).collect::<Vec<_>>().join(", ")),
+        format!("Subject: banjo: {}", summary),
+        "MIME-Version: 1.0".to_string(),
+        "Content-Type: text/plain; charset=utf-8".to_string(),
+        "Content-Transfer-Encoding: 8bit".to_string(),
+        String::new(),
+        summary
+    ];
+    lines.push(String::new());
+    // Lines starting with a dot are doubled so they can't end the data early
+    lines.extend(payload.lines().map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() }));
+    lines.push(".".to_string());
+
+    lines.join("\r\n")
+}
+
+/// Command and reply exchange with an SMTP server
+struct SmtpSession<S: Read + Write> {
+    stream: BufReader<S>
+}
+
+impl<S: Read + Write> SmtpSession<S> {
+    fn new(stream: S) -> SmtpSession<S> {
+        SmtpSession { stream: BufReader::new(stream) }
+    }
+
+    /// Send a command, then check the class of its reply, 2 for success and 3 for intermediate replies
+    fn command(&mut self, command: &str, class: u8) -> Result<(), NotifyErrors> {
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+
+        // Commands can hold the login, so only their verb appears in errors
+        let verb = command.split_whitespace().next().unwrap_or_default();
+        self.reply(class).map_err(|error| match error {
+            NotifyErrors::SmtpError(reply) => NotifyErrors::SmtpError(format!("{} refused: {}", verb, reply)),
+            error => error
+        })
+    }
+
+    /// Read a possibly multiline reply, failing unless its code is of `class`
+    fn reply(&mut self, class: u8) -> Result<(), NotifyErrors> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(NotifyErrors::SmtpError("the server closed the connection".to_string()))
+            }
+            reply.push_str(line.trim_end());
+
+            // Continuation lines have a dash after the code
+            if line.as_bytes().get(3) != Some(&b'-') { break }
+            reply.push(' ');
+        }
+
+        match reply.as_bytes().first() {
+            Some(digit) if *digit == b'0' + class => Ok(()),
+            _ => Err(NotifyErrors::SmtpError(escape_controls(&reply)))
+        }
+    }
+}
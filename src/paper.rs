@@ -0,0 +1,244 @@
+//! Paper backups of recovery material, as QR codes and BIP39 word lists
+//!
+//! QR codes contain a text payload, `banjo-sealed-secret:<base64>` for block secrets and `banjo-key:<base64>`
+//! for key contents. Block secrets give access to every key of their block, so they are never printed as is but
+//! sealed under a passphrase, see `SealedSecret`.
+//!
+//! Word lists are standard BIP39 mnemonics of up to 24 words, one for every 32 bytes of the payload. Each
+//! mnemonic can only encode 16 to 32 bytes in steps of 4 bytes, which covers sealed block secrets and small keys.
+
+use crate::secret::{Passphrase, Secret256, WrapAlgorithm, SECRET_SIZE};
+use bip39::Mnemonic;
+use openssl::base64::{decode_block, encode_block};
+use openssl::error::ErrorStack;
+use openssl::rand::rand_bytes;
+use qrcode::{Color, QrCode};
+use std::fmt;
+
+/// QR code prefix of sealed block secrets
+const SEALED_SECRET_PREFIX: &str = "banjo-sealed-secret:";
+/// QR code prefix of key contents
+const KEY_PREFIX: &str = "banjo-key:";
+/// Size of a QR code module in rendered PNGs, in pixels
+const PNG_MODULE_SIZE: usize = 8;
+/// Width of the blank border around rendered QR codes, in modules
+const QUIET_ZONE: usize = 4;
+/// Size of the random scrypt salt of sealed secrets
+const SEAL_SALT_SIZE: usize = 16;
+/// Size of a block secret wrapped with AES-256 key wrap, which adds an 8 bytes integrity check
+const SEALED_SECRET_SIZE: usize = SECRET_SIZE + 8;
+/// Bytes encoded by a full BIP39 mnemonic, and its number of words
+const MNEMONIC_SIZE: usize = 32;
+const MNEMONIC_WORDS: usize = 24;
+
+/// Recovery material stored on paper
+#[derive(Debug, Clone)]
+pub enum PaperPayload {
+    /// A block secret, sealed under a passphrase
+    Secret(SealedSecret),
+    /// The content of a key
+    Key(Vec<u8>)
+}
+
+/// Enumeration of the potential errors when handling paper backups
+#[derive(Debug)]
+pub enum PaperErrors {
+    /// The payload doesn't fit in a QR code
+    QrError(qrcode::types::QrError),
+    /// No QR code could be found in the image
+    NoQrCode,
+    /// The QR code couldn't be decoded
+    QrDecodeError(rqrr::DeQRError),
+    /// The PNG image couldn't be encoded or decoded
+    PngError(String),
+    /// The QR code or word list doesn't contain banjo recovery material
+    InvalidPayload,
+    /// The word list isn't a valid BIP39 mnemonic
+    InvalidWords(bip39::Error)
+}
+
+impl fmt::Display for PaperErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaperErrors::QrError(error) => write!(f, "failed to build the QR code: {}", error),
+            PaperErrors::NoQrCode => write!(f, "no QR code found in the image"),
+            PaperErrors::QrDecodeError(error) => write!(f, "failed to decode the QR code: {}", error),
+            PaperErrors::PngError(error) => write!(f, "PNG error: {}", error),
+            PaperErrors::InvalidPayload => write!(f, "the QR code doesn't contain banjo recovery material"),
+            PaperErrors::InvalidWords(error) => write!(f, "invalid word list: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for PaperErrors {}
+
+/// A block secret wrapped with AES-256 key wrap under a secret derived from a passphrase with scrypt
+///
+/// The salt is random, so sealing the same secret twice gives unrelated payloads. The integrity check of the
+/// key wrap tells wrong passphrases apart when opening.
+#[derive(Debug, Clone)]
+pub struct SealedSecret {
+    salt: [u8; SEAL_SALT_SIZE],
+    wrapped: Vec<u8>
+}
+
+impl SealedSecret {
+    /// Seal `secret` under `passphrase`
+    pub fn seal(secret: &Secret256, passphrase: &Passphrase) -> Result<SealedSecret, ErrorStack> {
+        let mut salt = [0; SEAL_SALT_SIZE];
+        rand_bytes(&mut salt)?;
+        let wrapped = secret.wrap(&passphrase.derive(&salt)?, WrapAlgorithm::AesKw)?;
+
+        Ok(SealedSecret { salt, wrapped })
+    }
+
+    /// Open this secret with `passphrase`, `None` if the passphrase is wrong or the payload was altered
+    pub fn open(&self, passphrase: &Passphrase) -> Result<Option<Secret256>, ErrorStack> {
+        Ok(Secret256::unwrap(&self.wrapped, &passphrase.derive(&self.salt)?, WrapAlgorithm::AesKw))
+    }
+
+    /// Serialized form of this secret, the salt followed by the wrapped secret
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.salt[..], &self.wrapped].concat()
+    }
+
+    /// Read a secret from its serialized form
+    pub fn from_bytes(bytes: &[u8]) -> Result<SealedSecret, PaperErrors> {
+        if bytes.len() != SEAL_SALT_SIZE + SEALED_SECRET_SIZE { return Err(PaperErrors::InvalidPayload) }
+
+        let mut salt = [0; SEAL_SALT_SIZE];
+        salt.copy_from_slice(&bytes[..SEAL_SALT_SIZE]);
+        Ok(SealedSecret { salt, wrapped: bytes[SEAL_SALT_SIZE..].to_vec() })
+    }
+}
+
+impl PaperPayload {
+    /// Text stored in the QR code of this payload
+    pub fn to_qr_text(&self) -> String {
+        match self {
+            PaperPayload::Secret(secret) => format!("{}{}", SEALED_SECRET_PREFIX, encode_block(&secret.to_bytes())),
+            PaperPayload::Key(content) => format!("{}{}", KEY_PREFIX, encode_block(content))
+        }
+    }
+
+    /// Parse the text stored in a QR code
+    pub fn from_qr_text(text: &str) -> Result<PaperPayload, PaperErrors> {
+        if let Some(encoded) = text.strip_prefix(SEALED_SECRET_PREFIX) {
+            let secret = decode_block(encoded).map_err(|_| PaperErrors::InvalidPayload)?;
+            Ok(PaperPayload::Secret(SealedSecret::from_bytes(&secret)?))
+        } else if let Some(encoded) = text.strip_prefix(KEY_PREFIX) {
+            Ok(PaperPayload::Key(decode_block(encoded).map_err(|_| PaperErrors::InvalidPayload)?))
+        } else {
+            Err(PaperErrors::InvalidPayload)
+        }
+    }
+
+    /// Raw bytes of this payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            PaperPayload::Secret(secret) => secret.to_bytes(),
+            PaperPayload::Key(content) => content.clone()
+        }
+    }
+
+    /// BIP39 word list of this payload, if the size of every 32 bytes chunk can be encoded
+    pub fn to_words(&self) -> Option<Vec<&'static str>> {
+        let mut words = Vec::new();
+        for chunk in self.to_bytes().chunks(MNEMONIC_SIZE) {
+            words.extend(Mnemonic::from_entropy(chunk).ok()?.words());
+        }
+
+        Some(words)
+    }
+
+    /// Build the QR code of this payload
+    pub fn to_qr_code(&self) -> Result<QrCode, PaperErrors> {
+        QrCode::new(self.to_qr_text()).map_err(PaperErrors::QrError)
+    }
+}
+
+/// Decode the bytes encoded by a BIP39 word list, made of one mnemonic for every 24 words
+pub fn words_to_bytes(words: &str) -> Result<Vec<u8>, PaperErrors> {
+    let words: Vec<&str> = words.split_whitespace().collect();
+    let mut bytes = Vec::new();
+    for mnemonic in words.chunks(MNEMONIC_WORDS) {
+        bytes.extend(Mnemonic::parse(mnemonic.join(" ")).map_err(PaperErrors::InvalidWords)?.to_entropy());
+    }
+
+    Ok(bytes)
+}
+
+/// Render a QR code as text, using half blocks so every line holds two rows of modules
+pub fn render_terminal(code: &QrCode) -> String {
+    let width = code.width();
+    let dark = |x: usize, y: usize| x < width && y < width && code[(x, y)] == Color::Dark;
+    let mut output = String::new();
+
+    // The quiet zone is drawn in the terminal background color, so dark modules are drawn blank
+    for y in (0..width + 2 * QUIET_ZONE).step_by(2) {
+        for x in 0..width + 2 * QUIET_ZONE {
+            let upper = x >= QUIET_ZONE && y >= QUIET_ZONE && dark(x - QUIET_ZONE, y - QUIET_ZONE);
+            let lower = x >= QUIET_ZONE && y + 1 >= QUIET_ZONE && dark(x - QUIET_ZONE, y + 1 - QUIET_ZONE);
+
+            output.push(match (upper, lower) {
+                (true, true) => ' ',
+                (true, false) => '▄',
+                (false, true) => '▀',
+                (false, false) => '█'
+            });
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Render a QR code as a greyscale PNG image
+pub fn render_png(code: &QrCode) -> Result<Vec<u8>, PaperErrors> {
+    let modules = code.width() + 2 * QUIET_ZONE;
+    let size = modules * PNG_MODULE_SIZE;
+    let mut pixels = vec![0xff; size * size];
+
+    for y in 0..size {
+        for x in 0..size {
+            let (module_x, module_y) = (x / PNG_MODULE_SIZE, y / PNG_MODULE_SIZE);
+            let inside = (QUIET_ZONE..modules - QUIET_ZONE).contains(&module_x)
+                && (QUIET_ZONE..modules - QUIET_ZONE).contains(&module_y);
+
+            if inside && code[(module_x - QUIET_ZONE, module_y - QUIET_ZONE)] == Color::Dark {
+                pixels[y * size + x] = 0;
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|error| PaperErrors::PngError(error.to_string()))?;
+    writer.write_image_data(&pixels).map_err(|error| PaperErrors::PngError(error.to_string()))?;
+    writer.finish().map_err(|error| PaperErrors::PngError(error.to_string()))?;
+
+    Ok(output)
+}
+
+/// Find and decode the QR code contained in a PNG image
+pub fn read_png(image: &[u8]) -> Result<String, PaperErrors> {
+    let mut decoder = png::Decoder::new(image);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| PaperErrors::PngError(error.to_string()))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|error| PaperErrors::PngError(error.to_string()))?;
+    let channels = info.color_type.samples();
+    let (width, height) = (info.width as usize, info.height as usize);
+
+    // Only the first channel is used, which is enough for the black and white images QR codes are
+    let mut image = rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| {
+        buffer[(y * width + x) * channels]
+    });
+
+    let grid = image.detect_grids().into_iter().next().ok_or(PaperErrors::NoQrCode)?;
+    Ok(grid.decode().map_err(PaperErrors::QrDecodeError)?.1)
+}
@@ -0,0 +1,118 @@
+//! Permission and ownership checks of keyblock files, and creation of files only readable by their owner
+
+use crate::utils::to_hex;
+use openssl::rand::rand_bytes;
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Mode of the files created by banjo
+const PRIVATE_MODE: u32 = 0o600;
+/// Mode bits a keyblock file must not have: any access by group or others
+const FILE_FORBIDDEN_BITS: u32 = 0o077;
+/// Mode bits the directory of a keyblock must not have: read or write access by group or others
+const DIRECTORY_FORBIDDEN_BITS: u32 = 0o066;
+
+/// Problems with the permissions or ownership of a keyblock file and its directory, empty if there are none
+pub fn check_block_permissions(path: &Path) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    // Safety: geteuid can't fail and has no side effect
+    let user = unsafe { libc::geteuid() };
+
+    let metadata = fs::metadata(path)?;
+    if metadata.mode() & FILE_FORBIDDEN_BITS != 0 {
+        problems.push(format!("{} is accessible by group or others (mode {:o})", path.display(), metadata.mode() & 0o777));
+    }
+    if metadata.uid() != user {
+        problems.push(format!("{} is owned by another user (uid {})", path.display(), metadata.uid()));
+    }
+
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new(".")
+    };
+    let metadata = fs::metadata(directory)?;
+    if metadata.mode() & DIRECTORY_FORBIDDEN_BITS != 0 {
+        problems.push(format!(
+            "directory {} is readable or writable by group or others (mode {:o})", directory.display(), metadata.mode() & 0o777
+        ));
+    }
+    if metadata.uid() != user && metadata.uid() != 0 {
+        problems.push(format!("directory {} is owned by another user (uid {})", directory.display(), metadata.uid()));
+    }
+
+    Ok(problems)
+}
+
+/// Open a file for writing, creating it with mode 0600 regardless of the umask
+///
+/// Existing files keep their mode. Without `overwrite`, the file must not exist yet.
+pub fn open_private(path: &Path, overwrite: bool) -> io::Result<File> {
+    // Without overwrite, opening only succeeds if the file is created
+    let created = !overwrite || !path.exists();
+
+    let mut options = OpenOptions::new();
+    options.write(true).mode(PRIVATE_MODE);
+    if overwrite { options.create(true).truncate(true); } else { options.create_new(true); }
+    let file = options.open(path)?;
+
+    if created {
+        file.set_permissions(Permissions::from_mode(PRIVATE_MODE))?;
+    }
+
+    Ok(file)
+}
+
+/// Atomically replace `path` with a file of mode 0600 holding `content`
+///
+/// The content is written to a temporary file in the same directory and synced before being renamed over
+/// `path`, so readers and crashes only ever see the previous or the new content. Symbolic links are
+/// followed, the file they point to is replaced.
+pub fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(error) => return Err(error)
+    };
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new(".")
+    };
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+
+    let mut suffix = [0; 8];
+    rand_bytes(&mut suffix).map_err(io::Error::other)?;
+    let temporary = directory.join(format!(".{}.{}.tmp", name.to_string_lossy(), to_hex(&suffix)));
+
+    let result = (|| {
+        let mut file = open_private(&temporary, false)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)?;
+        // The rename itself is only durable once the directory is synced
+        File::open(directory)?.sync_all()
+    })();
+    if result.is_err() { let _ = fs::remove_file(&temporary); }
+
+    result
+}
+
+/// Exclusive advisory lock on `<path>.lock`, held until the returned file is dropped
+///
+/// Processes writing the same file serialize on it, the lock file itself is never removed.
+pub fn lock_exclusive(path: &Path) -> io::Result<File> {
+    // Every link to the file shares the lock
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut lock_path = path.into_os_string();
+    lock_path.push(".lock");
+    let file = open_private(Path::new(&lock_path), true)?;
+
+    // Safety: the descriptor stays open as long as `file`
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error())
+    }
+
+    Ok(file)
+}
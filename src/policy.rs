@@ -0,0 +1,61 @@
+//! Usage policies of keys, stored in bits 16 to 19 of the keyfile flags
+
+use clap::ValueEnum;
+use std::fmt;
+
+/// Restriction on how a key can leave the keyblock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyPolicy {
+    /// The key content can never be exported
+    NeverExport,
+    /// The key can only be written to its deployment path
+    DeployOnly,
+    /// The key can only be used through the agent, never extracted
+    AgentOnly,
+    /// Any use of the key has to be confirmed interactively
+    RequireConfirmation
+}
+
+impl KeyPolicy {
+    /// Every policy, in flag order
+    pub const ALL: [KeyPolicy; 4] = [
+        KeyPolicy::NeverExport, KeyPolicy::DeployOnly, KeyPolicy::AgentOnly, KeyPolicy::RequireConfirmation
+    ];
+
+    /// Keyfile flag bit of this policy
+    pub fn flag(self) -> u64 {
+        match self {
+            KeyPolicy::NeverExport => 1 << 16,
+            KeyPolicy::DeployOnly => 1 << 17,
+            KeyPolicy::AgentOnly => 1 << 18,
+            KeyPolicy::RequireConfirmation => 1 << 19
+        }
+    }
+
+    /// Policies set in keyfile flags
+    pub fn from_flags(flags: u64) -> Vec<KeyPolicy> {
+        KeyPolicy::ALL.iter().copied().filter(|policy| flags & policy.flag() != 0).collect()
+    }
+
+    /// Whether the content of a key with these flags can be exported, to paper or elsewhere
+    pub fn allows_export(flags: u64) -> bool {
+        let forbidding = KeyPolicy::NeverExport.flag() | KeyPolicy::DeployOnly.flag() | KeyPolicy::AgentOnly.flag();
+        flags & forbidding == 0
+    }
+
+    /// Whether a key with these flags can be written to its deployment target, which deploy-only keys are meant for
+    pub fn allows_deploy(flags: u64) -> bool {
+        flags & (KeyPolicy::NeverExport.flag() | KeyPolicy::AgentOnly.flag()) == 0
+    }
+}
+
+impl fmt::Display for KeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyPolicy::NeverExport => write!(f, "never-export"),
+            KeyPolicy::DeployOnly => write!(f, "deploy-only"),
+            KeyPolicy::AgentOnly => write!(f, "agent-only"),
+            KeyPolicy::RequireConfirmation => write!(f, "require-confirmation")
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Public metadata split of a block ("pubblock"), without any secret or ciphertext
+//!
+//! A pubblock holds the metadata of a verified block and the keyed digests of its keys, signed with the root
+//! key, so monitoring and inventory systems can check what a block contains without ever handling its secrets.
+//! Keys whose content type has an end of validity, such as certificates, carry their expiry day.
+
+use crate::cache::{KeyMetadata, MetadataCache};
+use crate::id::GlobalId;
+use crate::keyblock::KeyBlock;
+use crate::rootkey::{RootKey, SigningKey};
+use crate::signature::{Signature, SignatureAlgorithm, SignatureErrors};
+use crate::utils::{format_uid, to_hex};
+use openssl::base64::{decode_block, encode_block};
+use openssl::error::ErrorStack;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Identifier of the pubblock layout
+const PUBLIC_BLOCK_FORMAT: &str = "banjo-pubblock/2";
+
+/// Public metadata of a block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicBlock {
+    pub format: String,
+    pub name: String,
+    pub uid: String,
+    /// Globally unique ID, missing from pubblocks signed before IDs existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<GlobalId>,
+    pub description: String,
+    pub format_specifier: u16,
+    pub cipher_suite: String,
+    /// Hex encoded SHA256 digest of the serialized block
+    pub block_sha256: String,
+    /// Fingerprint of the root key the block was verified with
+    pub root_key_fingerprint: String,
+    /// Generation time, in seconds since the UNIX epoch
+    pub generated: u64,
+    /// Keyfiles, sorted by path
+    pub keys: Vec<KeyMetadata>
+}
+
+/// A pubblock along with the root key signature of its JSON serialization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPublicBlock {
+    pub block: PublicBlock,
+    /// Signature algorithm identifier, as in keyblocks
+    pub signature_algorithm: u16,
+    /// Base64 encoded signature
+    pub signature: String
+}
+
+impl PublicBlock {
+    /// Public metadata of `block`, serialized as `serialized`
+    pub fn new(block: &KeyBlock, serialized: &[u8]) -> Result<PublicBlock, ErrorStack> {
+        Ok(PublicBlock {
+            format: PUBLIC_BLOCK_FORMAT.to_string(),
+            name: block.name.clone(),
+            uid: format_uid(block.uid),
+            id: Some(block.id),
+            description: block.description.clone(),
+            format_specifier: block.format_specifier,
+            cipher_suite: block.cipher_suite.to_string(),
+            block_sha256: to_hex(&sha256(serialized)),
+            root_key_fingerprint: block.root_pubkey.fingerprint()?,
+            generated: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            keys: MetadataCache::keys_of(block)?
+        })
+    }
+
+    /// Sign this pubblock with the root private key
+    pub fn sign(self, key: &SigningKey) -> Result<SignedPublicBlock, ErrorStack> {
+        let signature = Signature::sign(&self.signed_content(), key)?;
+
+        Ok(SignedPublicBlock {
+            block: self,
+            signature_algorithm: signature.algorithm.identifier(),
+            signature: encode_block(&signature.data)
+        })
+    }
+
+    /// Content covered by the signature, the compact JSON serialization
+    fn signed_content(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serializing a pubblock can't fail.")
+    }
+}
+
+impl SignedPublicBlock {
+    /// Check the signature of this pubblock against the root key
+    pub fn verify(&self, root_key: &RootKey) -> Result<(), SignatureErrors> {
+        let algorithm = SignatureAlgorithm::from_identifier(self.signature_algorithm)
+            .ok_or(SignatureErrors::UnknownAlgorithm(self.signature_algorithm))?;
+        let data = decode_block(&self.signature).map_err(|_| SignatureErrors::Mismatch)?;
+
+        Signature { algorithm, data }.verify(&self.block.signed_content(), root_key)
+    }
+}
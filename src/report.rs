@@ -0,0 +1,238 @@
+//! Machine-readable verification reports, listing every check performed on a keyblock
+
+use crate::content::ContentType;
+use crate::keyblock::{KeyBlock, ParseErrors};
+use crate::rootkey::RootKey;
+use crate::rotation::{describe as describe_rotation, today, Rotation};
+use crate::table::Table;
+use crate::utils::{format_uid, to_hex};
+use crate::x509;
+use itertools::Itertools;
+use openssl::sha::sha256;
+use serde::Serialize;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check found a problem that doesn't invalidate the block
+    Warn,
+    /// The check doesn't apply to this block, or couldn't run because of an earlier failure
+    Skip
+}
+
+/// Family of a check, so checks can be selected without relying on their name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckKind {
+    /// Magic number, format specifier, cipher suite and overall layout of the block
+    Structure,
+    Keyfile,
+    /// Decryption of the content of a key under its secret
+    Decryption,
+    /// Validity of the decrypted content of a key against its content type
+    Content,
+    Expiry,
+    Rotation,
+    Signature,
+    Permissions,
+    /// Signatures and digests of a bundle and its manifest
+    Bundle
+}
+
+/// A check performed while verifying a block
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub kind: CheckKind,
+    pub status: CheckStatus,
+    pub detail: String
+}
+
+/// Every check performed while verifying a block
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    /// Location of the block
+    pub block: String,
+    /// Hex encoded SHA256 digest of the whole block
+    pub sha256: String,
+    /// Whether every check passed
+    pub valid: bool,
+    pub checks: Vec<Check>
+}
+
+impl CheckStatus {
+    /// Name of this status, as serialized
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Fail => "fail",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Skip => "skip"
+        }
+    }
+}
+
+impl VerifyReport {
+    /// Table of the checks of `reports`, one row per check
+    pub fn checks_table(reports: &[VerifyReport]) -> Table {
+        let header = ["BLOCK", "SHA256", "VALID", "CHECK", "STATUS", "DETAIL"];
+        let rows = reports.iter()
+            .flat_map(|report| report.checks.iter().map(move |check| vec![
+                report.block.clone(), report.sha256.clone(), report.valid.to_string(), check.name.clone(),
+                check.status.name().to_string(), check.detail.clone()
+            ]))
+            .collect();
+
+        Table { header: header.iter().map(|header| header.to_string()).collect(), rows }
+    }
+
+    /// Verify a serialized block, recording every check
+    pub fn build(block: &str, content: &[u8], root_key: RootKey) -> VerifyReport {
+        let mut report = VerifyReport { block: block.to_string(), sha256: to_hex(&sha256(content)), valid: true, checks: Vec::new() };
+
+        match KeyBlock::parse(content, root_key) {
+            Ok((block, signed_length)) => {
+                report.pass("magic", CheckKind::Structure, "banjo");
+                report.pass("format-specifier", CheckKind::Structure, &block.format_specifier.to_string());
+                report.pass("cipher-suite", CheckKind::Structure, &block.cipher_suite.to_string());
+
+                let today = today();
+                let digest = block.content_digest();
+                for key in block.keys.values().sorted_by(|a, b| a.path.cmp(&b.path)) {
+                    let hmac = digest.as_ref().map_err(Clone::clone).and_then(|digest| digest.of(key))
+                        .unwrap_or_else(|error| format!("unavailable ({})", error));
+                    report.pass(&format!("keyfile {}", key.path), CheckKind::Keyfile, &format!(
+                        "uid {}, {} bytes, hmac {}", format_uid(key.uid), key.length, hmac
+                    ));
+
+                    // Content is sealed under the key secret, a key that can't be opened doesn't invalidate the block
+                    let plaintext = match key.open() {
+                        Ok(plaintext) => plaintext,
+                        Err(error) => {
+                            report.warn(&format!("content {}", key.path), CheckKind::Decryption, &format!("can't be decrypted: {}", error));
+                            continue
+                        }
+                    };
+
+                    // Certificates carry their own expiry date, an expired one doesn't invalidate the block
+                    if let Some(certificate) = x509::parse(&plaintext) {
+                        let detail = format!("expires {}", certificate.not_after());
+                        if x509::has_expired(&certificate) {
+                            report.warn(&format!("expiry {}", key.path), CheckKind::Expiry, &detail);
+                        } else {
+                            report.pass(&format!("expiry {}", key.path), CheckKind::Expiry, &detail);
+                        }
+                    }
+
+                    // Invalid content doesn't make the block unusable, only the key
+                    let name = format!("content {}", key.path);
+                    match ContentType::from_flags(key.flags) {
+                        Some(ContentType::Generic) => {},
+                        Some(content_type) => match content_type.handler().validate(&plaintext) {
+                            Ok(()) => report.pass(&name, CheckKind::Content, &content_type.to_string()),
+                            Err(error) => report.warn(&name, CheckKind::Content, &format!("{}: {}", content_type, error))
+                        },
+                        None => report.warn(&name, CheckKind::Content, "unknown content type")
+                    }
+
+                    if let Some(rotation) = Rotation::from_flags(key.flags) {
+                        let status = if rotation.days_left(today) < 0 { CheckStatus::Warn } else { CheckStatus::Pass };
+                        report.push(&format!("rotation {}", key.path), CheckKind::Rotation, status, &describe_rotation(rotation, today));
+                    }
+                }
+
+                match block.signature.verify(&content[..signed_length], &block.root_pubkey) {
+                    Ok(()) => report.pass("signature", CheckKind::Signature, &format!("{:?}", block.signature.algorithm)),
+                    Err(error) => report.fail("signature", CheckKind::Signature, &error.to_string())
+                }
+            },
+            Err(error) => report.parse_failure(&error)
+        }
+
+        report.skip("crc", CheckKind::Structure, "keyblocks don't carry a CRC");
+        report.skip("expiry", CheckKind::Expiry, "keyblocks don't carry an expiry date");
+
+        report
+    }
+
+    /// Report of a block that couldn't be read at all
+    pub fn unreadable(block: &str, error: &str) -> VerifyReport {
+        let mut report = VerifyReport { block: block.to_string(), sha256: String::new(), valid: true, checks: Vec::new() };
+        report.fail("read", CheckKind::Structure, error);
+
+        report
+    }
+
+    /// Record the checks implied by a parse failure
+    fn parse_failure(&mut self, error: &ParseErrors) {
+        match error {
+            ParseErrors::InvalidMagicNumber => {
+                self.fail("magic", CheckKind::Structure, &error.to_string());
+            },
+            ParseErrors::UnknownFormatSpecifier => {
+                self.pass("magic", CheckKind::Structure, "banjo");
+                self.fail("format-specifier", CheckKind::Structure, &error.to_string());
+            },
+            ParseErrors::UnknownCipherSuite(_) | ParseErrors::CipherSuiteMismatch(_) => {
+                self.pass("magic", CheckKind::Structure, "banjo");
+                self.pass("format-specifier", CheckKind::Structure, "known");
+                self.fail("cipher-suite", CheckKind::Structure, &error.to_string());
+            },
+            ParseErrors::KeyfileParseError(index, inner) => {
+                self.pass("magic", CheckKind::Structure, "banjo");
+                self.pass("format-specifier", CheckKind::Structure, "known");
+                self.fail(&format!("keyfile #{}", index), CheckKind::Keyfile, &inner.to_string());
+            },
+            ParseErrors::SignatureError(inner) => {
+                self.pass("magic", CheckKind::Structure, "banjo");
+                self.pass("format-specifier", CheckKind::Structure, "known");
+                self.fail("signature", CheckKind::Signature, &inner.to_string());
+            },
+            error => self.fail("structure", CheckKind::Structure, &error.to_string())
+        }
+
+        if !self.checks.iter().any(|check| check.kind == CheckKind::Signature) {
+            self.skip("signature", CheckKind::Signature, "the block couldn't be parsed");
+        }
+    }
+
+    /// Record a check performed outside of the report, failures make the report invalid
+    pub fn record(&mut self, name: &str, kind: CheckKind, status: CheckStatus, detail: &str) {
+        if status == CheckStatus::Fail { self.valid = false }
+        self.push(name, kind, status, detail);
+    }
+
+    /// Turn the warnings of the checks of `kind` into failures
+    pub fn escalate(&mut self, kind: CheckKind) {
+        for check in self.checks.iter_mut().filter(|check| check.kind == kind) {
+            if check.status == CheckStatus::Warn {
+                check.status = CheckStatus::Fail;
+                self.valid = false;
+            }
+        }
+    }
+
+    fn pass(&mut self, name: &str, kind: CheckKind, detail: &str) {
+        self.push(name, kind, CheckStatus::Pass, detail);
+    }
+
+    fn fail(&mut self, name: &str, kind: CheckKind, detail: &str) {
+        self.valid = false;
+        self.push(name, kind, CheckStatus::Fail, detail);
+    }
+
+    fn warn(&mut self, name: &str, kind: CheckKind, detail: &str) {
+        self.push(name, kind, CheckStatus::Warn, detail);
+    }
+
+    fn skip(&mut self, name: &str, kind: CheckKind, detail: &str) {
+        self.push(name, kind, CheckStatus::Skip, detail);
+    }
+
+    fn push(&mut self, name: &str, kind: CheckKind, status: CheckStatus, detail: &str) {
+        self.checks.push(Check { name: name.to_string(), kind, status, detail: detail.to_string() });
+    }
+}
@@ -0,0 +1,352 @@
+#[cfg(feature = "cli")]
+use crate::config::Config;
+use crate::utils::to_hex;
+use byteorder::{BigEndian, ReadBytesExt};
+use openssl::base64::decode_block;
+use openssl::bn::BigNum;
+use openssl::error::ErrorStack;
+use openssl::pkey::{PKey, Private, Public};
+#[cfg(feature = "pq")]
+use openssl::pkey::KeyType;
+use openssl::rsa::Rsa;
+use openssl::sha::sha256;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+#[cfg(feature = "cli")]
+use std::env;
+
+/// Environment variable pointing to the root public key
+pub const ROOT_KEY_ENV: &str = "BANJO_ROOT_KEY";
+/// System-wide location of the root public key
+pub const SYSTEM_ROOT_KEY: &str = "/etc/banjo/root.pem";
+
+/// Prefix of OpenSSH RSA public keys
+const OPENSSH_RSA_PREFIX: &str = "ssh-rsa";
+
+/// Public key used to verify keyblock signatures
+///
+/// With the `pq` feature, a PEM root key can hold an additional ML-DSA-65 public key block. Blocks must then
+/// carry a hybrid signature.
+#[derive(Debug, Clone)]
+pub struct RootKey {
+    /// Underlying RSA public key
+    pub rsa: Rsa<Public>,
+    /// ML-DSA-65 public key of hybrid root keys
+    #[cfg(feature = "pq")]
+    pub ml_dsa: Option<PKey<Public>>
+}
+
+/// Private key used to sign keyblocks
+///
+/// With the `pq` feature, a PEM signing key can hold an additional ML-DSA-65 private key block, blocks are then
+/// signed with a hybrid signature.
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    /// Underlying RSA private key
+    pub rsa: Rsa<Private>,
+    /// ML-DSA-65 private key of hybrid signing keys
+    #[cfg(feature = "pq")]
+    pub ml_dsa: Option<PKey<Private>>
+}
+
+/// Enumeration of the potential errors when decoding a root key
+#[derive(Debug)]
+pub enum KeyFormatErrors {
+    /// The OpenSSH key is truncated or isn't valid base64
+    InvalidOpenSsh,
+    /// The OpenSSH key isn't an RSA key
+    UnsupportedAlgorithm(String),
+    /// The PEM file doesn't hold an RSA key
+    MissingRsaKey,
+    /// The PEM file holds more than one key of this algorithm
+    DuplicateKey(&'static str),
+    /// The PEM block at this position, starting from 1, isn't a key this build can use
+    UnsupportedPemBlock(usize),
+    /// OpenSSL failed to decode the PEM or DER key
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for KeyFormatErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyFormatErrors::InvalidOpenSsh => write!(f, "malformed OpenSSH public key"),
+            KeyFormatErrors::UnsupportedAlgorithm(algorithm) => write!(f, "unsupported key algorithm {}", algorithm),
+            KeyFormatErrors::MissingRsaKey => write!(f, "no RSA key found"),
+            KeyFormatErrors::DuplicateKey(algorithm) => write!(f, "more than one {} key found", algorithm),
+            KeyFormatErrors::UnsupportedPemBlock(index) => write!(
+                f, "PEM block {} is neither an RSA key nor, with the pq feature, an ML-DSA-65 key", index
+            ),
+            KeyFormatErrors::OpenSSLError(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for KeyFormatErrors {}
+
+impl From<ErrorStack> for KeyFormatErrors {
+    fn from(error: ErrorStack) -> Self {
+        KeyFormatErrors::OpenSSLError(error)
+    }
+}
+
+impl RootKey {
+    /// Decode a root key, auto-detecting PEM, DER and OpenSSH encodings
+    ///
+    /// Both SubjectPublicKeyInfo and PKCS#1 structures are accepted for PEM and DER.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RootKey, KeyFormatErrors> {
+        let trimmed = bytes.trim_ascii_start();
+
+        if trimmed.starts_with(b"-----BEGIN") {
+            return RootKey::from_pem(trimmed)
+        }
+
+        let rsa = if trimmed.starts_with(OPENSSH_RSA_PREFIX.as_bytes()) {
+            parse_openssh(trimmed)?
+        } else {
+            Rsa::public_key_from_der(bytes).or_else(|_| Rsa::public_key_from_der_pkcs1(bytes))?
+        };
+
+        Ok(RootKey::from(rsa))
+    }
+
+    /// Decode a PEM root key, made of an RSA key and optionally an ML-DSA-65 key
+    ///
+    /// Every block has to be used, so a key can't be silently ignored.
+    fn from_pem(pem: &[u8]) -> Result<RootKey, KeyFormatErrors> {
+        let mut rsa = None;
+        #[cfg(feature = "pq")]
+        let mut ml_dsa = None;
+
+        for (index, block) in split_pem(pem).into_iter().enumerate() {
+            #[cfg(feature = "pq")]
+            if let Ok(key) = PKey::public_key_from_pem(block) {
+                if key.is_a(KeyType::ML_DSA_65) {
+                    if ml_dsa.replace(key).is_some() { return Err(KeyFormatErrors::DuplicateKey("ML-DSA-65")) }
+                    continue
+                }
+            }
+
+            let key = Rsa::public_key_from_pem(block).or_else(|_| Rsa::public_key_from_pem_pkcs1(block))
+                .map_err(|_| KeyFormatErrors::UnsupportedPemBlock(index + 1))?;
+            if rsa.replace(key).is_some() { return Err(KeyFormatErrors::DuplicateKey("RSA")) }
+        }
+
+        Ok(RootKey {
+            rsa: rsa.ok_or(KeyFormatErrors::MissingRsaKey)?,
+            #[cfg(feature = "pq")]
+            ml_dsa
+        })
+    }
+
+    /// PEM encoding of this key, the RSA key followed by the ML-DSA key if any, as read by `from_bytes`
+    pub fn to_pem(&self) -> Result<Vec<u8>, ErrorStack> {
+        #[allow(unused_mut)]
+        let mut pem = self.rsa.public_key_to_pem()?;
+        #[cfg(feature = "pq")]
+        if let Some(ml_dsa) = &self.ml_dsa {
+            pem.extend(ml_dsa.public_key_to_pem()?);
+        }
+
+        Ok(pem)
+    }
+
+    /// Hex encoded SHA256 digest of the DER encoding of this key, followed by the ML-DSA key if any
+    pub fn fingerprint(&self) -> Result<String, ErrorStack> {
+        #[allow(unused_mut)]
+        let mut der = self.rsa.public_key_to_der()?;
+        #[cfg(feature = "pq")]
+        if let Some(ml_dsa) = &self.ml_dsa {
+            der.extend(ml_dsa.public_key_to_der()?);
+        }
+
+        Ok(to_hex(&sha256(&der)))
+    }
+}
+
+impl From<Rsa<Public>> for RootKey {
+    fn from(rsa: Rsa<Public>) -> Self {
+        RootKey {
+            rsa,
+            #[cfg(feature = "pq")]
+            ml_dsa: None
+        }
+    }
+}
+
+impl SigningKey {
+    /// Check that this key is the private counterpart of `root_key`
+    pub fn matches(&self, root_key: &RootKey) -> bool {
+        let rsa_matches = self.rsa.n() == root_key.rsa.n() && self.rsa.e() == root_key.rsa.e();
+
+        #[cfg(feature = "pq")]
+        let ml_dsa_matches = match (&self.ml_dsa, &root_key.ml_dsa) {
+            (Some(private), Some(public)) => private.public_eq(public),
+            (None, None) => true,
+            _ => false
+        };
+        #[cfg(not(feature = "pq"))]
+        let ml_dsa_matches = true;
+
+        rsa_matches && ml_dsa_matches
+    }
+}
+
+impl From<Rsa<Private>> for SigningKey {
+    fn from(rsa: Rsa<Private>) -> Self {
+        SigningKey {
+            rsa,
+            #[cfg(feature = "pq")]
+            ml_dsa: None
+        }
+    }
+}
+
+/// Split a PEM file in its `-----BEGIN` blocks
+fn split_pem(pem: &[u8]) -> Vec<&[u8]> {
+    let marker = b"-----BEGIN";
+    let starts: Vec<usize> = (0..pem.len()).filter(|&index| pem[index..].starts_with(marker)).collect();
+
+    starts.iter().enumerate()
+        .map(|(index, &start)| &pem[start..starts.get(index + 1).copied().unwrap_or(pem.len())])
+        .collect()
+}
+
+/// Parse an `ssh-rsa <base64> [comment]` line
+fn parse_openssh(line: &[u8]) -> Result<Rsa<Public>, KeyFormatErrors> {
+    let line = std::str::from_utf8(line).map_err(|_| KeyFormatErrors::InvalidOpenSsh)?;
+    let encoded = line.split_whitespace().nth(1).ok_or(KeyFormatErrors::InvalidOpenSsh)?;
+    let blob = decode_block(encoded).map_err(|_| KeyFormatErrors::InvalidOpenSsh)?;
+    let mut reader = blob.as_slice();
+
+    // The blob is a sequence of length-prefixed fields: algorithm name, exponent and modulus
+    let algorithm = read_ssh_field(&mut reader)?;
+    if algorithm != OPENSSH_RSA_PREFIX.as_bytes() {
+        return Err(KeyFormatErrors::UnsupportedAlgorithm(String::from_utf8_lossy(&algorithm).into_owned()))
+    }
+
+    let exponent = BigNum::from_slice(&read_ssh_field(&mut reader)?)?;
+    let modulus = BigNum::from_slice(&read_ssh_field(&mut reader)?)?;
+
+    Ok(Rsa::from_public_components(modulus, exponent)?)
+}
+
+/// Read a 32 bits length-prefixed field of an OpenSSH key blob
+fn read_ssh_field<R: Read>(reader: &mut R) -> Result<Vec<u8>, KeyFormatErrors> {
+    let length = reader.read_u32::<BigEndian>().map_err(|_| KeyFormatErrors::InvalidOpenSsh)?;
+    // The declared length isn't trusted for allocations
+    let mut field = Vec::new();
+    reader.take(length as u64).read_to_end(&mut field).map_err(|_| KeyFormatErrors::InvalidOpenSsh)?;
+    if field.len() != length as usize { return Err(KeyFormatErrors::InvalidOpenSsh) }
+
+    Ok(field)
+}
+
+/// Enumeration of the potential errors when looking up the root key
+#[derive(Debug)]
+pub enum RootKeyErrors {
+    /// No root key was found, contains a description of every location searched
+    NotFound(Vec<String>),
+    /// The root key file couldn't be read
+    IOError(PathBuf, io::Error),
+    /// The root key file isn't a valid public key
+    InvalidKey(PathBuf, KeyFormatErrors)
+}
+
+impl fmt::Display for RootKeyErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RootKeyErrors::NotFound(locations) => write!(
+                f, "no root key found, searched: {}", locations.join(", ")
+            ),
+            RootKeyErrors::IOError(path, error) => write!(
+                f, "failed to read the root key {}: {}", path.display(), error
+            ),
+            RootKeyErrors::InvalidKey(path, error) => write!(
+                f, "{} is not a valid root key: {}", path.display(), error
+            )
+        }
+    }
+}
+
+impl std::error::Error for RootKeyErrors {}
+
+/// Find the root key location
+///
+/// The lookup order is the `--root-key` argument, the `BANJO_ROOT_KEY` environment variable,
+/// the `root-key` configuration option and finally `SYSTEM_ROOT_KEY`.
+#[cfg(feature = "cli")]
+pub fn discover_root_key(argument: Option<&Path>, config: &Config) -> Result<PathBuf, RootKeyErrors> {
+    if let Some(path) = argument {
+        return Ok(path.to_path_buf())
+    }
+
+    if let Some(path) = env::var_os(ROOT_KEY_ENV).filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path))
+    }
+
+    if let Some(path) = &config.root_key {
+        return Ok(path.clone())
+    }
+
+    if Path::new(SYSTEM_ROOT_KEY).exists() {
+        return Ok(PathBuf::from(SYSTEM_ROOT_KEY))
+    }
+
+    let config_location = match Config::path() {
+        Some(path) => format!("root-key in {}", path.display()),
+        None => "root-key in the configuration file".to_string()
+    };
+
+    Err(RootKeyErrors::NotFound(vec![
+        "--root-key".to_string(),
+        format!("${}", ROOT_KEY_ENV),
+        config_location,
+        SYSTEM_ROOT_KEY.to_string()
+    ]))
+}
+
+/// Load the root key at `path`, in any format supported by `RootKey::from_bytes`
+pub fn load_root_key(path: &Path) -> Result<RootKey, RootKeyErrors> {
+    let content = fs::read(path).map_err(|error| RootKeyErrors::IOError(path.to_path_buf(), error))?;
+
+    RootKey::from_bytes(&content).map_err(|error| RootKeyErrors::InvalidKey(path.to_path_buf(), error))
+}
+
+/// Load the root private key at `path`, used to sign keyblocks
+///
+/// The key can be PEM or DER encoded, in PKCS#8 or PKCS#1 structure. With the `pq` feature, a PEM file can also
+/// hold an ML-DSA-65 private key for hybrid signatures. Every PEM block has to be one of these keys.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, RootKeyErrors> {
+    let content = fs::read(path).map_err(|error| RootKeyErrors::IOError(path.to_path_buf(), error))?;
+    let invalid = |error: KeyFormatErrors| RootKeyErrors::InvalidKey(path.to_path_buf(), error);
+
+    if !content.trim_ascii_start().starts_with(b"-----BEGIN") {
+        let rsa = PKey::private_key_from_der(&content).and_then(|key| key.rsa())
+            .map_err(|error| invalid(KeyFormatErrors::OpenSSLError(error)))?;
+        return Ok(SigningKey::from(rsa))
+    }
+
+    let mut rsa = None;
+    #[cfg(feature = "pq")]
+    let mut ml_dsa = None;
+
+    for (index, block) in split_pem(&content).into_iter().enumerate() {
+        let key = PKey::private_key_from_pem(block).map_err(|error| invalid(KeyFormatErrors::OpenSSLError(error)))?;
+
+        #[cfg(feature = "pq")]
+        if key.is_a(KeyType::ML_DSA_65) {
+            if ml_dsa.replace(key).is_some() { return Err(invalid(KeyFormatErrors::DuplicateKey("ML-DSA-65"))) }
+            continue
+        }
+
+        let key = key.rsa().map_err(|_| invalid(KeyFormatErrors::UnsupportedPemBlock(index + 1)))?;
+        if rsa.replace(key).is_some() { return Err(invalid(KeyFormatErrors::DuplicateKey("RSA"))) }
+    }
+
+    Ok(SigningKey {
+        rsa: rsa.ok_or_else(|| invalid(KeyFormatErrors::MissingRsaKey))?,
+        #[cfg(feature = "pq")]
+        ml_dsa
+    })
+}
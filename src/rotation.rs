@@ -0,0 +1,69 @@
+//! Rotation schedules of keys, stored in the upper half of the keyfile flags
+//!
+//! Bits 32 to 47 hold the rotation interval in days, 0 meaning the key has no schedule, and bits 48 to 63
+//! the day the key was last rotated, counted in days since the UNIX epoch.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Keyfile flags: rotation interval, in days
+const KEYFILE_ROTATION_INTERVAL_MASK: u64 = 0xffff << KEYFILE_ROTATION_INTERVAL_SHIFT;
+const KEYFILE_ROTATION_INTERVAL_SHIFT: u32 = 32;
+/// Keyfile flags: day of the last rotation
+const KEYFILE_LAST_ROTATED_MASK: u64 = 0xffff << KEYFILE_LAST_ROTATED_SHIFT;
+const KEYFILE_LAST_ROTATED_SHIFT: u32 = 48;
+
+/// Rotation schedule of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    /// Maximum number of days between rotations
+    pub interval: u16,
+    /// Day of the last rotation, in days since the UNIX epoch
+    pub last_rotated: u16
+}
+
+impl Rotation {
+    /// Schedule set in keyfile flags, if any
+    pub fn from_flags(flags: u64) -> Option<Rotation> {
+        let interval = ((flags & KEYFILE_ROTATION_INTERVAL_MASK) >> KEYFILE_ROTATION_INTERVAL_SHIFT) as u16;
+        let last_rotated = ((flags & KEYFILE_LAST_ROTATED_MASK) >> KEYFILE_LAST_ROTATED_SHIFT) as u16;
+
+        if interval == 0 { None } else { Some(Rotation { interval, last_rotated }) }
+    }
+
+    /// Keyfile flags with the schedule replaced by this one
+    pub fn apply(self, flags: u64) -> u64 {
+        Rotation::clear(flags)
+            | ((self.interval as u64) << KEYFILE_ROTATION_INTERVAL_SHIFT)
+            | ((self.last_rotated as u64) << KEYFILE_LAST_ROTATED_SHIFT)
+    }
+
+    /// Keyfile flags without any schedule
+    pub fn clear(flags: u64) -> u64 {
+        flags & !(KEYFILE_ROTATION_INTERVAL_MASK | KEYFILE_LAST_ROTATED_MASK)
+    }
+
+    /// Day the key has to be rotated by, in days since the UNIX epoch
+    pub fn due(self) -> u32 {
+        self.last_rotated as u32 + self.interval as u32
+    }
+
+    /// Days left until the rotation is due, negative once it is overdue
+    pub fn days_left(self, today: u16) -> i64 {
+        self.due() as i64 - today as i64
+    }
+}
+
+/// Current day, in days since the UNIX epoch, as stored in the flags
+pub fn today() -> u16 {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs() / 86400).unwrap_or(0);
+    days.min(u16::MAX as u64) as u16
+}
+
+/// Human readable description of the rotation state
+pub fn describe(rotation: Rotation, today: u16) -> String {
+    match rotation.days_left(today) {
+        days if days < 0 => format!("overdue by {} days", -days),
+        0 => "due today".to_string(),
+        days => format!("due in {} days", days)
+    }
+}
@@ -0,0 +1,215 @@
+//! Last-resort recovery of keyfile metadata from damaged keyblocks
+//!
+//! Scavenging doesn't parse the block from its start, which fails as soon as the header or a single keyfile
+//! is damaged. It instead tries every offset of the file, keeping the ones where a plausible keyfile starts:
+//! known flag bits, wrap algorithm and content type, a UID starting with an uppercase letter, printable
+//! strings and a content length fitting in the file. Whatever is found is only a guess, and is never
+//! decrypted or trusted.
+//!
+//! The keyfiles of blocks with compressed metadata are inside a zstd frame, only their header can be found.
+
+use crate::content::ContentType;
+use crate::host::HOST_FINGERPRINT_SIZE;
+use crate::id::{GlobalId, ID_SIZE};
+use crate::keyblock::{
+    BLOCK_HOST_BOUND, FORMAT_SPECIFIER, KEYFILE_DERIVED_SECRET, KEYFILE_WRAP_MASK, KEYFILE_WRAP_SHIFT, MAGIC_NUMBER, PRE_ID_FORMAT_SPECIFIER
+};
+use crate::secret::{WrapAlgorithm, SECRET_SIZE};
+use crate::utils::{format_uid, to_hex};
+use byteorder::{ByteOrder, LittleEndian};
+use openssl::sha::sha256;
+use serde::Serialize;
+use std::convert::TryFrom;
+
+/// Keyfile flag bits with no meaning, set in no valid keyfile
+const UNUSED_FLAGS: u64 = 0b1111_1100 | 0xf << 20;
+/// Longest string considered plausible, in bytes
+const MAX_STRING_LENGTH: usize = 4096;
+/// Largest number of tags considered plausible
+const MAX_TAGS: u16 = 1024;
+
+/// A keyblock header found in a file
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderCandidate {
+    pub offset: usize,
+    pub format: u16,
+    /// Block name and description, when they could still be read
+    pub name: Option<String>,
+    pub description: Option<String>
+}
+
+/// Something looking like a keyfile found in a file
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyfileCandidate {
+    /// Offset of the first and past the last byte of the keyfile
+    pub offset: usize,
+    pub end: usize,
+    pub flags: u64,
+    pub uid: String,
+    /// ID, `None` when the keyfile looks like one of a format without IDs
+    pub id: Option<String>,
+    pub path: String,
+    pub name: String,
+    pub description: String,
+    /// Tags, `None` when the keyfile looks like a format 1 one
+    pub tags: Option<Vec<String>>,
+    pub length: u64,
+    pub content_sha256: String,
+    /// Hex encoded stored secret, wrapped under the block secret unless the wrap algorithm is none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Hex encoded encrypted content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>
+}
+
+/// Everything recovered from a file
+#[derive(Debug, Clone, Serialize)]
+pub struct ScavengeReport {
+    pub size: usize,
+    pub headers: Vec<HeaderCandidate>,
+    pub keyfiles: Vec<KeyfileCandidate>
+}
+
+/// Byte reader failing softly when running out of data
+struct Scanner<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Scanner<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|end| *end <= self.data.len())?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(LittleEndian::read_u16)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(LittleEndian::read_u64)
+    }
+
+    /// Null terminated UTF-8 string without control characters
+    fn string(&mut self) -> Option<String> {
+        let rest = &self.data[self.position..];
+        let length = rest.iter().take(MAX_STRING_LENGTH + 1).position(|byte| *byte == 0)?;
+        let string = std::str::from_utf8(&rest[..length]).ok()?;
+        if string.chars().any(char::is_control) { return None }
+
+        self.position += length + 1;
+        Some(string.to_string())
+    }
+}
+
+/// Whether keyfile flags only use known bits and values
+fn plausible_flags(flags: u64) -> bool {
+    let wrap = ((flags & KEYFILE_WRAP_MASK) >> KEYFILE_WRAP_SHIFT) as u8;
+    flags & UNUSED_FLAGS == 0 && WrapAlgorithm::from_identifier(wrap).is_some() && ContentType::from_flags(flags).is_some()
+}
+
+/// Keyfile starting at `offset`, with or without its tags and ID, if it looks plausible
+fn keyfile_at(data: &[u8], offset: usize, with_tags: bool, with_id: bool, include_data: bool) -> Option<KeyfileCandidate> {
+    let mut scanner = Scanner { data, position: offset };
+
+    let flags = scanner.u64().filter(|flags| plausible_flags(*flags))?;
+    let secret = if flags & KEYFILE_DERIVED_SECRET == 0 {
+        let wrap = WrapAlgorithm::from_identifier(((flags & KEYFILE_WRAP_MASK) >> KEYFILE_WRAP_SHIFT) as u8)?;
+        Some(scanner.bytes(wrap.wrapped_size())?)
+    } else {
+        None
+    };
+    let uid = scanner.u16().filter(|uid| ((uid >> 8) as u8).is_ascii_uppercase())?;
+    let id = if with_id {
+        let mut id = [0; ID_SIZE];
+        id.copy_from_slice(scanner.bytes(ID_SIZE)?);
+        Some(GlobalId(id).to_string())
+    } else {
+        None
+    };
+
+    let path = scanner.string().filter(|path| !path.is_empty())?;
+    let name = scanner.string()?;
+    let description = scanner.string()?;
+    let tags = if with_tags {
+        let count = scanner.u16().filter(|count| *count <= MAX_TAGS)?;
+        Some((0..count).map(|_| scanner.string()).collect::<Option<Vec<String>>>()?)
+    } else {
+        None
+    };
+
+    let length = scanner.u64()?;
+    let content = scanner.bytes(usize::try_from(length).ok()?)?;
+
+    Some(KeyfileCandidate {
+        offset,
+        end: scanner.position,
+        flags,
+        uid: format_uid(uid),
+        id,
+        path,
+        name,
+        description,
+        tags,
+        length,
+        content_sha256: to_hex(&sha256(content)),
+        secret: secret.filter(|_| include_data).map(to_hex),
+        content: Some(content).filter(|_| include_data).map(to_hex)
+    })
+}
+
+/// Header starting at `offset`, the magic number being there
+fn header_at(data: &[u8], offset: usize) -> Option<HeaderCandidate> {
+    let mut scanner = Scanner { data, position: offset + MAGIC_NUMBER.len() };
+    let format = scanner.u16()?;
+
+    // The cipher suite of format 3 and 4 blocks, the block secret, the UID, the ID of format 4 blocks and the host binding
+    let flags = scanner.u64();
+    let suite = if format >= PRE_ID_FORMAT_SPECIFIER { 2 } else { 0 };
+    let id = if format == FORMAT_SPECIFIER { ID_SIZE } else { 0 };
+    let host = if flags.is_some_and(|flags| flags & BLOCK_HOST_BOUND != 0) { HOST_FINGERPRINT_SIZE } else { 0 };
+    let skipped = suite + SECRET_SIZE + 2 + id + host;
+    let strings = flags.and_then(|_| scanner.bytes(skipped)).and_then(|_| Some((scanner.string()?, scanner.string()?)));
+
+    Some(HeaderCandidate {
+        offset,
+        format,
+        name: strings.as_ref().map(|(name, _)| name.clone()),
+        description: strings.map(|(_, description)| description)
+    })
+}
+
+/// Look for keyblock headers and keyfiles anywhere in `data`, including their raw bytes if `include_data` is set
+///
+/// Keyfiles can't overlap, so the scan resumes after the end of each keyfile found.
+pub fn scavenge(data: &[u8], include_data: bool) -> ScavengeReport {
+    let headers: Vec<HeaderCandidate> = data.windows(MAGIC_NUMBER.len())
+        .enumerate()
+        .filter(|(_, window)| window == MAGIC_NUMBER)
+        .filter_map(|(offset, _)| header_at(data, offset))
+        .collect();
+
+    // Any string can be misread as an ID, so keyfiles are first tried with the layout of the header found
+    let with_id = headers.first().is_none_or(|header| header.format == FORMAT_SPECIFIER);
+
+    let mut keyfiles = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let candidate = keyfile_at(data, offset, true, with_id, include_data)
+            .or_else(|| keyfile_at(data, offset, true, !with_id, include_data))
+            .or_else(|| keyfile_at(data, offset, false, false, include_data));
+
+        match candidate {
+            Some(keyfile) => {
+                offset = keyfile.end;
+                keyfiles.push(keyfile);
+            },
+            None => offset += 1
+        }
+    }
+
+    ScavengeReport { size: data.len(), headers, keyfiles }
+}
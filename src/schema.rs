@@ -0,0 +1,371 @@
+//! Formal description of the keyblock format, for third-party tools
+//!
+//! The description is built from the constants and identifier tables used by the parser, and covers every
+//! format version it can read. Conditions, sizes and repeat counts are expressions in the subset of the
+//! Kaitai Struct expression language shared with C: field names, integers, `==`, `!=`, `>=`, `&`, `>>`, `/` and
+//! `? :`, root fields being prefixed by `_root.`.
+//!
+//! The schema can be rendered as JSON, as a Kaitai Struct specification, or as an 010 Editor binary template,
+//! which makes inspecting corrupted blocks in a hex editor practical.
+
+use crate::host::HOST_FINGERPRINT_SIZE;
+use crate::id::ID_SIZE;
+use crate::keyblock::{
+    BLOCK_COMPRESSED_METADATA, BLOCK_DEDUPLICATED, BLOCK_HOST_BOUND, KEYFILE_DERIVED_SECRET, KEYFILE_SHARED_CONTENT, KEYFILE_WRAP_MASK,
+    KEYFILE_WRAP_SHIFT, FORMAT_SPECIFIER, LEGACY_FORMAT_SPECIFIER, MAGIC_NUMBER, PRE_ID_FORMAT_SPECIFIER,
+    PRE_SUITE_FORMAT_SPECIFIER
+};
+use crate::secret::{WrapAlgorithm, SECRET_SIZE};
+use crate::format::LEGACY_SIGNATURE_SIZE;
+use crate::signature::SignatureAlgorithm;
+use crate::suite::CipherSuite;
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Type of a field
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FieldType {
+    /// Fixed bytes
+    Magic { bytes: Vec<u8> },
+    /// Little endian unsigned integer
+    Unsigned { bits: u8 },
+    /// UTF-8 string terminated by a null byte
+    NullString,
+    /// Raw bytes
+    Bytes { size: String },
+    /// Another structure of the schema
+    Structure { name: &'static str }
+}
+
+/// A field of a structure
+#[derive(Debug, Clone, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub doc: String,
+    /// Expression deciding whether the field is present
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Expression counting the repetitions of the field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<String>,
+    /// Enumeration naming the values of the field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<&'static str>
+}
+
+/// A sequence of fields
+#[derive(Debug, Clone, Serialize)]
+pub struct Structure {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub fields: Vec<Field>
+}
+
+/// Named values of an integer field
+#[derive(Debug, Clone, Serialize)]
+pub struct Enumeration {
+    pub name: &'static str,
+    pub values: Vec<(u64, String)>
+}
+
+/// Description of the keyblock format, the first structure being the whole file
+#[derive(Debug, Clone, Serialize)]
+pub struct Schema {
+    /// Format specifiers described, the last one being written by this implementation
+    pub versions: Vec<u16>,
+    pub structures: Vec<Structure>,
+    pub enumerations: Vec<Enumeration>
+}
+
+impl Field {
+    fn new(name: &'static str, field_type: FieldType, doc: &str) -> Field {
+        Field { name, field_type, doc: doc.to_string(), condition: None, repeat: None, values: None }
+    }
+
+    fn unsigned(name: &'static str, bits: u8, doc: &str) -> Field {
+        Field::new(name, FieldType::Unsigned { bits }, doc)
+    }
+
+    fn only_if(self, condition: String) -> Field {
+        Field { condition: Some(condition), ..self }
+    }
+
+    fn repeated(self, count: &str) -> Field {
+        Field { repeat: Some(count.to_string()), ..self }
+    }
+
+    fn named_by(self, values: &'static str) -> Field {
+        Field { values: Some(values), ..self }
+    }
+}
+
+/// `snake_case` form of a `CamelCase` identifier
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, character) in name.chars().enumerate() {
+        if character.is_ascii_uppercase() && index > 0 { snake.push('_') }
+        snake.push(character.to_ascii_lowercase());
+    }
+
+    snake
+}
+
+/// Named identifiers of 8 bits, as recognized by `from_identifier`
+fn enumeration<T: std::fmt::Debug>(name: &'static str, from_identifier: impl Fn(u8) -> Option<T>) -> Enumeration {
+    let values = (0..=u8::MAX)
+        .filter_map(|identifier| from_identifier(identifier).map(|value| (identifier as u64, snake_case(&format!("{:?}", value)))))
+        .collect();
+
+    Enumeration { name, values }
+}
+
+/// Description of every keyblock format version this implementation can read
+pub fn format_schema() -> Schema {
+    let legacy = format!("_root.format == {}", LEGACY_FORMAT_SPECIFIER);
+    let not_legacy = format!("_root.format != {}", LEGACY_FORMAT_SPECIFIER);
+    let has_id = format!("_root.format == {}", FORMAT_SPECIFIER);
+    let wrapped_size = WrapAlgorithm::AesKw.wrapped_size();
+
+    let keyblock = Structure {
+        name: "keyblock",
+        doc: "A keyblock file",
+        fields: vec![
+            Field::new("magic", FieldType::Magic { bytes: MAGIC_NUMBER.to_vec() }, "Magic number"),
+            Field::unsigned("format", 16, "Format specifier"),
+            Field::unsigned("flags", 64, &format!(
+                "Feature and setting flags, bit {}: zstd compressed metadata, which this schema doesn't describe, \
+                 bit {}: deduplicated content, bit {}: bound to a host", BLOCK_COMPRESSED_METADATA.trailing_zeros(),
+                BLOCK_DEDUPLICATED.trailing_zeros(), BLOCK_HOST_BOUND.trailing_zeros()
+            )),
+            Field::unsigned("cipher_suite", 16, "Cipher suite, implied by the signature in older formats")
+                .only_if(format!("_root.format >= {}", PRE_ID_FORMAT_SPECIFIER))
+                .named_by("cipher_suite"),
+            Field::new("secret", FieldType::Bytes { size: SECRET_SIZE.to_string() }, "Block secret, encrypted by the root key"),
+            Field::unsigned("uid", 16, "Block UID, its high byte being the letter B"),
+            Field::new("id", FieldType::Bytes { size: ID_SIZE.to_string() }, "Globally unique block ID")
+                .only_if(has_id.clone()),
+            Field::new("host", FieldType::Bytes { size: HOST_FINGERPRINT_SIZE.to_string() }, "Fingerprint of the bound host")
+                .only_if(format!("(_root.flags & {}) != 0", BLOCK_HOST_BOUND)),
+            Field::new("name", FieldType::NullString, "Block name"),
+            Field::new("description", FieldType::NullString, "Block description"),
+            Field::unsigned("keyfile_count", 64, "Number of keyfiles"),
+            Field::new("keyfiles", FieldType::Structure { name: "keyfile" }, "Keyfiles, sorted by path")
+                .repeated("_root.keyfile_count"),
+            Field::new("signature", FieldType::Structure { name: "signature" }, "Signature of everything above")
+                .only_if(not_legacy.clone()),
+            Field::new("legacy_signature", FieldType::Bytes { size: (LEGACY_SIGNATURE_SIZE / 8).to_string() }, "Unused signature field")
+                .only_if(legacy.clone())
+        ]
+    };
+
+    let keyfile = Structure {
+        name: "keyfile",
+        doc: "A key and its metadata",
+        fields: vec![
+            Field::unsigned("flags", 64, &format!(
+                "Bit 0: derived secret, bit 1: frozen, bit 2: shared content, bits {} to {}: secret wrap algorithm, \
+                 bits 16 to 19: usage policy, bits 24 to 31: content type, bits 32 to 63: rotation schedule",
+                KEYFILE_WRAP_SHIFT, KEYFILE_WRAP_SHIFT + KEYFILE_WRAP_MASK.count_ones() - 1
+            )),
+            Field::new("secret", FieldType::Bytes { size: format!(
+                "((flags >> {}) & {:#x}) == {} ? {} : {}",
+                KEYFILE_WRAP_SHIFT, KEYFILE_WRAP_MASK >> KEYFILE_WRAP_SHIFT, WrapAlgorithm::None.identifier(), SECRET_SIZE, wrapped_size
+            ) }, "Key secret, wrapped under the block secret")
+                .only_if(format!("(flags & {}) == 0", KEYFILE_DERIVED_SECRET | KEYFILE_SHARED_CONTENT)),
+            Field::unsigned("uid", 16, "Key UID, unique within the block"),
+            Field::new("id", FieldType::Bytes { size: ID_SIZE.to_string() }, "Globally unique key ID").only_if(has_id),
+            Field::new("path", FieldType::NullString, "Key path"),
+            Field::new("name", FieldType::NullString, "Key name"),
+            Field::new("description", FieldType::NullString, "Key description"),
+            Field::unsigned("tag_count", 16, "Number of tags").only_if(not_legacy.clone()),
+            Field::new("tags", FieldType::NullString, "Tags").repeated("tag_count").only_if(not_legacy),
+            Field::unsigned("length", 64, "Content length, in bits in the legacy format and in bytes otherwise"),
+            Field::new("content", FieldType::Bytes { size: format!("{} ? length / 8 : length", legacy) }, "Encrypted content")
+                .only_if(format!("(flags & {}) == 0", KEYFILE_SHARED_CONTENT)),
+            Field::unsigned("shared_uid", 16, "UID of the earlier keyfile storing the content")
+                .only_if(format!("(flags & {}) != 0", KEYFILE_SHARED_CONTENT))
+        ]
+    };
+
+    let signature = Structure {
+        name: "signature",
+        doc: "Signature section",
+        fields: vec![
+            Field::unsigned("algorithm", 16, "Signature algorithm").named_by("signature_algorithm"),
+            Field::unsigned("length", 32, "Signature length, in bytes"),
+            Field::new("data", FieldType::Bytes { size: "length".to_string() }, "Signature bytes")
+        ]
+    };
+
+    Schema {
+        versions: vec![LEGACY_FORMAT_SPECIFIER, PRE_SUITE_FORMAT_SPECIFIER, PRE_ID_FORMAT_SPECIFIER, FORMAT_SPECIFIER],
+        structures: vec![keyblock, keyfile, signature],
+        enumerations: vec![
+            enumeration("cipher_suite", |identifier| CipherSuite::from_identifier(identifier as u16)),
+            enumeration("signature_algorithm", |identifier| SignatureAlgorithm::from_identifier(identifier as u16)),
+            enumeration("wrap_algorithm", WrapAlgorithm::from_identifier)
+        ]
+    }
+}
+
+impl Schema {
+    /// JSON serialization of this schema
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serializing a schema can't fail.")
+    }
+
+    /// Kaitai Struct specification of this schema, in YAML
+    pub fn to_kaitai(&self) -> String {
+        let sequence = |structure: &Structure| -> Value {
+            structure.fields.iter().map(|field| {
+                let mut attribute = Map::new();
+                attribute.insert("id".to_string(), json!(field.name));
+                attribute.insert("doc".to_string(), json!(field.doc));
+
+                match &field.field_type {
+                    FieldType::Magic { bytes } => { attribute.insert("contents".to_string(), json!(bytes)); },
+                    FieldType::Unsigned { bits } => { attribute.insert("type".to_string(), json!(format!("u{}le", bits / 8))); },
+                    FieldType::NullString => {
+                        attribute.insert("type".to_string(), json!("strz"));
+                        attribute.insert("encoding".to_string(), json!("UTF-8"));
+                    },
+                    FieldType::Bytes { size } => {
+                        let size = size.parse::<u64>().map_or_else(|_| json!(size), |size| json!(size));
+                        attribute.insert("size".to_string(), size);
+                    },
+                    FieldType::Structure { name } => { attribute.insert("type".to_string(), json!(name)); }
+                }
+                if let Some(condition) = &field.condition {
+                    attribute.insert("if".to_string(), json!(condition));
+                }
+                if let Some(count) = &field.repeat {
+                    attribute.insert("repeat".to_string(), json!("expr"));
+                    attribute.insert("repeat-expr".to_string(), json!(count));
+                }
+                if let Some(values) = field.values {
+                    attribute.insert("enum".to_string(), json!(values));
+                }
+
+                Value::Object(attribute)
+            }).collect()
+        };
+
+        let root = &self.structures[0];
+        let types: Map<String, Value> = self.structures[1..].iter()
+            .map(|structure| (structure.name.to_string(), json!({ "doc": structure.doc, "seq": sequence(structure) })))
+            .collect();
+        let enums: Map<String, Value> = self.enumerations.iter()
+            .map(|enumeration| {
+                let values: Map<String, Value> = enumeration.values.iter()
+                    .map(|(value, name)| (value.to_string(), json!(name)))
+                    .collect();
+                (enumeration.name.to_string(), Value::Object(values))
+            })
+            .collect();
+
+        let specification = json!({
+            "meta": {
+                "id": "banjo_keyblock",
+                "title": format!("banjo keyblock, formats {}", self.versions.iter().join(", ")),
+                "file-extension": "banjo",
+                "endian": "le"
+            },
+            "doc": root.doc,
+            "seq": sequence(root),
+            "types": types,
+            "enums": enums
+        });
+
+        serde_yaml::to_string(&specification).expect("Serializing a schema can't fail.")
+    }
+    /// 010 Editor binary template of this schema
+    ///
+    /// Root fields are declared at file scope, so the structures can refer to them without the `_root.` prefix.
+    pub fn to_010_template(&self) -> String {
+        let mut template = String::new();
+        template.push_str("//------------------------------------------------\n");
+        template.push_str("//--- 010 Editor Binary Template\n");
+        template.push_str("//   File: banjo.bt\n");
+        template.push_str(&format!("//   Purpose: banjo keyblock, formats {}\n", self.versions.iter().join(", ")));
+        template.push_str("//   Generated by banjo debug schema\n");
+        template.push_str("//------------------------------------------------\n\n");
+        template.push_str("LittleEndian();\n\n");
+        template.push_str("typedef struct { string value; } NULL_STRING;\n\n");
+
+        for enumeration in &self.enumerations {
+            let bits = self.structures.iter()
+                .flat_map(|structure| &structure.fields)
+                .find_map(|field| match field.field_type {
+                    FieldType::Unsigned { bits } if field.values == Some(enumeration.name) => Some(bits),
+                    _ => None
+                })
+                .unwrap_or(8);
+            // Enumerators share a single namespace, so they are prefixed by their enumeration
+            let values = enumeration.values.iter()
+                .map(|(value, name)| format!("{}_{} = {}", enumeration.name.to_uppercase(), name.to_uppercase(), value))
+                .join(", ");
+            template.push_str(&format!(
+                "typedef enum <{}> {{ {} }} {};\n", template_integer(bits), values, enumeration.name.to_uppercase()
+            ));
+        }
+        template.push('\n');
+
+        // Structures are declared before the ones using them
+        for structure in self.structures[1..].iter().rev() {
+            template.push_str("typedef struct {\n");
+            for field in &structure.fields {
+                template.push_str(&template_field(field, 1));
+            }
+            template.push_str(&format!("}} {} <comment=\"{}\">;\n\n", structure.name.to_uppercase(), structure.doc));
+        }
+
+        for field in &self.structures[0].fields {
+            template.push_str(&template_field(field, 0));
+        }
+
+        template
+    }
+}
+
+/// 010 Editor unsigned integer type of `bits` bits
+fn template_integer(bits: u8) -> &'static str {
+    match bits {
+        8 => "uchar",
+        16 => "uint16",
+        32 => "uint32",
+        _ => "uint64"
+    }
+}
+
+/// 010 Editor declaration of a field, indented by `depth` levels
+fn template_field(field: &Field, depth: usize) -> String {
+    let expression = |expression: &str| expression.replace("_root.", "");
+    let count = field.repeat.as_deref().map(|count| format!("[{}]", expression(count))).unwrap_or_default();
+    let mut attributes = Vec::new();
+    if field.repeat.is_some() { attributes.push("optimize=false".to_string()) }
+    attributes.push(format!("comment=\"{}\"", field.doc.replace('"', "'")));
+
+    let (type_name, size) = match &field.field_type {
+        FieldType::Magic { bytes } => ("char".to_string(), format!("[{}]", bytes.len())),
+        FieldType::Unsigned { bits } => match field.values {
+            Some(values) => (values.to_uppercase(), String::new()),
+            None => (template_integer(*bits).to_string(), String::new())
+        },
+        FieldType::NullString if field.repeat.is_some() => ("NULL_STRING".to_string(), String::new()),
+        FieldType::NullString => ("string".to_string(), String::new()),
+        FieldType::Bytes { size } => ("uchar".to_string(), format!("[{}]", expression(size))),
+        FieldType::Structure { name } => (name.to_uppercase(), String::new())
+    };
+
+    let indent = "    ".repeat(depth);
+    let declaration = format!("{} {}{}{} <{}>;", type_name, field.name, size, count, attributes.join(", "));
+
+    match &field.condition {
+        Some(condition) => format!("{}if ({}) {{\n{}    {}\n{}}}\n", indent, expression(condition), indent, declaration, indent),
+        None => format!("{}{}\n", indent, declaration)
+    }
+}
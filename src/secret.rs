@@ -0,0 +1,254 @@
+use crate::memlock;
+use openssl::cipher::Cipher;
+use openssl::cipher_ctx::{CipherCtx, CipherCtxFlags};
+use openssl::error::ErrorStack;
+use openssl::md::Md;
+use openssl::pkcs5::scrypt;
+use openssl::pkey::Id;
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rand::rand_bytes;
+use std::convert::TryFrom;
+use std::{fmt, io, ptr};
+use std::io::{BufRead, Read};
+use std::sync::atomic::{compiler_fence, Ordering};
+
+pub use crate::format::{WrapAlgorithm, SECRET_SIZE};
+
+/// Longest passphrase, in bytes, so its buffer is allocated once and never leaves copies behind
+pub const MAX_PASSPHRASE_SIZE: usize = 1024;
+/// scrypt cost of passphrase derivations, each guess takes 128 MiB of memory
+const SCRYPT_COST: u64 = 1 << 17;
+const SCRYPT_BLOCK_SIZE: u64 = 8;
+const SCRYPT_PARALLELISM: u64 = 1;
+/// Memory scrypt is allowed to use, above the 128 MiB it needs
+const SCRYPT_MAX_MEMORY: u64 = 256 << 20;
+
+/// An AES256 secret, guaranteed to be exactly `SECRET_SIZE` bytes long
+///
+/// Secrets live on the heap so they never move, are locked in memory if `memlock` is enabled, and are zeroed
+/// when dropped.
+#[derive(PartialEq, Eq)]
+pub struct Secret256(Box<[u8; SECRET_SIZE]>);
+
+/// A passphrase typed by the user, locked in memory like secrets and zeroed when dropped
+pub struct Passphrase {
+    buffer: Box<[u8; MAX_PASSPHRASE_SIZE]>,
+    length: usize
+}
+
+/// Error returned when building a secret from a slice of the wrong size
+#[derive(Debug)]
+pub struct InvalidSecretLength(pub usize);
+
+impl fmt::Display for InvalidSecretLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "secrets must be {} bytes long, got {} bytes", SECRET_SIZE, self.0)
+    }
+}
+
+impl std::error::Error for InvalidSecretLength {}
+
+impl Secret256 {
+    /// Secret made of zeros, locked before it is filled in
+    fn zeroed() -> Secret256 {
+        let secret = Secret256(Box::new([0; SECRET_SIZE]));
+        memlock::lock(&secret.0[..]);
+        secret
+    }
+
+    /// Generate a new random secret using the OpenSSL CSPRNG
+    pub fn generate() -> Result<Secret256, ErrorStack> {
+        let mut secret = Secret256::zeroed();
+        rand_bytes(&mut secret.0[..])?;
+
+        Ok(secret)
+    }
+
+    /// Read a secret from its serialized form
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Secret256> {
+        let mut secret = Secret256::zeroed();
+        reader.read_exact(&mut secret.0[..])?;
+
+        Ok(secret)
+    }
+
+    /// Raw bytes of this secret
+    pub fn as_bytes(&self) -> &[u8; SECRET_SIZE] {
+        &self.0
+    }
+
+    /// Wrap this secret under the key encryption secret `kek`
+    pub fn wrap(&self, kek: &Secret256, algorithm: WrapAlgorithm) -> Result<Vec<u8>, ErrorStack> {
+        let name = match cipher_name(algorithm) {
+            Some(name) => name,
+            None => return Ok(self.0.to_vec())
+        };
+
+        let cipher = Cipher::fetch(None, name, None)?;
+        let mut context = CipherCtx::new()?;
+        context.set_flags(CipherCtxFlags::FLAG_WRAP_ALLOW);
+        context.encrypt_init(Some(&cipher), Some(kek.as_bytes()), None)?;
+
+        let mut wrapped = Vec::new();
+        context.cipher_update_vec(&self.0[..], &mut wrapped)?;
+        context.cipher_final_vec(&mut wrapped)?;
+
+        Ok(wrapped)
+    }
+
+    /// Unwrap a secret wrapped under `kek`, returning `None` if its integrity check doesn't match
+    pub fn unwrap(wrapped: &[u8], kek: &Secret256, algorithm: WrapAlgorithm) -> Option<Secret256> {
+        let name = match cipher_name(algorithm) {
+            Some(name) => name,
+            None => return Secret256::try_from(wrapped).ok()
+        };
+
+        let cipher = Cipher::fetch(None, name, None).ok()?;
+        let mut context = CipherCtx::new().ok()?;
+        context.set_flags(CipherCtxFlags::FLAG_WRAP_ALLOW);
+        context.decrypt_init(Some(&cipher), Some(kek.as_bytes()), None).ok()?;
+
+        let mut unwrapped = vec![0; wrapped.len() + cipher.block_size()];
+        let length = context.cipher_update(wrapped, Some(&mut unwrapped)).ok()
+            .and_then(|length| Some(length + context.cipher_final(&mut unwrapped[length..]).ok()?));
+        let secret = length.and_then(|length| Secret256::try_from(&unwrapped[..length]).ok());
+
+        zero(&mut unwrapped);
+        secret
+    }
+
+    /// Derive a new secret from this one with HKDF-SHA256, `info` binding it to its purpose
+    pub fn derive(&self, info: &[u8]) -> Result<Secret256, ErrorStack> {
+        Secret256::derive_from(&self.0[..], info)
+    }
+
+    /// Derive a secret from arbitrary key material with HKDF-SHA256, `info` binding it to its purpose
+    pub fn derive_from(material: &[u8], info: &[u8]) -> Result<Secret256, ErrorStack> {
+        let mut context = PkeyCtx::new_id(Id::HKDF)?;
+        context.derive_init()?;
+        context.set_hkdf_md(Md::sha256())?;
+        context.set_hkdf_key(material)?;
+        context.add_hkdf_info(info)?;
+
+        let mut secret = Secret256::zeroed();
+        context.derive(Some(&mut secret.0[..]))?;
+
+        Ok(secret)
+    }
+}
+
+/// Name of the OpenSSL cipher implementing `algorithm`
+fn cipher_name(algorithm: WrapAlgorithm) -> Option<&'static str> {
+    match algorithm {
+        WrapAlgorithm::None => None,
+        WrapAlgorithm::AesKw => Some("AES-256-WRAP"),
+        WrapAlgorithm::AesKwp => Some("AES-256-WRAP-PAD")
+    }
+}
+
+/// Overwrite `bytes` with zeros, in a way the compiler can't optimize away
+fn zero(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // Safety: the pointer comes from a mutable reference
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+impl Clone for Secret256 {
+    fn clone(&self) -> Self {
+        let mut secret = Secret256::zeroed();
+        secret.0.copy_from_slice(&self.0[..]);
+        secret
+    }
+}
+
+impl Drop for Secret256 {
+    fn drop(&mut self) {
+        zero(&mut self.0[..]);
+        memlock::unlock(&self.0[..]);
+    }
+}
+
+impl Passphrase {
+    /// Read a line as a passphrase, without its line ending
+    pub fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Passphrase> {
+        let mut passphrase = Passphrase { buffer: Box::new([0; MAX_PASSPHRASE_SIZE]), length: 0 };
+        memlock::lock(&passphrase.buffer[..]);
+
+        loop {
+            let available = reader.fill_buf()?;
+            if available.is_empty() { break }
+
+            let (line, ended) = match available.iter().position(|byte| *byte == b'\n') {
+                Some(end) => (&available[..end], true),
+                None => (available, false)
+            };
+            let end = passphrase.length + line.len();
+            if end > MAX_PASSPHRASE_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "the passphrase is too long"))
+            }
+            passphrase.buffer[passphrase.length..end].copy_from_slice(line);
+            passphrase.length = end;
+
+            let consumed = line.len() + ended as usize;
+            reader.consume(consumed);
+            if ended { break }
+        }
+        if passphrase.length > 0 && passphrase.buffer[passphrase.length - 1] == b'\r' { passphrase.length -= 1 }
+
+        std::str::from_utf8(&passphrase.buffer[..passphrase.length])
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the passphrase isn't valid UTF-8"))?;
+        Ok(passphrase)
+    }
+
+    /// The passphrase itself
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.buffer[..self.length]).expect("Passphrases are checked when read.")
+    }
+
+    /// Derive a secret from this passphrase and `salt` with scrypt, which makes every guess expensive
+    pub fn derive(&self, salt: &[u8]) -> Result<Secret256, ErrorStack> {
+        let mut secret = Secret256::zeroed();
+        scrypt(
+            &self.buffer[..self.length], salt, SCRYPT_COST, SCRYPT_BLOCK_SIZE, SCRYPT_PARALLELISM, SCRYPT_MAX_MEMORY,
+            &mut secret.0[..]
+        )?;
+
+        Ok(secret)
+    }
+}
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        zero(&mut self.buffer[..]);
+        memlock::unlock(&self.buffer[..]);
+    }
+}
+
+impl From<[u8; SECRET_SIZE]> for Secret256 {
+    fn from(bytes: [u8; SECRET_SIZE]) -> Self {
+        let mut secret = Secret256::zeroed();
+        secret.0.copy_from_slice(&bytes);
+        secret
+    }
+}
+
+impl TryFrom<&[u8]> for Secret256 {
+    type Error = InvalidSecretLength;
+
+    fn try_from(secret: &[u8]) -> Result<Self, Self::Error> {
+        if secret.len() != SECRET_SIZE { return Err(InvalidSecretLength(secret.len())) }
+
+        let mut copy = Secret256::zeroed();
+        copy.0.copy_from_slice(secret);
+        Ok(copy)
+    }
+}
+
+/// Never print secrets, even encrypted ones
+impl fmt::Debug for Secret256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret256(..)")
+    }
+}
@@ -0,0 +1,280 @@
+use crate::format::LEGACY_SIGNATURE_SIZE;
+use crate::keyblock::ParseErrors;
+use crate::rootkey::{RootKey, SigningKey};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::md::Md;
+use openssl::pkey::PKey;
+use openssl::pkey_ctx::PkeyCtx;
+#[cfg(feature = "pq")]
+use openssl::pkey::Private;
+use openssl::rsa::Padding;
+use openssl::sign::{Signer, Verifier};
+use std::{fmt, io};
+use std::io::Read;
+
+/// Algorithms that can be used to sign a keyblock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// The block isn't signed, used for blocks migrated from format 1
+    None,
+    /// RSA PKCS#1 v1.5 signature of the SHA256 digest
+    RsaSha256,
+    /// RSA PKCS#1 v1.5 SHA256 signature along with an ML-DSA-65 signature, both must be valid
+    RsaSha256MlDsa65
+}
+
+impl SignatureAlgorithm {
+    /// Identifier of this algorithm in the serialized format
+    pub fn identifier(self) -> u16 {
+        match self {
+            SignatureAlgorithm::None => 0,
+            SignatureAlgorithm::RsaSha256 => 1,
+            SignatureAlgorithm::RsaSha256MlDsa65 => 2
+        }
+    }
+
+    /// Algorithm matching a serialized identifier, if known
+    pub fn from_identifier(identifier: u16) -> Option<SignatureAlgorithm> {
+        match identifier {
+            0 => Some(SignatureAlgorithm::None),
+            1 => Some(SignatureAlgorithm::RsaSha256),
+            2 => Some(SignatureAlgorithm::RsaSha256MlDsa65),
+            _ => None
+        }
+    }
+}
+
+/// Enumeration of the potential errors when checking a signature
+#[derive(Debug)]
+pub enum SignatureErrors {
+    /// The block isn't signed
+    Unsigned,
+    /// The signature algorithm identifier isn't known by this implementation
+    UnknownAlgorithm(u16),
+    /// The signature doesn't match the content and root key
+    Mismatch,
+    /// This build can't check signatures of this algorithm
+    Unsupported(SignatureAlgorithm),
+    /// The signature is hybrid but the root key has no ML-DSA key
+    MissingPostQuantumKey,
+    /// The root key is hybrid but the block only has an RSA signature
+    Downgrade,
+    /// Signatures of this algorithm can't be checked from a digest, the block has to be read whole
+    NotStreamable(SignatureAlgorithm),
+    /// OpenSSL failed to compute the signature
+    OpenSSLError(ErrorStack)
+}
+
+impl fmt::Display for SignatureErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureErrors::Unsigned => write!(f, "the block is not signed"),
+            SignatureErrors::UnknownAlgorithm(identifier) => write!(f, "unknown signature algorithm {}", identifier),
+            SignatureErrors::Mismatch => write!(f, "the signature doesn't match the root key"),
+            SignatureErrors::Unsupported(algorithm) => write!(
+                f, "{:?} signatures are not supported by this build, enable the pq feature", algorithm
+            ),
+            SignatureErrors::MissingPostQuantumKey => write!(f, "the signature is hybrid but the root key has no ML-DSA key"),
+            SignatureErrors::Downgrade => write!(f, "the root key is hybrid but the block only has an RSA signature"),
+            SignatureErrors::NotStreamable(algorithm) => write!(
+                f, "{:?} signatures can only be checked over a whole block, not while streaming it", algorithm
+            ),
+            SignatureErrors::OpenSSLError(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for SignatureErrors {}
+
+impl SignatureErrors {
+    /// Code of this error, explained by `banjo explain`
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignatureErrors::Unsigned => "E200",
+            SignatureErrors::UnknownAlgorithm(_) => "E201",
+            SignatureErrors::Mismatch => "E202",
+            SignatureErrors::Unsupported(_) => "E203",
+            SignatureErrors::MissingPostQuantumKey => "E204",
+            SignatureErrors::Downgrade => "E205",
+            SignatureErrors::OpenSSLError(_) => "E206",
+            SignatureErrors::NotStreamable(_) => "E207"
+        }
+    }
+}
+
+impl From<ErrorStack> for SignatureErrors {
+    fn from(error: ErrorStack) -> Self {
+        SignatureErrors::OpenSSLError(error)
+    }
+}
+
+/// Signature section of a keyblock
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// Algorithm used to produce this signature
+    pub algorithm: SignatureAlgorithm,
+    /// Raw signature
+    pub data: Vec<u8>
+}
+
+impl Signature {
+    /// Placeholder signature of unsigned blocks
+    pub fn none() -> Signature {
+        Signature { algorithm: SignatureAlgorithm::None, data: Vec::new() }
+    }
+
+    /// Sign `content` with the root private key, using a hybrid signature if it has an ML-DSA key
+    pub fn sign(content: &[u8], key: &SigningKey) -> Result<Signature, ErrorStack> {
+        #[cfg(feature = "pq")]
+        if let Some(ml_dsa) = &key.ml_dsa { return sign_hybrid(content, key, ml_dsa) }
+
+        Ok(Signature { algorithm: SignatureAlgorithm::RsaSha256, data: sign_rsa(content, key)? })
+    }
+
+    /// Sign `content` with the root private key using `algorithm`, which the key has to support
+    pub fn sign_with(content: &[u8], key: &SigningKey, algorithm: SignatureAlgorithm) -> Result<Signature, SignatureErrors> {
+        match algorithm {
+            SignatureAlgorithm::None => Ok(Signature::none()),
+            SignatureAlgorithm::RsaSha256 => Ok(Signature { algorithm, data: sign_rsa(content, key)? }),
+            #[cfg(feature = "pq")]
+            SignatureAlgorithm::RsaSha256MlDsa65 => match &key.ml_dsa {
+                Some(ml_dsa) => Ok(sign_hybrid(content, key, ml_dsa)?),
+                None => Err(SignatureErrors::MissingPostQuantumKey)
+            },
+            #[cfg(not(feature = "pq"))]
+            SignatureAlgorithm::RsaSha256MlDsa65 => Err(SignatureErrors::Unsupported(algorithm))
+        }
+    }
+
+    /// Check that this signature was produced over `content` by the root key
+    pub fn verify(&self, content: &[u8], root_key: &RootKey) -> Result<(), SignatureErrors> {
+        match self.algorithm {
+            SignatureAlgorithm::None => Err(SignatureErrors::Unsigned),
+            SignatureAlgorithm::RsaSha256 => {
+                // A hybrid root key must not accept signatures a quantum attacker could forge
+                #[cfg(feature = "pq")]
+                if root_key.ml_dsa.is_some() { return Err(SignatureErrors::Downgrade) }
+
+                verify_rsa(content, &self.data, root_key)
+            },
+            #[cfg(feature = "pq")]
+            SignatureAlgorithm::RsaSha256MlDsa65 => {
+                let ml_dsa = root_key.ml_dsa.as_ref().ok_or(SignatureErrors::MissingPostQuantumKey)?;
+
+                let mut reader = self.data.as_slice();
+                let rsa_length = reader.read_u32::<LittleEndian>().map_err(|_| SignatureErrors::Mismatch)? as usize;
+                if rsa_length > reader.len() { return Err(SignatureErrors::Mismatch) }
+                let (rsa_signature, ml_dsa_signature) = reader.split_at(rsa_length);
+
+                verify_rsa(content, rsa_signature, root_key)?;
+
+                match Verifier::new_without_digest(ml_dsa)?.verify_oneshot(ml_dsa_signature, content) {
+                    Ok(true) => Ok(()),
+                    _ => Err(SignatureErrors::Mismatch)
+                }
+            },
+            #[cfg(not(feature = "pq"))]
+            SignatureAlgorithm::RsaSha256MlDsa65 => Err(SignatureErrors::Unsupported(self.algorithm))
+        }
+    }
+
+    /// Check this signature against the SHA256 `digest` of the signed content, for blocks read as a stream
+    ///
+    /// ML-DSA signs the content itself rather than a digest of it, so hybrid signatures can't be checked this way.
+    pub fn verify_digest(&self, digest: &[u8; 32], root_key: &RootKey) -> Result<(), SignatureErrors> {
+        match self.algorithm {
+            SignatureAlgorithm::None => Err(SignatureErrors::Unsigned),
+            SignatureAlgorithm::RsaSha256 => {
+                #[cfg(feature = "pq")]
+                if root_key.ml_dsa.is_some() { return Err(SignatureErrors::Downgrade) }
+
+                let pkey = PKey::from_rsa(root_key.rsa.clone())?;
+                let mut context = PkeyCtx::new(&pkey)?;
+                context.verify_init()?;
+                context.set_rsa_padding(Padding::PKCS1)?;
+                context.set_signature_md(Md::sha256())?;
+
+                match context.verify(digest, &self.data) {
+                    Ok(true) => Ok(()),
+                    _ => Err(SignatureErrors::Mismatch)
+                }
+            },
+            SignatureAlgorithm::RsaSha256MlDsa65 => Err(SignatureErrors::NotStreamable(self.algorithm))
+        }
+    }
+
+    /// Read a signature section: 16 bits algorithm, 32 bits length and the signature itself
+    pub fn read<R: Read>(reader: &mut R) -> Result<Signature, ParseErrors> {
+        let identifier = reader.read_u16::<LittleEndian>()?;
+        let length = reader.read_u32::<LittleEndian>()?;
+
+        // The declared length isn't trusted for allocations, a truncated block ends the read early instead
+        let mut data = Vec::new();
+        reader.take(length as u64).read_to_end(&mut data)?;
+        if data.len() != length as usize { return Err(ParseErrors::UnexpectedEof) }
+
+        match SignatureAlgorithm::from_identifier(identifier) {
+            Some(algorithm) => Ok(Signature { algorithm, data }),
+            None => Err(ParseErrors::SignatureError(SignatureErrors::UnknownAlgorithm(identifier)))
+        }
+    }
+
+    /// Read the fixed size signature field of format 1 keyblocks
+    ///
+    /// Format 1 couldn't hold an actual signature, so those blocks are always considered unsigned.
+    pub fn read_legacy<R: Read>(reader: &mut R) -> io::Result<Signature> {
+        let mut data = vec![0; LEGACY_SIGNATURE_SIZE / 8];
+        reader.read_exact(&mut data)?;
+
+        Ok(Signature::none())
+    }
+
+    /// Serialize this signature section
+    pub fn serialize(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buffer: Vec<u8> = Vec::new();
+
+        buffer.write_u16::<LittleEndian>(self.algorithm.identifier())?;
+        buffer.write_u32::<LittleEndian>(self.data.len() as u32)?;
+        buffer.extend(&self.data);
+
+        Ok(buffer)
+    }
+}
+
+/// Check an RSA PKCS#1 v1.5 SHA256 signature against the root key
+fn verify_rsa(content: &[u8], signature: &[u8], root_key: &RootKey) -> Result<(), SignatureErrors> {
+    let pkey = PKey::from_rsa(root_key.rsa.clone())?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey)?;
+    verifier.update(content)?;
+
+    // OpenSSL reports malformed signatures as errors, which are mismatches for us
+    match verifier.verify(signature) {
+        Ok(true) => Ok(()),
+        _ => Err(SignatureErrors::Mismatch)
+    }
+}
+
+/// RSA PKCS#1 v1.5 signature of the SHA256 digest of `content`
+fn sign_rsa(content: &[u8], key: &SigningKey) -> Result<Vec<u8>, ErrorStack> {
+    let pkey = PKey::from_rsa(key.rsa.clone())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &pkey)?;
+    signer.update(content)?;
+
+    signer.sign_to_vec()
+}
+
+/// Hybrid signature of `content`, with both the RSA and the ML-DSA root private keys
+#[cfg(feature = "pq")]
+fn sign_hybrid(content: &[u8], key: &SigningKey, ml_dsa: &PKey<Private>) -> Result<Signature, ErrorStack> {
+    let rsa_signature = sign_rsa(content, key)?;
+    let ml_dsa_signature = Signer::new_without_digest(ml_dsa)?.sign_oneshot_to_vec(content)?;
+
+    // RSA signature length, RSA signature and ML-DSA signature
+    let mut data = (rsa_signature.len() as u32).to_le_bytes().to_vec();
+    data.extend(rsa_signature);
+    data.extend(ml_dsa_signature);
+
+    Ok(Signature { algorithm: SignatureAlgorithm::RsaSha256MlDsa65, data })
+}
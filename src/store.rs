@@ -0,0 +1,208 @@
+//! Storage backends holding serialized keyblocks, selected by the scheme of the block location
+//!
+//! Locations without a scheme, or with `file://`, are local files. `http://` and `https://` locations are
+//! read with GET and written with PUT. Every load returns a version of the stored block, which can be
+//! passed back when saving to only overwrite the block if nobody changed it in the meantime. HTTP servers
+//! have to return strong ETags for blocks to be overwritten, PUT requests are always conditional.
+
+use crate::keyblock::{read_limited, ParseErrors};
+use crate::permissions::{lock_exclusive, write_private};
+use crate::utils::to_hex;
+use native_tls::TlsConnector;
+use openssl::sha::sha256;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, fs, io};
+
+/// Timeout of HTTP requests
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A serialized block along with the version it was read at
+#[derive(Debug, Clone)]
+pub struct StoredBlock {
+    pub content: Vec<u8>,
+    /// Opaque version, changing every time the block is updated
+    pub version: String
+}
+
+/// Enumeration of the potential errors when accessing stored blocks
+#[derive(Debug)]
+pub enum StoreErrors {
+    /// An IO error occurred
+    IOError(io::Error),
+    /// The block couldn't be read within the memory ceiling
+    ReadError(ParseErrors),
+    /// No backend handles this scheme
+    UnsupportedScheme(String),
+    /// The block changed since it was loaded
+    Conflict(String),
+    /// The server gave no strong ETag, so the block can't be overwritten safely
+    NoStrongETag(String),
+    /// The TLS backend couldn't be initialized
+    TlsError(native_tls::Error),
+    /// An HTTP request failed
+    HttpError(Box<ureq::Error>)
+}
+
+impl fmt::Display for StoreErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreErrors::IOError(error) => write!(f, "IO error: {}", error),
+            StoreErrors::ReadError(error) => write!(f, "{}", error),
+            StoreErrors::UnsupportedScheme(scheme) => write!(f, "no storage backend for {}:// locations", scheme),
+            StoreErrors::Conflict(location) => write!(f, "{} changed since it was loaded, not overwriting it", location),
+            StoreErrors::NoStrongETag(url) => {
+                write!(f, "{} has no strong ETag, refusing to overwrite it without a precondition", url)
+            },
+            StoreErrors::TlsError(error) => write!(f, "failed to initialize TLS: {}", error),
+            StoreErrors::HttpError(error) => write!(f, "HTTP request failed: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for StoreErrors {}
+
+impl From<io::Error> for StoreErrors {
+    fn from(error: io::Error) -> Self {
+        StoreErrors::IOError(error)
+    }
+}
+
+impl From<ParseErrors> for StoreErrors {
+    fn from(error: ParseErrors) -> Self {
+        StoreErrors::ReadError(error)
+    }
+}
+
+/// A place a single keyblock is stored
+pub trait BlockStore {
+    /// Path of the block if it is a local file
+    fn local_path(&self) -> Option<&Path>;
+
+    /// Whether a block is stored there
+    fn exists(&self) -> Result<bool, StoreErrors>;
+
+    /// Read the block, refusing to use more than `max_memory` bytes
+    fn load(&self, max_memory: Option<u64>) -> Result<StoredBlock, StoreErrors>;
+
+    /// Write the block, only if it is still at version `expected` when given, returning the new version
+    fn save(&self, content: &[u8], expected: Option<&str>) -> Result<String, StoreErrors>;
+}
+
+/// Backend handling `location`, dispatched on its scheme
+pub fn open_store(location: &Path) -> Result<Box<dyn BlockStore>, StoreErrors> {
+    let text = location.to_string_lossy();
+
+    match text.split_once("://") {
+        None => Ok(Box::new(FileStore { path: location.to_path_buf() })),
+        Some(("file", path)) => Ok(Box::new(FileStore { path: PathBuf::from(path) })),
+        Some(("http", _)) | Some(("https", _)) => Ok(Box::new(HttpStore::new(&text)?)),
+        Some((scheme, _)) => Err(StoreErrors::UnsupportedScheme(scheme.to_string()))
+    }
+}
+
+/// Block stored in a local file, versioned by its SHA256 digest
+///
+/// Saves hold a lock on `<path>.lock` from the version check to the rename of the new content, so two banjo
+/// processes can't both pass the check and overwrite each other.
+pub struct FileStore {
+    pub path: PathBuf
+}
+
+impl BlockStore for FileStore {
+    fn local_path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn exists(&self) -> Result<bool, StoreErrors> {
+        Ok(self.path.exists())
+    }
+
+    fn load(&self, max_memory: Option<u64>) -> Result<StoredBlock, StoreErrors> {
+        let content = read_limited(File::open(&self.path)?, max_memory)?;
+        let version = to_hex(&sha256(&content));
+
+        Ok(StoredBlock { content, version })
+    }
+
+    fn save(&self, content: &[u8], expected: Option<&str>) -> Result<String, StoreErrors> {
+        let _lock = lock_exclusive(&self.path)?;
+        if let Some(expected) = expected {
+            let current = to_hex(&sha256(&fs::read(&self.path)?));
+            if current != expected { return Err(StoreErrors::Conflict(self.path.display().to_string())) }
+        }
+
+        write_private(&self.path, content)?;
+        Ok(to_hex(&sha256(content)))
+    }
+}
+
+/// Block stored behind an HTTP(S) URL, versioned by its ETag and updated with conditional PUT requests
+pub struct HttpStore {
+    url: String,
+    agent: ureq::Agent
+}
+
+impl HttpStore {
+    pub fn new(url: &str) -> Result<HttpStore, StoreErrors> {
+        let agent = ureq::AgentBuilder::new()
+            .tls_connector(Arc::new(TlsConnector::new().map_err(StoreErrors::TlsError)?))
+            .timeout(HTTP_TIMEOUT)
+            .build();
+
+        Ok(HttpStore { url: url.to_string(), agent })
+    }
+}
+
+impl BlockStore for HttpStore {
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
+
+    fn exists(&self) -> Result<bool, StoreErrors> {
+        match self.agent.head(&self.url).call() {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::Status(404, _)) => Ok(false),
+            Err(error) => Err(StoreErrors::HttpError(Box::new(error)))
+        }
+    }
+
+    fn load(&self, max_memory: Option<u64>) -> Result<StoredBlock, StoreErrors> {
+        let response = self.agent.get(&self.url).call().map_err(|error| StoreErrors::HttpError(Box::new(error)))?;
+        let etag = response.header("ETag").map(ToString::to_string);
+
+        // Same estimate as local blocks, twice the serialized size
+        let mut content = Vec::new();
+        let mut reader = response.into_reader();
+        match max_memory {
+            Some(limit) => {
+                reader.take(limit / 2 + 1).read_to_end(&mut content)?;
+                let needed = (content.len() as u64).saturating_mul(2);
+                if needed > limit { return Err(ParseErrors::MemoryLimitExceeded(needed, limit).into()) }
+            },
+            None => { reader.read_to_end(&mut content)?; }
+        }
+
+        let version = etag.unwrap_or_else(|| to_hex(&sha256(&content)));
+        Ok(StoredBlock { content, version })
+    }
+
+    fn save(&self, content: &[u8], expected: Option<&str>) -> Result<String, StoreErrors> {
+        let request = self.agent.put(&self.url).set("Content-Type", "application/octet-stream");
+        // If-Match only uses strong comparison, weak ETags and digests of servers without ETags never match
+        let request = match expected {
+            Some(expected) if expected.starts_with('"') => request.set("If-Match", expected),
+            Some(_) => return Err(StoreErrors::NoStrongETag(self.url.clone())),
+            None => request.set("If-None-Match", "*")
+        };
+
+        match request.send_bytes(content) {
+            Ok(response) => Ok(response.header("ETag").map(ToString::to_string).unwrap_or_else(|| to_hex(&sha256(content)))),
+            Err(ureq::Error::Status(412, _)) => Err(StoreErrors::Conflict(self.url.clone())),
+            Err(error) => Err(StoreErrors::HttpError(Box::new(error)))
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Cipher suites, naming the set of algorithms a keyblock is protected with
+//!
+//! The suite is stored in the block header starting with format 3, so the cryptography can evolve by adding
+//! suites instead of bumping the format specifier. Older blocks get the suite implied by their signature.
+
+use crate::secret::WrapAlgorithm;
+use crate::signature::SignatureAlgorithm;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use std::fmt;
+
+/// Cipher encrypting key content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCipher {
+    /// AES-256-GCM in 64 KiB chunks, see the `crypto` module
+    Aes256GcmChunked
+}
+
+/// Function deriving secrets from other secrets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// HKDF with SHA256, see RFC 5869
+    HkdfSha256
+}
+
+/// Set of algorithms used by a keyblock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum CipherSuite {
+    /// Chunked AES-256-GCM content, AES-KW(P) wrapping, HKDF-SHA256 and RSA-SHA256 signatures
+    Classic,
+    /// Same as `Classic`, with hybrid RSA-SHA256 and ML-DSA-65 signatures
+    Hybrid
+}
+
+impl CipherSuite {
+    /// Identifier of this suite in the serialized format
+    pub fn identifier(self) -> u16 {
+        match self {
+            CipherSuite::Classic => 1,
+            CipherSuite::Hybrid => 2
+        }
+    }
+
+    /// Suite matching a serialized identifier, if known
+    pub fn from_identifier(identifier: u16) -> Option<CipherSuite> {
+        match identifier {
+            1 => Some(CipherSuite::Classic),
+            2 => Some(CipherSuite::Hybrid),
+            _ => None
+        }
+    }
+
+    /// Suite of a block signed with `algorithm`, used for blocks without a suite field
+    pub fn for_signature(algorithm: SignatureAlgorithm) -> CipherSuite {
+        match algorithm {
+            SignatureAlgorithm::RsaSha256MlDsa65 => CipherSuite::Hybrid,
+            SignatureAlgorithm::None | SignatureAlgorithm::RsaSha256 => CipherSuite::Classic
+        }
+    }
+
+    /// Cipher encrypting the key contents of blocks using this suite
+    pub fn content_cipher(self) -> ContentCipher {
+        match self {
+            CipherSuite::Classic | CipherSuite::Hybrid => ContentCipher::Aes256GcmChunked
+        }
+    }
+
+    /// Function deriving the key secrets of blocks using this suite, like derived keyfile secrets
+    pub fn kdf(self) -> Kdf {
+        match self {
+            CipherSuite::Classic | CipherSuite::Hybrid => Kdf::HkdfSha256
+        }
+    }
+
+    /// Whether key secrets can be wrapped with `algorithm` in this suite
+    pub fn allows_wrap(self, algorithm: WrapAlgorithm) -> bool {
+        match self {
+            // Unwrapped secrets stay allowed, blocks from before wrapping was introduced have them
+            CipherSuite::Classic | CipherSuite::Hybrid => {
+                matches!(algorithm, WrapAlgorithm::None | WrapAlgorithm::AesKw | WrapAlgorithm::AesKwp)
+            }
+        }
+    }
+
+    /// Algorithm of the block signature
+    pub fn signature_algorithm(self) -> SignatureAlgorithm {
+        match self {
+            CipherSuite::Classic => SignatureAlgorithm::RsaSha256,
+            CipherSuite::Hybrid => SignatureAlgorithm::RsaSha256MlDsa65
+        }
+    }
+}
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CipherSuite::Classic => write!(f, "classic (AES-256-GCM, AES-KW, HKDF-SHA256, RSA-SHA256)"),
+            CipherSuite::Hybrid => write!(f, "hybrid (AES-256-GCM, AES-KW, HKDF-SHA256, RSA-SHA256 + ML-DSA-65)")
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Tables printed by commands, with selectable columns and sorting
+//!
+//! A command lists its items as rows of a table whose columns are picked by the user. Each column knows how
+//! to render an item and how to order items, so sorting by sizes or dates doesn't compare their text. Cells
+//! are rendered with the `display` module, so they are safe to print whatever the items contain.
+//!
+//! Tables can also be written as JSON, CSV or TSV for reporting tools and spreadsheets. Those keep the cells
+//! intact, except for cells starting like a spreadsheet formula, which are prefixed by a quote so opening the
+//! report can't run anything.
+//!
+//! Porcelain output is meant for scripts: fixed fields, no header, no alignment and no rendering. Its layout
+//! never changes within a porcelain version, new fields only come with a new version.
+
+use crate::cache::KeyMetadata;
+use crate::content::ContentType;
+use crate::display::{pad, render, width, RenderOptions};
+use crate::i18n;
+use crate::rotation::Rotation;
+use crate::utils::{format_day, format_uid};
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::iter;
+
+/// Characters starting spreadsheet formulas
+const FORMULA_PREFIXES: [char; 4] = ['=', '+', '-', '@'];
+
+/// Versions of the porcelain output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PorcelainVersion {
+    V1,
+    /// Adds the key ID
+    V2
+}
+
+/// Formats tables can be printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns, for humans
+    Table,
+    /// Array of objects keyed by column
+    Json,
+    /// Comma separated values, RFC 4180
+    Csv,
+    /// Tab separated values, with tabs, newlines and backslashes escaped
+    Tsv
+}
+
+/// A column of a table listing items of type `T`
+pub trait Column<T>: Copy {
+    /// Title of the column
+    fn header(self) -> &'static str;
+
+    /// Content of the cell of `item`
+    fn cell(self, item: &T) -> String;
+
+    /// Order of two items along this column, their cells by default
+    fn compare(self, a: &T, b: &T) -> Ordering {
+        self.cell(a).cmp(&self.cell(b))
+    }
+}
+
+/// A table ready to be printed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>
+}
+
+impl Table {
+    /// Table of `items` with `columns`, sorted along `sort` if given and keeping their order otherwise
+    pub fn build<'a, T: 'a, C: Column<T>>(items: impl IntoIterator<Item = &'a T>, columns: &[C], sort: Option<C>) -> Table {
+        let mut items: Vec<&T> = items.into_iter().collect();
+        if let Some(column) = sort {
+            // The sort is stable, so items equal along the column keep their order
+            items.sort_by(|a, b| column.compare(a, b));
+        }
+
+        Table {
+            header: columns.iter().map(|column| column.header().to_string()).collect(),
+            rows: items.iter().map(|item| columns.iter().map(|column| column.cell(item)).collect()).collect()
+        }
+    }
+
+    /// Lines of this table, with aligned columns separated by two spaces
+    pub fn lines(&self, options: RenderOptions) -> Vec<String> {
+        let header: Vec<String> = self.header.iter().map(|header| i18n::header(header)).collect();
+        let rows: Vec<Vec<String>> = self.rows.iter()
+            .map(|row| row.iter().map(|cell| render(cell, options)).collect())
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| rows.iter().chain(iter::once(&header)).map(|row| width(&row[column])).max().unwrap_or(0))
+            .collect();
+
+        iter::once(&header).chain(&rows)
+            .map(|row| row.iter().zip(&widths).map(|(cell, width)| pad(cell, *width)).join("  ").trim_end().to_string())
+            .collect()
+    }
+
+    /// Print this table
+    pub fn print(&self, options: RenderOptions) {
+        for line in self.lines(options) {
+            println!("{}", line);
+        }
+    }
+
+    /// Rows of this table as objects keyed by the lowercase column headers
+    pub fn to_json(&self) -> Value {
+        self.rows.iter().map(|row| {
+            let object: Map<String, Value> = self.header.iter().zip(row)
+                .map(|(header, cell)| (header.to_lowercase(), Value::String(cell.clone())))
+                .collect();
+            Value::Object(object)
+        }).collect()
+    }
+
+    /// CSV serialization of this table, header included
+    pub fn to_csv(&self) -> String {
+        let quote = |cell: &String| {
+            let cell = neutralize_formula(cell);
+            if cell.contains(['"', ',', '\n', '\r'].as_ref()) { format!("\"{}\"", cell.replace('"', "\"\"")) } else { cell }
+        };
+
+        iter::once(&self.header).chain(&self.rows).map(|row| row.iter().map(quote).join(",") + "\r\n").collect()
+    }
+
+    /// TSV serialization of this table, header included
+    pub fn to_tsv(&self) -> String {
+        let escape = |cell: &String| {
+            neutralize_formula(cell).replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+        };
+
+        iter::once(&self.header).chain(&self.rows).map(|row| row.iter().map(escape).join("\t") + "\n").collect()
+    }
+
+    /// Porcelain serialization of the rows of this table
+    ///
+    /// Rows are lines of tab separated fields, tabs, newlines and backslashes being escaped like in TSV. With
+    /// `nul`, every field is instead terminated by a null byte and left as is.
+    pub fn to_porcelain(&self, nul: bool) -> String {
+        if nul {
+            return self.rows.iter().flatten().map(|cell| format!("{}\0", cell)).collect()
+        }
+
+        let escape = |cell: &String| cell.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r");
+        self.rows.iter().map(|row| row.iter().map(escape).join("\t") + "\n").collect()
+    }
+
+    /// Print this table in `format`
+    pub fn print_as(&self, format: OutputFormat, options: RenderOptions) {
+        match format {
+            OutputFormat::Table => self.print(options),
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&self.to_json()).expect("Serializing strings can't fail."));
+            },
+            OutputFormat::Csv => print!("{}", self.to_csv()),
+            OutputFormat::Tsv => print!("{}", self.to_tsv())
+        }
+    }
+}
+
+/// `cell` prefixed by a quote if a spreadsheet would read it as a formula
+fn neutralize_formula(cell: &str) -> String {
+    if cell.starts_with(FORMULA_PREFIXES.as_ref()) { format!("'{}", cell) } else { cell.to_string() }
+}
+
+/// Columns of key listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyColumn {
+    Uid,
+    /// Globally unique ID
+    Id,
+    Path,
+    Name,
+    Description,
+    Type,
+    Size,
+    Tags,
+    /// Expiry date of the content, for types which have one
+    Expiry,
+    /// Date the next rotation is due, for keys with a schedule
+    Rotation,
+    /// Subject of `x509` keys
+    Subject,
+    /// Issuer of `x509` keys
+    Issuer,
+    /// Subject alternative names of `x509` keys
+    Sans,
+    /// SHA256 fingerprint of `ssh-key` keys
+    Fingerprint,
+    /// Date the key was last handed out, when access tracking is enabled
+    LastAccess,
+    /// Keyed digest of the stored content
+    Hmac
+}
+
+impl KeyColumn {
+    /// Columns listed by default
+    pub const DEFAULT: [KeyColumn; 5] = [KeyColumn::Uid, KeyColumn::Path, KeyColumn::Type, KeyColumn::Size, KeyColumn::Tags];
+
+    /// Columns listed by `list --long`
+    pub const LONG: [KeyColumn; 11] = [
+        KeyColumn::Uid, KeyColumn::Path, KeyColumn::Type, KeyColumn::Size, KeyColumn::Tags, KeyColumn::Subject,
+        KeyColumn::Issuer, KeyColumn::Sans, KeyColumn::Expiry, KeyColumn::Fingerprint, KeyColumn::LastAccess
+    ];
+
+    /// Fields of each porcelain version, the path being last as it is the most likely to contain separators
+    pub fn porcelain(version: PorcelainVersion) -> &'static [KeyColumn] {
+        match version {
+            PorcelainVersion::V1 => &[KeyColumn::Uid, KeyColumn::Type, KeyColumn::Size, KeyColumn::Tags, KeyColumn::Path],
+            PorcelainVersion::V2 => &[KeyColumn::Uid, KeyColumn::Id, KeyColumn::Type, KeyColumn::Size, KeyColumn::Tags, KeyColumn::Path]
+        }
+    }
+}
+
+impl Column<KeyMetadata> for KeyColumn {
+    fn header(self) -> &'static str {
+        match self {
+            KeyColumn::Uid => "UID",
+            KeyColumn::Id => "ID",
+            KeyColumn::Path => "PATH",
+            KeyColumn::Name => "NAME",
+            KeyColumn::Description => "DESCRIPTION",
+            KeyColumn::Type => "TYPE",
+            KeyColumn::Size => "SIZE",
+            KeyColumn::Tags => "TAGS",
+            KeyColumn::Expiry => "EXPIRY",
+            KeyColumn::Rotation => "ROTATION",
+            KeyColumn::Subject => "SUBJECT",
+            KeyColumn::Issuer => "ISSUER",
+            KeyColumn::Sans => "SANS",
+            KeyColumn::Fingerprint => "FINGERPRINT",
+            KeyColumn::LastAccess => "LAST ACCESS",
+            KeyColumn::Hmac => "HMAC"
+        }
+    }
+
+    fn cell(self, key: &KeyMetadata) -> String {
+        match self {
+            KeyColumn::Uid => format_uid(key.uid),
+            KeyColumn::Id => key.id.map(|id| id.to_string()).unwrap_or_default(),
+            KeyColumn::Path => key.path.clone(),
+            KeyColumn::Name => key.name.clone(),
+            KeyColumn::Description => key.description.clone(),
+            KeyColumn::Type => ContentType::from_flags(key.flags).map_or("unknown".to_string(), |type_| type_.to_string()),
+            KeyColumn::Size => key.length.to_string(),
+            KeyColumn::Tags => key.tags.join(","),
+            KeyColumn::Expiry => key.expiry.map(format_day).unwrap_or_default(),
+            KeyColumn::Rotation => Rotation::from_flags(key.flags).map(|rotation| format_day(rotation.due())).unwrap_or_default(),
+            KeyColumn::Subject => key.certificate.as_ref().map(|certificate| certificate.subject.clone()).unwrap_or_default(),
+            KeyColumn::Issuer => key.certificate.as_ref().map(|certificate| certificate.issuer.clone()).unwrap_or_default(),
+            KeyColumn::Sans => key.certificate.as_ref().map(|certificate| certificate.names.join(",")).unwrap_or_default(),
+            KeyColumn::Fingerprint => key.ssh_fingerprint.clone().unwrap_or_default(),
+            KeyColumn::LastAccess => key.last_access.map(format_day).unwrap_or_default(),
+            KeyColumn::Hmac => key.content_hmac.clone()
+        }
+    }
+
+    fn compare(self, a: &KeyMetadata, b: &KeyMetadata) -> Ordering {
+        // Keys without a date are listed last
+        let last_if_none = |a: Option<u32>, b: Option<u32>| match (a, b) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (a, b) => b.is_some().cmp(&a.is_some())
+        };
+
+        match self {
+            KeyColumn::Uid => a.uid.cmp(&b.uid),
+            KeyColumn::Size => a.length.cmp(&b.length),
+            KeyColumn::Expiry => last_if_none(a.expiry, b.expiry),
+            KeyColumn::LastAccess => last_if_none(a.last_access, b.last_access),
+            KeyColumn::Rotation => {
+                let due = |key: &KeyMetadata| Rotation::from_flags(key.flags).map(Rotation::due);
+                last_if_none(due(a), due(b))
+            },
+            column => column.cell(a).cmp(&column.cell(b))
+        }
+    }
+}
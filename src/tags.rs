@@ -0,0 +1,101 @@
+//! Tags attached to keyfiles, and the expressions used to select keyfiles by tag
+//!
+//! An expression is a list of alternatives separated by `|`, at least one of which must match.
+//! An alternative is a tag, which can end with `*` to match every tag starting with the given prefix,
+//! and can start with `!` to match keyfiles which don't have such a tag.
+//!
+//! For instance `env:prod|env:staging`, `team:*` or `!deprecated`.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Characters with a special meaning in tag expressions
+const RESERVED_CHARACTERS: &[char] = &['|', '*', '!'];
+
+/// Error returned for malformed tags and tag expressions
+#[derive(Debug)]
+pub struct InvalidTag(pub String);
+
+impl fmt::Display for InvalidTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "invalid tag \"{}\": tags must be non-empty and can't contain whitespace, control characters or {}",
+            self.0, RESERVED_CHARACTERS.iter().map(char::to_string).collect::<Vec<_>>().join(" ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidTag {}
+
+/// Check that `tag` can be stored and matched by expressions
+pub fn validate_tag(tag: &str) -> Result<(), InvalidTag> {
+    let valid = !tag.is_empty() && !tag.chars().any(|character| {
+        character.is_whitespace() || character.is_control() || RESERVED_CHARACTERS.contains(&character)
+    });
+
+    if valid { Ok(()) } else { Err(InvalidTag(tag.to_string())) }
+}
+
+/// A single alternative of a tag expression
+#[derive(Debug, Clone)]
+struct TagPattern {
+    /// Match keyfiles without a matching tag
+    negated: bool,
+    /// Match tags starting with `value` instead of equal to it
+    prefix: bool,
+    value: String
+}
+
+impl TagPattern {
+    fn matches(&self, tags: &[String]) -> bool {
+        let found = tags.iter().any(|tag| {
+            if self.prefix { tag.starts_with(&self.value) } else { *tag == self.value }
+        });
+
+        found != self.negated
+    }
+}
+
+/// A tag expression, matching keyfiles with at least one of its alternatives
+#[derive(Debug, Clone)]
+pub struct TagExpression(Vec<TagPattern>);
+
+impl TagExpression {
+    /// Check if a keyfile with these tags matches this expression
+    pub fn matches(&self, tags: &[String]) -> bool {
+        self.0.iter().any(|pattern| pattern.matches(tags))
+    }
+}
+
+impl FromStr for TagExpression {
+    type Err = InvalidTag;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut patterns = Vec::new();
+
+        for alternative in expression.split('|') {
+            let (negated, rest) = match alternative.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, alternative)
+            };
+            let (prefix, value) = match rest.strip_suffix('*') {
+                Some(value) => (true, value),
+                None => (false, rest)
+            };
+
+            // A lone `*` matches any tag
+            if !(prefix && value.is_empty()) {
+                validate_tag(value).map_err(|_| InvalidTag(alternative.to_string()))?;
+            }
+
+            patterns.push(TagPattern { negated, prefix, value: value.to_string() });
+        }
+
+        Ok(TagExpression(patterns))
+    }
+}
+
+/// Check if a keyfile with these tags matches every expression
+pub fn matches_all(expressions: &[TagExpression], tags: &[String]) -> bool {
+    expressions.iter().all(|expression| expression.matches(tags))
+}
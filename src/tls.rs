@@ -0,0 +1,170 @@
+//! TLS private keys, certificate signing requests and certificates, generated directly into keyblocks
+//!
+//! Private keys are stored PKCS#8 PEM encoded, so they are recognized by the `ssh-key` content type, and
+//! certificates PEM encoded with the `x509` content type.
+
+use clap::ValueEnum;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, BigNumRef, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private};
+use openssl::rsa::Rsa;
+use openssl::stack::Stack;
+use openssl::x509::extension::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier
+};
+use openssl::x509::{X509Builder, X509Name, X509NameBuilder, X509NameRef, X509Ref, X509Req, X509ReqBuilder, X509};
+use serde::Deserialize;
+use std::net::IpAddr;
+
+/// Algorithms of generated TLS keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyAlgorithm {
+    /// ECDSA over NIST P-256
+    EcP256,
+    /// ECDSA over NIST P-384
+    EcP384,
+    /// RSA with a 2048 bits modulus
+    Rsa2048,
+    /// RSA with a 4096 bits modulus
+    Rsa4096,
+    /// Ed25519, which some clients still don't support
+    Ed25519
+}
+
+impl KeyAlgorithm {
+    /// Algorithm of an existing private key, if it is one of the supported ones
+    pub fn of(key: &PKeyRef<Private>) -> Option<KeyAlgorithm> {
+        match (key.id(), key.bits()) {
+            (Id::EC, 256) => Some(KeyAlgorithm::EcP256),
+            (Id::EC, 384) => Some(KeyAlgorithm::EcP384),
+            (Id::RSA, 2048) => Some(KeyAlgorithm::Rsa2048),
+            (Id::RSA, 4096) => Some(KeyAlgorithm::Rsa4096),
+            (Id::ED25519, _) => Some(KeyAlgorithm::Ed25519),
+            _ => None
+        }
+    }
+
+    /// Generate a private key of this algorithm
+    pub fn generate(self) -> Result<PKey<Private>, ErrorStack> {
+        let ec_key = |curve| -> Result<PKey<Private>, ErrorStack> {
+            let group = EcGroup::from_curve_name(curve)?;
+            PKey::from_ec_key(EcKey::generate(&group)?)
+        };
+
+        match self {
+            KeyAlgorithm::EcP256 => ec_key(Nid::X9_62_PRIME256V1),
+            KeyAlgorithm::EcP384 => ec_key(Nid::SECP384R1),
+            KeyAlgorithm::Rsa2048 => PKey::from_rsa(Rsa::generate(2048)?),
+            KeyAlgorithm::Rsa4096 => PKey::from_rsa(Rsa::generate(4096)?),
+            KeyAlgorithm::Ed25519 => PKey::generate_ed25519()
+        }
+    }
+}
+
+/// Key and certificate signing a certificate
+pub struct Issuer<'a> {
+    pub key: &'a PKeyRef<Private>,
+    /// Certificate of the issuer, `None` for self-signed certificates
+    pub certificate: Option<&'a X509Ref>
+}
+
+/// Subject name made of a common name
+pub fn subject_name(common_name: &str) -> Result<X509Name, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
+
+    Ok(name.build())
+}
+
+/// Subject alternative names extension, IP addresses and DNS names being told apart by their syntax
+pub fn alternative_names(names: &[String]) -> SubjectAlternativeName {
+    let mut extension = SubjectAlternativeName::new();
+    for name in names {
+        match name.parse::<IpAddr>() {
+            Ok(_) => extension.ip(name),
+            Err(_) => extension.dns(name)
+        };
+    }
+
+    extension
+}
+
+/// Digest to sign with `key`, Ed25519 signing the message directly
+pub fn signature_digest(key: &PKeyRef<Private>) -> MessageDigest {
+    if key.id() == Id::ED25519 { MessageDigest::null() } else { MessageDigest::sha256() }
+}
+
+/// Certificate signing request of `key` for `common_name` and the alternative `names`
+pub fn certificate_request(key: &PKeyRef<Private>, common_name: &str, names: &[String]) -> Result<X509Req, ErrorStack> {
+    let subject = subject_name(common_name)?;
+    let mut request = X509ReqBuilder::new()?;
+    request.set_version(0)?;
+    request.set_subject_name(&subject)?;
+    request.set_pubkey(key)?;
+
+    if !names.is_empty() {
+        let mut extensions = Stack::new()?;
+        extensions.push(alternative_names(names).build(&request.x509v3_context(None))?)?;
+        request.add_extensions(&extensions)?;
+    }
+
+    request.sign(key, signature_digest(key))?;
+    Ok(request.build())
+}
+
+/// Random positive serial number, as recommended by the CA/Browser forum
+pub fn random_serial() -> Result<BigNum, ErrorStack> {
+    let mut serial = BigNum::new()?;
+    serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+
+    Ok(serial)
+}
+
+/// Certificate of `public_key` for `subject`, valid for `days` days and usable for TLS servers and clients,
+/// or to sign other certificates if `is_ca` is set
+pub fn issue_certificate<T: HasPublic>(
+    public_key: &PKeyRef<T>, subject: &X509NameRef, names: &[String], days: u32, is_ca: bool, serial: &BigNumRef,
+    issuer: &Issuer
+) -> Result<X509, ErrorStack> {
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(days)?;
+
+    let mut certificate = X509Builder::new()?;
+    certificate.set_version(2)?;
+    certificate.set_serial_number(&serial)?;
+    certificate.set_subject_name(subject)?;
+    certificate.set_issuer_name(issuer.certificate.map(|issuer| issuer.subject_name()).unwrap_or(subject))?;
+    certificate.set_pubkey(public_key)?;
+    certificate.set_not_before(&not_before)?;
+    certificate.set_not_after(&not_after)?;
+
+    if is_ca {
+        certificate.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        certificate.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build()?)?;
+    } else {
+        certificate.append_extension(BasicConstraints::new().critical().build()?)?;
+        certificate.append_extension(KeyUsage::new().critical().digital_signature().key_encipherment().build()?)?;
+        certificate.append_extension(ExtendedKeyUsage::new().server_auth().client_auth().build()?)?;
+    }
+
+    let subject_key_identifier = SubjectKeyIdentifier::new().build(&certificate.x509v3_context(issuer.certificate, None))?;
+    certificate.append_extension(subject_key_identifier)?;
+    if issuer.certificate.is_some() {
+        let authority_key_identifier = AuthorityKeyIdentifier::new().keyid(false)
+            .build(&certificate.x509v3_context(issuer.certificate, None))?;
+        certificate.append_extension(authority_key_identifier)?;
+    }
+    if !names.is_empty() {
+        let extension = alternative_names(names).build(&certificate.x509v3_context(issuer.certificate, None))?;
+        certificate.append_extension(extension)?;
+    }
+
+    certificate.sign(issuer.key, signature_digest(issuer.key))?;
+    Ok(certificate.build())
+}
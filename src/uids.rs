@@ -0,0 +1,109 @@
+//! Registry of the UIDs allocated across a directory of keyblocks
+//!
+//! UIDs are only unique within a block, and with 8 bits left after the prefix letter, blocks of the same
+//! store easily collide. The registry, stored as `.banjo-uids.json` in the directory, records which block
+//! file uses every block and keyfile UID so new UIDs can avoid all of them. Once a directory has a registry,
+//! the UIDs of its other blocks are reserved whenever one of its blocks is loaded, and the registry is
+//! updated whenever one of them is signed.
+
+use crate::keyblock::KeyBlock;
+use crate::permissions::write_private;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// Name of the registry file in a directory of keyblocks
+pub const REGISTRY_FILE: &str = ".banjo-uids.json";
+
+/// A UID used by a block, or by one of its keyfiles if `path` is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub uid: u16,
+    /// File name of the block in the directory
+    pub file: String,
+    /// Path of the keyfile, `None` for the block UID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}", self.file, path),
+            None => write!(f, "{}", self.file)
+        }
+    }
+}
+
+/// UIDs allocated across a directory of keyblocks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UidRegistry {
+    pub allocations: Vec<Allocation>
+}
+
+impl UidRegistry {
+    /// Path of the registry of `directory`
+    pub fn path(directory: &Path) -> PathBuf {
+        directory.join(REGISTRY_FILE)
+    }
+
+    /// Directory and file name of the block at `block`, if its directory keeps a registry
+    pub fn locate(block: &Path) -> Option<(PathBuf, String)> {
+        let directory = block.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let file = block.file_name()?.to_str()?;
+        if !UidRegistry::path(directory).exists() { return None }
+
+        Some((directory.to_path_buf(), file.to_string()))
+    }
+
+    /// Read the registry of `directory`, empty if there is none yet
+    pub fn load(directory: &Path) -> io::Result<UidRegistry> {
+        match fs::read(UidRegistry::path(directory)) {
+            Ok(content) => serde_json::from_slice(&content).map_err(io::Error::other),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(UidRegistry::default()),
+            Err(error) => Err(error)
+        }
+    }
+
+    /// Write this registry to `directory`
+    pub fn save(&self, directory: &Path) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(self).expect("Serializing a registry can't fail.");
+        write_private(&UidRegistry::path(directory), &content)
+    }
+
+    /// Registry of the UIDs of `blocks`, designated by their file names
+    pub fn scan<'a>(blocks: impl IntoIterator<Item = (&'a str, &'a KeyBlock)>) -> UidRegistry {
+        let mut registry = UidRegistry::default();
+        for (file, block) in blocks {
+            registry.record(file, block);
+        }
+
+        registry
+    }
+
+    /// Replace the allocations of the block `file` with the UIDs of `block`
+    pub fn record(&mut self, file: &str, block: &KeyBlock) {
+        self.allocations.retain(|allocation| allocation.file != file);
+        self.allocations.push(Allocation { uid: block.uid, file: file.to_string(), path: None });
+        self.allocations.extend(block.keys.values().map(|key| {
+            Allocation { uid: key.uid, file: file.to_string(), path: Some(key.path.clone()) }
+        }));
+        self.allocations.sort_by(|a, b| (a.uid, &a.file, &a.path).cmp(&(b.uid, &b.file, &b.path)));
+    }
+
+    /// UIDs allocated to other blocks than `file` or to their keyfiles
+    pub fn reserved(&self, file: &str) -> Vec<u16> {
+        self.allocations.iter().filter(|allocation| allocation.file != file).map(|allocation| allocation.uid).collect()
+    }
+
+    /// UIDs allocated more than once, with their allocations
+    pub fn collisions(&self) -> Vec<(u16, Vec<&Allocation>)> {
+        self.allocations.iter()
+            .into_group_map_by(|allocation| allocation.uid)
+            .into_iter()
+            .filter(|(_, allocations)| allocations.len() > 1)
+            .sorted_by_key(|(uid, _)| *uid)
+            .collect()
+    }
+}
@@ -0,0 +1,141 @@
+//! Self-updates from signature verified releases
+//!
+//! Releases are published under a base URL, as the binary `<base>/banjo-<arch>-<os>` and its detached
+//! signature `<base>/banjo-<arch>-<os>.sig`. Signatures are made with the release key, pinned at build time
+//! through the `BANJO_RELEASE_KEY` environment variable holding its PEM public key, Ed25519 or RSA with SHA256.
+//! Builds without a pinned release key can't update themselves. The default base URL is pinned the same way
+//! through `BANJO_RELEASE_URL`, and can be overridden with the `update-url` setting.
+
+use crate::install::{install, InstallErrors};
+use native_tls::TlsConnector;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fmt};
+
+/// PEM public key releases are signed with, if pinned in this build
+pub const RELEASE_KEY: Option<&str> = option_env!("BANJO_RELEASE_KEY");
+/// Default base URL of releases, if pinned in this build
+pub const RELEASE_URL: Option<&str> = option_env!("BANJO_RELEASE_URL");
+
+/// Timeout of release downloads
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+/// Size above which a download is refused, in bytes
+const MAX_DOWNLOAD_SIZE: u64 = 256 << 20;
+/// Mode of the installed binary
+const EXECUTABLE_MODE: u32 = 0o755;
+
+/// Enumeration of the potential errors when updating
+#[derive(Debug)]
+pub enum UpdateErrors {
+    /// This build has no pinned release key
+    NoReleaseKey,
+    /// No release URL is pinned nor configured
+    NoReleaseUrl,
+    /// The pinned release key isn't a valid PEM public key
+    InvalidReleaseKey(ErrorStack),
+    /// The TLS backend couldn't be initialized
+    TlsError(native_tls::Error),
+    /// A download failed
+    DownloadError(Box<ureq::Error>),
+    /// A download is larger than `MAX_DOWNLOAD_SIZE`
+    DownloadTooLarge(String),
+    /// The release doesn't match its signature
+    InvalidSignature,
+    /// The new binary couldn't be installed
+    InstallError(InstallErrors),
+    /// An IO error occurred
+    IOError(io::Error)
+}
+
+impl fmt::Display for UpdateErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateErrors::NoReleaseKey => write!(f, "this build has no pinned release key and can't update itself"),
+            UpdateErrors::NoReleaseUrl => write!(f, "no release URL is pinned in this build, set update-url"),
+            UpdateErrors::InvalidReleaseKey(error) => write!(f, "the pinned release key is invalid: {}", error),
+            UpdateErrors::TlsError(error) => write!(f, "failed to initialize TLS: {}", error),
+            UpdateErrors::DownloadError(error) => write!(f, "download failed: {}", error),
+            UpdateErrors::DownloadTooLarge(url) => write!(f, "{} is larger than {} bytes", url, MAX_DOWNLOAD_SIZE),
+            UpdateErrors::InvalidSignature => write!(f, "the release doesn't match its signature, refusing to install it"),
+            UpdateErrors::InstallError(error) => write!(f, "failed to install the release: {}", error),
+            UpdateErrors::IOError(error) => write!(f, "IO error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for UpdateErrors {}
+
+impl From<io::Error> for UpdateErrors {
+    fn from(error: io::Error) -> Self {
+        UpdateErrors::IOError(error)
+    }
+}
+
+/// Name of the release binary for the current platform
+pub fn asset_name() -> String {
+    format!("banjo-{}-{}", env::consts::ARCH, env::consts::OS)
+}
+
+/// A downloaded and verified release
+#[derive(Debug)]
+pub struct Release {
+    /// URL the binary was downloaded from
+    pub url: String,
+    pub binary: Vec<u8>
+}
+
+impl Release {
+    /// Download the release for the current platform from `base_url` and verify its signature
+    pub fn fetch(base_url: &str) -> Result<Release, UpdateErrors> {
+        let key = RELEASE_KEY.ok_or(UpdateErrors::NoReleaseKey)?;
+        let url = format!("{}/{}", base_url.trim_end_matches('/'), asset_name());
+
+        let agent = ureq::AgentBuilder::new()
+            .tls_connector(Arc::new(TlsConnector::new().map_err(UpdateErrors::TlsError)?))
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build();
+        let binary = download(&agent, &url)?;
+        let signature = download(&agent, &format!("{}.sig", url))?;
+
+        verify(&binary, &signature, key.as_bytes())?;
+        Ok(Release { url, binary })
+    }
+
+    /// Atomically replace the executable at `path` with this release
+    ///
+    /// Renaming over the running executable is fine on Unix, the running process keeps the old inode.
+    pub fn install(&self, path: &Path) -> Result<(), UpdateErrors> {
+        install(path, &self.binary, EXECUTABLE_MODE, None).map_err(UpdateErrors::InstallError)
+    }
+}
+
+/// Body of `url`, refusing responses larger than `MAX_DOWNLOAD_SIZE`
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>, UpdateErrors> {
+    let response = agent.get(url).call().map_err(|error| UpdateErrors::DownloadError(Box::new(error)))?;
+
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_DOWNLOAD_SIZE + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_DOWNLOAD_SIZE { return Err(UpdateErrors::DownloadTooLarge(url.to_string())) }
+
+    Ok(body)
+}
+
+/// Check the detached `signature` of `binary` against the PEM public key `key`
+pub fn verify(binary: &[u8], signature: &[u8], key: &[u8]) -> Result<(), UpdateErrors> {
+    let key = PKey::public_key_from_pem(key).map_err(UpdateErrors::InvalidReleaseKey)?;
+    let mut verifier = match key.id() {
+        Id::ED25519 => Verifier::new_without_digest(&key),
+        _ => Verifier::new(MessageDigest::sha256(), &key)
+    }.map_err(UpdateErrors::InvalidReleaseKey)?;
+
+    match verifier.verify_oneshot(signature, binary) {
+        Ok(true) => Ok(()),
+        _ => Err(UpdateErrors::InvalidSignature)
+    }
+}
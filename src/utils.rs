@@ -1,25 +1,87 @@
 use itertools::Itertools;
-use std::io::Read;
+use std::io::{self, BufRead};
+use std::str;
 use log::debug;
 
-pub fn compare_buffers(a: &Vec<u8>, b: &Vec<u8>) -> bool {
+pub fn compare_buffers(a: &[u8], b: &[u8]) -> bool {
     let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
     matching == a.len() && matching == b.len()
 }
 
-pub fn buffer_to_string(buf: &Vec<u8>) -> String {
+pub fn buffer_to_string(buf: &[u8]) -> String {
     buf.iter().join(" ")
 }
 
-pub fn read_null_string<R: Read>(reader: &mut R) -> String {
-    let mut buffer = String::new();
-    let mut iterator = reader.bytes();
+/// Format a UID as its prefix letter followed by its hexadecimal number, e.g. `F2a`
+pub fn format_uid(uid: u16) -> String {
+    format!("{}{:02x}", char::from((uid >> 8) as u8), uid & 0xff)
+}
 
-    while let Some(Ok(byte)) = iterator.next() {
-        if byte == 0 { break }
-        buffer.push(char::from(byte));
-    }
+pub fn to_hex(buf: &[u8]) -> String {
+    buf.iter().map(|byte| format!("{:02x}", byte)).join("")
+}
+
+/// Parse a number of days, optionally followed by `d`, e.g. `180d`
+pub fn parse_days(text: &str) -> Result<u32, String> {
+    text.strip_suffix('d').unwrap_or(text).parse::<u32>()
+        .map_err(|_| format!("invalid duration \"{}\", expected a number of days optionally followed by d", text))
+}
+
+/// Format a day counted since the UNIX epoch as an ISO 8601 date, e.g. `2024-02-29`
+pub fn format_day(day: u32) -> String {
+    // Civil calendar conversion over 400 years eras, see http://howardhinnant.github.io/date_algorithms.html
+    let days = day as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// Parse a size in bytes, optionally followed by a binary `K`, `M` or `G` multiplier, e.g. `10M`
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let (number, multiplier) = match text.chars().last().map(|unit| unit.to_ascii_uppercase()) {
+        Some('K') => (&text[..text.len() - 1], 1 << 10),
+        Some('M') => (&text[..text.len() - 1], 1 << 20),
+        Some('G') => (&text[..text.len() - 1], 1 << 30),
+        _ => (text, 1)
+    };
 
+    number.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size \"{}\", expected a number of bytes optionally followed by K, M or G", text))
+}
+
+/// Read a null terminated UTF-8 string, failing if the input ends before the terminator
+///
+/// Invalid UTF-8 is reported as an `InvalidData` error wrapping the `Utf8Error`.
+pub fn read_null_string<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    read_null_string_with(reader, &mut Vec::new())
+}
+
+/// Read a null terminated string like `read_null_string`, using `scratch` as the read buffer
+pub fn read_null_string_with<R: BufRead>(reader: &mut R, scratch: &mut Vec<u8>) -> io::Result<String> {
+    scratch.clear();
+    reader.read_until(0, scratch)?;
+    if scratch.pop() != Some(0) { return Err(io::ErrorKind::UnexpectedEof.into()) }
+
+    // Strings are serialized as UTF-8, decoding them byte per byte would mangle anything outside of ASCII
+    let buffer = str::from_utf8(scratch).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?.to_string();
     debug!("Read string \"{}\"", buffer);
-    buffer
+    Ok(buffer)
+}
+
+/// Write `string` followed by its null terminator, refusing strings that contain one already
+pub fn write_null_string(buffer: &mut Vec<u8>, field: &str, string: &str) -> io::Result<()> {
+    if string.contains('\0') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} contains a null byte", field)))
+    }
+    buffer.extend(string.as_bytes());
+    buffer.push(0);
+    Ok(())
 }
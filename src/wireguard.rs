@@ -0,0 +1,160 @@
+//! Deployment of the WireGuard keys stored in keyblocks
+//!
+//! Keys are either rendered into a wg-quick configuration file, keeping everything but the `PrivateKey` and
+//! `PresharedKey` lines as is, or handed to `wg set`. `wg` reads them on its standard input, so they never
+//! appear in process arguments.
+
+use openssl::base64::{decode_block, encode_block};
+use openssl::error::ErrorStack;
+use openssl::pkey::{Id, PKey};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Length of decoded WireGuard keys
+const KEY_SIZE: usize = 32;
+/// Longest name of a network interface
+const MAX_INTERFACE_LENGTH: usize = 15;
+
+/// Enumeration of the potential errors when deploying WireGuard keys
+#[derive(Debug)]
+pub enum WireguardErrors {
+    /// The interface name isn't a valid network interface name
+    InvalidInterface(String),
+    /// A key isn't a base64 encoded 32 bytes key
+    InvalidKey(String),
+    /// The configuration has no `[Interface]` section
+    NoInterfaceSection,
+    /// A preshared key is given for a peer the configuration doesn't declare
+    UnknownPeer(String),
+    /// `wg` exited with an error
+    CommandFailed(ExitStatus),
+    IOError(io::Error)
+}
+
+impl fmt::Display for WireguardErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireguardErrors::InvalidInterface(name) => write!(f, "invalid interface name {:?}", name),
+            WireguardErrors::InvalidKey(name) => write!(f, "{} isn't a base64 encoded 32 bytes key", name),
+            WireguardErrors::NoInterfaceSection => write!(f, "the configuration has no [Interface] section"),
+            WireguardErrors::UnknownPeer(peer) => write!(f, "the configuration has no peer with public key {}", peer),
+            WireguardErrors::CommandFailed(status) => write!(f, "wg set failed ({})", status),
+            WireguardErrors::IOError(error) => write!(f, "IO error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for WireguardErrors {}
+
+impl From<io::Error> for WireguardErrors {
+    fn from(error: io::Error) -> Self {
+        WireguardErrors::IOError(error)
+    }
+}
+
+/// Raw bytes of a base64 encoded WireGuard key
+pub fn decode_key(content: &[u8]) -> Option<Vec<u8>> {
+    let decoded = decode_block(std::str::from_utf8(content).ok()?.trim()).ok()?;
+    if decoded.len() == KEY_SIZE { Some(decoded) } else { None }
+}
+
+/// Base64 encoded public key of a base64 encoded private key
+pub fn public_key(private_key: &[u8]) -> Option<String> {
+    let key = PKey::private_key_from_raw_bytes(&decode_key(private_key)?, Id::X25519).ok()?;
+    Some(encode_block(&key.raw_public_key().ok()?))
+}
+
+/// New base64 encoded private key, newline terminated like `wg genkey` prints it
+pub fn generate_key() -> Result<Vec<u8>, ErrorStack> {
+    let mut content = encode_block(&PKey::generate_x25519()?.raw_private_key()?).into_bytes();
+    content.push(b'\n');
+    Ok(content)
+}
+
+/// Check that `name` can name a network interface, so it can't be mistaken for an option of `wg`
+pub fn validate_interface(name: &str) -> Result<(), WireguardErrors> {
+    let valid = !name.is_empty() && name.len() <= MAX_INTERFACE_LENGTH && !name.starts_with('-')
+        && name.chars().all(|char| char.is_ascii_alphanumeric() || "_=+.-".contains(char));
+    if valid { Ok(()) } else { Err(WireguardErrors::InvalidInterface(name.to_string())) }
+}
+
+/// Set the private key of a live interface, then the preshared key shared with each peer
+///
+/// `preshared` maps the base64 public key of peers to the base64 preshared key.
+pub fn set_interface(interface: &str, private_key: &[u8], preshared: &HashMap<String, Vec<u8>>) -> Result<(), WireguardErrors> {
+    validate_interface(interface)?;
+    wg_set(&[interface, "private-key", "/dev/stdin"], private_key)?;
+
+    for (peer, key) in preshared {
+        if decode_key(peer.as_bytes()).is_none() { return Err(WireguardErrors::InvalidKey(peer.clone())) }
+        wg_set(&[interface, "peer", peer, "preshared-key", "/dev/stdin"], key)?;
+    }
+
+    Ok(())
+}
+
+/// Run `wg set` with `key` on its standard input
+fn wg_set(arguments: &[&str], key: &[u8]) -> Result<(), WireguardErrors> {
+    let mut child = Command::new("wg").arg("set").args(arguments).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(key)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() { return Err(WireguardErrors::CommandFailed(status)) }
+    Ok(())
+}
+
+/// Replace the keys of a wg-quick configuration
+///
+/// The `PrivateKey` of the `[Interface]` section and the `PresharedKey` of the `[Peer]` sections whose
+/// `PublicKey` is in `preshared` are replaced, or added after the section header when missing.
+pub fn update_config(config: &str, private_key: &str, preshared: &HashMap<String, String>) -> Result<String, WireguardErrors> {
+    let mut sections: Vec<Vec<String>> = vec![Vec::new()];
+    for line in config.lines() {
+        if line.trim_start().starts_with('[') { sections.push(Vec::new()) }
+        sections.last_mut().expect("There is always a section.").push(line.to_string());
+    }
+
+    let mut found_interface = false;
+    let mut updated_peers = Vec::new();
+    for section in sections.iter_mut().skip(1) {
+        let header = section[0].trim().to_ascii_lowercase();
+        if header == "[interface]" {
+            found_interface = true;
+            set_entry(section, "PrivateKey", private_key);
+        } else if header == "[peer]" {
+            let peer = section.iter().find_map(|line| entry_value(line, "PublicKey")).map(str::to_string);
+            if let Some((peer, key)) = peer.and_then(|peer| preshared.get_key_value(&peer)) {
+                set_entry(section, "PresharedKey", key);
+                updated_peers.push(peer.clone());
+            }
+        }
+    }
+
+    if !found_interface { return Err(WireguardErrors::NoInterfaceSection) }
+    if let Some(peer) = preshared.keys().find(|peer| !updated_peers.contains(peer)) {
+        return Err(WireguardErrors::UnknownPeer(peer.clone()))
+    }
+
+    let mut rendered = sections.concat().join("\n");
+    rendered.push('\n');
+    Ok(rendered)
+}
+
+/// Value of a `name = value` line, names being case insensitive as in wg-quick
+fn entry_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let (entry, value) = line.split_once('=')?;
+    if entry.trim().eq_ignore_ascii_case(name) { Some(value.trim()) } else { None }
+}
+
+/// Replace the value of an entry of a section, or add it after the section header
+fn set_entry(section: &mut Vec<String>, name: &str, value: &str) {
+    let line = format!("{} = {}", name, value);
+    match section.iter().position(|existing| entry_value(existing, name).is_some()) {
+        Some(index) => section[index] = line,
+        None => section.insert(1, line)
+    }
+}
@@ -0,0 +1,69 @@
+//! X.509 details of the certificates stored in keyblocks
+//!
+//! Certificates are recognized by parsing the key content, in PEM or DER form. Only public details are
+//! read, so they can be shown alongside the other metadata of the key.
+
+use openssl::asn1::Asn1Time;
+use openssl::x509::{X509NameRef, X509Ref, X509};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Public details of an X.509 certificate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses of the subject alternative names extension
+    pub names: Vec<String>,
+    /// End of the validity period, as printed by OpenSSL
+    pub not_after: String
+}
+
+impl CertificateInfo {
+    /// Details of the PEM or DER encoded certificate `content`, `None` if it isn't one
+    pub fn read(content: &[u8]) -> Option<CertificateInfo> {
+        let certificate = parse(content)?;
+        let names = certificate.subject_alt_names().map(|names| {
+            names.iter().filter_map(|name| match (name.dnsname(), name.ipaddress()) {
+                (Some(dns), _) => Some(dns.to_string()),
+                (None, Some(ip)) => format_ip(ip),
+                (None, None) => None
+            }).collect()
+        }).unwrap_or_default();
+
+        Some(CertificateInfo {
+            subject: describe_name(certificate.subject_name()),
+            issuer: describe_name(certificate.issuer_name()),
+            names,
+            not_after: certificate.not_after().to_string()
+        })
+    }
+}
+
+/// Parse a PEM or DER encoded certificate, `None` if `content` isn't one
+pub fn parse(content: &[u8]) -> Option<X509> {
+    X509::from_pem(content).or_else(|_| X509::from_der(content)).ok()
+}
+
+/// Whether the validity period of `certificate` is over
+pub fn has_expired(certificate: &X509Ref) -> bool {
+    Asn1Time::days_from_now(0).is_ok_and(|now| certificate.not_after() < now)
+}
+
+/// Text form of a distinguished name, its values separated by commas
+pub fn describe_name(name: &X509NameRef) -> String {
+    name.entries()
+        .filter_map(|entry| entry.data().to_string().ok())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Text form of an IP address stored in a certificate, 4 or 16 bytes long
+fn format_ip(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string()),
+        _ => None
+    }
+}
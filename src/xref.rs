@@ -0,0 +1,185 @@
+//! References to keys from service configurations
+//!
+//! Configurations reference keys as `BLOCK-UID/KEY-UID`, e.g. `B1a/F2c`. Checking them against a set of
+//! keyblocks finds the references to blocks that don't exist, to keys missing from their block, and to block
+//! UIDs shared by several blocks, which can't be told apart.
+
+use crate::display::escape_controls;
+use crate::keyblock::{KeyBlock, BLOCK_UID_PREFIX};
+use crate::utils::format_uid;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A key reference found in a configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub file: PathBuf,
+    /// Line of the reference, starting at 1
+    pub line: usize,
+    pub block: u16,
+    pub key: u16
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", format_uid(self.block), format_uid(self.key))
+    }
+}
+
+/// What a reference designates among a set of keyblocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The key at this path of this block
+    Resolved(PathBuf, String),
+    /// No block has this UID
+    DanglingBlock,
+    /// The block has no key with this UID
+    DanglingKey(PathBuf),
+    /// Several blocks have this UID
+    Ambiguous(Vec<PathBuf>)
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resolution::Resolved(block, path) => write!(f, "{} in {}", escape_controls(path), block.display()),
+            Resolution::DanglingBlock => write!(f, "no keyblock has this UID"),
+            Resolution::DanglingKey(block) => write!(f, "no such key in {}", block.display()),
+            Resolution::Ambiguous(blocks) => {
+                let blocks: Vec<String> = blocks.iter().map(|block| block.display().to_string()).collect();
+                write!(f, "ambiguous between {}", blocks.join(", "))
+            }
+        }
+    }
+}
+
+/// Parse a UID formatted like `F2a`, an uppercase prefix letter followed by two hexadecimal digits
+pub fn parse_uid(text: &str) -> Option<u16> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 3 || !bytes[0].is_ascii_uppercase() || !bytes[1..].iter().all(u8::is_ascii_hexdigit) { return None }
+
+    let number = u8::from_str_radix(&text[1..], 16).ok()?;
+    Some(((bytes[0] as u16) << 8) | number as u16)
+}
+
+/// Key references in the content of the configuration `file`
+pub fn find_references(file: &Path, content: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let bytes = line.as_bytes();
+        // A reference is exactly 7 ASCII characters, not surrounded by other alphanumeric characters
+        for start in (0..bytes.len().saturating_sub(6)).filter(|start| bytes[*start + 3] == b'/') {
+            let end = start + 7;
+            if start > 0 && bytes[start - 1].is_ascii_alphanumeric() { continue }
+            if end < bytes.len() && bytes[end].is_ascii_alphanumeric() { continue }
+            if !line.is_char_boundary(start) || !line.is_char_boundary(end) { continue }
+
+            // Keys of older blocks may have other prefix letters
+            let block = parse_uid(&line[start..start + 3]).filter(|uid| (uid >> 8) as u8 == BLOCK_UID_PREFIX);
+            let key = parse_uid(&line[start + 4..end]);
+            if let (Some(block), Some(key)) = (block, key) {
+                references.push(Reference { file: file.to_path_buf(), line: index + 1, block, key });
+            }
+        }
+    }
+
+    references
+}
+
+/// Resolve a reference against keyblocks, given with their path
+pub fn resolve(reference: &Reference, blocks: &[(PathBuf, KeyBlock)]) -> Resolution {
+    let candidates: Vec<&(PathBuf, KeyBlock)> = blocks.iter().filter(|(_, block)| block.uid == reference.block).collect();
+
+    match candidates.as_slice() {
+        [] => Resolution::DanglingBlock,
+        [(path, block)] => match block.keys.values().find(|key| key.uid == reference.key) {
+            Some(key) => Resolution::Resolved(path.clone(), key.path.clone()),
+            None => Resolution::DanglingKey(path.clone())
+        },
+        candidates => Resolution::Ambiguous(candidates.iter().map(|(path, _)| path.clone()).collect())
+    }
+}
+
+/// Whether `text` matches the shell pattern `pattern`, `*` and `?` not matching `/`
+fn matches_component(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => matches_component(rest, text) || (!text.is_empty() && matches_component(pattern, &text[1..])),
+        (Some((b'?', rest)), Some((_, text))) => matches_component(rest, text),
+        (Some((expected, rest)), Some((actual, text))) => expected == actual && matches_component(rest, text),
+        _ => false
+    }
+}
+
+/// Whether the path components `path` match the glob components `pattern`, `**` matching any number of them
+fn matches_glob(pattern: &[&str], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skipped| matches_glob(rest, &path[skipped..])),
+        Some((component, rest)) => match path.split_first() {
+            Some((first, path)) => matches_component(component.as_bytes(), first.as_bytes()) && matches_glob(rest, path),
+            None => false
+        }
+    }
+}
+
+/// Regular files matching a glob pattern like `/etc/**/*.conf`, sorted by path
+///
+/// `*` and `?` match within a path component, and `**` matches any number of directories.
+pub fn expand_glob(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let pattern_path = Path::new(pattern);
+    let is_wildcard = |component: &str| component.contains(['*', '?'].as_ref());
+
+    // Directories are only walked below the longest prefix without wildcards
+    let mut base = PathBuf::new();
+    let mut components = Vec::new();
+    for component in pattern_path.components() {
+        let name = component.as_os_str().to_string_lossy().to_string();
+        if components.is_empty() && !is_wildcard(&name) {
+            base.push(component);
+        } else {
+            components.push(name);
+        }
+    }
+    if components.is_empty() {
+        return Ok(if base.is_file() { vec![base] } else { Vec::new() })
+    }
+    let root = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base.clone() };
+
+    let components: Vec<&str> = components.iter().map(String::as_str).collect();
+    let mut files = Vec::new();
+    walk(&root, &mut Vec::new(), &components, &mut files)?;
+
+    files.sort();
+    Ok(files.into_iter().map(|relative| base.join(relative)).collect())
+}
+
+/// Collect the files below `directory` matching `pattern`, `relative` being the components walked so far
+fn walk(directory: &Path, relative: &mut Vec<String>, pattern: &[&str], files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        // Unreadable directories are skipped, like a shell would
+        Err(error) if error.kind() == io::ErrorKind::PermissionDenied => return Ok(()),
+        Err(error) => return Err(error)
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        relative.push(entry.file_name().to_string_lossy().to_string());
+
+        // Directories deeper than the pattern can't contain matches
+        if file_type.is_dir() && (pattern.contains(&"**") || relative.len() < pattern.len()) {
+            walk(&entry.path(), relative, pattern, files)?;
+        } else if file_type.is_file() && matches_glob(pattern, relative) {
+            files.push(relative.iter().collect());
+        }
+
+        relative.pop();
+    }
+
+    Ok(())
+}